@@ -0,0 +1,461 @@
+//! Generates the LALR(1) tables of the parser from `src/grammar.txt`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    env,
+    fmt::Write,
+    fs,
+    path::Path,
+};
+
+const GRAMMAR: &str = "src/grammar.txt";
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Symbol {
+    Terminal(usize),
+    Nonterminal(usize),
+}
+
+#[derive(Clone, Copy)]
+enum Assoc {
+    Left,
+    Right,
+    Nonassoc,
+}
+
+struct Production {
+    lhs: usize,
+    rhs: Vec<Symbol>,
+    /// Precedence level, higher binding tighter, and associativity.
+    prec: Option<(usize, Assoc)>,
+    text: String,
+}
+
+struct Grammar {
+    terminals: Vec<char>,
+    nonterminals: Vec<char>,
+    /// The augmented start production first, then the productions by id.
+    productions: Vec<Production>,
+    precedence: HashMap<char, (usize, Assoc)>,
+}
+
+/// Production, position of the dot and lookahead terminal.
+type Item = (usize, usize, usize);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+    Shift(usize),
+    Reduce(usize),
+    /// A nonassociative operator following itself.
+    Error,
+}
+
+fn parse(text: &str) -> Grammar {
+    let mut terminals = vec![];
+    let mut precedence = HashMap::new();
+    let mut levels = 0;
+    let mut rules = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let assoc = match words.next() {
+            Some("%tokens") => {
+                terminals = words.map(symbol).collect();
+                continue;
+            }
+            Some("%left") => Assoc::Left,
+            Some("%right") => Assoc::Right,
+            Some("%nonassoc") => Assoc::Nonassoc,
+            _ => {
+                let (lhs, rhs) = line
+                    .split_once("->")
+                    .unwrap_or_else(|| panic!("invalid grammar line {}", line));
+                let (rhs, prec) = match rhs.split_once("%prec") {
+                    Some((rhs, name)) => (rhs, Some(symbol(name.trim()))),
+                    None => (rhs, None),
+                };
+                rules.push((symbol(lhs.trim()), rhs.trim().to_string(), prec));
+                continue;
+            }
+        };
+        levels += 1;
+        for word in words {
+            precedence.insert(symbol(word), (levels, assoc));
+        }
+    }
+    let mut nonterminals = vec![];
+    for (lhs, _, _) in &rules {
+        if !nonterminals.contains(lhs) {
+            nonterminals.push(*lhs);
+        }
+    }
+    let start = Production {
+        lhs: nonterminals.len(),
+        rhs: vec![Symbol::Nonterminal(0)],
+        prec: None,
+        text: String::new(),
+    };
+    let mut productions = vec![start];
+    for (lhs, rhs, prec) in rules {
+        let rhs = rhs
+            .chars()
+            .map(|c| match nonterminals.iter().position(|&n| n == c) {
+                Some(n) => Symbol::Nonterminal(n),
+                None if c.is_ascii_uppercase() => panic!("{} has no production", c),
+                None => match terminals.iter().position(|&t| t == c) {
+                    Some(t) => Symbol::Terminal(t),
+                    None => panic!("{} isn't in %tokens", c),
+                },
+            })
+            .collect::<Vec<_>>();
+        let last = rhs.iter().rev().find_map(|symbol| match symbol {
+            Symbol::Terminal(t) => Some(terminals[*t]),
+            Symbol::Nonterminal(_) => None,
+        });
+        let prec = prec
+            .or(last)
+            .and_then(|name| precedence.get(&name).copied());
+        let text = format!(
+            "{} -> {}",
+            lhs,
+            rhs.iter()
+                .map(|s| name(&terminals, &nonterminals, *s))
+                .collect::<String>()
+        );
+        productions.push(Production {
+            lhs: nonterminals.iter().position(|&n| n == lhs).unwrap(),
+            rhs,
+            prec,
+            text,
+        });
+    }
+    Grammar {
+        terminals,
+        nonterminals,
+        productions,
+        precedence,
+    }
+}
+
+fn symbol(word: &str) -> char {
+    let mut chars = word.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => c,
+        _ => panic!("symbol {} isn't a single character", word),
+    }
+}
+
+fn name(terminals: &[char], nonterminals: &[char], symbol: Symbol) -> char {
+    match symbol {
+        Symbol::Terminal(t) => terminals[t],
+        Symbol::Nonterminal(n) => nonterminals[n],
+    }
+}
+
+impl Grammar {
+    /// Whether each nonterminal derives the empty string, and the terminals each may start with.
+    fn first_sets(&self) -> (Vec<bool>, Vec<BTreeSet<usize>>) {
+        let count = self.nonterminals.len() + 1;
+        let (mut nullable, mut first) = (vec![false; count], vec![BTreeSet::new(); count]);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for p in &self.productions {
+                let mut all_nullable = true;
+                for symbol in &p.rhs {
+                    match *symbol {
+                        Symbol::Terminal(t) => {
+                            changed |= first[p.lhs].insert(t);
+                            all_nullable = false;
+                        }
+                        Symbol::Nonterminal(n) => {
+                            for t in first[n].clone() {
+                                changed |= first[p.lhs].insert(t);
+                            }
+                            all_nullable = nullable[n];
+                        }
+                    }
+                    if !all_nullable {
+                        break;
+                    }
+                }
+                if all_nullable && !nullable[p.lhs] {
+                    nullable[p.lhs] = true;
+                    changed = true;
+                }
+            }
+        }
+        (nullable, first)
+    }
+
+    fn closure(
+        &self,
+        items: BTreeSet<Item>,
+        nullable: &[bool],
+        first: &[BTreeSet<usize>],
+    ) -> BTreeSet<Item> {
+        let mut closure = items.clone();
+        let mut pending = items.into_iter().collect::<Vec<_>>();
+        while let Some((p, dot, lookahead)) = pending.pop() {
+            let rhs = &self.productions[p].rhs;
+            let n = match rhs.get(dot) {
+                Some(Symbol::Nonterminal(n)) => *n,
+                _ => continue,
+            };
+            // the terminals that may follow the nonterminal
+            let mut follow = BTreeSet::new();
+            let mut rest_nullable = true;
+            for symbol in &rhs[dot + 1..] {
+                match *symbol {
+                    Symbol::Terminal(t) => {
+                        follow.insert(t);
+                        rest_nullable = false;
+                    }
+                    Symbol::Nonterminal(m) => {
+                        follow.extend(&first[m]);
+                        rest_nullable = nullable[m];
+                    }
+                }
+                if !rest_nullable {
+                    break;
+                }
+            }
+            if rest_nullable {
+                follow.insert(lookahead);
+            }
+            for (q, production) in self.productions.iter().enumerate() {
+                if production.lhs == n {
+                    for &t in &follow {
+                        if closure.insert((q, 0, t)) {
+                            pending.push((q, 0, t));
+                        }
+                    }
+                }
+            }
+        }
+        closure
+    }
+
+    /// The LALR(1) states, as the lookaheads of their items, and their transitions.
+    #[allow(clippy::type_complexity)]
+    fn states(
+        &self,
+    ) -> (
+        Vec<BTreeMap<(usize, usize), BTreeSet<usize>>>,
+        Vec<BTreeMap<Symbol, usize>>,
+    ) {
+        let (nullable, first) = self.first_sets();
+        let end = self.terminals.len();
+        let start = self.closure([(0, 0, end)].iter().copied().collect(), &nullable, &first);
+        // the canonical LR(1) states, merged by core below
+        let mut states = vec![start.clone()];
+        let mut index = BTreeMap::new();
+        index.insert(start, 0);
+        let mut transitions = vec![];
+        let mut queue = VecDeque::from(vec![0]);
+        while let Some(s) = queue.pop_front() {
+            let mut moved = BTreeMap::<Symbol, BTreeSet<Item>>::new();
+            for &(p, dot, lookahead) in &states[s] {
+                if let Some(&symbol) = self.productions[p].rhs.get(dot) {
+                    moved
+                        .entry(symbol)
+                        .or_default()
+                        .insert((p, dot + 1, lookahead));
+                }
+            }
+            let mut edges = BTreeMap::new();
+            for (symbol, items) in moved {
+                let target = self.closure(items, &nullable, &first);
+                let next = match index.get(&target) {
+                    Some(&next) => next,
+                    None => {
+                        states.push(target.clone());
+                        index.insert(target, states.len() - 1);
+                        queue.push_back(states.len() - 1);
+                        states.len() - 1
+                    }
+                };
+                edges.insert(symbol, next);
+            }
+            if transitions.len() <= s {
+                transitions.resize(s + 1, BTreeMap::new());
+            }
+            transitions[s] = edges;
+        }
+        let mut cores = BTreeMap::new();
+        let mut merged = vec![];
+        let mut lalr = vec![];
+        for state in &states {
+            let core = state
+                .iter()
+                .map(|&(p, dot, _)| (p, dot))
+                .collect::<BTreeSet<_>>();
+            let id = *cores.entry(core).or_insert_with(|| {
+                lalr.push(BTreeMap::<(usize, usize), BTreeSet<usize>>::new());
+                lalr.len() - 1
+            });
+            for &(p, dot, lookahead) in state {
+                lalr[id].entry((p, dot)).or_default().insert(lookahead);
+            }
+            merged.push(id);
+        }
+        let mut edges = vec![BTreeMap::new(); lalr.len()];
+        for (s, targets) in transitions.into_iter().enumerate() {
+            for (symbol, target) in targets {
+                edges[merged[s]].insert(symbol, merged[target]);
+            }
+        }
+        (lalr, edges)
+    }
+
+    fn describe(&self, items: &BTreeMap<(usize, usize), BTreeSet<usize>>) -> String {
+        let mut description = String::new();
+        for &(p, dot) in items.keys() {
+            let production = &self.productions[p];
+            let rhs = production
+                .rhs
+                .iter()
+                .map(|s| name(&self.terminals, &self.nonterminals, *s))
+                .collect::<String>();
+            let lhs = self.nonterminals.get(production.lhs).unwrap_or(&'^');
+            let _ = writeln!(description, "  {} -> {}.{}", lhs, &rhs[..dot], &rhs[dot..]);
+        }
+        description
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", GRAMMAR);
+    let text = fs::read_to_string(GRAMMAR).expect("grammar");
+    let grammar = parse(&text);
+    let (states, edges) = grammar.states();
+    let end = grammar.terminals.len();
+    let mut action = vec![vec![None; end + 1]; states.len()];
+    let mut goto = vec![vec![0; grammar.nonterminals.len()]; states.len()];
+    let mut accept = 0;
+    for (s, targets) in edges.iter().enumerate() {
+        for (&symbol, &target) in targets {
+            match symbol {
+                Symbol::Terminal(t) => action[s][t] = Some(Action::Shift(target)),
+                Symbol::Nonterminal(n) => goto[s][n] = target,
+            }
+        }
+    }
+    for (s, items) in states.iter().enumerate() {
+        for (&(p, dot), lookaheads) in items {
+            let production = &grammar.productions[p];
+            if dot < production.rhs.len() {
+                continue;
+            }
+            if p == 0 {
+                accept = s;
+                continue;
+            }
+            for &t in lookaheads {
+                let token = grammar
+                    .terminals
+                    .get(t)
+                    .map_or("the end".to_string(), char::to_string);
+                let conflict = |kind: &str| -> Option<Action> {
+                    panic!(
+                        "{} conflict on {} in state {}:\n{}",
+                        kind,
+                        token,
+                        s,
+                        grammar.describe(items)
+                    )
+                };
+                action[s][t] = match action[s][t] {
+                    None => Some(Action::Reduce(p)),
+                    Some(Action::Shift(target)) => {
+                        let shifted = grammar
+                            .terminals
+                            .get(t)
+                            .and_then(|c| grammar.precedence.get(c));
+                        match (production.prec, shifted) {
+                            (Some((reduced, _)), Some(&(shifted, _))) if reduced > shifted => {
+                                Some(Action::Reduce(p))
+                            }
+                            (Some((reduced, _)), Some(&(shifted, _))) if reduced < shifted => {
+                                Some(Action::Shift(target))
+                            }
+                            (Some(_), Some((_, Assoc::Left))) => Some(Action::Reduce(p)),
+                            (Some(_), Some((_, Assoc::Right))) => Some(Action::Shift(target)),
+                            (Some(_), Some((_, Assoc::Nonassoc))) => Some(Action::Error),
+                            _ => conflict("shift-reduce"),
+                        }
+                    }
+                    Some(Action::Reduce(q)) if q != p => conflict("reduce-reduce"),
+                    entry => entry,
+                };
+            }
+        }
+    }
+    let mut out = String::new();
+    let productions = &grammar.productions;
+    let texts = productions
+        .iter()
+        .map(|p| p.text.as_str())
+        .collect::<Vec<_>>();
+    let shapes = productions
+        .iter()
+        .map(|p| (p.lhs, p.rhs.len()))
+        .collect::<Vec<_>>();
+    let _ = writeln!(out, "// Generated by build.rs from {}.\n", GRAMMAR);
+    let _ = writeln!(out, "/// Productions by id, as written in the grammar.");
+    let _ = writeln!(
+        out,
+        "const GRAMMAR: [&str; {}] = {:?};\n",
+        texts.len(),
+        texts
+    );
+    let _ = writeln!(out, "/// Left side and length of the productions by id.");
+    let _ = writeln!(
+        out,
+        "const PRODUCTIONS: [(usize, usize); {}] = {:?};\n",
+        shapes.len(),
+        shapes
+    );
+    let _ = writeln!(out, "/// Terminals of the grammar.");
+    let _ = writeln!(out, "const TOKENS: usize = {};\n", end);
+    let _ = writeln!(
+        out,
+        "/// Next state (positive) or production to reduce (negative) by state and token, the end of"
+    );
+    let _ = writeln!(out, "/// input last; 0 rejects the token.");
+    let _ = writeln!(
+        out,
+        "const ACTION: [[i32; {}]; {}] = [",
+        end + 1,
+        states.len()
+    );
+    for row in &action {
+        let row = row
+            .iter()
+            .map(|entry| match entry {
+                Some(Action::Shift(s)) => *s as i32,
+                Some(Action::Reduce(p)) => -(*p as i32),
+                Some(Action::Error) | None => 0,
+            })
+            .collect::<Vec<_>>();
+        let _ = writeln!(out, "    {:?},", row);
+    }
+    let _ = writeln!(out, "];\n");
+    let _ = writeln!(
+        out,
+        "/// State after reducing to each nonterminal, by state."
+    );
+    let _ = writeln!(
+        out,
+        "const GOTO: [[u32; {}]; {}] = {:?};\n",
+        grammar.nonterminals.len(),
+        states.len(),
+        goto
+    );
+    let _ = writeln!(out, "/// State holding a whole statement.");
+    let _ = writeln!(out, "const ACCEPT: u32 = {};", accept);
+    let path = Path::new(&env::var("OUT_DIR").unwrap()).join("grammar.rs");
+    fs::write(path, out).expect("generated tables");
+}
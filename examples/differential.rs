@@ -0,0 +1,14 @@
+use mfni::harness::Harness;
+
+fn main() {
+    let mut args = std::env::args().skip(1).map(|arg| arg.parse::<u64>());
+    let seed = args.next().and_then(Result::ok).unwrap_or(0);
+    let cases = args.next().and_then(Result::ok).unwrap_or(10_000);
+    match Harness::new().run(seed, cases as usize, 5) {
+        Ok(()) => println!("{} expressions agree with seed {}", cases, seed),
+        Err(discrepancy) => {
+            println!("{}", discrepancy);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use mfni::{Interpreter, Repl};
+
+fn main() -> rustyline::Result<()> {
+    let history =
+        std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".mfni_history"));
+    let mut interpreter = Interpreter::new();
+    if let Some(home) = std::env::var_os("HOME") {
+        let rc = std::path::Path::new(&home).join(".mfnirc");
+        if let Ok(script) = std::fs::read_to_string(&rc) {
+            if let Err(e) = interpreter.load_script(&script) {
+                eprintln!("!Error: {} in {}", e, rc.display());
+            }
+        }
+    }
+    Repl::new(interpreter, history)?.run()
+}
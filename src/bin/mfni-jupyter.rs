@@ -0,0 +1,40 @@
+//! Jupyter kernel, see `mfni::jupyter`
+//!
+//! `mfni-jupyter spec` prints a kernel spec running this executable, to be saved as
+//! `kernel.json` in a directory `mfni` of the Jupyter kernels, which then runs
+//! `mfni-jupyter connection-file`. Interrupts are sent as messages, which the kernel refuses,
+//! rather than as signals ending it.
+
+use std::{fs, io};
+
+use mfni::{
+    jupyter::{self, Connection},
+    Interpreter,
+};
+use serde_json::json;
+
+fn main() -> io::Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    match args.as_slice() {
+        ["spec"] => {
+            let exe = std::env::current_exe()?;
+            let spec = json!({
+                "argv": [exe, "{connection_file}"],
+                "display_name": "mfni",
+                "language": "mfni",
+                "interrupt_mode": "message",
+            });
+            println!("{:#}", spec);
+            Ok(())
+        }
+        [path] => {
+            let connection = Connection::parse(&fs::read_to_string(path)?)?;
+            jupyter::run(&connection, Interpreter::new())
+        }
+        _ => {
+            eprintln!("usage: mfni-jupyter connection-file | spec");
+            std::process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,398 @@
+//! Language server for mfni scripts (`.mfn` files, one statement per line)
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    ffi::CString,
+    io::{self, BufRead, Write},
+};
+
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents,
+    HoverParams, HoverProviderCapability, Location, MarkupContent, MarkupKind, NumberOrString,
+    OneOf, Position, PositionEncodingKind, PublishDiagnosticsParams, Range, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+use mfni::{Budget, CompletionKind, InputError, InputState, Interpreter, Severity, TokenKind};
+use serde_json::{json, Value};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// The state of a script evaluated line by line.
+struct Analysis {
+    interpreter: Interpreter,
+    diagnostics: Vec<Diagnostic>,
+    /// Range of the defined identifier and source of the definition.
+    definitions: HashMap<String, (Range, String)>,
+}
+
+/// What the columns of positions count, UTF-16 code units unless the client takes bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Utf8,
+    Utf16,
+}
+
+impl Encoding {
+    /// The encoding offered first by the client `capabilities` among those supported.
+    fn negotiate(capabilities: &Value) -> Self {
+        let offered = capabilities["general"]["positionEncodings"].as_array();
+        let offered = offered.into_iter().flatten().filter_map(Value::as_str);
+        for encoding in offered {
+            match encoding {
+                "utf-8" => return Encoding::Utf8,
+                "utf-16" => return Encoding::Utf16,
+                _ => (),
+            }
+        }
+        Encoding::Utf16
+    }
+
+    fn kind(self) -> PositionEncodingKind {
+        match self {
+            Encoding::Utf8 => PositionEncodingKind::UTF8,
+            Encoding::Utf16 => PositionEncodingKind::UTF16,
+        }
+    }
+
+    /// The column of the byte offset `byte` of `line`.
+    fn column(self, line: &str, byte: usize) -> u32 {
+        let byte = byte.min(line.len());
+        match self {
+            Encoding::Utf8 => byte as u32,
+            Encoding::Utf16 => line
+                .char_indices()
+                .take_while(|&(i, _)| i < byte)
+                .map(|(_, c)| c.len_utf16() as u32)
+                .sum(),
+        }
+    }
+
+    /// The byte offset of the column `column` of `line`.
+    fn byte(self, line: &str, column: u32) -> usize {
+        match self {
+            Encoding::Utf8 => (column as usize).min(line.len()),
+            Encoding::Utf16 => {
+                let mut units = 0;
+                for (i, c) in line.char_indices() {
+                    if units >= column {
+                        return i;
+                    }
+                    units += c.len_utf16() as u32;
+                }
+                line.len()
+            }
+        }
+    }
+}
+
+/// The range of the bytes `span` of `text`, the line `line`.
+fn line_range(encoding: Encoding, line: usize, text: &str, span: std::ops::Range<usize>) -> Range {
+    Range::new(
+        Position::new(line as u32, encoding.column(text, span.start)),
+        Position::new(line as u32, encoding.column(text, span.end)),
+    )
+}
+
+fn arguments(n: usize) -> String {
+    match n {
+        1 => "1 argument".to_string(),
+        n => format!("{} arguments", n),
+    }
+}
+
+fn to_lsp(encoding: Encoding, diagnostic: mfni::Diagnostic, line: usize, text: &str) -> Diagnostic {
+    Diagnostic {
+        range: line_range(
+            encoding,
+            line,
+            text,
+            diagnostic.span.unwrap_or(0..text.len()),
+        ),
+        severity: Some(match diagnostic.severity {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+        }),
+        code: Some(NumberOrString::String(diagnostic.code.to_string())),
+        source: Some("mfni".to_string()),
+        message: diagnostic.message,
+        ..Default::default()
+    }
+}
+
+/// Evaluate the first `count` lines of `text`, failing statements beyond a `Budget`.
+fn analyze(encoding: Encoding, text: &str, count: usize) -> Analysis {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_budget(Some(Budget {
+        steps: 1_000_000,
+        depth: 256,
+    }));
+    let mut analysis = Analysis {
+        interpreter,
+        diagnostics: vec![],
+        definitions: HashMap::new(),
+    };
+    let lines = text.lines().take(count).collect::<Vec<_>>();
+    let mut start = None;
+    for (i, line) in lines.iter().enumerate() {
+        let first = *start.get_or_insert(i);
+        let state = match CString::new(*line) {
+            Ok(input) => analysis.interpreter.input(input.as_bytes_with_nul()),
+            Err(_) => continue,
+        };
+        let warnings = match state {
+            Ok(InputState::Empty)
+            | Ok(InputState::Help { .. })
+            | Ok(InputState::Workspace { .. }) => {
+                vec![]
+            }
+            Ok(InputState::Incomplete) => continue,
+            Ok(InputState::Assignment { warnings, .. }) => {
+                let tokens = analysis.interpreter.tokenize(lines[first]);
+                if let [ident, op, ..] = tokens.as_slice() {
+                    if ident.kind == TokenKind::Identifier
+                        && matches!(&lines[first][op.span.clone()], "=" | ":")
+                    {
+                        analysis.definitions.insert(
+                            lines[first][ident.span.clone()].to_string(),
+                            (
+                                line_range(encoding, first, lines[first], ident.span.clone()),
+                                lines[first..=i].join("\n"),
+                            ),
+                        );
+                    }
+                }
+                warnings
+            }
+            Ok(InputState::Expression { warnings, .. }) => warnings,
+            Err(e) => {
+                // every syntax error of the statement the parser finds
+                let mut errors = vec![];
+                if let InputError::SyntaxError { .. } = e {
+                    let statement = lines[first..=i].join("\n");
+                    errors = analysis.interpreter.syntax_errors(&statement);
+                }
+                if errors.is_empty() {
+                    errors.push(e);
+                }
+                for e in errors {
+                    let diagnostic = e.offset_lines(first).diagnostic();
+                    let at = diagnostic.line.map_or(i, |line| line - 1).min(i);
+                    let diagnostic = to_lsp(encoding, diagnostic, at, lines[at]);
+                    analysis.diagnostics.push(diagnostic);
+                }
+                vec![]
+            }
+        };
+        for w in warnings {
+            let diagnostic = to_lsp(encoding, w.diagnostic(), first, lines[first]);
+            analysis.diagnostics.push(diagnostic);
+        }
+        start = None;
+    }
+    analysis
+}
+
+/// The identifier at `position`.
+fn word_at(
+    encoding: Encoding,
+    interpreter: &Interpreter,
+    text: &str,
+    position: Position,
+) -> Option<(String, Range)> {
+    let line = text.lines().nth(position.line as usize)?;
+    let column = encoding.byte(line, position.character);
+    interpreter
+        .tokenize(line)
+        .into_iter()
+        .filter(|t| t.kind == TokenKind::Identifier || t.kind == TokenKind::Builtin)
+        .find(|t| t.span.start <= column && column <= t.span.end)
+        .map(|t| {
+            let range = line_range(encoding, position.line as usize, line, t.span.clone());
+            (line[t.span].to_string(), range)
+        })
+}
+
+fn hover(encoding: Encoding, text: &str, position: Position) -> Option<Hover> {
+    let analysis = analyze(encoding, text, usize::MAX);
+    let (word, range) = word_at(encoding, &analysis.interpreter, text, position)?;
+    let value = match analysis.definitions.get(&word) {
+        Some((_, source)) => format!("```\n{}\n```", source),
+        None => {
+            let entry = analysis.interpreter.help(&word)?;
+            format!("`{}`  \n{}", entry.signature, entry.description)
+        }
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(range),
+    })
+}
+
+fn complete(encoding: Encoding, text: &str, position: Position) -> Vec<CompletionItem> {
+    let analysis = analyze(encoding, text, position.line as usize);
+    let line = text.lines().nth(position.line as usize).unwrap_or("");
+    analysis
+        .interpreter
+        .complete(line, encoding.byte(line, position.character))
+        .into_iter()
+        .map(|c| CompletionItem {
+            label: c.text,
+            kind: Some(match (c.kind, c.arity) {
+                (CompletionKind::Variable, _) => CompletionItemKind::VARIABLE,
+                (_, None) => CompletionItemKind::CONSTANT,
+                (_, Some(_)) => CompletionItemKind::FUNCTION,
+            }),
+            detail: c.arity.map(arguments),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn definition(
+    encoding: Encoding,
+    text: &str,
+    uri: Uri,
+    position: Position,
+) -> Option<GotoDefinitionResponse> {
+    let analysis = analyze(encoding, text, usize::MAX);
+    let (word, _) = word_at(encoding, &analysis.interpreter, text, position)?;
+    let (range, _) = analysis.definitions.get(&word)?;
+    Some(GotoDefinitionResponse::Scalar(Location::new(uri, *range)))
+}
+
+fn read_message(input: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+    let mut body = vec![0; length.ok_or("missing Content-Length header")?];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn write_message(output: &mut impl Write, message: Value) -> Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()?;
+    Ok(())
+}
+
+fn publish(output: &mut impl Write, encoding: Encoding, uri: Uri, text: &str) -> Result<()> {
+    let params = PublishDiagnosticsParams {
+        uri,
+        diagnostics: analyze(encoding, text, usize::MAX).diagnostics,
+        version: None,
+    };
+    write_message(
+        output,
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": params,
+        }),
+    )
+}
+
+fn main() -> Result<()> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut output = io::stdout();
+    let mut documents = HashMap::<String, String>::new();
+    let mut encoding = Encoding::Utf16;
+    while let Some(message) = read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or("").to_string();
+        let params = message["params"].clone();
+        let result = match method.as_str() {
+            "initialize" => {
+                encoding = Encoding::negotiate(&params["capabilities"]);
+                let capabilities = ServerCapabilities {
+                    position_encoding: Some(encoding.kind()),
+                    text_document_sync: Some(TextDocumentSyncCapability::Kind(
+                        TextDocumentSyncKind::FULL,
+                    )),
+                    hover_provider: Some(HoverProviderCapability::Simple(true)),
+                    completion_provider: Some(CompletionOptions::default()),
+                    definition_provider: Some(OneOf::Left(true)),
+                    ..Default::default()
+                };
+                json!({ "capabilities": capabilities })
+            }
+            "shutdown" => Value::Null,
+            "exit" => break,
+            "textDocument/didOpen" => {
+                let params: DidOpenTextDocumentParams = serde_json::from_value(params)?;
+                let doc = params.text_document;
+                publish(&mut output, encoding, doc.uri.clone(), &doc.text)?;
+                documents.insert(doc.uri.as_str().to_string(), doc.text);
+                continue;
+            }
+            "textDocument/didChange" => {
+                let params: DidChangeTextDocumentParams = serde_json::from_value(params)?;
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    let uri = params.text_document.uri;
+                    publish(&mut output, encoding, uri.clone(), &change.text)?;
+                    documents.insert(uri.as_str().to_string(), change.text);
+                }
+                continue;
+            }
+            "textDocument/didClose" => {
+                let params: DidCloseTextDocumentParams = serde_json::from_value(params)?;
+                documents.remove(params.text_document.uri.as_str());
+                continue;
+            }
+            "textDocument/hover" => {
+                let params: HoverParams = serde_json::from_value(params)?;
+                let doc = params.text_document_position_params;
+                let text = documents.get(doc.text_document.uri.as_str());
+                json!(text.and_then(|text| hover(encoding, text, doc.position)))
+            }
+            "textDocument/completion" => {
+                let params: CompletionParams = serde_json::from_value(params)?;
+                let doc = params.text_document_position;
+                let text = documents.get(doc.text_document.uri.as_str());
+                let items = text.map_or(vec![], |text| complete(encoding, text, doc.position));
+                json!(CompletionResponse::Array(items))
+            }
+            "textDocument/definition" => {
+                let params: GotoDefinitionParams = serde_json::from_value(params)?;
+                let doc = params.text_document_position_params;
+                let (uri, position) = (doc.text_document.uri, doc.position);
+                let text = documents.get(uri.as_str());
+                json!(text.and_then(|text| definition(encoding, text, uri.clone(), position)))
+            }
+            _ if message.get("id").is_none() => continue,
+            _ => {
+                write_message(
+                    &mut output,
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": message["id"],
+                        "error": { "code": -32601, "message": "method not found" },
+                    }),
+                )?;
+                continue;
+            }
+        };
+        write_message(
+            &mut output,
+            json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,35 @@
+//! Evaluation server sharing one interpreter, see `mfni::server`
+//!
+//! `mfni-server [tcp] 127.0.0.1:7878` or, on Unix, `mfni-server unix /tmp/mfni.sock`, and with the
+//! `http` feature `mfni-server http 127.0.0.1:8080` evaluating each request in a new interpreter.
+
+use std::io;
+
+use mfni::{server, Budget, Interpreter};
+
+/// An interpreter failing statements beyond a million steps or calls 256 deep.
+fn budgeted() -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_budget(Some(Budget {
+        steps: 1_000_000,
+        depth: 256,
+    }));
+    interpreter
+}
+
+fn main() -> io::Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    match args.as_slice() {
+        [] => server::serve_tcp("127.0.0.1:7878", budgeted()),
+        ["tcp", addr] | [addr] => server::serve_tcp(addr, budgeted()),
+        #[cfg(unix)]
+        ["unix", path] => server::serve_unix(path, budgeted()),
+        #[cfg(feature = "http")]
+        ["http", addr] => mfni::http::serve_http(addr.to_string(), budgeted),
+        _ => {
+            eprintln!("usage: mfni-server [tcp] [address] | unix path | http address");
+            std::process::exit(2);
+        }
+    }
+}
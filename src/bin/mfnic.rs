@@ -0,0 +1,321 @@
+//! Command line calculator
+//!
+//! Without `-e` or `-f` it reads statements interactively, or in batch mode if the input isn't a
+//! terminal; otherwise it evaluates them in the order given. It exits with status 1 if a statement
+//! fails, 2 on invalid arguments or unreadable files.
+//!
+//! Definitions in `~/.mfnirc`, or the file given with `--init`, are loaded first.
+
+use std::{
+    ffi::CString,
+    fmt::Display,
+    io::{stdin, stdout, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+use mfni::{render_error, Assigned, ColorChoice, CommandError, InputState, Interpreter};
+
+const USAGE: &str =
+    "usage: mfnic [--init file | --no-init] [--batch] [--json] [--precision digits] [--strict]
+             [-e statement]... [-f script]...
+
+  --init file          load definitions from a file instead of ~/.mfnirc
+  --no-init            don't load ~/.mfnirc
+  -e statement         evaluate a statement, lines separated by newlines
+  -f script            evaluate the statements of a file, stopping at the first failure
+  --batch              then read statements from standard input without prompts, printing errors
+                       to standard output and going on after them
+  --json               print each result as a line of JSON (needs the server feature)
+  --precision digits   show numbers rounded to 1 to 17 significant digits
+  --strict             make NaN and infinite results errors
+  -h, --help           show this help";
+
+enum Source {
+    Statement(String),
+    Script(String),
+}
+
+#[derive(Default)]
+struct Options {
+    sources: Vec<Source>,
+    /// Startup file, `None` for none.
+    init: Option<PathBuf>,
+    /// Whether `init` was given explicitly.
+    explicit_init: bool,
+    batch: bool,
+    json: bool,
+    precision: Option<usize>,
+    strict: bool,
+}
+
+enum Status {
+    Done,
+    Incomplete,
+    Failed,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        init: std::env::var_os("HOME").map(|home| Path::new(&home).join(".mfnirc")),
+        ..Options::default()
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("missing argument of {}", name));
+        match arg.as_str() {
+            "-e" => options.sources.push(Source::Statement(value("-e")?)),
+            "-f" => options.sources.push(Source::Script(value("-f")?)),
+            "--init" => {
+                options.init = Some(value("--init")?.into());
+                options.explicit_init = true;
+            }
+            "--no-init" => options.init = None,
+            "--batch" => options.batch = true,
+            "--json" if cfg!(feature = "server") => options.json = true,
+            "--json" => return Err("--json needs the server feature".to_string()),
+            "--precision" => {
+                let digits = value("--precision")?;
+                match digits.parse() {
+                    Ok(digits @ 1..=17) => options.precision = Some(digits),
+                    _ => return Err(format!("invalid precision {}", digits)),
+                }
+            }
+            "--strict" => options.strict = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            arg => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    Ok(options)
+}
+
+/// Print an error, to standard output with its code if any in batch mode.
+fn report(options: &Options, code: Option<&str>, message: &dyn Display) -> Status {
+    match (options.batch, code) {
+        (true, Some(code)) => println!("error[{}]: {}", code, message),
+        (true, None) => println!("error: {}", message),
+        (false, _) => eprintln!("!Error: {}", message),
+    }
+    Status::Failed
+}
+
+/// Evaluate a line of a statement starting after the first `before` lines of its script, or run
+/// it as a meta-command, printing its result.
+fn run_line(it: &mut Interpreter, line: &str, before: usize, options: &Options) -> Status {
+    #[cfg(feature = "server")]
+    if options.json {
+        let response = mfni::server::respond_at(it, line, before);
+        println!("{}", response);
+        return match (response.get("error"), response.get("incomplete")) {
+            (Some(_), _) => Status::Failed,
+            (_, Some(_)) => Status::Incomplete,
+            _ => Status::Done,
+        };
+    }
+    if let Some(result) = it.command(line) {
+        return match result {
+            Ok(output) if output.is_empty() => Status::Done,
+            Ok(output) => {
+                println!("{}", output);
+                Status::Done
+            }
+            Err(e) => report(options, None, &e),
+        };
+    }
+    let text = match CString::new(line) {
+        Ok(text) => text,
+        Err(_) => {
+            it.cancel();
+            return report(options, None, &"NUL character in input");
+        }
+    };
+    match it.input(text.as_bytes_with_nul()) {
+        Ok(InputState::Empty) => (),
+        Ok(InputState::Incomplete) => return Status::Incomplete,
+        Ok(InputState::Assignment {
+            name,
+            assigned,
+            warnings,
+            echo,
+        }) => {
+            for w in warnings {
+                eprintln!("!Warning: {}", w);
+            }
+            for step in it.trace() {
+                println!("{}", step);
+            }
+            // batch mode prints a line for every statement
+            match assigned {
+                Assigned::Value(value) if echo || options.batch => {
+                    println!("{} = {}", name, it.display(&value))
+                }
+                Assigned::Function { .. } if options.batch => {
+                    let parameters = it
+                        .free_variables(&name)
+                        .map(|names| names.parameters)
+                        .unwrap_or_default();
+                    println!("{}({})", name, parameters.join(", "));
+                }
+                _ => (),
+            }
+        }
+        Ok(InputState::Expression {
+            value,
+            warnings,
+            echo,
+        }) => {
+            for w in warnings {
+                eprintln!("!Warning: {}", w);
+            }
+            for step in it.trace() {
+                println!("{}", step);
+            }
+            if echo || options.batch {
+                println!("{}", it.display(&value));
+            }
+        }
+        Ok(InputState::Help { entry }) => println!("{}", entry),
+        Ok(InputState::Workspace { name }) => println!("workspace {}", name),
+        Err(e) if !options.batch => {
+            let e = e.offset_lines(before);
+            eprint!("{}", render_error(line, &e, ColorChoice::Auto));
+            return Status::Failed;
+        }
+        Err(e) => {
+            let e = e.offset_lines(before);
+            return report(options, Some(e.diagnostic().code), &e);
+        }
+    }
+    Status::Done
+}
+
+/// Evaluate the lines of `text` from `origin`, returning whether all succeeded.
+fn run_lines(it: &mut Interpreter, text: &str, origin: &str, options: &Options) -> bool {
+    let mut status = Status::Done;
+    let mut before = 0;
+    for (i, line) in text.lines().enumerate() {
+        if !matches!(status, Status::Incomplete) {
+            before = i;
+        }
+        status = run_line(it, line, before, options);
+        if let Status::Failed = status {
+            eprintln!("  at {}:{}", origin, i + 1);
+            return false;
+        }
+    }
+    if let Status::Incomplete = status {
+        it.cancel();
+        report(
+            options,
+            None,
+            &format!("unfinished statement at the end of {}", origin),
+        );
+        return false;
+    }
+    true
+}
+
+/// Evaluate the statements of standard input, going on after failures, returning whether all
+/// succeeded.
+fn batch(it: &mut Interpreter, options: &Options) -> bool {
+    let mut succeeded = true;
+    let mut status = Status::Done;
+    let mut before = 0;
+    for (i, line) in stdin().lock().lines().enumerate() {
+        if !matches!(status, Status::Incomplete) {
+            before = i;
+        }
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                report(options, None, &e);
+                return false;
+            }
+        };
+        status = run_line(it, &line, before, options);
+        if let Status::Failed = status {
+            succeeded = false;
+        }
+    }
+    if let Status::Incomplete = status {
+        it.cancel();
+        report(options, None, &"unfinished statement at the end of input");
+        return false;
+    }
+    succeeded
+}
+
+fn interactive(it: &mut Interpreter, options: &Options) {
+    let mut prefix = ">>> ";
+    loop {
+        print!("{}", prefix);
+        stdout().flush().unwrap();
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        let line = line.trim();
+        if prefix == ">>> " && line == "quit" {
+            break;
+        }
+        prefix = match run_line(it, line, 0, options) {
+            Status::Incomplete => "... ",
+            _ => ">>> ",
+        };
+    }
+}
+
+fn main() {
+    let mut options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("mfnic: {}\n{}", message, USAGE);
+            exit(2);
+        }
+    };
+    let mut it = Interpreter::new();
+    it.set_precision(options.precision);
+    it.set_strict(options.strict);
+    if let Some(path) = &options.init {
+        let loaded = std::fs::read_to_string(path)
+            .map_err(CommandError::from)
+            .and_then(|script| it.load_script(&script));
+        match loaded {
+            Ok(()) => (),
+            // only a missing default startup file is fine
+            Err(CommandError::Io { .. }) if !options.explicit_init && !path.exists() => (),
+            Err(e) => {
+                eprintln!("mfnic: {}: {}", path.display(), e);
+                if options.explicit_init {
+                    exit(2);
+                }
+            }
+        }
+    }
+    if options.sources.is_empty() && !options.batch {
+        match stdin().is_terminal() {
+            true => interactive(&mut it, &options),
+            false => options.batch = true,
+        }
+    }
+    for source in &options.sources {
+        let succeeded = match source {
+            Source::Statement(statement) => run_lines(&mut it, statement, "-e", &options),
+            Source::Script(path) => match std::fs::read_to_string(path) {
+                Ok(script) => run_lines(&mut it, &script, path, &options),
+                Err(e) => {
+                    eprintln!("mfnic: {}: {}", path, e);
+                    exit(2);
+                }
+            },
+        };
+        if !succeeded {
+            exit(1);
+        }
+    }
+    if options.batch && !batch(&mut it, &options) {
+        exit(1);
+    }
+}
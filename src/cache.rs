@@ -0,0 +1,77 @@
+//! Least recently used cache
+
+use std::{collections::HashMap, hash::Hash};
+
+/// Use counts of a cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    /// Entries held.
+    pub len: usize,
+    pub capacity: usize,
+}
+
+/// Entries tagged with the tick of their last use, the oldest evicted when full.
+pub(crate) struct Lru<K, V> {
+    entries: HashMap<K, (u64, V)>,
+    tick: u64,
+    capacity: usize,
+    hits: usize,
+    misses: usize,
+}
+
+impl<K: Eq + Hash, V> Lru<K, V> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Lru {
+            entries: HashMap::new(),
+            tick: 0,
+            capacity,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// The entry of `key` if `valid` accepts it, counting a hit, else a miss.
+    pub(crate) fn get<F: FnOnce(&V) -> bool>(&mut self, key: &K, valid: F) -> Option<&V> {
+        self.tick += 1;
+        match self.entries.get_mut(key) {
+            Some((used, value)) if valid(value) => {
+                *used = self.tick;
+                self.hits += 1;
+                Some(value)
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub(crate) fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            // ticks are unique, so this evicts a single entry
+            if let Some(oldest) = self.entries.values().map(|(used, _)| *used).min() {
+                self.entries.retain(|_, (used, _)| *used != oldest);
+            }
+        }
+        self.tick += 1;
+        self.entries.insert(key, (self.tick, value));
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            len: self.entries.len(),
+            capacity: self.capacity,
+        }
+    }
+}
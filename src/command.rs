@@ -0,0 +1,185 @@
+//! Meta-commands
+
+use std::fmt;
+
+use crate::{Atan2Order, InputError, Real};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandError {
+    UnknownCommand { name: String },
+    MissingArgument { command: &'static str },
+    InvalidArgument { expect: &'static str, found: String },
+    Io { message: String },
+    InvalidInput(InputError),
+    Load { line: usize, error: InputError },
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::UnknownCommand { name } => write!(f, "Unknown Command: :{}", name),
+            CommandError::MissingArgument { command } => {
+                write!(f, "Missing Argument: :{}", command)
+            }
+            CommandError::InvalidArgument { expect, found } => {
+                write!(f, "Invalid Argument: expect {}, found {}", expect, found)
+            }
+            CommandError::Io { message } => write!(f, "IO Error: {}", message),
+            CommandError::InvalidInput(e) => write!(f, "{}", e),
+            CommandError::Load { line, error } => write!(f, "{} at line {}", error, line),
+        }
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(e: std::io::Error) -> Self {
+        CommandError::Io {
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<InputError> for CommandError {
+    fn from(e: InputError) -> Self {
+        CommandError::InvalidInput(e)
+    }
+}
+
+pub(crate) enum Command<'a> {
+    List(Option<&'a str>),
+    Delete(&'a str),
+    Save(&'a str),
+    Load(&'a str),
+    Help(Option<&'a str>),
+    Clear,
+    Precision(Option<usize>),
+    Fraction(bool),
+    Epsilon(Option<Real>),
+    Compensated(bool),
+    Atan2(Atan2Order),
+    Inline(bool),
+    DecimalComma(bool),
+    Si(bool),
+    Strict(bool),
+    Trace(bool),
+    Time(&'a str),
+}
+
+pub(crate) const COMMANDS: &[(&str, &str)] = &[
+    (
+        ":list [name|prefix*]",
+        "list user variables and functions, or those matching",
+    ),
+    (":del name", "delete a user variable or function"),
+    (":save file", "write the user definitions to a script"),
+    (":load file", "input a script, reverting all of it on error"),
+    (":help [name]", "list commands, or describe a definition"),
+    (":clear", "remove all user definitions"),
+    (
+        ":precision [digits]",
+        "show numbers to significant digits, or in full",
+    ),
+    (
+        ":fraction on|off",
+        "show numbers equal to simple fractions also as fractions",
+    ),
+    (
+        ":compensated on|off",
+        "sum accurately, or fast, in sum, mean, var and std",
+    ),
+    (
+        ":atan2 yx|xy",
+        "take the arguments of atan2 as (y, x) or (x, y)",
+    ),
+    (
+        ":inline on|off",
+        "inline calls of small user functions in new definitions",
+    ),
+    (
+        ":decimal_comma on|off",
+        "input numbers like 1 234,5 with a decimal comma",
+    ),
+    (":si on|off", "accept SI suffixes on numbers like 4.7k"),
+    (":strict on|off", "make NaN and infinite results errors"),
+    (
+        ":trace on|off",
+        "show the evaluation steps of each statement",
+    ),
+    (
+        ":epsilon [tolerance]",
+        "set the relative tolerance of ~=, or show it",
+    ),
+    (":time expr", "time repeated evaluations of an expression"),
+];
+
+/// Parse a `:command argument` line, `None` if `line` isn't a command.
+pub(crate) fn parse(line: &str) -> Option<Result<Command<'_>, CommandError>> {
+    let line = line.trim().strip_prefix(':')?;
+    let (name, arg) = match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => (line, ""),
+    };
+    let arg = match arg.is_empty() {
+        true => None,
+        false => Some(arg),
+    };
+    let required = |command| arg.ok_or(CommandError::MissingArgument { command });
+    Some(match name {
+        "list" => Ok(Command::List(arg)),
+        "del" => required("del").map(Command::Delete),
+        "save" => required("save").map(Command::Save),
+        "load" => required("load").map(Command::Load),
+        "help" => Ok(Command::Help(arg)),
+        "clear" => Ok(Command::Clear),
+        "precision" => match arg.map(str::parse) {
+            None => Ok(Command::Precision(None)),
+            Some(Ok(digits @ 1..=17)) => Ok(Command::Precision(Some(digits))),
+            Some(_) => Err(CommandError::InvalidArgument {
+                expect: "digits from 1 to 17",
+                found: arg.unwrap().to_string(),
+            }),
+        },
+        "fraction" => required("fraction").and_then(switch).map(Command::Fraction),
+        "epsilon" => match arg.map(str::parse::<Real>) {
+            None => Ok(Command::Epsilon(None)),
+            Some(Ok(tol)) if tol >= 0.0 && tol.is_finite() => Ok(Command::Epsilon(Some(tol))),
+            Some(_) => Err(CommandError::InvalidArgument {
+                expect: "non-negative tolerance",
+                found: arg.unwrap().to_string(),
+            }),
+        },
+        "compensated" => required("compensated")
+            .and_then(switch)
+            .map(Command::Compensated),
+        "atan2" => required("atan2").and_then(|arg| match arg {
+            "yx" => Ok(Command::Atan2(Atan2Order::YFirst)),
+            "xy" => Ok(Command::Atan2(Atan2Order::XFirst)),
+            found => Err(CommandError::InvalidArgument {
+                expect: "yx or xy",
+                found: found.to_string(),
+            }),
+        }),
+        "inline" => required("inline").and_then(switch).map(Command::Inline),
+        "decimal_comma" => required("decimal_comma")
+            .and_then(switch)
+            .map(Command::DecimalComma),
+        "si" => required("si").and_then(switch).map(Command::Si),
+        "strict" => required("strict").and_then(switch).map(Command::Strict),
+        "trace" => required("trace").and_then(switch).map(Command::Trace),
+        "time" => required("time").map(Command::Time),
+        _ => Err(CommandError::UnknownCommand {
+            name: name.to_string(),
+        }),
+    })
+}
+
+fn switch(arg: &str) -> Result<bool, CommandError> {
+    match arg {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        found => Err(CommandError::InvalidArgument {
+            expect: "on or off",
+            found: found.to_string(),
+        }),
+    }
+}
@@ -0,0 +1,96 @@
+//! CSV data import
+
+use std::{fmt, io::BufRead};
+
+use crate::{InputError, Numeric};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CsvError {
+    Io { message: String },
+    MissingColumn { line: usize, column: usize },
+    InvalidNumber { line: usize, found: String },
+    InvalidVariable(InputError),
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CsvError::Io { message } => write!(f, "IO Error: {}", message),
+            CsvError::MissingColumn { line, column } => {
+                write!(f, "Missing Column {} at line {}", column, line)
+            }
+            CsvError::InvalidNumber { line, found } => {
+                write!(f, "Invalid Number at line {}: {}", line, found)
+            }
+            CsvError::InvalidVariable(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for CsvError {
+    fn from(e: std::io::Error) -> Self {
+        CsvError::Io {
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<InputError> for CsvError {
+    fn from(e: InputError) -> Self {
+        CsvError::InvalidVariable(e)
+    }
+}
+
+/// Read the numbers of a 0-based `column`, skipping a header line and blank lines.
+pub(crate) fn read_column<T: Numeric, R: BufRead>(
+    reader: R,
+    column: usize,
+) -> Result<Vec<T>, CsvError> {
+    let mut values = vec![];
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let field = match split(&line).into_iter().nth(column) {
+            Some(field) => field,
+            None => {
+                return Err(CsvError::MissingColumn {
+                    line: i + 1,
+                    column,
+                })
+            }
+        };
+        match T::parse(field.trim()) {
+            Some(r) => values.push(r),
+            None if i == 0 => (),
+            None => {
+                return Err(CsvError::InvalidNumber {
+                    line: i + 1,
+                    found: field,
+                })
+            }
+        }
+    }
+    Ok(values)
+}
+
+fn split(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
@@ -0,0 +1,168 @@
+//! Diagnostics
+
+use std::{
+    fmt,
+    io::{stderr, IsTerminal},
+    ops::Range,
+};
+
+use crate::InputError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// An error or warning with a stable code, e.g. `E0002` or `W0001`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    /// Line, from 1, if known: in the statement, or in the script after `InputError::offset_lines`.
+    pub line: Option<usize>,
+    /// Columns of the input line, if known.
+    pub span: Option<Range<usize>>,
+}
+
+impl Diagnostic {
+    pub(crate) fn error(
+        code: &'static str,
+        message: String,
+        position: Option<(usize, Range<usize>)>,
+    ) -> Self {
+        let (line, span) = match position {
+            Some((line, span)) => (Some(line), Some(span)),
+            None => (None, None),
+        };
+        Diagnostic {
+            code,
+            severity: Severity::Error,
+            message,
+            line,
+            span,
+        }
+    }
+
+    pub(crate) fn warning(code: &'static str, message: String) -> Self {
+        Diagnostic {
+            code,
+            severity: Severity::Warning,
+            message,
+            line: None,
+            span: None,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}[{}]: {}", severity, self.code, self.message)
+    }
+}
+
+/// Whether `render_error` colors its output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Always,
+    Never,
+    /// Color if standard error is a terminal and `NO_COLOR` isn't set.
+    Auto,
+}
+
+impl ColorChoice {
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        }
+    }
+}
+
+/// `error` of the input `line` as the message, the line with a caret under the columns at
+/// fault when known, and the names suggested for an undefined identifier, e.g.
+///
+/// ```text
+/// error[E0004]: Undefined Identifier: sqr
+///   | sqr(2) + 1
+///   | ^^^
+///   = help: did you mean 'shr' or 'sqrt'?
+/// ```
+pub fn render_error(line: &str, error: &InputError, color: ColorChoice) -> String {
+    let (red, cyan, bold, reset) = match color.enabled() {
+        true => ("\x1b[1;31m", "\x1b[1;36m", "\x1b[1m", "\x1b[0m"),
+        false => ("", "", "", ""),
+    };
+    let diagnostic = error.diagnostic();
+    let (message, suggestions) = match error {
+        InputError::UndefinedIdentifier {
+            ident, suggestions, ..
+        } => {
+            let bare = InputError::UndefinedIdentifier {
+                ident: ident.clone(),
+                suggestions: vec![],
+                position: None,
+            };
+            (bare.to_string(), suggestions.as_slice())
+        }
+        e => (e.to_string(), &[][..]),
+    };
+    let line = line.trim_end_matches(&['\r', '\n', '\0'][..]);
+    let mut text = format!(
+        "{}error[{}]{}: {}{}{}\n",
+        red, diagnostic.code, reset, bold, message, reset
+    );
+    text += &format!("{}  |{} {}\n", cyan, reset, line);
+    // errors of the API name no position
+    let span = diagnostic.span.or_else(|| match error {
+        InputError::UndefinedIdentifier { ident, .. }
+        | InputError::BuiltinIdentifier { ident, .. }
+        | InputError::InconsistentVariablesCount { ident, .. } => word_span(line.as_bytes(), ident),
+        _ => None,
+    });
+    if let Some(span) = span {
+        let start = line
+            .get(..span.start)
+            .map_or(span.start, |s| s.chars().count());
+        let width = line
+            .get(span.clone())
+            .map_or(1, |s| s.chars().count())
+            .max(1);
+        text += &format!(
+            "{}  |{} {}{}{}{}\n",
+            cyan,
+            reset,
+            " ".repeat(start),
+            red,
+            "^".repeat(width),
+            reset
+        );
+    }
+    if !suggestions.is_empty() {
+        text += &format!(
+            "{}  ={} help: did you mean '{}'?\n",
+            cyan,
+            reset,
+            suggestions.join("' or '")
+        );
+    }
+    text
+}
+
+/// Columns of the first occurrence of `word` in `line` not inside a longer identifier.
+fn word_span(line: &[u8], word: &[u8]) -> Option<Range<usize>> {
+    let is_ident = |c: u8| c.is_ascii_alphanumeric() || c == b'_';
+    (0..line.len())
+        .filter(|&i| line[i..].starts_with(word))
+        .find(|&i| {
+            let end = i + word.len();
+            (i == 0 || !is_ident(line[i - 1])) && (end == line.len() || !is_ident(line[end]))
+        })
+        .map(|i| i..i + word.len())
+}
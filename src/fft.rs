@@ -0,0 +1,95 @@
+//! Discrete Fourier transform
+//!
+//! Spectra are vectors of interleaved real and imaginary parts, `[re0, im0, re1, im1, ...]`.
+
+use crate::{
+    consts::PI,
+    value::{EvalError, Value},
+    Real,
+};
+
+type Complex = (Real, Real);
+
+/// Transform of a real signal.
+pub(crate) fn fft(args: &[Value]) -> Result<Value, EvalError> {
+    let signal = args[0].vector()?;
+    let mut data = signal.iter().map(|&r| (r, 0.0)).collect::<Vec<_>>();
+    transform(&mut data, false);
+    Ok(Value::from(
+        data.into_iter()
+            .flat_map(|(re, im)| [re, im])
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Inverse transform of a spectrum, keeping the real part of the signal.
+pub(crate) fn ifft(args: &[Value]) -> Result<Value, EvalError> {
+    let spectrum = args[0].vector()?;
+    if spectrum.len() % 2 != 0 {
+        return Err(EvalError::InvalidArgument {
+            expect: "interleaved real and imaginary parts",
+        });
+    }
+    let mut data = spectrum.chunks(2).map(|c| (c[0], c[1])).collect::<Vec<_>>();
+    transform(&mut data, true);
+    let n = data.len() as Real;
+    Ok(Value::from(
+        data.into_iter().map(|(re, _)| re / n).collect::<Vec<_>>(),
+    ))
+}
+
+fn transform(data: &mut [Complex], inverse: bool) {
+    let sign = if inverse { 1.0 } else { -1.0 };
+    match data.len().is_power_of_two() {
+        true => radix2(data, sign),
+        false => dft(data, sign),
+    }
+}
+
+fn mul(a: Complex, b: Complex) -> Complex {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+/// Iterative Cooley-Tukey transform, the length must be a power of two.
+fn radix2(data: &mut [Complex], sign: Real) {
+    let n = data.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * PI / len as Real;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let w = ((angle * k as Real).cos(), (angle * k as Real).sin());
+                let u = data[start + k];
+                let v = mul(data[start + k + len / 2], w);
+                data[start + k] = (u.0 + v.0, u.1 + v.1);
+                data[start + k + len / 2] = (u.0 - v.0, u.1 - v.1);
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Direct transform for lengths other than powers of two.
+fn dft(data: &mut [Complex], sign: Real) {
+    let n = data.len();
+    let input = data.to_vec();
+    for (k, out) in data.iter_mut().enumerate() {
+        *out = input.iter().enumerate().fold((0.0, 0.0), |acc, (t, &x)| {
+            let angle = sign * 2.0 * PI * ((k * t) % n) as Real / n as Real;
+            let y = mul(x, (angle.cos(), angle.sin()));
+            (acc.0 + y.0, acc.1 + y.1)
+        });
+    }
+}
@@ -0,0 +1,72 @@
+//! Financial functions
+//!
+//! Cash paid out is negative and cash received is positive, payments are made at the end of
+//! each period.
+
+use crate::{
+    solver,
+    value::{EvalError, Value},
+    Real,
+};
+
+fn growth(rate: Real, nper: Real) -> Real {
+    (1.0 + rate).powf(nper)
+}
+
+/// Present value of the annuity factor, `((1 + rate)^nper - 1) / rate`.
+fn annuity(rate: Real, nper: Real) -> Real {
+    match rate == 0.0 {
+        true => nper,
+        false => (growth(rate, nper) - 1.0) / rate,
+    }
+}
+
+pub(crate) fn pmt(v: &[Real]) -> Real {
+    let (rate, nper, pv, fv) = (v[0], v[1], v[2], v[3]);
+    -(pv * growth(rate, nper) + fv) / annuity(rate, nper)
+}
+
+pub(crate) fn fv(v: &[Real]) -> Real {
+    let (rate, nper, pmt, pv) = (v[0], v[1], v[2], v[3]);
+    -(pv * growth(rate, nper) + pmt * annuity(rate, nper))
+}
+
+pub(crate) fn pv(v: &[Real]) -> Real {
+    let (rate, nper, pmt, fv) = (v[0], v[1], v[2], v[3]);
+    -(fv + pmt * annuity(rate, nper)) / growth(rate, nper)
+}
+
+fn present_value(rate: Real, cashflows: &[Real]) -> Real {
+    cashflows
+        .iter()
+        .rev()
+        .fold(0.0, |acc, cf| acc / (1.0 + rate) + cf)
+}
+
+/// Net present value, the first cash flow occurring now.
+pub(crate) fn npv(args: &[Value]) -> Result<Value, EvalError> {
+    let cashflows = args[1].vector()?;
+    Ok(args[0].map(|rate| present_value(rate, cashflows)))
+}
+
+/// Internal rate of return, the rate at which the net present value is zero.
+pub(crate) fn irr(args: &[Value]) -> Result<Value, EvalError> {
+    let cashflows = args[0].vector()?;
+    if !cashflows.iter().any(|&cf| cf > 0.0) || !cashflows.iter().any(|&cf| cf < 0.0) {
+        return Err(EvalError::InvalidArgument {
+            expect: "both positive and negative cash flows",
+        });
+    }
+    let f = |rate| Ok(present_value(rate, cashflows));
+    let lo = -0.99;
+    let mut hi = 1.0;
+    while present_value(lo, cashflows).signum() == present_value(hi, cashflows).signum() {
+        if hi > 1e6 {
+            return Ok(Value::Num(Real::NAN));
+        }
+        hi *= 2.0;
+    }
+    Ok(Value::Num(
+        solver::find_root(f, lo, hi).unwrap_or(Real::NAN),
+    ))
+}
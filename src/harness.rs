@@ -0,0 +1,296 @@
+//! Differential testing
+//!
+//! A [`Harness`] evaluates random expressions in `x` along several paths of the interpreter that
+//! must agree: folded at translation, walked without folding, and as the body of a function taking
+//! `x`. A [`Discrepancy`] is shrunk to a smallest expression still disagreeing. Expressions are
+//! generated from a seed, so a failure is reproduced by running the same seed again.
+
+use std::fmt;
+
+use crate::{InputError, InputState, Interpreter, Real, Value};
+
+/// Leaves of the generated expressions, the simplest first.
+const LEAVES: &[&str] = &[
+    "0", "1", "2", "3", "0.5", "2.5", "1e300", "x", "true", "false",
+];
+
+const PREFIX: &[&str] = &["-", "!"];
+
+const BINARY: &[&str] = &[
+    "^", "*", "/", "+", "-", "<", "<=", ">", ">=", "==", "!=", "<=>", "&&", "||",
+];
+
+/// Builtins called, with their argument count.
+const CALLS: &[(&str, usize)] = &[
+    ("abs", 1),
+    ("floor", 1),
+    ("sqrt", 1),
+    ("min", 2),
+    ("max", 2),
+    ("if", 3),
+];
+
+/// Values of `x` the expressions are evaluated at.
+const XS: &[Real] = &[-2.0, -0.5, 0.0, 1.0, 3.0];
+
+/// A generated expression, shown with every compound operand in parentheses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Leaf(&'static str),
+    Prefix(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Condition(Box<Expr>, Box<Expr>, Box<Expr>),
+    Call(&'static str, Vec<Expr>),
+}
+
+impl Expr {
+    fn operands(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Leaf(_) => vec![],
+            Expr::Prefix(_, e) => vec![e],
+            Expr::Binary(_, e1, e2) => vec![e1, e2],
+            Expr::Condition(e1, e2, e3) => vec![e1, e2, e3],
+            Expr::Call(_, args) => args.iter().collect(),
+        }
+    }
+
+    /// This expression with the operand `i` replaced by `operand`.
+    fn with_operand(&self, i: usize, operand: Expr) -> Expr {
+        let mut expr = self.clone();
+        match &mut expr {
+            Expr::Leaf(_) => unreachable!(),
+            Expr::Prefix(_, e) => **e = operand,
+            Expr::Binary(_, e1, e2) => *[e1, e2][i].as_mut() = operand,
+            Expr::Condition(e1, e2, e3) => *[e1, e2, e3][i].as_mut() = operand,
+            Expr::Call(_, args) => args[i] = operand,
+        }
+        expr
+    }
+
+    /// Expressions one step simpler: an operand in its place, a simpler leaf, or one of its
+    /// operands simplified.
+    fn simpler(&self) -> Vec<Expr> {
+        if let Expr::Leaf(leaf) = self {
+            let rank = LEAVES
+                .iter()
+                .position(|l| l == leaf)
+                .unwrap_or(LEAVES.len());
+            return LEAVES[..rank.min(2)]
+                .iter()
+                .map(|l| Expr::Leaf(l))
+                .collect();
+        }
+        let operands = self.operands();
+        let mut simpler = operands.iter().map(|e| (*e).clone()).collect::<Vec<_>>();
+        for (i, operand) in operands.iter().enumerate() {
+            for e in operand.simpler() {
+                simpler.push(self.with_operand(i, e));
+            }
+        }
+        simpler
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = |e: &Expr| match e {
+            Expr::Leaf(_) | Expr::Call(..) => e.to_string(),
+            _ => format!("({})", e),
+        };
+        match self {
+            Expr::Leaf(leaf) => write!(f, "{}", leaf),
+            Expr::Prefix(op, e) => write!(f, "{}{}", op, operand(e)),
+            Expr::Binary(op, e1, e2) => write!(f, "{} {} {}", operand(e1), op, operand(e2)),
+            Expr::Condition(e1, e2, e3) => {
+                write!(f, "{} ? {} : {}", operand(e1), operand(e2), operand(e3))
+            }
+            Expr::Call(name, args) => {
+                let args = args.iter().map(Expr::to_string).collect::<Vec<_>>();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
+
+/// Random expressions from a seed, by xorshift.
+pub struct Generator {
+    state: u64,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Generator {
+            state: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1,
+        }
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state % n as u64) as usize
+    }
+
+    /// An expression of operators nested up to `depth` deep.
+    pub fn expression(&mut self, depth: usize) -> Expr {
+        if depth == 0 || self.below(4) == 0 {
+            return Expr::Leaf(LEAVES[self.below(LEAVES.len())]);
+        }
+        match self.below(8) {
+            0 => Expr::Prefix(
+                PREFIX[self.below(PREFIX.len())],
+                Box::new(self.expression(depth - 1)),
+            ),
+            1 => Expr::Condition(
+                Box::new(self.expression(depth - 1)),
+                Box::new(self.expression(depth - 1)),
+                Box::new(self.expression(depth - 1)),
+            ),
+            2 => {
+                let (name, count) = CALLS[self.below(CALLS.len())];
+                Expr::Call(
+                    name,
+                    (0..count).map(|_| self.expression(depth - 1)).collect(),
+                )
+            }
+            _ => Expr::Binary(
+                BINARY[self.below(BINARY.len())],
+                Box::new(self.expression(depth - 1)),
+                Box::new(self.expression(depth - 1)),
+            ),
+        }
+    }
+
+    fn x(&mut self) -> Real {
+        XS[self.below(XS.len())]
+    }
+}
+
+/// A way of evaluating an expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Path {
+    /// With `Interpreter::eval_pure`, constants folded at translation.
+    Folded,
+    /// With `Interpreter::eval_unfolded`, every operation evaluated on the translated expression.
+    Unfolded,
+    /// As the body of a function taking `x`, called on its value.
+    Parameter,
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Path::Folded => write!(f, "folded"),
+            Path::Unfolded => write!(f, "unfolded"),
+            Path::Parameter => write!(f, "parameter"),
+        }
+    }
+}
+
+/// An expression the paths disagree on, with the result of each.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discrepancy {
+    pub expression: String,
+    pub x: Real,
+    pub results: Vec<(Path, Result<Value, InputError>)>,
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Discrepancy: {} at x = {}", self.expression, self.x)?;
+        for (path, result) in &self.results {
+            match result {
+                Ok(value) => write!(f, "\n  {}: {}", path, value)?,
+                Err(e) => write!(f, "\n  {}: {}", path, e)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Harness {
+    interpreter: Interpreter,
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        Harness {
+            interpreter: Interpreter::new(),
+        }
+    }
+
+    /// Evaluate `expression` at `x` along each path, failing if the results differ.
+    pub fn check(&mut self, expression: &str, x: Real) -> Result<(), Discrepancy> {
+        let results = vec![
+            (Path::Folded, self.eval(expression, x, Path::Folded)),
+            (Path::Unfolded, self.eval(expression, x, Path::Unfolded)),
+            (Path::Parameter, self.eval(expression, x, Path::Parameter)),
+        ];
+        let (_, first) = &results[0];
+        if results.iter().all(|(_, result)| agree(first, result)) {
+            return Ok(());
+        }
+        Err(Discrepancy {
+            expression: expression.to_string(),
+            x,
+            results,
+        })
+    }
+
+    /// Check `cases` expressions of up to `depth` nested operators generated from `seed`,
+    /// returning the first discrepancy, shrunk.
+    pub fn run(&mut self, seed: u64, cases: usize, depth: usize) -> Result<(), Discrepancy> {
+        let mut generator = Generator::new(seed);
+        for _ in 0..cases {
+            let expr = generator.expression(depth);
+            let x = generator.x();
+            if self.check(&expr.to_string(), x).is_err() {
+                let expr = self.shrink(expr, x);
+                return self.check(&expr.to_string(), x);
+            }
+        }
+        Ok(())
+    }
+
+    /// A smallest expression simpler than `expr` the paths still disagree on at `x`.
+    pub fn shrink(&mut self, expr: Expr, x: Real) -> Expr {
+        let mut expr = expr;
+        while let Some(simpler) = expr
+            .simpler()
+            .into_iter()
+            .find(|e| self.check(&e.to_string(), x).is_err())
+        {
+            expr = simpler;
+        }
+        expr
+    }
+
+    fn eval(&mut self, expression: &str, x: Real, path: Path) -> Result<Value, InputError> {
+        let itp = &mut self.interpreter;
+        itp.set_many(&[("x", x)])?;
+        match path {
+            Path::Folded => itp.eval_pure(expression),
+            Path::Unfolded => itp.eval_unfolded(expression),
+            Path::Parameter => match itp.input(format!("probe: x = {}", expression).as_bytes())? {
+                InputState::Assignment { .. } => itp.eval_pure("probe(x)"),
+                _ => unreachable!(),
+            },
+        }
+    }
+}
+
+/// Whether two results are the same value, `nan` being the same as itself, or the same error.
+fn agree(r1: &Result<Value, InputError>, r2: &Result<Value, InputError>) -> bool {
+    match (r1, r2) {
+        (Ok(Value::Num(n1)), Ok(Value::Num(n2))) => n1 == n2 || n1.is_nan() && n2.is_nan(),
+        (Ok(v1), Ok(v2)) => v1 == v2,
+        (Err(e1), Err(e2)) => e1.to_string() == e2.to_string(),
+        _ => false,
+    }
+}
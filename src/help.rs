@@ -0,0 +1,265 @@
+//! Builtin documentation
+
+use std::fmt;
+
+/// Signature and one-line description of a definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HelpEntry {
+    pub name: String,
+    pub signature: String,
+    pub description: String,
+}
+
+impl fmt::Display for HelpEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.signature, self.description)
+    }
+}
+
+const BUILTINS: &[(&str, &str, &str)] = &[
+    (
+        "pi",
+        "pi",
+        "ratio of a circle's circumference to its diameter",
+    ),
+    ("e", "e", "base of the natural logarithm"),
+    ("tau", "tau", "2 * pi"),
+    ("phi", "phi", "golden ratio (1 + sqrt(5)) / 2"),
+    ("sqrt2", "sqrt2", "square root of 2"),
+    ("ln2", "ln2", "natural logarithm of 2"),
+    ("ln10", "ln10", "natural logarithm of 10"),
+    ("inf", "inf", "positive infinity"),
+    ("nan", "nan", "not a number"),
+    (
+        "true",
+        "true",
+        "the boolean true, 1 with numeric comparisons",
+    ),
+    (
+        "false",
+        "false",
+        "the boolean false, 0 with numeric comparisons",
+    ),
+    ("abs", "abs(x)", "absolute value"),
+    ("floor", "floor(x)", "largest integer not above x"),
+    ("ceil", "ceil(x)", "smallest integer not below x"),
+    (
+        "round",
+        "round(x[, digits])",
+        "nearest integer, or multiple of 10^-digits, halves away from zero",
+    ),
+    ("sgn", "sgn(x)", "sign of x, 1 or -1"),
+    ("min", "min(a, b)", "smaller of a and b, ignoring NaN"),
+    ("max", "max(a, b)", "larger of a and b, ignoring NaN"),
+    (
+        "clamp",
+        "clamp(x, lo, hi)",
+        "x limited to [lo, hi], hi if lo > hi",
+    ),
+    (
+        "lerp",
+        "lerp(a, b, t)",
+        "linear interpolation a + (b - a) * t",
+    ),
+    (
+        "copysign",
+        "copysign(x, y)",
+        "magnitude of x with the sign of y",
+    ),
+    (
+        "if",
+        "if(cond, a, b)",
+        "a if cond is true, else b, evaluating only that one",
+    ),
+    (
+        "fold",
+        "fold(i, a, b, acc0, expr)",
+        "acc0, then expr of i and acc for i from a to b",
+    ),
+    ("map", "map(f, v)", "vector of f(x) for the elements x of v"),
+    (
+        "filter",
+        "filter(p, v)",
+        "elements x of v for which p(x) is true",
+    ),
+    (
+        "contfrac_fn",
+        "contfrac_fn(a, b, n)",
+        "b(0) + a(1) / (b(1) + a(2) / (... + a(n) / b(n)))",
+    ),
+    (
+        "reduce",
+        "reduce(f, init, v)",
+        "init, then f(acc, x) for the elements x of v",
+    ),
+    (
+        "grad",
+        "grad(f, x1, ..., xn)",
+        "vector of the partial derivatives of f at the point (x1, ..., xn)",
+    ),
+    (
+        "jacobian",
+        "jacobian(f, x1, ..., xn)",
+        "Jacobian of the vector-valued f at the point (x1, ..., xn), row by row",
+    ),
+    (
+        "fit",
+        "fit(f, xs, ys)",
+        "fit the parameters p of f: x, p1, ..., pn to the data and define f_fit: x",
+    ),
+    (
+        "approx",
+        "approx(a, b, tol)",
+        "|a - b| at most tol times the larger of |a|, |b| and 1, also a ~= b",
+    ),
+    (
+        "cmp",
+        "cmp(a, b)",
+        "-1, 0 or 1 as a is less than, equal to or greater than b, also a <=> b",
+    ),
+    ("sqrt", "sqrt(x)", "square root"),
+    ("cbrt", "cbrt(x)", "cube root"),
+    ("sin", "sin(x)", "sine of x in radians"),
+    ("cos", "cos(x)", "cosine of x in radians"),
+    ("tan", "tan(x)", "tangent of x in radians"),
+    ("asin", "asin(x)", "arcsine in radians"),
+    ("acos", "acos(x)", "arccosine in radians"),
+    ("atan", "atan(x)", "arctangent in radians"),
+    (
+        "atan2",
+        "atan2(y, x)",
+        "angle of the point (x, y) in radians, in [-pi, pi]; atan2(x, y) after :atan2 xy",
+    ),
+    (
+        "atan2yx",
+        "atan2yx(y, x)",
+        "angle of the point (x, y) in radians, in [-pi, pi]",
+    ),
+    (
+        "atan2xy",
+        "atan2xy(x, y)",
+        "angle of the point (x, y) in radians, in [-pi, pi]",
+    ),
+    ("ln", "ln(x)", "natural logarithm"),
+    ("log", "log(x[, base])", "logarithm to base 10, or to base"),
+    (
+        "normpdf",
+        "normpdf(x, mu, sigma)",
+        "normal probability density",
+    ),
+    (
+        "normcdf",
+        "normcdf(x, mu, sigma)",
+        "normal cumulative distribution",
+    ),
+    (
+        "norminv",
+        "norminv(p, mu, sigma)",
+        "inverse of the normal cumulative distribution",
+    ),
+    (
+        "binompdf",
+        "binompdf(k, n, p)",
+        "probability of k successes in n trials",
+    ),
+    (
+        "binomcdf",
+        "binomcdf(k, n, p)",
+        "probability of at most k successes in n trials",
+    ),
+    (
+        "poissonpdf",
+        "poissonpdf(k, lambda)",
+        "Poisson probability of k events",
+    ),
+    (
+        "tcdf",
+        "tcdf(t, nu)",
+        "Student's t cumulative distribution with nu degrees of freedom",
+    ),
+    ("pmt", "pmt(rate, nper, pv, fv)", "payment per period"),
+    ("fv", "fv(rate, nper, pmt, pv)", "future value"),
+    ("pv", "pv(rate, nper, pmt, fv)", "present value"),
+    (
+        "npv",
+        "npv(rate, cashflows)",
+        "net present value, the first cash flow occurs now",
+    ),
+    ("irr", "irr(cashflows)", "internal rate of return"),
+    (
+        "fft",
+        "fft(v)",
+        "discrete Fourier transform, interleaved [re, im] output",
+    ),
+    (
+        "ifft",
+        "ifft(s)",
+        "real part of the inverse transform of an interleaved spectrum",
+    ),
+    (
+        "interp_linear",
+        "interp_linear(xs, ys, x)",
+        "linear interpolation of the table (xs, ys)",
+    ),
+    (
+        "interp_spline",
+        "interp_spline(xs, ys, x)",
+        "natural cubic spline interpolation of the table (xs, ys)",
+    ),
+    ("factor", "factor(n)", "prime factors in ascending order"),
+    ("nextprime", "nextprime(n)", "smallest prime above n"),
+    ("totient", "totient(n)", "Euler's totient function"),
+    (
+        "contfrac",
+        "contfrac(c)",
+        "c0 + 1 / (c1 + 1 / (... + 1 / cn)) of the vector c",
+    ),
+    (
+        "tobase",
+        "tobase(n, b)",
+        "digits of n in base b, most significant first",
+    ),
+    (
+        "frombase",
+        "frombase(digits, b)",
+        "integer with the digits in base b",
+    ),
+    ("band", "band(a, b)", "bitwise and"),
+    ("bor", "bor(a, b)", "bitwise or"),
+    ("bxor", "bxor(a, b)", "bitwise exclusive or"),
+    ("bnot", "bnot(a)", "bitwise not"),
+    ("shl", "shl(a, n)", "left shift by n bits"),
+    ("shr", "shr(a, n)", "arithmetic right shift by n bits"),
+    (
+        "range",
+        "range(a, b, step)",
+        "vector from a up to b by step, also a..b by 1",
+    ),
+    (
+        "linspace",
+        "linspace(a, b, n)",
+        "vector of n numbers evenly spaced from a to b",
+    ),
+    ("sum", "sum(v)", "sum of the elements"),
+    ("mean", "mean(v)", "arithmetic mean"),
+    ("median", "median(v)", "median"),
+    ("var", "var(v)", "sample variance"),
+    ("std", "std(v)", "sample standard deviation"),
+    (
+        "quantile",
+        "quantile(v, p)",
+        "quantile with linear interpolation",
+    ),
+    ("corr", "corr(xs, ys)", "Pearson correlation coefficient"),
+];
+
+pub(crate) fn builtin(name: &str) -> Option<HelpEntry> {
+    BUILTINS
+        .iter()
+        .find(|(ident, _, _)| *ident == name)
+        .map(|(name, signature, description)| HelpEntry {
+            name: name.to_string(),
+            signature: signature.to_string(),
+            description: description.to_string(),
+        })
+}
@@ -0,0 +1,122 @@
+//! HTTP evaluation endpoint
+//!
+//! `POST /eval` with a script as the body evaluates its lines in a new interpreter, answering
+//! `{"results": [...]}` with the response of `server::respond` to each line, up to the first
+//! error. Meta-commands are refused, as they reach the file system.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::Arc,
+    thread,
+};
+
+use serde_json::{json, Value as Json};
+
+use crate::{command, server, Interpreter};
+
+/// Largest request body accepted, in bytes.
+const MAX_BODY: usize = 64 * 1024;
+
+/// Stack of a request thread, room for user function calls a few hundred deep.
+const STACK_SIZE: usize = 32 * 1024 * 1024;
+
+/// Answer the script `body` in the interpreter `sandbox`.
+pub fn evaluate(mut sandbox: Interpreter, body: &str) -> Json {
+    let mut results = vec![];
+    // lines before the statement being read
+    let mut before = 0;
+    for (i, line) in body.lines().enumerate() {
+        let response = match command::parse(line) {
+            Some(_) => json!({
+                "error": { "severity": "error", "message": "Meta-commands are not available" }
+            }),
+            None => server::respond_at(&mut sandbox, line, before),
+        };
+        if response.get("incomplete").is_none() {
+            before = i + 1;
+        }
+        let failed = response.get("error").is_some();
+        results.push(response);
+        if failed {
+            break;
+        }
+    }
+    json!({ "results": results })
+}
+
+/// Answer one request on `stream`, evaluating it in an interpreter made by `sandbox`.
+pub fn handle<F: Fn() -> Interpreter>(sandbox: &F, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let (status, body) = match read_request(&mut reader)? {
+        Ok(script) => ("200 OK", evaluate(sandbox(), &script)),
+        Err(status) => (status, json!({ "error": { "message": status } })),
+    };
+    let body = body.to_string();
+    let stream = reader.get_mut();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// The body of a `POST /eval` request, or the status refusing it.
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Result<String, &'static str>> {
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut words = request.split_whitespace();
+    let (method, path) = (words.next(), words.next());
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(Err("400 Bad Request"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    match (method, path) {
+        (Some("POST"), Some("/eval")) => (),
+        (Some(_), Some("/eval")) => return Ok(Err("405 Method Not Allowed")),
+        _ => return Ok(Err("404 Not Found")),
+    }
+    let length = match length {
+        Some(length) if length > MAX_BODY => return Ok(Err("413 Payload Too Large")),
+        Some(length) => length,
+        None => return Ok(Err("411 Length Required")),
+    };
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(String::from_utf8(body).map_err(|_| "400 Bad Request"))
+}
+
+/// Accept requests at `addr`, each on its own thread and in its own interpreter made by
+/// `sandbox`, which should set a `Budget`.
+pub fn serve_http<A, F>(addr: A, sandbox: F) -> io::Result<()>
+where
+    A: ToSocketAddrs,
+    F: Fn() -> Interpreter + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(addr)?;
+    let sandbox = Arc::new(sandbox);
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let sandbox = sandbox.clone();
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || handle(&*sandbox, stream))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! Interpolation over data tables
+
+use crate::{
+    value::{EvalError, Value},
+    Real,
+};
+
+pub(crate) fn linear(args: &[Value]) -> Result<Value, EvalError> {
+    let (xs, ys) = table(&args[0], &args[1])?;
+    Ok(args[2].map(|x| {
+        let k = segment(xs, x);
+        let t = (x - xs[k]) / (xs[k + 1] - xs[k]);
+        ys[k] + t * (ys[k + 1] - ys[k])
+    }))
+}
+
+/// Natural cubic spline.
+pub(crate) fn spline(args: &[Value]) -> Result<Value, EvalError> {
+    let (xs, ys) = table(&args[0], &args[1])?;
+    let m = second_derivatives(xs, ys);
+    Ok(args[2].map(|x| {
+        let k = segment(xs, x);
+        let h = xs[k + 1] - xs[k];
+        let a = (xs[k + 1] - x) / h;
+        let b = (x - xs[k]) / h;
+        a * ys[k]
+            + b * ys[k + 1]
+            + ((a * a * a - a) * m[k] + (b * b * b - b) * m[k + 1]) * h * h / 6.0
+    }))
+}
+
+fn table<'a>(xs: &'a Value, ys: &'a Value) -> Result<(&'a [Real], &'a [Real]), EvalError> {
+    let (xs, ys) = (xs.vector()?, ys.vector()?);
+    if xs.len() != ys.len() {
+        return Err(EvalError::LengthMismatch {
+            left: xs.len(),
+            right: ys.len(),
+        });
+    }
+    if xs.len() < 2 {
+        return Err(EvalError::InvalidArgument {
+            expect: "at least 2 data points",
+        });
+    }
+    if !xs.windows(2).all(|w| w[0] < w[1]) {
+        return Err(EvalError::InvalidArgument {
+            expect: "strictly increasing xs",
+        });
+    }
+    Ok((xs, ys))
+}
+
+/// Index of the segment `[xs[k], xs[k + 1]]` used for `x`, the nearest one outside the table.
+fn segment(xs: &[Real], x: Real) -> usize {
+    xs.partition_point(|&xi| xi <= x)
+        .saturating_sub(1)
+        .min(xs.len() - 2)
+}
+
+/// Solve the tridiagonal system for the second derivatives, zero at both ends.
+fn second_derivatives(xs: &[Real], ys: &[Real]) -> Vec<Real> {
+    let n = xs.len();
+    let mut m = vec![0.0; n];
+    let mut c = vec![0.0; n];
+    for i in 1..n - 1 {
+        let h0 = xs[i] - xs[i - 1];
+        let h1 = xs[i + 1] - xs[i];
+        let d = 6.0 * ((ys[i + 1] - ys[i]) / h1 - (ys[i] - ys[i - 1]) / h0);
+        let diag = 2.0 * (h0 + h1) - h0 * c[i - 1];
+        c[i] = h1 / diag;
+        m[i] = (d - h0 * m[i - 1]) / diag;
+    }
+    for i in (1..n - 1).rev() {
+        m[i] -= c[i] * m[i + 1];
+    }
+    m
+}
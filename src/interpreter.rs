@@ -1,181 +1,2819 @@
 //! Interpreter
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    fmt,
+    fs::File,
+    io::BufReader,
+    ops::{Bound, Range},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use smallvec::smallvec;
+
+#[cfg(not(feature = "pratt"))]
+use crate::parser::Parser;
+#[cfg(feature = "pratt")]
+use crate::pratt::Parser;
+
+#[cfg(feature = "fft")]
+use crate::fft;
+#[cfg(feature = "finance")]
+use crate::finance;
+use crate::{
+    cache::{CacheStats, Lru},
+    command::{self, Command, CommandError},
+    csv::{self, CsvError},
+    diagnostic::Diagnostic,
+    dist,
+    help::{self, HelpEntry},
+    interpolate,
+    lexer::{
+        is_operator_start, AddSubOp, CompareOp, Ident, Lexer, MulDivOp, SpannedToken, Token,
+        TokenKind, TokenStream,
+    },
+    number,
+    numeric::digits,
+    parser::{ASTNode, Position, Unexpected},
+    sequence,
+    solver::{self, SolveError},
+    stats,
+    value::{EvalError, Value},
+    InvalidToken, Numeric, Real,
+};
+
+mod arena;
+mod passes;
+mod session;
+
+use self::arena::{Arena, Args, Body, ExprId, ExprOrValue, Expression};
+use self::session::{literal, Binding, Snapshot, Workspace, DEFAULT_WORKSPACE};
+pub use self::session::{AuditEntry, Change, ChangeKind, SnapshotId};
+
+struct Function<T> {
+    name: Ident,
+    incount: usize,
+    variables: Vec<Ident>,
+    doc: Option<String>,
+    source: Option<Source<T>>,
+    fimpl: FunctionImpl<T>,
+    /// Builtins of the same name taking other numbers of arguments.
+    overloads: Vec<Arc<Function<T>>>,
+}
+
+/// Whether a value is builtin, the value and the statement assigning it.
+type Variable<T> = (bool, Value<T>, Option<Source<T>>);
+
+/// Hasher of the maps of bindings, FxHash with the `fxhash` feature.
+#[cfg(feature = "fxhash")]
+type BindingHasher = std::hash::BuildHasherDefault<rustc_hash::FxHasher>;
+#[cfg(not(feature = "fxhash"))]
+type BindingHasher = std::collections::hash_map::RandomState;
+
+/// Values or functions by name.
+type Bindings<T> = HashMap<Ident, T, BindingHasher>;
+
+/// Numbers the defining statements in the order they are read.
+static SERIAL: AtomicU64 = AtomicU64::new(1);
+
+/// Lines of a defining statement as typed, and when it was read.
+#[derive(Clone, PartialEq)]
+struct Source<T> {
+    text: String,
+    defined: SystemTime,
+    serial: u64,
+    /// User variables and functions the statement read, as bound when it was translated.
+    captures: Vec<Capture<T>>,
+}
+
+impl<T: Numeric> Source<T> {
+    fn new(text: String) -> Self {
+        Source {
+            text,
+            defined: SystemTime::now(),
+            serial: SERIAL.fetch_add(1, Ordering::Relaxed),
+            captures: vec![],
+        }
+    }
+
+    fn capturing(mut self, captures: Vec<Capture<T>>) -> Self {
+        self.captures = captures;
+        self
+    }
+}
+
+/// A binding read by a definition.
+#[derive(Clone)]
+enum Capture<T> {
+    Value(Ident, Value<T>),
+    Function(Ident, Arc<Function<T>>),
+}
+
+impl<T: PartialEq> PartialEq for Capture<T> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Capture::Value(i1, v1), Capture::Value(i2, v2)) => i1 == i2 && v1 == v2,
+            (Capture::Function(i1, f1), Capture::Function(i2, f2)) => {
+                i1 == i2 && Arc::ptr_eq(f1, f2)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Evaluates the argument of a lazy builtin at an index.
+type LazyArgs<'a, T> = dyn FnMut(usize) -> Result<Value<T>, EvalError> + 'a;
+
+enum FunctionImpl<T> {
+    Lib(fn(&[T]) -> T),
+    Generic(GenericFn<T>),
+    /// A builtin of the libraries, its arguments and result converted from and to `Real`.
+    RealLib(fn(&[Real]) -> Real),
+    RealGeneric(GenericFn),
+    /// A builtin evaluating only the arguments it needs.
+    Lazy(fn(&mut LazyArgs<T>) -> Result<Value<T>, EvalError>),
+    User(Body<T>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Higher {
+    Map,
+    Filter,
+    Reduce,
+    ContFrac,
+    Grad,
+    Jacobian,
+}
+
+impl Higher {
+    fn named(name: &[u8]) -> Option<Higher> {
+        match name {
+            b"map" => Some(Higher::Map),
+            b"filter" => Some(Higher::Filter),
+            b"reduce" => Some(Higher::Reduce),
+            b"contfrac_fn" => Some(Higher::ContFrac),
+            b"grad" => Some(Higher::Grad),
+            b"jacobian" => Some(Higher::Jacobian),
+            _ => None,
+        }
+    }
+
+    /// Name, argument count, and count and arity of the leading arguments naming functions, for
+    /// a call with `args` arguments; `grad` and `jacobian` take a coordinate per parameter.
+    fn signature(self, args: usize) -> (&'static [u8], usize, usize, usize) {
+        match self {
+            Higher::Map => (b"map", 2, 1, 1),
+            Higher::Filter => (b"filter", 2, 1, 1),
+            Higher::Reduce => (b"reduce", 3, 1, 2),
+            Higher::ContFrac => (b"contfrac_fn", 3, 2, 1),
+            Higher::Grad => (b"grad", args.max(2), 1, args.saturating_sub(1)),
+            Higher::Jacobian => (b"jacobian", args.max(2), 1, args.saturating_sub(1)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputError {
+    InvalidToken(InvalidToken),
+    SyntaxError {
+        /// Line in the statement, from 1, or in the script after `offset_lines`.
+        line: usize,
+        column: usize,
+        /// Names of the tokens that could come there, if known.
+        expected: Vec<&'static str>,
+    },
+    RepeatVariable {
+        ident: Ident,
+        /// Line, from 1, and columns of the identifier, if from a statement.
+        position: Option<(usize, Range<usize>)>,
+    },
+    UndefinedIdentifier {
+        ident: Ident,
+        /// Defined names close to `ident`, closest first.
+        suggestions: Vec<String>,
+        position: Option<(usize, Range<usize>)>,
+    },
+    BuiltinIdentifier {
+        ident: Ident,
+        position: Option<(usize, Range<usize>)>,
+    },
+    InconsistentVariablesCount {
+        ident: Ident,
+        position: Option<(usize, Range<usize>)>,
+    },
+    /// An error evaluating, with the line and columns of the operation or call at fault, if from a
+    /// statement.
+    EvalError(EvalError, Option<(usize, Range<usize>)>),
+    /// A mismatch between the parser and the translator, a bug of the interpreter.
+    InternalError {
+        message: &'static str,
+    },
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputError::InvalidToken(e) => write!(f, "{:?}", e),
+            InputError::SyntaxError {
+                line,
+                column,
+                expected,
+            } => {
+                write!(f, "Syntax Error at line {}, column {}", line, column)?;
+                match expected.split_last() {
+                    Some((last, [])) => write!(f, ", expect {}", last),
+                    Some((last, others)) => {
+                        write!(f, ", expect {} or {}", others.join(", "), last)
+                    }
+                    None => Ok(()),
+                }
+            }
+            InputError::RepeatVariable { ident, .. } => {
+                write!(f, "Repeat Variable: {}", String::from_utf8_lossy(ident))
+            }
+            InputError::UndefinedIdentifier {
+                ident, suggestions, ..
+            } => {
+                write!(
+                    f,
+                    "Undefined Identifier: {}",
+                    String::from_utf8_lossy(ident)
+                )?;
+                if !suggestions.is_empty() {
+                    write!(f, ", did you mean '{}'?", suggestions.join("' or '"))?;
+                }
+                Ok(())
+            }
+            InputError::BuiltinIdentifier { ident, .. } => write!(
+                f,
+                "Use Builtin Identifier: {}",
+                String::from_utf8_lossy(ident)
+            ),
+            InputError::InconsistentVariablesCount { ident, .. } => write!(
+                f,
+                "Inconsistent Variables Count: {}",
+                String::from_utf8_lossy(ident)
+            ),
+            InputError::EvalError(e, _) => write!(f, "{}", e),
+            InputError::InternalError { message } => write!(f, "Internal Error: {}", message),
+        }
+    }
+}
+
+/// Non-fatal findings about an accepted input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    ConstantCondition {
+        value: bool,
+    },
+    UnusedParameter {
+        ident: Ident,
+    },
+    UnboundedRecursion {
+        ident: Ident,
+    },
+    SelfComparison {
+        ident: Ident,
+    },
+    /// A deprecated name, with the name to use instead.
+    Deprecated {
+        ident: Ident,
+        replacement: Option<Ident>,
+    },
+    /// `atan2` in a function, whose argument order depends on the convention at definition.
+    AmbiguousAtan2 {
+        order: Atan2Order,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::ConstantCondition { value } => write!(
+                f,
+                "Constant Condition: always {}, the {} branch is unreachable",
+                value,
+                match value {
+                    true => "else",
+                    false => "then",
+                }
+            ),
+            Warning::UnusedParameter { ident } => {
+                write!(f, "Unused Parameter: {}", String::from_utf8_lossy(ident))
+            }
+            Warning::UnboundedRecursion { ident } => {
+                write!(f, "Unbounded Recursion: {}", String::from_utf8_lossy(ident))
+            }
+            Warning::SelfComparison { ident } => {
+                write!(f, "Self Comparison: {}", String::from_utf8_lossy(ident))
+            }
+            Warning::Deprecated { ident, replacement } => {
+                write!(f, "Deprecated: {}", String::from_utf8_lossy(ident))?;
+                match replacement {
+                    Some(replacement) => {
+                        write!(f, ", use {}", String::from_utf8_lossy(replacement))
+                    }
+                    None => Ok(()),
+                }
+            }
+            Warning::AmbiguousAtan2 { order } => write!(
+                f,
+                "Ambiguous Atan2: taking {}, use atan2yx or atan2xy",
+                match order {
+                    Atan2Order::YFirst => "(y, x)",
+                    Atan2Order::XFirst => "(x, y)",
+                }
+            ),
+        }
+    }
+}
+
+impl InputError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        let code = match self {
+            InputError::InvalidToken(_) => "E0001",
+            InputError::SyntaxError { .. } => "E0002",
+            InputError::RepeatVariable { .. } => "E0003",
+            InputError::UndefinedIdentifier { .. } => "E0004",
+            InputError::BuiltinIdentifier { .. } => "E0005",
+            InputError::InconsistentVariablesCount { .. } => "E0006",
+            InputError::EvalError(EvalError::TypeMismatch { .. }, _) => "E0007",
+            InputError::EvalError(EvalError::LengthMismatch { .. }, _) => "E0008",
+            InputError::EvalError(EvalError::InvalidArgument { .. }, _) => "E0009",
+            InputError::InternalError { .. } => "E0010",
+            InputError::EvalError(EvalError::NonFinite { .. }, _) => "E0011",
+            InputError::EvalError(EvalError::BudgetExceeded { .. }, _) => "E0012",
+            InputError::EvalError(EvalError::Unsolved { .. }, _) => "E0013",
+        };
+        Diagnostic::error(code, self.to_string(), self.position())
+    }
+
+    /// Line, from 1, and columns the error is at, if known.
+    pub fn position(&self) -> Option<(usize, Range<usize>)> {
+        match self {
+            InputError::InvalidToken(e) => Some((e.line(), e.column()..e.column() + 1)),
+            InputError::SyntaxError { line, column, .. } => Some((*line, *column..*column + 1)),
+            InputError::RepeatVariable { position, .. }
+            | InputError::UndefinedIdentifier { position, .. }
+            | InputError::BuiltinIdentifier { position, .. }
+            | InputError::InconsistentVariablesCount { position, .. }
+            | InputError::EvalError(_, position) => position.clone(),
+            InputError::InternalError { .. } => None,
+        }
+    }
+
+    /// The error of a statement starting after the first `lines` lines of a script, its line
+    /// counted from the start of the script.
+    pub fn offset_lines(mut self, lines: usize) -> Self {
+        match &mut self {
+            InputError::InvalidToken(e) => e.offset_lines(lines),
+            InputError::SyntaxError { line, .. } => *line += lines,
+            InputError::RepeatVariable { position, .. }
+            | InputError::UndefinedIdentifier { position, .. }
+            | InputError::BuiltinIdentifier { position, .. }
+            | InputError::InconsistentVariablesCount { position, .. }
+            | InputError::EvalError(_, position) => {
+                if let Some((line, _)) = position {
+                    *line += lines;
+                }
+            }
+            InputError::InternalError { .. } => (),
+        }
+        self
+    }
+
+    /// The error at `at` if it has no position yet.
+    fn at(mut self, at: Option<Position>) -> Self {
+        match &mut self {
+            InputError::RepeatVariable { position, .. }
+            | InputError::UndefinedIdentifier { position, .. }
+            | InputError::BuiltinIdentifier { position, .. }
+            | InputError::InconsistentVariablesCount { position, .. }
+            | InputError::EvalError(_, position)
+                if position.is_none() =>
+            {
+                *position = at
+            }
+            _ => (),
+        }
+        self
+    }
+}
+
+impl Warning {
+    pub fn diagnostic(&self) -> Diagnostic {
+        let code = match self {
+            Warning::ConstantCondition { .. } => "W0001",
+            Warning::UnusedParameter { .. } => "W0002",
+            Warning::UnboundedRecursion { .. } => "W0003",
+            Warning::SelfComparison { .. } => "W0004",
+            Warning::Deprecated { .. } => "W0005",
+            Warning::AmbiguousAtan2 { .. } => "W0006",
+        };
+        Diagnostic::warning(code, self.to_string())
+    }
+}
+
+impl From<InvalidToken> for InputError {
+    fn from(e: InvalidToken) -> Self {
+        InputError::InvalidToken(e)
+    }
+}
+
+impl From<EvalError> for InputError {
+    fn from(e: EvalError) -> Self {
+        InputError::EvalError(e, None)
+    }
+}
+
+impl From<SolveError> for InputError {
+    fn from(e: SolveError) -> Self {
+        match e {
+            SolveError::InvalidFunction(e) => e,
+            SolveError::DataMismatch { xs, ys } => EvalError::LengthMismatch {
+                left: xs,
+                right: ys,
+            }
+            .into(),
+            e => EvalError::Unsolved {
+                reason: e.to_string(),
+            }
+            .into(),
+        }
+    }
+}
+
+/// The order of the arguments of `atan2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Atan2Order {
+    /// `atan2(y, x)`, as in C and most languages, the default.
+    YFirst,
+    /// `atan2(x, y)`, as in spreadsheets.
+    XFirst,
+}
+
+/// A set of builtins an interpreter can be built with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BuiltinGroup {
+    /// `pi`, `e`, `tau`, `phi`, `sqrt2`, `ln2`, `ln10`, `inf` and `nan`.
+    Constants,
+    /// Rounding, `min`, `max`, `clamp`, `lerp`, `copysign`, `approx`, roots and `if`.
+    Core,
+    /// Trigonometric functions and their inverses.
+    Trig,
+    /// `ln` and `log`.
+    Logs,
+    /// Number theory, bases and bitwise operations on integers.
+    Numbers,
+    /// `range`, `linspace` and interpolation.
+    Vectors,
+    /// Sums and statistics over vectors.
+    Stats,
+    /// Probability distributions.
+    Distributions,
+    /// Financial functions, with the `finance` feature.
+    Finance,
+    /// Fourier transforms, with the `fft` feature.
+    Fft,
+}
+
+impl BuiltinGroup {
+    pub const ALL: &'static [BuiltinGroup] = &[
+        BuiltinGroup::Constants,
+        BuiltinGroup::Core,
+        BuiltinGroup::Trig,
+        BuiltinGroup::Logs,
+        BuiltinGroup::Numbers,
+        BuiltinGroup::Vectors,
+        BuiltinGroup::Stats,
+        BuiltinGroup::Distributions,
+        BuiltinGroup::Finance,
+        BuiltinGroup::Fft,
+    ];
+}
+
+/// Builds an interpreter with the chosen builtin groups, all of them by default.
+///
+/// `fold`, `map`, `filter`, `reduce`, `contfrac_fn`, `grad`, `jacobian` and `fit` are part of the
+/// syntax and always available.
+#[derive(Debug, Clone)]
+pub struct InterpreterBuilder {
+    groups: Vec<BuiltinGroup>,
+}
+
+impl Default for InterpreterBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InterpreterBuilder {
+    pub fn new() -> Self {
+        InterpreterBuilder {
+            groups: BuiltinGroup::ALL.to_vec(),
+        }
+    }
+
+    /// A builder without any builtin group.
+    pub fn empty() -> Self {
+        InterpreterBuilder { groups: vec![] }
+    }
+
+    pub fn include(mut self, group: BuiltinGroup) -> Self {
+        if !self.groups.contains(&group) {
+            self.groups.push(group);
+        }
+        self
+    }
+
+    pub fn exclude(mut self, group: BuiltinGroup) -> Self {
+        self.groups.retain(|g| *g != group);
+        self
+    }
+
+    pub fn build(self) -> Interpreter {
+        Interpreter::with_groups(self.groups)
+    }
+
+    /// Build an interpreter computing in the number type `T`.
+    pub fn build_numeric<T: Numeric>(self) -> Interpreter<T> {
+        Interpreter::with_groups(self.groups)
+    }
+}
+
+/// Reads statements and evaluates them in the number type `T`, `Real` by default.
+pub struct Interpreter<T = Real> {
+    values: Bindings<Variable<T>>,
+    functions: Bindings<Arc<Function<T>>>,
+    /// Constants added by the host, with their descriptions.
+    constants: HashMap<Ident, (T, String)>,
+    /// Sorted names of `values` and `functions`.
+    names: BTreeSet<Ident>,
+    /// Alternative names of builtins, with the builtins they stand for.
+    aliases: HashMap<Ident, Ident>,
+    /// Names bound to the result of the last expression statement.
+    result_names: Vec<Ident>,
+    /// Names still working but warned about, with the names to use instead.
+    deprecated: HashMap<Ident, Option<Ident>>,
+    parser: Option<Parser>,
+    cur_ident: Ident,
+    cur_variables: Vec<Ident>,
+    /// Index and accumulator names bound by the enclosing `fold`s.
+    cur_bound: Vec<Ident>,
+    /// Whether bare function names stand for their call on `cur_variables`.
+    cur_lifted: bool,
+    /// Whether the statement ends with a `;`.
+    cur_terminated: bool,
+    /// Blocks left open by the lines read of the statement.
+    cur_blocks: usize,
+    /// Locals of the enclosing blocks, with their translations.
+    cur_locals: Vec<(Ident, ExprOrValue<T>)>,
+    /// Expressions of the statement being translated.
+    cur_arena: Arena<T>,
+    cur_doc: Option<String>,
+    /// The operator the statement defines, with its previous tier, restored if it fails.
+    cur_operator: Option<Binding<T>>,
+    /// User bindings read by the statement.
+    cur_captures: Vec<Capture<T>>,
+    cur_source: Vec<String>,
+    warnings: Vec<Warning>,
+    history: Vec<String>,
+    pending: Vec<String>,
+    journal: VecDeque<Vec<Binding<T>>>,
+    changes: Vec<Change>,
+    snapshots: Vec<Snapshot<T>>,
+    workspace: String,
+    workspaces: HashMap<String, Workspace<T>>,
+    precision: Option<usize>,
+    fractions: bool,
+    /// Tolerance of `~=`.
+    epsilon: Real,
+    decimal_comma: bool,
+    si_suffixes: bool,
+    /// Whether `SUMS` use compensated summation.
+    compensated: bool,
+    /// Whether a `;` ending a statement silences its result.
+    quiet_semicolon: bool,
+    atan2_order: Atan2Order,
+    /// Whether comparisons produce `1` and `0` instead of booleans.
+    numeric_comparisons: bool,
+    strict: bool,
+    budget: Option<Budget>,
+    /// Whether function definitions inline the calls of small user functions.
+    inlining: bool,
+    tracing: bool,
+    trace: Vec<TraceStep<T>>,
+    breakpoints: HashSet<Ident>,
+    debugger: Option<DebugHandler<T>>,
+    /// Supplies numbers for names bound nowhere else.
+    resolver: Option<Resolver<T>>,
+    profile: Option<HashMap<Ident, (usize, Duration)>>,
+    audit: Option<Vec<AuditEntry<T>>>,
+    /// Whether operations on constants are evaluated at translation.
+    fold: bool,
+    /// Spellings of the user-defined operators of the workspace and the token ids of their
+    /// precedence tiers.
+    operators: Vec<(Ident, u32)>,
+    /// Builtin groups, also given to new workspaces.
+    groups: Vec<BuiltinGroup>,
+    /// Translated expression statements by their text.
+    cache: Lru<String, CachedStatement<T>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Variable,
+    Function,
+    Builtin,
+}
+
+/// A suggested identifier, with the number of arguments for functions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub text: String,
+    pub kind: CompletionKind,
+    pub arity: Option<usize>,
+}
+
+/// A name of the environment, with the number of arguments for functions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentInfo {
+    pub name: String,
+    pub kind: CompletionKind,
+    pub arity: Option<usize>,
+}
+
+/// Representations of a result for display side by side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResultViews {
+    pub decimal: String,
+    pub scientific: Option<String>,
+    /// Hexadecimal and binary literals of an integer.
+    pub hex: Option<String>,
+    pub binary: Option<String>,
+    pub fraction: Option<String>,
+}
+
+/// An operation or call performed during evaluation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceStep<T = Real> {
+    /// Number of user function calls the step is nested in.
+    pub depth: usize,
+    /// Operator symbol, `?:`, or the name of the function called.
+    pub operation: String,
+    pub operands: Vec<Value<T>>,
+    pub result: Value<T>,
+}
+
+impl<T: Numeric> fmt::Display for TraceStep<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:width$}", "", width = self.depth * 2)?;
+        let op = &self.operation;
+        let symbolic = !op.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+        match self.operands.as_slice() {
+            [cond] if op == "?:" => write!(f, "{} ? .. : ..", cond)?,
+            [v1] if op == "||" || op == "&&" => write!(f, "{} {} ..", v1, op)?,
+            [v1, v2] if symbolic => write!(f, "{} {} {}", v1, op, v2)?,
+            [val] if symbolic => write!(f, "{}{}", op, val)?,
+            operands => {
+                let operands = operands.iter().map(Value::to_string).collect::<Vec<_>>();
+                write!(f, "{}({})", op, operands.join(", "))?
+            }
+        }
+        write!(f, " = {}", self.result)
+    }
+}
+
+/// A paused call of a user function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame<T = Real> {
+    pub function: String,
+    /// Parameter names with the argument values.
+    pub arguments: Vec<(String, Value<T>)>,
+    /// Number of user function calls the call is nested in.
+    pub depth: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    /// Pause again at the next user function call.
+    Step,
+    /// Run until the next breakpoint.
+    Continue,
+}
+
+type DebugHandler<T> = Box<dyn FnMut(&Frame<T>) -> DebugAction + Send>;
+
+type Resolver<T> = Box<dyn Fn(&str) -> Option<T> + Send>;
+
+/// A translated expression statement kept by the parse cache.
+struct CachedStatement<T> {
+    statement: Arc<Function<T>>,
+    warnings: Vec<Warning>,
+    /// Bindings of the names in the statement when it was translated.
+    bindings: Vec<Binding<T>>,
+    /// Folding, `~=` tolerance, number input options, operator count and numeric comparisons of
+    /// the translation.
+    settings: (bool, Real, bool, bool, usize, bool),
+    /// Whether the statement ends with a `;`.
+    terminated: bool,
+}
+
+struct Debugger<'a, T> {
+    breakpoints: &'a HashSet<Ident>,
+    handler: &'a mut DebugHandler<T>,
+    stepping: bool,
+}
+
+/// The statement defining a user function or variable as typed, lines joined with newlines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub source: String,
+    /// When the statement was read.
+    pub defined: SystemTime,
+}
+
+/// Names a formula refers to, in order of first use, see [`Interpreter::free_variables`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FreeVariables {
+    /// Parameters of the function, bound at each call.
+    pub parameters: Vec<String>,
+    /// User variables and functions it refers to.
+    pub environment: Vec<String>,
+    /// Names defined nowhere, to be supplied before it can be evaluated.
+    pub missing: Vec<String>,
+    /// The names of `missing` with the role of their first use, e.g. to generate an input form.
+    pub inputs: Vec<RequiredInput>,
+}
+
+/// A name a formula needs bound, see [`FreeVariables::inputs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequiredInput {
+    pub name: String,
+    pub role: InputRole,
+}
+
+/// How a formula uses a name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputRole {
+    /// A number or vector.
+    Value,
+    /// A function called with `arity` arguments, or named as such by `map` and the like.
+    Function { arity: usize },
+}
+
+/// Repeated evaluation times of an expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    pub runs: usize,
+    pub min: Duration,
+    pub mean: Duration,
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} runs, min {:?}, mean {:?}",
+            self.runs, self.min, self.mean
+        )
+    }
+}
+
+const BENCH_TIME: Duration = Duration::from_millis(500);
+const BENCH_RUNS: usize = 1_000_000;
+
+/// Calls of a function while profiling.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileEntry {
+    pub name: String,
+    pub calls: usize,
+    /// Time spent in the calls, including the functions they call.
+    pub time: Duration,
+}
+
+/// Limits on the evaluation of a statement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Budget {
+    /// Operations and function calls.
+    pub steps: usize,
+    /// Nesting of user function calls.
+    pub depth: usize,
+}
+
+/// State threaded through an evaluation.
+#[derive(Default)]
+struct Context<'a, T> {
+    /// Steps recorded when tracing.
+    trace: Option<Vec<TraceStep<T>>>,
+    debugger: Option<Debugger<'a, T>>,
+    /// Call counts and times by function when profiling.
+    profile: Option<&'a mut HashMap<Ident, (usize, Duration)>>,
+    depth: usize,
+    /// Reject NaN and infinite results.
+    strict: bool,
+    /// Steps left and call depth allowed.
+    budget: Option<Budget>,
+    /// Values of the temporaries of the `Let`s being evaluated.
+    temps: Vec<Value<T>>,
+    /// Where the temporaries of the innermost `Let` start in `temps`.
+    temp_base: usize,
+    /// Emptied argument buffers of returned calls, reused by later ones.
+    frames: Vec<Vec<Value<T>>>,
+}
+
+impl<T: Numeric> Context<'_, T> {
+    /// Call the debugger before `f` runs, if it is at a breakpoint or stepping.
+    fn pause(&mut self, f: &Function<T>, args: &[Value<T>]) {
+        let debugger = match &mut self.debugger {
+            Some(debugger) => debugger,
+            None => return,
+        };
+        if debugger.stepping || debugger.breakpoints.contains(&f.name) {
+            let frame = Frame {
+                function: String::from_utf8_lossy(&f.name).into_owned(),
+                arguments: f
+                    .variables
+                    .iter()
+                    .map(|v| String::from_utf8_lossy(v).into_owned())
+                    .zip(args.iter().cloned())
+                    .collect(),
+                depth: self.depth,
+            };
+            debugger.stepping = (debugger.handler)(&frame) == DebugAction::Step;
+        }
+    }
+
+    /// Trace a step, failing on a non-finite result in strict mode.
+    fn record<F: FnOnce() -> Vec<Value<T>>>(
+        &mut self,
+        operation: &str,
+        operands: F,
+        result: &Value<T>,
+    ) -> Result<(), EvalError> {
+        if let Some(budget) = &mut self.budget {
+            budget.steps = budget
+                .steps
+                .checked_sub(1)
+                .ok_or(EvalError::BudgetExceeded { limit: "steps" })?;
+        }
+        let strict = self.strict && result.is_non_finite();
+        if self.trace.is_none() && !strict {
+            return Ok(());
+        }
+        let step = TraceStep {
+            depth: self.depth,
+            operation: operation.to_string(),
+            operands: operands(),
+            result: result.clone(),
+        };
+        if strict {
+            return Err(EvalError::NonFinite {
+                step: TraceStep { depth: 0, ..step }.to_string(),
+            });
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.push(step);
+        }
+        Ok(())
+    }
+}
+
+/// What an assignment statement bound to its name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Assigned<T = Real> {
+    Value(Value<T>),
+    Function { arity: usize },
+}
+
+pub enum InputState<T = Real> {
+    Empty,
+    Incomplete,
+    Assignment {
+        name: String,
+        assigned: Assigned<T>,
+        warnings: Vec<Warning>,
+        /// False if the statement ended with a `;` silencing its result.
+        echo: bool,
+    },
+    Expression {
+        value: Value<T>,
+        warnings: Vec<Warning>,
+        /// False if the statement ended with a `;` silencing its result.
+        echo: bool,
+    },
+    Help {
+        entry: HelpEntry,
+    },
+    Workspace {
+        name: String,
+    },
+}
+
+/// An interpreter of the number type `T`, with all builtin groups, e.g.
+/// `Interpreter::<f32>::default()`.
+impl<T: Numeric> Default for Interpreter<T> {
+    fn default() -> Self {
+        Self::with_groups(BuiltinGroup::ALL.to_vec())
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start building an interpreter with a chosen set of builtin groups.
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder::new()
+    }
+}
+
+impl<T: Numeric> Interpreter<T> {
+    fn with_groups(groups: Vec<BuiltinGroup>) -> Self {
+        let mut itp = Interpreter {
+            values: Bindings::with_capacity_and_hasher(BINDINGS_CAPACITY.0, Default::default()),
+            functions: Bindings::with_capacity_and_hasher(BINDINGS_CAPACITY.1, Default::default()),
+            constants: HashMap::new(),
+            names: BTreeSet::new(),
+            aliases: HashMap::new(),
+            result_names: vec![b"_".to_vec()],
+            deprecated: HashMap::new(),
+            parser: None,
+            cur_ident: vec![],
+            cur_variables: vec![],
+            cur_bound: vec![],
+            cur_terminated: false,
+            cur_blocks: 0,
+            cur_locals: vec![],
+            cur_lifted: false,
+            cur_arena: Arena::default(),
+            cur_doc: None,
+            cur_operator: None,
+            cur_captures: vec![],
+            cur_source: vec![],
+            warnings: vec![],
+            history: vec![],
+            pending: vec![],
+            journal: VecDeque::new(),
+            changes: vec![],
+            snapshots: vec![],
+            workspace: DEFAULT_WORKSPACE.to_string(),
+            workspaces: HashMap::new(),
+            precision: None,
+            epsilon: DEFAULT_EPSILON,
+            decimal_comma: false,
+            si_suffixes: false,
+            compensated: true,
+            atan2_order: Atan2Order::YFirst,
+            numeric_comparisons: false,
+            quiet_semicolon: true,
+            strict: false,
+            budget: None,
+            inlining: false,
+            fractions: false,
+            tracing: false,
+            trace: vec![],
+            breakpoints: HashSet::new(),
+            debugger: None,
+            resolver: None,
+            profile: None,
+            audit: None,
+            fold: true,
+            operators: vec![],
+            groups: vec![],
+            cache: Lru::new(0),
+        };
+        itp.insert_results();
+        itp.insert_booleans();
+        for group in groups {
+            itp.insert_group(group);
+        }
+        itp
+    }
+
+    fn insert_group(&mut self, group: BuiltinGroup) {
+        if self.groups.contains(&group) {
+            return;
+        }
+        self.groups.push(group);
+        match group {
+            BuiltinGroup::Constants => {
+                for (ident, digits) in [
+                    (&b"pi"[..], digits::PI),
+                    (b"e", digits::E),
+                    (b"tau", digits::TAU),
+                    (b"phi", digits::PHI),
+                    (b"sqrt2", digits::SQRT_2),
+                    (b"ln2", digits::LN_2),
+                    (b"ln10", digits::LN_10),
+                ] {
+                    self.insert_builtin_value(ident, T::parse(digits).unwrap());
+                }
+                self.insert_builtin_value(b"inf", T::INFINITY);
+                self.insert_builtin_value(b"nan", T::NAN);
+            }
+            BuiltinGroup::Core => {
+                self.insert_builtin_fn(b"abs", 1, |v| v[0].abs());
+                self.insert_builtin_fn(b"floor", 1, |v| v[0].floor());
+                self.insert_builtin_fn(b"ceil", 1, |v| v[0].ceil());
+                self.insert_builtin_fn(b"round", 1, |v| v[0].round());
+                self.overload_builtin_fn(b"round", 2, |v| {
+                    let scale = T::from_i64(10).powf(v[1].round());
+                    let scaled = v[0] * scale;
+                    // too many digits to round any away, or too few to keep any
+                    if !scaled.is_finite() {
+                        v[0]
+                    } else if scale == T::ZERO {
+                        T::ZERO.copysign(v[0])
+                    } else {
+                        scaled.round() / scale
+                    }
+                });
+                self.insert_builtin_fn(b"sgn", 1, |v| v[0].signum());
+                self.insert_builtin_fn(b"min", 2, |v| v[0].min(v[1]));
+                self.insert_builtin_fn(b"max", 2, |v| v[0].max(v[1]));
+                self.insert_builtin_fn(b"clamp", 3, |v| v[0].max(v[1]).min(v[2]));
+                self.insert_builtin_fn(b"lerp", 3, |v| v[0] + (v[1] - v[0]) * v[2]);
+                self.insert_builtin_fn(b"copysign", 2, |v| v[0].copysign(v[1]));
+                self.insert_generic_fn(b"approx", 3, |v| v[0].approx(&v[1], &v[2]));
+                self.insert_generic_fn(b"cmp", 2, |v| v[0].compare(&v[1], CompareOp::CMP));
+                self.insert_builtin_fn(b"sqrt", 1, |v| v[0].sqrt());
+                self.insert_builtin_fn(b"cbrt", 1, |v| v[0].cbrt());
+                self.insert_lazy_fn(b"if", 3, lazy_if);
+            }
+            BuiltinGroup::Trig => {
+                self.insert_builtin_fn(b"sin", 1, |v| v[0].sin());
+                self.insert_builtin_fn(b"cos", 1, |v| v[0].cos());
+                self.insert_builtin_fn(b"tan", 1, |v| v[0].tan());
+                self.insert_builtin_fn(b"asin", 1, |v| v[0].asin());
+                self.insert_builtin_fn(b"acos", 1, |v| v[0].acos());
+                self.insert_builtin_fn(b"atan", 1, |v| v[0].atan());
+                self.insert_atan2();
+                self.insert_builtin_fn(b"atan2yx", 2, |v| v[0].atan2(v[1]));
+                self.insert_builtin_fn(b"atan2xy", 2, |v| v[1].atan2(v[0]));
+            }
+            BuiltinGroup::Logs => {
+                self.insert_builtin_fn(b"ln", 1, |v| v[0].ln());
+                self.insert_builtin_fn(b"log", 1, |v| v[0].log10());
+                self.overload_builtin_fn(b"log", 2, |v| v[0].ln() / v[1].ln());
+            }
+            BuiltinGroup::Numbers => {
+                self.insert_real_generic_fn(b"factor", 1, number::factor);
+                self.insert_real_generic_fn(b"nextprime", 1, number::nextprime);
+                self.insert_real_generic_fn(b"totient", 1, number::totient);
+                self.insert_real_generic_fn(b"contfrac", 1, number::contfrac);
+                self.insert_real_generic_fn(b"tobase", 2, number::tobase);
+                self.insert_real_generic_fn(b"frombase", 2, number::frombase);
+                self.insert_real_generic_fn(b"band", 2, number::band);
+                self.insert_real_generic_fn(b"bor", 2, number::bor);
+                self.insert_real_generic_fn(b"bxor", 2, number::bxor);
+                self.insert_real_generic_fn(b"bnot", 1, number::bnot);
+                self.insert_real_generic_fn(b"shl", 2, number::shl);
+                self.insert_real_generic_fn(b"shr", 2, number::shr);
+            }
+            BuiltinGroup::Vectors => {
+                self.insert_real_generic_fn(b"interp_linear", 3, interpolate::linear);
+                self.insert_real_generic_fn(b"interp_spline", 3, interpolate::spline);
+                self.insert_real_generic_fn(b"range", 3, sequence::range);
+                self.insert_real_generic_fn(b"linspace", 3, sequence::linspace);
+            }
+            BuiltinGroup::Stats => {
+                self.insert_sums();
+                self.insert_real_generic_fn(b"median", 1, stats::median);
+                self.insert_real_generic_fn(b"quantile", 2, stats::quantile);
+                self.insert_real_generic_fn(b"corr", 2, stats::corr);
+            }
+            BuiltinGroup::Distributions => {
+                self.insert_real_fn(b"normpdf", 3, dist::normpdf);
+                self.insert_real_fn(b"normcdf", 3, dist::normcdf);
+                self.insert_real_fn(b"norminv", 3, dist::norminv);
+                self.insert_real_fn(b"binompdf", 3, dist::binompdf);
+                self.insert_real_fn(b"binomcdf", 3, dist::binomcdf);
+                self.insert_real_fn(b"poissonpdf", 2, dist::poissonpdf);
+                self.insert_real_fn(b"tcdf", 2, dist::tcdf);
+            }
+            BuiltinGroup::Finance => {
+                #[cfg(feature = "finance")]
+                {
+                    self.insert_real_fn(b"pmt", 4, finance::pmt);
+                    self.insert_real_fn(b"fv", 4, finance::fv);
+                    self.insert_real_fn(b"pv", 4, finance::pv);
+                    self.insert_real_generic_fn(b"npv", 2, finance::npv);
+                    self.insert_real_generic_fn(b"irr", 1, finance::irr);
+                }
+            }
+            BuiltinGroup::Fft => {
+                #[cfg(feature = "fft")]
+                {
+                    self.insert_real_generic_fn(b"fft", 1, fft::fft);
+                    self.insert_real_generic_fn(b"ifft", 1, fft::ifft);
+                }
+            }
+        }
+    }
+
+    fn insert_builtin_value(&mut self, ident: &[u8], value: T) {
+        self.values
+            .insert(ident.to_vec(), (true, Value::Num(value), None));
+        self.names.insert(ident.to_vec());
+    }
+
+    fn insert_builtin_fn(&mut self, ident: &[u8], incount: usize, f: fn(&[T]) -> T) {
+        self.functions.insert(
+            ident.to_vec(),
+            Function::builtin(ident, incount, FunctionImpl::Lib(f)),
+        );
+        self.names.insert(ident.to_vec());
+    }
+
+    /// Add a builtin of the libraries, computing in `Real`.
+    fn insert_real_fn(&mut self, ident: &[u8], incount: usize, f: fn(&[Real]) -> Real) {
+        self.functions.insert(
+            ident.to_vec(),
+            Function::builtin(ident, incount, FunctionImpl::RealLib(f)),
+        );
+        self.names.insert(ident.to_vec());
+    }
+
+    /// Add a form of the builtin `ident` taking another number of arguments.
+    fn overload_builtin_fn(&mut self, ident: &[u8], incount: usize, f: fn(&[T]) -> T) {
+        let overload = Function::builtin(ident, incount, FunctionImpl::Lib(f));
+        let function = self.functions.get_mut(ident).unwrap();
+        Arc::get_mut(function).unwrap().overloads.push(overload);
+    }
+
+    /// Bind the aggregates in `SUMS` to their compensated or fast forms.
+    fn insert_sums(&mut self) {
+        if !self.groups.contains(&BuiltinGroup::Stats) {
+            return;
+        }
+        for &(ident, accurate, fast) in SUMS {
+            let f = match self.compensated {
+                true => accurate,
+                false => fast,
+            };
+            self.insert_real_generic_fn(ident, 1, f);
+        }
+    }
+
+    /// Bind `atan2` to `atan2yx` or `atan2xy` following the convention.
+    fn insert_atan2(&mut self) {
+        if !self.groups.contains(&BuiltinGroup::Trig) {
+            return;
+        }
+        match self.atan2_order {
+            Atan2Order::YFirst => self.insert_builtin_fn(b"atan2", 2, |v| v[0].atan2(v[1])),
+            Atan2Order::XFirst => self.insert_builtin_fn(b"atan2", 2, |v| v[1].atan2(v[0])),
+        }
+    }
+
+    fn lexer<'a>(&'a self, line: &'a [u8]) -> Lexer<'a> {
+        let line_number = match self.parser {
+            Some(_) => self.cur_source.len() + 1,
+            None => 1,
+        };
+        Lexer::with_operators(line, &self.operators)
+            .line_number(line_number)
+            .decimal_comma(self.decimal_comma)
+            .si_suffixes(self.si_suffixes)
+    }
+
+    fn insert_lazy_fn(
+        &mut self,
+        ident: &[u8],
+        incount: usize,
+        f: fn(&mut LazyArgs<T>) -> Result<Value<T>, EvalError>,
+    ) {
+        self.functions.insert(
+            ident.to_vec(),
+            Function::builtin(ident, incount, FunctionImpl::Lazy(f)),
+        );
+        self.names.insert(ident.to_vec());
+    }
+
+    fn insert_generic_fn(&mut self, ident: &[u8], incount: usize, f: GenericFn<T>) {
+        self.functions.insert(
+            ident.to_vec(),
+            Function::builtin(ident, incount, FunctionImpl::Generic(f)),
+        );
+        self.names.insert(ident.to_vec());
+    }
+
+    /// Add a builtin of the libraries taking and returning values of `Real`.
+    fn insert_real_generic_fn(&mut self, ident: &[u8], incount: usize, f: GenericFn) {
+        self.functions.insert(
+            ident.to_vec(),
+            Function::builtin(ident, incount, FunctionImpl::RealGeneric(f)),
+        );
+        self.names.insert(ident.to_vec());
+    }
+
+    /// Input a line, ending at its first NUL byte or at the end of the slice.
+    pub fn input(&mut self, line: &[u8]) -> Result<InputState<T>, InputError> {
+        let text = line.split(|&c| c == b'\0').next().unwrap_or_default();
+        let text = String::from_utf8_lossy(text).into_owned();
+        let state = self.input_statement(line);
+        match &state {
+            Ok(InputState::Empty) => (),
+            Ok(InputState::Incomplete) => self.pending.push(text),
+            Ok(_) => {
+                self.pending.push(text);
+                self.history.push(self.pending.join("\n"));
+                self.pending.clear();
+            }
+            Err(_) => self.pending.clear(),
+        }
+        state
+    }
+
+    /// Bind the builtins depending on settings and the registered constants.
+    fn insert_settings(&mut self) {
+        self.insert_sums();
+        self.insert_atan2();
+        self.insert_results();
+        self.insert_booleans();
+        for (ident, (value, _)) in &self.constants {
+            self.values
+                .insert(ident.clone(), (true, Value::Num(*value), None));
+        }
+        for (ident, target) in &self.aliases {
+            if let Some(value) = self.values.get(target).cloned() {
+                self.values.insert(ident.clone(), value);
+            }
+            if let Some(f) = self.functions.get(target).cloned() {
+                self.functions.insert(ident.clone(), f);
+            }
+        }
+        self.names = self
+            .values
+            .keys()
+            .chain(self.functions.keys())
+            .cloned()
+            .collect();
+    }
+
+    /// Bind the names of results still unbound to 0.
+    fn insert_results(&mut self) {
+        let earlier = EARLIER_RESULTS.iter().map(|ident| ident.to_vec());
+        for ident in self.result_names.clone().into_iter().chain(earlier) {
+            self.names.insert(ident.clone());
+            self.values
+                .entry(ident)
+                .or_insert((false, Value::Int(0), None));
+        }
+    }
+
+    /// Bind `true` and `false` to booleans, or to `1` and `0` with numeric comparisons.
+    fn insert_booleans(&mut self) {
+        for (ident, b) in [(&b"true"[..], true), (&b"false"[..], false)] {
+            let value = match self.numeric_comparisons {
+                true => Value::Num(T::from_i64(b as i64)),
+                false => Value::Bool(b),
+            };
+            self.values.insert(ident.to_vec(), (true, value, None));
+            self.names.insert(ident.to_vec());
+        }
+    }
+
+    /// Whether `ident` is bound to one of the last results.
+    fn is_result(&self, ident: &[u8]) -> bool {
+        self.result_names.iter().any(|name| name == ident) || EARLIER_RESULTS.contains(&ident)
+    }
+
+    /// Input a line, discarding the statement state once it is finished or failed.
+    fn input_statement(&mut self, line: &[u8]) -> Result<InputState<T>, InputError> {
+        self.changes.clear();
+        self.trace.clear();
+        let state = self.read_statement(line);
+        if let Ok(
+            InputState::Assignment { .. }
+            | InputState::Expression { .. }
+            | InputState::Workspace { .. },
+        ) = &state
+        {
+            let statement = match self.cur_source.is_empty() {
+                true => {
+                    String::from_utf8_lossy(line.split(|&c| c == b'\0').next().unwrap_or_default())
+                        .into_owned()
+                }
+                false => self.cur_source.join("\n"),
+            };
+            self.audit(Some(statement));
+        }
+        match &state {
+            Ok(InputState::Incomplete) => (),
+            Ok(_) => {
+                self.cur_ident.clear();
+                self.cur_variables.clear();
+                self.cur_doc = None;
+                self.cur_operator = None;
+                self.cur_source.clear();
+            }
+            Err(_) => self.discard_statement(),
+        }
+        state
+    }
+
+    /// Discard an unfinished multi-line statement.
+    pub fn cancel(&mut self) {
+        self.discard_statement();
+        self.pending.clear();
+    }
+
+    fn discard_statement(&mut self) {
+        if let Some(Binding::Operator(spelling, tier)) = self.cur_operator.take() {
+            self.set_operator(spelling, tier);
+        }
+        self.parser = None;
+        self.cur_blocks = 0;
+        self.cur_locals.clear();
+        self.cur_ident.clear();
+        self.cur_variables.clear();
+        self.cur_doc = None;
+        self.cur_source.clear();
+        self.warnings.clear();
+    }
+
+    fn read_statement(&mut self, line: &[u8]) -> Result<InputState<T>, InputError> {
+        if self.parser.is_none() && self.cache.capacity() > 0 && self.resolver.is_none() {
+            if let Some(state) = self.read_cached(line) {
+                return state;
+            }
+        }
+        let operator = match self.parser {
+            Some(_) => None,
+            None => self.read_operator(line)?,
+        };
+        let ts = match operator {
+            Some(ts) => ts,
+            None => {
+                span!("lex", columns = line.len());
+                self.lexer(line).tokenize()?
+            }
+        };
+        let mut parser = match self.parser.take() {
+            Some(parser) => parser,
+            None => {
+                if ts.tokens.is_empty() {
+                    return Ok(InputState::Empty);
+                }
+                // workspace name
+                if let [(_, Token::IDENT(keyword)), (_, Token::IDENT(name))] = ts.tokens.as_slice()
+                {
+                    if keyword == b"workspace" {
+                        let name = String::from_utf8_lossy(name).into_owned();
+                        self.switch_workspace(&name);
+                        return Ok(InputState::Workspace { name });
+                    }
+                }
+                self.cur_doc = None;
+                self.cur_source.clear();
+                Parser::new()
+            }
+        };
+        let text = line.split(|&c| c == b'\0').next().unwrap_or_default();
+        self.cur_source
+            .push(String::from_utf8_lossy(text).into_owned());
+        // comments in blocks are about their lines
+        if let (Some(span), 0) = (&ts.comment, self.cur_blocks) {
+            let doc = String::from_utf8_lossy(&line[span.start + 1..span.end]);
+            self.cur_doc = Some(doc.trim().to_string());
+        }
+        let ast = {
+            span!("parse", tokens = ts.tokens.len());
+            let number = self.cur_source.len();
+            if !feed_line(&mut parser, ts, &mut self.cur_blocks, number, line.len())? {
+                self.parser.replace(parser);
+                return Ok(InputState::Incomplete);
+            }
+            parser.accept()
+        };
+        match ast {
+            Ok(ast) => self.translate_ast(ast),
+            Err(errors) => {
+                let end = (self.cur_source.len(), line.len());
+                Err(syntax_error(errors.into_iter().next(), end))
+            }
+        }
+    }
+
+    /// Evaluate the cached translation of the statement `line`, if its names are still bound the
+    /// same and it would be translated with the same settings.
+    fn read_cached(&mut self, line: &[u8]) -> Option<Result<InputState<T>, InputError>> {
+        let text = line.split(|&c| c == b'\0').next().unwrap_or_default();
+        let text = String::from_utf8_lossy(text).into_owned();
+        let settings = self.translation_settings();
+        let (values, functions) = (&self.values, &self.functions);
+        let cached = self.cache.get(&text, |cached| {
+            cached.settings == settings
+                && cached.bindings.iter().all(|binding| match binding {
+                    Binding::Value(ident, value) => values.get(ident) == value.as_ref(),
+                    Binding::Function(ident, f) => match (functions.get(ident), f) {
+                        (Some(f1), Some(f2)) => Arc::ptr_eq(f1, f2),
+                        (f1, f2) => f1.is_none() && f2.is_none(),
+                    },
+                    Binding::Operator(..) => false,
+                })
+        })?;
+        let (statement, warnings) = (cached.statement.clone(), cached.warnings.clone());
+        self.cur_terminated = cached.terminated;
+        Some(
+            self.evaluate(&statement)
+                .map(|value| self.expression_result(value, warnings))
+                .map_err(InputError::from),
+        )
+    }
+
+    fn translation_settings(&self) -> (bool, Real, bool, bool, usize, bool) {
+        (
+            self.folding(),
+            self.epsilon,
+            self.decimal_comma,
+            self.si_suffixes,
+            self.operators.len(),
+            self.numeric_comparisons,
+        )
+    }
+
+    /// Keep the translations of up to `capacity` distinct single-line expression statements,
+    /// reused while the names they use are bound the same; `0`, the default, disables the cache.
+    pub fn set_parse_cache(&mut self, capacity: usize) {
+        self.cache = Lru::new(capacity);
+    }
+
+    /// Hits, misses and size of the parse cache.
+    pub fn parse_cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Read an `operator ⊕ like + (a, b) = expression` definition as the function definition
+    /// `⊕: a, b = expression`, registering the spelling of the operator at the tier of the
+    /// operator after `like`.
+    fn read_operator(&mut self, line: &[u8]) -> Result<Option<TokenStream>, InputError> {
+        let skip = |i: usize| {
+            i + line[i..]
+                .iter()
+                .take_while(|c| c.is_ascii_whitespace())
+                .count()
+        };
+        let byte = |i: usize| line.get(i).copied().unwrap_or(b'\0');
+        let keyword = skip(0) + b"operator".len();
+        let begin = skip(keyword.min(line.len()));
+        if !line[..keyword.min(line.len())].ends_with(b"operator")
+            || begin == keyword
+            || !is_operator_start(byte(begin))
+        {
+            return Ok(None);
+        }
+        let mut end = begin;
+        while !(byte(end).is_ascii_whitespace() || b"\0#(".contains(&byte(end))) {
+            end += 1;
+        }
+        let spelling = line[begin..end].to_vec();
+        // like TIER
+        let (ts, _) = self.lexer(line).starting_at(end).tokenize_partial();
+        let (tier, column) = match ts.tokens.as_slice() {
+            [(_, Token::IDENT(like)), (span, token), ..] if like == b"like" => {
+                (token.id(), span.start)
+            }
+            [(span, _), ..] => (0, span.start),
+            [] => (0, line.len()),
+        };
+        if !(6..=11).contains(&tier) {
+            return Err(InputError::SyntaxError {
+                line: 1,
+                column,
+                expected: Token::NAMES[6..=11].to_vec(),
+            });
+        }
+        let old = self.set_operator(spelling.clone(), Some(tier));
+        self.cur_operator = Some(Binding::Operator(spelling.clone(), old));
+        // (a, b) = expression
+        let mut ts = self
+            .lexer(line)
+            .starting_at(ts.tokens[1].0.end)
+            .tokenize()?;
+        // token ids of '(' IDENT ',' IDENT ')'
+        for (i, &id) in [3, 0, 14, 0, 4].iter().enumerate() {
+            let expected = vec![Token::NAMES[id as usize]];
+            match ts.tokens.get(i) {
+                Some((_, found)) if found.id() == id => (),
+                Some((span, _)) => {
+                    return Err(InputError::SyntaxError {
+                        line: 1,
+                        column: span.start,
+                        expected,
+                    })
+                }
+                None => {
+                    return Err(InputError::SyntaxError {
+                        line: 1,
+                        column: line.len(),
+                        expected,
+                    })
+                }
+            }
+        }
+        ts.tokens.remove(4);
+        ts.tokens[0].1 = Token::COLON;
+        ts.tokens.insert(0, (begin..end, Token::IDENT(spelling)));
+        Ok(Some(ts))
+    }
+
+    /// Evaluate an expression without binding the results or changing any other state.
+    pub fn eval_pure(&mut self, expression: &str) -> Result<Value<T>, InputError> {
+        let expr = self.translate_pure(expression)?;
+        Ok(evaluate(expr, &mut Context::default())?)
+    }
+
+    /// Evaluate an expression like [`Interpreter::eval_pure`], without folding constants, so every
+    /// operation runs on the translated expression.
+    pub fn eval_unfolded(&mut self, expression: &str) -> Result<Value<T>, InputError> {
+        self.fold = false;
+        let expr = self.translate_pure(expression);
+        self.fold = true;
+        Ok(evaluate(expr?, &mut Context::default())?)
+    }
+
+    /// Evaluate an expression repeatedly for about half a second, without folding constants.
+    pub fn time(&mut self, expression: &str) -> Result<Timing, InputError> {
+        self.fold = false;
+        let expr = self.translate_pure(expression);
+        self.fold = true;
+        let f = Function::anonymous(expr?);
+        let (mut runs, mut total, mut min) = (0, Duration::ZERO, Duration::MAX);
+        while runs < BENCH_RUNS && total < BENCH_TIME {
+            let start = Instant::now();
+            f.invoke(&[], &mut Context::default())?;
+            let time = start.elapsed();
+            runs += 1;
+            total += time;
+            min = min.min(time);
+        }
+        Ok(Timing {
+            runs,
+            min,
+            mean: total / runs as u32,
+        })
+    }
+
+    /// Translate an expression statement without assigning it.
+    fn translate_pure(&mut self, expression: &str) -> Result<Body<T>, InputError> {
+        self.cur_arena = Arena::default();
+        let ts = self.lexer(expression.as_bytes()).tokenize()?;
+        let mut parser = Parser::new();
+        let mut assign = 0;
+        for (i, (span, token)) in ts.tokens.into_iter().enumerate() {
+            if i == 1 {
+                assign = span.start;
+            }
+            if let Err(e) = parser.action(token, (1, span)) {
+                return Err(syntax_error(Some(e), (1, expression.len())));
+            }
+        }
+        let end = (1, expression.len());
+        let ast = match parser.accept() {
+            Ok(ast) if ts.complete => ast,
+            Ok(_) => return Err(syntax_error(None, end)),
+            Err(errors) => return Err(syntax_error(errors.into_iter().next(), end)),
+        };
+        match ast {
+            // statement: expression
+            ASTNode::Inner(2, mut children) => {
+                let result = self.translate_expression(children.pop().unwrap());
+                self.warnings.clear();
+                result.map(|root| self.take_body(root))
+            }
+            // an assignment would change a binding
+            _ => Err(InputError::SyntaxError {
+                line: 1,
+                column: assign,
+                expected: vec![],
+            }),
+        }
+    }
+
+    /// Split `line` into classified tokens for syntax highlighting, marking invalid input as errors.
+    pub fn tokenize(&self, line: &str) -> Vec<SpannedToken> {
+        let bytes = line.as_bytes();
+        let mut spanned = vec![];
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let (ts, err) = self.lexer(&bytes[offset..]).tokenize_partial();
+            let mut end = offset;
+            for (span, token) in ts.tokens {
+                let kind = match token {
+                    Token::IDENT(ident) if self.is_builtin(&ident) => TokenKind::Builtin,
+                    Token::IDENT(_) => TokenKind::Identifier,
+                    Token::NUM(_) => TokenKind::Number,
+                    _ => TokenKind::Operator,
+                };
+                end = offset + span.end;
+                spanned.push(SpannedToken {
+                    kind,
+                    span: offset + span.start..end,
+                });
+            }
+            match err {
+                Some(e) => {
+                    // cover the partially read token up to the offending character
+                    let mut stop = (offset + e.column() + 1).min(bytes.len());
+                    while stop < bytes.len() && bytes[stop] & 0xC0 == 0x80 {
+                        stop += 1;
+                    }
+                    let text = &line.as_bytes()[end..stop];
+                    let start = end + text.iter().take_while(|c| c.is_ascii_whitespace()).count();
+                    let trailing = text.iter().rev().take_while(|c| c.is_ascii_whitespace());
+                    let stop = stop - trailing.count();
+                    spanned.push(SpannedToken {
+                        kind: TokenKind::Error,
+                        span: start..stop,
+                    });
+                    offset = stop;
+                }
+                None => {
+                    if let Some(span) = ts.comment {
+                        spanned.push(SpannedToken {
+                            kind: TokenKind::Comment,
+                            span: span.start + offset..span.end + offset,
+                        });
+                    }
+                    if !ts.complete {
+                        let start = end + line[end..].find("...").unwrap_or(0);
+                        spanned.push(SpannedToken {
+                            kind: TokenKind::Operator,
+                            span: start..start + 3,
+                        });
+                    }
+                    break;
+                }
+            }
+        }
+        spanned
+    }
+
+    /// Suggest identifiers starting with the identifier prefix before the byte offset `cursor`.
+    pub fn complete(&self, line: &str, cursor: usize) -> Vec<Completion> {
+        let head = &line.as_bytes()[..cursor.min(line.len())];
+        let len = head
+            .iter()
+            .rev()
+            .take_while(|c| c.is_ascii_alphanumeric() || **c == b'_')
+            .count();
+        let prefix = &head[head.len() - len..];
+        if prefix.first().is_some_and(u8::is_ascii_digit) {
+            return vec![];
+        }
+        self.find_bytes(prefix)
+            .map(|info| Completion {
+                text: info.name,
+                kind: info.kind,
+                arity: info.arity,
+            })
+            .collect()
+    }
+
+    /// Variables and functions whose names start with `prefix`, sorted by name.
+    pub fn find(&self, prefix: &str) -> Vec<IdentInfo> {
+        self.find_bytes(prefix.as_bytes()).collect()
+    }
+
+    fn find_bytes<'a>(&'a self, prefix: &'a [u8]) -> impl Iterator<Item = IdentInfo> + 'a {
+        self.names
+            .range::<[u8], _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(move |ident| ident.starts_with(prefix))
+            .flat_map(move |ident| {
+                let name = String::from_utf8_lossy(ident).into_owned();
+                let value = self.values.get(ident).map(|(builtin, _, _)| IdentInfo {
+                    name: name.clone(),
+                    kind: match builtin {
+                        true => CompletionKind::Builtin,
+                        false => CompletionKind::Variable,
+                    },
+                    arity: None,
+                });
+                let function = self.functions.get(ident).map(|f| IdentInfo {
+                    name,
+                    kind: match f.fimpl {
+                        FunctionImpl::User(_) => CompletionKind::Function,
+                        _ => CompletionKind::Builtin,
+                    },
+                    arity: Some(f.incount),
+                });
+                value.into_iter().chain(function)
+            })
+    }
+
+    /// The statement defining the user function `name`, or else the user variable `name`.
+    pub fn definition_of(&self, name: &str) -> Option<Definition> {
+        let ident = name.as_bytes();
+        let function = self.functions.get(ident).and_then(|f| f.source.as_ref());
+        let value = self
+            .values
+            .get(ident)
+            .and_then(|(_, _, source)| source.as_ref());
+        function.or(value).map(|source| Definition {
+            source: source.text.clone(),
+            defined: source.defined,
+        })
+    }
+
+    /// The names used by the definition of the user function or variable `expr_or_name`, or by
+    /// the statement `expr_or_name`, apart from builtins and the indices and accumulators bound by
+    /// `fold`.
+    pub fn free_variables(&self, expr_or_name: &str) -> Result<FreeVariables, InputError> {
+        let source = match self.definition_of(expr_or_name) {
+            Some(definition) => definition.source,
+            None => expr_or_name.to_string(),
+        };
+        let mut parameters = vec![];
+        // the function being defined, calling itself
+        let mut function = None;
+        let mut found = vec![];
+        let operator = self.functions.get(expr_or_name.as_bytes());
+        match operator.filter(|_| source.trim_start().starts_with("operator")) {
+            // operator ⊕ like + (a, b) = expression
+            Some(f) => {
+                let body = source.split_once('=').map_or("", |(_, body)| body);
+                parameters = f.variables.clone();
+                function = Some(f.name.clone());
+                let ast = self.parse_text(body)?;
+                self.referenced(&ast, &mut parameters.clone(), &mut found);
+            }
+            None => match unterminated(self.parse_text(&source)?).0 {
+                // statement: assignment
+                ASTNode::Inner(1, mut children) => match children.pop().unwrap() {
+                    // assignment: IDENT ':' variable_list '=' expression
+                    ASTNode::Inner(4, mut children) => {
+                        let expr_ast = children.pop().unwrap();
+                        children.pop();
+                        parameters = self.translate_variable_list(children.pop().unwrap())?;
+                        children.pop();
+                        function = Some(children.pop().unwrap().assume_leaf()?.assume_ident()?);
+                        self.referenced(&expr_ast, &mut parameters.clone(), &mut found);
+                    }
+                    // assignment: IDENT '=' expression
+                    ASTNode::Inner(3, children) => {
+                        self.referenced(&children[2], &mut vec![], &mut found)
+                    }
+                    _ => {
+                        return Err(InputError::InternalError {
+                            message: "unknown assignment node",
+                        })
+                    }
+                },
+                ast => self.referenced(&ast, &mut vec![], &mut found),
+            },
+        }
+        let name = |ident: &Ident| String::from_utf8_lossy(ident).into_owned();
+        let mut free = FreeVariables {
+            parameters: parameters.iter().map(name).collect(),
+            ..FreeVariables::default()
+        };
+        let mut seen = HashSet::new();
+        for (ident, role) in &found {
+            if function.as_ref() == Some(ident) || self.is_builtin(ident) || !seen.insert(ident) {
+                continue;
+            }
+            match self.values.contains_key(ident) || self.functions.contains_key(ident) {
+                true => free.environment.push(name(ident)),
+                false => {
+                    free.missing.push(name(ident));
+                    free.inputs.push(RequiredInput {
+                        name: name(ident),
+                        role: *role,
+                    });
+                }
+            }
+        }
+        Ok(free)
+    }
+
+    /// A Graphviz digraph of the user variables (ellipses) and functions (boxes), with an edge from
+    /// each definition to those it refers to.
+    pub fn dependency_graph_dot(&self) -> String {
+        let quote = |name: &str| format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""));
+        let names = self
+            .names
+            .iter()
+            .filter(|ident| !self.is_result(ident) && !self.is_builtin(ident))
+            .map(|ident| String::from_utf8_lossy(ident).into_owned())
+            .collect::<Vec<_>>();
+        let mut dot = String::from("digraph dependencies {\n");
+        for name in &names {
+            let shape = match self.functions.contains_key(name.as_bytes()) {
+                true => "box",
+                false => "ellipse",
+            };
+            dot += &format!("    {} [shape={}];\n", quote(name), shape);
+        }
+        for name in &names {
+            let environment = match self.definition_of(name) {
+                Some(_) => self
+                    .free_variables(name)
+                    .map_or(vec![], |free| free.environment),
+                None => vec![],
+            };
+            for dependency in environment {
+                dot += &format!("    {} -> {};\n", quote(name), quote(&dependency));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The syntax errors of the statement `text`, of one or more lines: all of them with the
+    /// `pratt` feature, which resumes parsing after an error in an argument, a parenthesized
+    /// expression or a local, only the first one otherwise.
+    pub fn syntax_errors(&self, text: &str) -> Vec<InputError> {
+        match self.parse_statement(text) {
+            Ok(_) => vec![],
+            Err(errors) => errors,
+        }
+    }
+
+    /// Parse a statement of one or more lines without translating it.
+    fn parse_text(&self, text: &str) -> Result<ASTNode, InputError> {
+        self.parse_statement(text)
+            .map_err(|errors| errors.into_iter().next().unwrap())
+    }
+
+    fn parse_statement(&self, text: &str) -> Result<ASTNode, Vec<InputError>> {
+        let mut parser = Parser::new();
+        let mut blocks = 0;
+        let lines = text.split('\n').collect::<Vec<_>>();
+        for (i, line) in lines.iter().enumerate() {
+            let ts = self.lexer(line.as_bytes()).line_number(i + 1).tokenize();
+            let ts = ts.map_err(|e| vec![e.into()])?;
+            if feed_line(&mut parser, ts, &mut blocks, i + 1, line.len()).map_err(|e| vec![e])? {
+                break;
+            }
+        }
+        let end = (lines.len(), lines.last().map_or(0, |line| line.len()));
+        parser.accept().map_err(|errors| match errors.is_empty() {
+            true => vec![syntax_error(None, end)],
+            false => errors
+                .into_iter()
+                .map(|e| syntax_error(Some(e), end))
+                .collect(),
+        })
+    }
+
+    /// Append the names `ast` refers to that aren't `bound`, with the role of each use.
+    fn referenced(
+        &self,
+        ast: &ASTNode,
+        bound: &mut Vec<Ident>,
+        found: &mut Vec<(Ident, InputRole)>,
+    ) {
+        match ast {
+            // expression: IDENT '(' parameter_list ')'
+            ASTNode::Inner(17, children) => {
+                let (f, params) = match children.as_slice() {
+                    [ASTNode::Leaf(Token::IDENT(f), _), _, params, _] => (f, params),
+                    _ => return,
+                };
+                let mut args = vec![];
+                parameter_refs(params, &mut args);
+                // expression: IDENT
+                if let (b"fold", [ASTNode::Inner(18, index), lo, hi, init, body]) =
+                    (f.as_slice(), args.as_slice())
+                {
+                    if let [ASTNode::Leaf(Token::IDENT(index), _)] = index.as_slice() {
+                        for arg in [lo, hi, init] {
+                            self.referenced(arg, bound, found);
+                        }
+                        bound.push(index.clone());
+                        bound.push(b"acc".to_vec());
+                        self.referenced(body, bound, found);
+                        bound.truncate(bound.len() - 2);
+                        return;
+                    }
+                }
+                let arity = args.len();
+                found.push((f.clone(), InputRole::Function { arity }));
+                // the functions named by the leading arguments of `map` and the like
+                let (functions, arity) = match Higher::named(f) {
+                    Some(higher) => {
+                        let (_, _, functions, arity) = higher.signature(args.len());
+                        (functions, arity)
+                    }
+                    None => (0, 0),
+                };
+                for (i, arg) in args.into_iter().enumerate() {
+                    match arg {
+                        // expression: IDENT
+                        ASTNode::Inner(18, children) if i < functions => {
+                            if let [ASTNode::Leaf(Token::IDENT(ident), _)] = children.as_slice() {
+                                found.push((ident.clone(), InputRole::Function { arity }));
+                            }
+                        }
+                        arg => self.referenced(arg, bound, found),
+                    }
+                }
+            }
+            // expression: IDENT
+            ASTNode::Inner(18, children) => {
+                if let [ASTNode::Leaf(Token::IDENT(ident), _)] = children.as_slice() {
+                    if !bound.contains(ident) {
+                        found.push((ident.clone(), InputRole::Value));
+                    }
+                }
+            }
+            // expression: '{' locals expression '}', optionally with a ';' before the '}'
+            ASTNode::Inner(22..=23, children) => {
+                let scope = bound.len();
+                for (ident, expr) in block_locals(&children[1]) {
+                    self.referenced(expr, bound, found);
+                    bound.push(ident.clone());
+                }
+                self.referenced(&children[2], bound, found);
+                bound.truncate(scope);
+            }
+            ASTNode::Leaf(Token::OP(_, spelling), _) => {
+                // the builtin operators stand for `range` and `approx`
+                let ident = match spelling.as_slice() {
+                    b".." => b"range".to_vec(),
+                    b"~=" => b"approx".to_vec(),
+                    _ => spelling.clone(),
+                };
+                found.push((ident, InputRole::Function { arity: 2 }))
+            }
+            ASTNode::Leaf(..) => (),
+            ASTNode::Inner(_, children) => {
+                for child in children {
+                    self.referenced(child, bound, found);
+                }
+            }
+        }
+    }
 
-use crate::{
-    lexer::{AddSubOp, CompareOp, Ident, Lexer, MulDivOp},
-    parser::{ASTNode, Parser},
-    InvalidToken, Real,
-};
+    /// Signature and description of a builtin, user function or variable.
+    pub fn help(&self, name: &str) -> Option<HelpEntry> {
+        let ident = name.as_bytes().to_vec();
+        if let Some(target) = self.aliases.get(&ident) {
+            let target = String::from_utf8_lossy(target);
+            let entry = self.help(&target)?;
+            return Some(HelpEntry {
+                name: name.to_string(),
+                signature: entry.signature.replacen(&*target, name, 1),
+                description: format!("{}, alias of {}", entry.description, target),
+            });
+        }
+        if let Some((_, doc)) = self.constants.get(&ident) {
+            return Some(HelpEntry {
+                name: name.to_string(),
+                signature: name.to_string(),
+                description: doc.clone(),
+            });
+        }
+        if self.is_builtin(&ident) {
+            return help::builtin(name);
+        }
+        if let Some(f) = self.functions.get(&ident) {
+            let variables = f
+                .variables
+                .iter()
+                .map(|v| String::from_utf8_lossy(v))
+                .collect::<Vec<_>>();
+            return Some(HelpEntry {
+                name: name.to_string(),
+                signature: format!("{}({})", name, variables.join(", ")),
+                description: match &f.doc {
+                    Some(doc) => doc.clone(),
+                    None => "user-defined function".to_string(),
+                },
+            });
+        }
+        self.values.get(&ident).map(|(_, val, _)| HelpEntry {
+            name: name.to_string(),
+            signature: name.to_string(),
+            description: format!("variable, {}", val),
+        })
+    }
 
-struct Function {
-    incount: usize,
-    fimpl: FunctionImpl,
-}
+    pub fn last_result(&self) -> &Value<T> {
+        &self.values[&self.result_names[0]].1
+    }
 
-enum FunctionImpl {
-    Lib(fn(&[Real]) -> Real),
-    User(ExprOrNum),
-}
+    /// Names bound to the result of the last expression statement, `_` by default.
+    pub fn result_names(&self) -> Vec<&str> {
+        self.result_names
+            .iter()
+            .map(|ident| std::str::from_utf8(ident).unwrap())
+            .collect()
+    }
 
-enum ExprOrNum {
-    Expr(Box<Expression>),
-    Num(Real),
-}
+    /// Bind the results of the following expression statements to `names`, like `ans`, instead of
+    /// `_`, which an empty list restores; `__` and `___` stay the two results before.
+    pub fn set_result_names(&mut self, names: &[&str]) -> Result<(), InputError> {
+        let mut idents = names
+            .iter()
+            .map(|name| name.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        if let Some(ident) = idents
+            .iter()
+            .find(|ident| self.is_builtin(ident) || EARLIER_RESULTS.contains(&ident.as_slice()))
+        {
+            let ident = ident.clone();
+            return Err(InputError::BuiltinIdentifier {
+                ident,
+                position: None,
+            });
+        }
+        if idents.is_empty() {
+            idents.push(b"_".to_vec());
+        }
+        let last = self.last_result().clone();
+        for ident in std::mem::replace(&mut self.result_names, idents) {
+            self.values.remove(&ident);
+            self.names.remove(&ident);
+        }
+        for ident in self.result_names.clone() {
+            self.values
+                .insert(ident.clone(), (false, last.clone(), None));
+            self.names.insert(ident);
+        }
+        Ok(())
+    }
 
-impl ExprOrNum {
-    fn assume_num(self) -> Real {
-        match self {
-            ExprOrNum::Num(real) => real,
-            _ => panic!("Can't unwrap a number"),
+    /// The last result as decimal, scientific, hexadecimal, binary and fraction where they apply.
+    pub fn last_result_views(&self) -> ResultViews {
+        let value = self.last_result();
+        let integer = match value {
+            Value::Int(i) => Some(*i),
+            Value::Num(r) if r.fract() == T::ZERO && r.abs() <= T::from_f64(number::MAX_EXACT) => {
+                Some(r.to_f64() as i64)
+            }
+            _ => None,
+        };
+        let sign = |i: i64| if i < 0 { "-" } else { "" };
+        ResultViews {
+            decimal: value.to_string(),
+            scientific: match value {
+                Value::Int(i) => Some(format!("{:e}", i)),
+                Value::Num(r) => Some(format!("{:e}", r)),
+                _ => None,
+            },
+            hex: integer.map(|i| format!("{}0x{:x}", sign(i), i.unsigned_abs())),
+            binary: integer.map(|i| format!("{}0b{:b}", sign(i), i.unsigned_abs())),
+            fraction: match value {
+                Value::Num(r) => number::fraction(r.to_f64()),
+                _ => None,
+            },
         }
     }
-}
 
-enum Expression {
-    Not(Box<Expression>),
-    Neg(Box<Expression>),
-    Exp(ExprOrNum, ExprOrNum),
-    Mul(ExprOrNum, ExprOrNum),
-    Div(ExprOrNum, ExprOrNum),
-    Add(ExprOrNum, ExprOrNum),
-    Sub(ExprOrNum, ExprOrNum),
-    Compare(CompareOp, ExprOrNum, ExprOrNum),
-    Or(ExprOrNum, ExprOrNum),
-    And(ExprOrNum, ExprOrNum),
-    Condition(Box<Expression>, ExprOrNum, ExprOrNum),
-    Invoke(Option<Arc<Function>>, Vec<ExprOrNum>),
-    Variable(usize),
-}
+    /// `value` as shown to the user, numbers rounded to the digits set by `:precision`
+    /// and followed by a simple fraction they equal with `:fraction on`.
+    pub fn display(&self, value: &Value<T>) -> String {
+        let text = match (self.precision, value) {
+            (Some(digits), Value::Num(_)) | (Some(digits), Value::Vector(_)) => value
+                .map(|r| T::parse(&format!("{:.*e}", digits - 1, r)).unwrap_or(r))
+                .to_string(),
+            _ => value.to_string(),
+        };
+        match (self.fractions, value) {
+            (true, Value::Num(r)) => match number::fraction(r.to_f64()) {
+                Some(fraction) => format!("{} = {}", text, fraction),
+                None => text,
+            },
+            _ => text,
+        }
+    }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum InputError {
-    InvalidToken(InvalidToken),
-    SyntaxError { column: usize },
-    RepeatVariable { ident: Ident },
-    UndefinedIdentifier { ident: Ident },
-    BuiltinIdentifier { ident: Ident },
-    InconsistentVariablesCount { ident: Ident },
-}
+    /// Significant digits of numbers shown by `display`, `None` in full.
+    pub fn precision(&self) -> Option<usize> {
+        self.precision
+    }
 
-impl ToString for InputError {
-    fn to_string(&self) -> String {
-        match self {
-            InputError::InvalidToken(e) => format!("{:?}", e),
-            InputError::SyntaxError { column } => format!("Syntax Error at column {}", column),
-            InputError::RepeatVariable { ident } => format!(
-                "Repeat Variable: {}",
-                String::from_utf8(ident.clone()).unwrap()
-            ),
-            InputError::UndefinedIdentifier { ident } => format!(
-                "Undefined Identifier: {}",
-                String::from_utf8(ident.clone()).unwrap()
-            ),
-            InputError::BuiltinIdentifier { ident } => format!(
-                "Use Builtin Identifier: {}",
-                String::from_utf8(ident.clone()).unwrap()
-            ),
-            InputError::InconsistentVariablesCount { ident } => format!(
-                "Inconsistent Variables Count: {}",
-                String::from_utf8(ident.clone()).unwrap()
-            ),
-        }
+    /// Show numbers rounded to `digits` significant digits, from 1 to 17, or in full.
+    pub fn set_precision(&mut self, digits: Option<usize>) {
+        self.precision = digits.map(|digits| digits.clamp(1, 17));
     }
-}
 
-impl From<InvalidToken> for InputError {
-    fn from(e: InvalidToken) -> Self {
-        InputError::InvalidToken(e)
+    /// Whether a `;` ending a statement silences its result.
+    pub fn quiet_semicolon(&self) -> bool {
+        self.quiet_semicolon
     }
-}
 
-pub struct Interpreter {
-    values: HashMap<Ident, (bool, Real)>,
-    functions: HashMap<Ident, Arc<Function>>,
-    parser: Option<Parser>,
-    cur_ident: Ident,
-    cur_variables: Vec<Ident>,
-}
+    /// Silence the results of statements ending with a `;`, as in Matlab, the default, or ignore
+    /// the `;`, with `echo` in the returned [`InputState`].
+    pub fn set_quiet_semicolon(&mut self, on: bool) {
+        self.quiet_semicolon = on;
+    }
 
-pub enum InputState {
-    Empty,
-    Incomplete,
-    Assignment,
-    Expression,
-}
+    pub fn atan2_order(&self) -> Atan2Order {
+        self.atan2_order
+    }
 
-impl Interpreter {
-    pub fn new() -> Self {
-        let mut itp = Interpreter {
-            values: HashMap::new(),
-            functions: HashMap::new(),
-            parser: None,
-            cur_ident: vec![],
-            cur_variables: vec![],
-        };
-        itp.values.insert(b"_".to_vec(), (false, 0.0));
-        itp.insert_builtin_value(b"pi", 3.141592653589793);
-        itp.insert_builtin_value(b"e", 2.718281828459045);
-        itp.insert_builtin_fn(b"abs", 1, |v| v[0].abs());
-        itp.insert_builtin_fn(b"floor", 1, |v| v[0].floor());
-        itp.insert_builtin_fn(b"ceil", 1, |v| v[0].ceil());
-        itp.insert_builtin_fn(b"round", 1, |v| v[0].round());
-        itp.insert_builtin_fn(b"sgn", 1, |v| v[0].signum());
-        itp.insert_builtin_fn(b"sqrt", 1, |v| v[0].sqrt());
-        itp.insert_builtin_fn(b"cbrt", 1, |v| v[0].cbrt());
-        itp.insert_builtin_fn(b"sin", 1, |v| v[0].sin());
-        itp.insert_builtin_fn(b"cos", 1, |v| v[0].cos());
-        itp.insert_builtin_fn(b"tan", 1, |v| v[0].tan());
-        itp.insert_builtin_fn(b"asin", 1, |v| v[0].asin());
-        itp.insert_builtin_fn(b"acos", 1, |v| v[0].acos());
-        itp.insert_builtin_fn(b"atan", 1, |v| v[0].atan());
-        itp.insert_builtin_fn(b"atan2", 2, |v| v[1].atan2(v[0]));
-        itp.insert_builtin_fn(b"ln", 1, |v| v[0].ln());
-        itp.insert_builtin_fn(b"log", 1, |v| v[0].log10());
-        itp
+    /// Set the order of the arguments of `atan2` in the statements read afterwards; functions
+    /// defined before keep the order of their definition, with a warning when it was defined.
+    pub fn set_atan2_order(&mut self, order: Atan2Order) {
+        self.atan2_order = order;
+        self.insert_atan2();
     }
 
-    fn insert_builtin_value(&mut self, ident: &[u8], value: Real) {
-        self.values.insert(ident.to_vec(), (true, value));
+    /// Whether comparisons produce the numbers `1` and `0` instead of booleans.
+    pub fn numeric_comparisons(&self) -> bool {
+        self.numeric_comparisons
     }
 
-    fn insert_builtin_fn(&mut self, ident: &[u8], incount: usize, f: fn(&[Real]) -> Real) {
-        self.functions
-            .insert(ident.to_vec(), Function::builtin(incount, f));
+    /// Make `<`, `>`, `<=`, `>=`, `==` and `!=` in the statements read afterwards produce `1` and
+    /// `0`, usable in arithmetic, or booleans, the default; `true` and `false` follow.
+    pub fn set_numeric_comparisons(&mut self, on: bool) {
+        self.numeric_comparisons = on;
+        self.insert_booleans();
     }
 
-    pub fn input(&mut self, line: &[u8]) -> Result<InputState, InputError> {
-        let ts = Lexer::new(line).tokenize()?;
-        let mut parser = match self.parser.take() {
-            Some(parser) => parser,
-            None => {
-                if ts.tokens.is_empty() {
-                    return Ok(InputState::Empty);
+    /// Tolerance of `~=` relative to the larger magnitude of the operands, or absolute below 1.
+    pub fn epsilon(&self) -> Real {
+        self.epsilon
+    }
+
+    /// Set the tolerance of `~=` in the statements read afterwards.
+    pub fn set_epsilon(&mut self, epsilon: Real) {
+        self.epsilon = epsilon;
+    }
+
+    /// Whether `sum`, `mean`, `var` and `std` use compensated summation.
+    pub fn compensated(&self) -> bool {
+        self.compensated
+    }
+
+    /// Switch `sum`, `mean`, `var` and `std` in the statements read afterwards between
+    /// compensated summation, the default, and faster plain summation.
+    pub fn set_compensated(&mut self, on: bool) {
+        self.compensated = on;
+        self.insert_sums();
+    }
+
+    /// Whether function definitions inline the calls of small user functions.
+    pub fn inlining(&self) -> bool {
+        self.inlining
+    }
+
+    /// Replace the calls of small user functions by their bodies in the functions defined
+    /// afterwards, which then aren't traced, paused at or profiled as calls.
+    ///
+    /// Callees are bound at definition, so inlining doesn't change results.
+    pub fn set_inlining(&mut self, on: bool) {
+        self.inlining = on;
+    }
+
+    /// Whether numbers are input with a decimal comma.
+    pub fn decimal_comma(&self) -> bool {
+        self.decimal_comma
+    }
+
+    /// Accept `,` as decimal separator in the following input, and `_` or thin spaces grouping digits.
+    ///
+    /// A comma followed by a digit right after the digits of a number is then its decimal separator,
+    /// so `max(1,5, 2)` is `max(1.5, 2)`; arguments are separated by a comma and a space.
+    pub fn set_decimal_comma(&mut self, on: bool) {
+        self.decimal_comma = on;
+    }
+
+    /// Whether numbers may have SI magnitude suffixes.
+    pub fn si_suffixes(&self) -> bool {
+        self.si_suffixes
+    }
+
+    /// Accept numbers directly followed by an SI prefix in the following input, like `4.7k`, `10M`,
+    /// `3u` or `5n`, scaled by its power of ten.
+    pub fn set_si_suffixes(&mut self, on: bool) {
+        self.si_suffixes = on;
+    }
+
+    /// Whether NaN and infinite results are errors.
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Make any NaN or infinite result of an operation or call in the following statements an
+    /// error naming the step, instead of letting it propagate to the answer.
+    ///
+    /// Operations on constants are then evaluated as written instead of at translation.
+    pub fn set_strict(&mut self, on: bool) {
+        self.strict = on;
+    }
+
+    pub fn budget(&self) -> Option<Budget> {
+        self.budget
+    }
+
+    /// Limit each following statement to `budget.steps` operations and calls, and user function
+    /// calls to `budget.depth` deep, failing the statement beyond them.
+    ///
+    /// Operations on constants are then evaluated as written instead of at translation.
+    pub fn set_budget(&mut self, budget: Option<Budget>) {
+        self.budget = budget;
+    }
+
+    /// Run a `:command` line, returning its output, or `None` if `line` isn't a command.
+    pub fn command(&mut self, line: &str) -> Option<Result<String, CommandError>> {
+        Some(match command::parse(line)? {
+            Ok(command) => self.execute(command),
+            Err(e) => Err(e),
+        })
+    }
+
+    fn execute(&mut self, command: Command) -> Result<String, CommandError> {
+        match command {
+            Command::List(pattern) => return Ok(self.list(pattern.unwrap_or("*"))),
+            Command::Delete(name) => self.delete(name)?,
+            Command::Save(path) => std::fs::write(path, self.dump_script())?,
+            Command::Load(path) => self.load_script(&std::fs::read_to_string(path)?)?,
+            Command::Help(None) => {
+                let lines = command::COMMANDS
+                    .iter()
+                    .map(|(command, description)| format!("{}: {}", command, description))
+                    .collect::<Vec<_>>();
+                return Ok(lines.join("\n"));
+            }
+            Command::Help(Some(name)) => {
+                return match self.help(name) {
+                    Some(entry) => Ok(entry.to_string()),
+                    None => Err(self.undefined(name.as_bytes().to_vec()).into()),
                 }
-                Parser::new()
             }
+            Command::Clear => self.reset(),
+            Command::Precision(digits) => self.precision = digits,
+            Command::Fraction(on) => self.fractions = on,
+            Command::Epsilon(None) => return Ok(self.epsilon.to_string()),
+            Command::Epsilon(Some(epsilon)) => self.epsilon = epsilon,
+            Command::Compensated(on) => self.set_compensated(on),
+            Command::Atan2(order) => self.set_atan2_order(order),
+            Command::Inline(on) => self.inlining = on,
+            Command::DecimalComma(on) => self.decimal_comma = on,
+            Command::Si(on) => self.si_suffixes = on,
+            Command::Strict(on) => self.strict = on,
+            Command::Trace(on) => self.tracing = on,
+            Command::Time(expression) => return Ok(self.time(expression)?.to_string()),
+        }
+        Ok(String::new())
+    }
+
+    /// User variables with their values, then user functions with their signatures, named
+    /// `pattern`, or starting with it before a trailing `*`.
+    fn list(&self, pattern: &str) -> String {
+        let (prefix, exact) = match pattern.strip_suffix('*') {
+            Some(prefix) => (prefix, false),
+            None => (pattern, true),
+        };
+        let found = self
+            .find(prefix)
+            .into_iter()
+            .filter(|info| !exact || info.name == prefix)
+            .collect::<Vec<_>>();
+        let values = found
+            .iter()
+            .filter(|info| {
+                info.kind == CompletionKind::Variable && !self.is_result(info.name.as_bytes())
+            })
+            .map(|info| {
+                let (_, value, _) = &self.values[info.name.as_bytes()];
+                format!("{} = {}", info.name, self.display(value))
+            });
+        let functions = found
+            .iter()
+            .filter(|info| info.kind == CompletionKind::Function)
+            .filter_map(|info| self.help(&info.name))
+            .map(|entry| entry.to_string());
+        values.chain(functions).collect::<Vec<_>>().join("\n")
+    }
+
+    fn delete(&mut self, name: &str) -> Result<(), InputError> {
+        let ident = name.as_bytes().to_vec();
+        let value =
+            matches!(self.values.get(&ident), Some((false, _, _))) && !self.is_result(&ident);
+        let function = matches!(
+            self.functions.get(&ident).map(|f| &f.fimpl),
+            Some(FunctionImpl::User(_))
+        );
+        if !value && !function {
+            return Err(
+                match self.values.contains_key(&ident) || self.is_builtin(&ident) {
+                    true => InputError::BuiltinIdentifier {
+                        ident,
+                        position: None,
+                    },
+                    false => self.undefined(ident),
+                },
+            );
+        }
+        if value {
+            self.values.remove(&ident);
+        }
+        if function {
+            self.functions.remove(&ident);
+            self.operators.retain(|(s, _)| *s != ident);
+        }
+        self.unindex(&ident);
+        Ok(())
+    }
+
+    /// Input the lines of `script`, reverting all of them if one fails, e.g. a startup file of
+    /// personal definitions.
+    pub fn load_script(&mut self, script: &str) -> Result<(), CommandError> {
+        self.cancel();
+        let snapshot = self.snapshot();
+        let mut result = Ok(());
+        let mut count = 0;
+        // lines before the statement being read
+        let mut before = 0;
+        for (i, line) in script.lines().enumerate() {
+            count = i + 1;
+            if self.parser.is_none() {
+                before = i;
+            }
+            if let Err(error) = self.input(line.as_bytes()) {
+                let error = error.offset_lines(before);
+                result = Err(CommandError::Load { line: count, error });
+                break;
+            }
+        }
+        if result.is_ok() && self.parser.is_some() {
+            let column = script.lines().last().unwrap_or_default().len();
+            let error = InputError::SyntaxError {
+                line: count,
+                column,
+                expected: vec![],
+            };
+            result = Err(CommandError::Load { line: count, error });
+        }
+        if result.is_err() {
+            self.rollback(snapshot);
+        }
+        // the snapshot is only needed while loading
+        if snapshot.0 + 1 == self.snapshots.len() {
+            self.snapshots.pop();
+        }
+        result
+    }
+
+    /// Add the builtin constant `name` described by `doc`, in all workspaces.
+    ///
+    /// A user variable of the same name is replaced.
+    pub fn register_constant(&mut self, name: &str, value: T, doc: &str) -> Result<(), InputError> {
+        let ident = name.as_bytes().to_vec();
+        if self.is_builtin(&ident) && !self.constants.contains_key(&ident) {
+            return Err(InputError::BuiltinIdentifier {
+                ident,
+                position: None,
+            });
+        }
+        self.insert_builtin_value(&ident, value);
+        self.constants.insert(ident, (value, doc.to_string()));
+        Ok(())
+    }
+
+    /// Make `name` another name of the builtin `target`, in all workspaces.
+    pub fn alias(&mut self, name: &str, target: &str) -> Result<(), InputError> {
+        let (ident, target) = (name.as_bytes().to_vec(), target.as_bytes().to_vec());
+        if self.is_builtin(&ident) {
+            return Err(InputError::BuiltinIdentifier {
+                ident,
+                position: None,
+            });
+        }
+        if !self.is_builtin(&target) || SPECIAL_FORMS.contains(&target.as_slice()) {
+            return Err(self.undefined(target));
+        }
+        let target = self.aliases.get(&target).cloned().unwrap_or(target);
+        self.aliases.insert(ident, target);
+        self.insert_settings();
+        Ok(())
+    }
+
+    /// Keep the builtin `name` working, but warn where it is used, suggesting `replacement`.
+    pub fn deprecate(&mut self, name: &str, replacement: Option<&str>) -> Result<(), InputError> {
+        let ident = name.as_bytes().to_vec();
+        if !self.is_builtin(&ident) {
+            return Err(self.undefined(ident));
+        }
+        let replacement = replacement.map(|r| r.as_bytes().to_vec());
+        self.deprecated.insert(ident, replacement);
+        Ok(())
+    }
+
+    /// Warn of `atan2` in a function, which keeps the argument order of its definition.
+    fn warn_atan2(&mut self, ident: &[u8]) {
+        let warning = Warning::AmbiguousAtan2 {
+            order: self.atan2_order,
         };
-        for (column, token) in ts.tokens {
-            if !parser.action(token) {
-                return Err(InputError::SyntaxError { column });
+        if ident == b"atan2" && !self.cur_ident.is_empty() && !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    fn warn_deprecated(&mut self, ident: &Ident) {
+        if let Some(replacement) = self.deprecated.get(ident) {
+            let warning = Warning::Deprecated {
+                ident: ident.clone(),
+                replacement: replacement.clone(),
+            };
+            if !self.warnings.contains(&warning) {
+                self.warnings.push(warning);
+            }
+        }
+    }
+
+    /// Bind a vector of numbers to the variable `ident`.
+    pub fn set_vector(&mut self, ident: &str, values: Vec<T>) -> Result<(), InputError> {
+        let ident = ident.as_bytes().to_vec();
+        if self.is_builtin_value(&ident) {
+            return Err(InputError::BuiltinIdentifier {
+                ident,
+                position: None,
+            });
+        }
+        let binding = self.set_value(ident, Value::from(values), None);
+        self.record(vec![binding]);
+        self.audit(None);
+        Ok(())
+    }
+
+    /// Bind numbers to several variables as one change, undone at once, after checking all names
+    /// are identifiers, none twice, that may be assigned.
+    pub fn set_many(&mut self, bindings: &[(&str, T)]) -> Result<(), InputError> {
+        let mut idents: Vec<Ident> = Vec::with_capacity(bindings.len());
+        for (name, _) in bindings {
+            let ident = self.assignable(name)?;
+            if idents.contains(&ident) {
+                return Err(InputError::RepeatVariable {
+                    ident,
+                    position: None,
+                });
             }
+            idents.push(ident);
         }
-        if ts.complete {
-            match parser.accept() {
-                Some(ast) => self.translate_ast(ast),
-                None => Err(InputError::SyntaxError { column: line.len() }),
+        let bindings = idents
+            .into_iter()
+            .zip(bindings)
+            .map(|(ident, (_, value))| self.set_value(ident, Value::Num(*value), None))
+            .collect();
+        self.record(bindings);
+        self.audit(None);
+        Ok(())
+    }
+
+    /// The user variables holding numbers, by name, leaving out vectors, booleans and results.
+    pub fn export_values(&self) -> HashMap<String, T> {
+        self.values
+            .iter()
+            .filter(|(ident, (builtin, _, _))| !builtin && !self.is_result(ident))
+            .filter_map(|(ident, (_, value, _))| {
+                let name = String::from_utf8_lossy(ident).into_owned();
+                value.num().ok().map(|value| (name, value))
+            })
+            .collect()
+    }
+
+    /// `name` as an identifier that isn't a builtin constant.
+    fn assignable(&self, name: &str) -> Result<Ident, InputError> {
+        let ts = self.lexer(name.as_bytes()).tokenize()?;
+        let ident = match ts.tokens.as_slice() {
+            [(_, Token::IDENT(ident))] if ident.as_slice() == name.as_bytes() => ident.clone(),
+            [(_, Token::IDENT(_)), (span, _), ..] => {
+                return Err(InputError::SyntaxError {
+                    line: 1,
+                    column: span.start,
+                    expected: vec![],
+                });
             }
-        } else {
-            self.parser.replace(parser);
-            Ok(InputState::Incomplete)
+            _ => {
+                return Err(InputError::SyntaxError {
+                    line: 1,
+                    column: 0,
+                    expected: vec![Token::NAMES[0]],
+                })
+            }
+        };
+        if self.is_builtin_value(&ident) {
+            return Err(InputError::BuiltinIdentifier {
+                ident,
+                position: None,
+            });
+        }
+        Ok(ident)
+    }
+
+    /// Load the numbers of a 0-based `column` of a CSV file into the vector variable `ident`.
+    pub fn load_csv<P: AsRef<Path>>(
+        &mut self,
+        ident: &str,
+        path: P,
+        column: usize,
+    ) -> Result<(), CsvError> {
+        let file = File::open(path)?;
+        let values = csv::read_column(BufReader::new(file), column)?;
+        Ok(self.set_vector(ident, values)?)
+    }
+
+    /// Solve `f(x) = g(x)` for `x` in `[a, b]`.
+    pub fn solve_eq(&self, f: &str, g: &str, a: Real, b: Real) -> Result<Real, SolveError> {
+        let f = self.lookup_function(f.as_bytes(), 1)?;
+        let g = self.lookup_function(g.as_bytes(), 1)?;
+        solver::find_root(|x| Ok(f.invoke_real(&[x])? - g.invoke_real(&[x])?), a, b)
+    }
+
+    /// Solve the system `fs[i](x) = 0` for `x` with Newton's method, starting from `x0`.
+    pub fn solve_system(&self, fs: &[&str], x0: &[Real]) -> Result<Vec<Real>, SolveError> {
+        if fs.len() != x0.len() {
+            return Err(SolveError::DimensionMismatch {
+                equations: fs.len(),
+                unknowns: x0.len(),
+            });
+        }
+        let fs = fs
+            .iter()
+            .map(|f| self.lookup_function(f.as_bytes(), x0.len()))
+            .collect::<Result<Vec<_>, _>>()?;
+        solver::find_system_root(|x| fs.iter().map(|f| Ok(f.invoke_real(x)?)).collect(), x0)
+    }
+
+    /// Numerical gradient of `f` at `x`.
+    pub fn grad(&self, f: &str, x: &[Real]) -> Result<Vec<Real>, SolveError> {
+        let f = self.lookup_function(f.as_bytes(), x.len())?;
+        let jac = solver::jacobian(&|x: &[Real]| Ok(vec![f.invoke_real(x)?]), x)?;
+        Ok(jac.into_iter().next().unwrap())
+    }
+
+    /// Numerical Jacobian at `x` of the vector-valued function with components `fs`.
+    pub fn jacobian(&self, fs: &[&str], x: &[Real]) -> Result<Vec<Vec<Real>>, SolveError> {
+        let fs = fs
+            .iter()
+            .map(|f| self.lookup_function(f.as_bytes(), x.len()))
+            .collect::<Result<Vec<_>, _>>()?;
+        solver::jacobian(
+            &|x: &[Real]| fs.iter().map(|f| Ok(f.invoke_real(x)?)).collect(),
+            x,
+        )
+    }
+
+    /// Fit the parameters of `f: x, p1, ..., pn` to the data points `(xs, ys)` with the
+    /// Levenberg-Marquardt method, starting from `guess`.
+    ///
+    /// The fitted parameters are assigned to the variables `p1, ..., pn` and the fitted
+    /// function is defined as `f_fit: x`.
+    pub fn fit(
+        &mut self,
+        f: &str,
+        xs: &[Real],
+        ys: &[Real],
+        guess: &[Real],
+    ) -> Result<Vec<Real>, SolveError> {
+        let params = self.fit_bindings(f, xs, ys, guess)?;
+        self.audit(None);
+        Ok(params)
+    }
+
+    /// `fit` without auditing, left to the `fit` statement or the host calling it.
+    fn fit_bindings(
+        &mut self,
+        f: &str,
+        xs: &[Real],
+        ys: &[Real],
+        guess: &[Real],
+    ) -> Result<Vec<Real>, SolveError> {
+        if xs.len() != ys.len() {
+            return Err(SolveError::DataMismatch {
+                xs: xs.len(),
+                ys: ys.len(),
+            });
+        }
+        if xs.len() < guess.len() {
+            return Err(SolveError::DimensionMismatch {
+                equations: xs.len(),
+                unknowns: guess.len(),
+            });
+        }
+        let model = self.lookup_function(f.as_bytes(), guess.len() + 1)?.clone();
+        let fitted = format!("{}_fit", f).into_bytes();
+        if self.is_builtin(&fitted) {
+            return Err(InputError::BuiltinIdentifier {
+                ident: fitted,
+                position: None,
+            }
+            .into());
+        }
+        let params = solver::least_squares(
+            |p| {
+                let mut args = [&[0.0], p].concat();
+                xs.iter()
+                    .zip(ys)
+                    .map(|(&x, &y)| {
+                        args[0] = x;
+                        Ok(model.invoke_real(&args)? - y)
+                    })
+                    .collect()
+            },
+            guess,
+        )?;
+        let values = params.iter().map(|&p| T::from_f64(p)).collect::<Vec<_>>();
+        let mut bindings = vec![];
+        for (ident, &value) in model.variables[1..].iter().zip(&values) {
+            bindings.push(self.set_value(ident.clone(), Value::Num(value), None));
+        }
+        let mut arena = Arena::default();
+        let mut inputs: Args<T> = smallvec![ExprOrValue::Expr(arena.push(Expression::Variable(0)))];
+        inputs.extend(values.iter().map(|&p| ExprOrValue::Value(Value::Num(p))));
+        let x = String::from_utf8_lossy(&model.variables[0]);
+        let doc = format!("{} fitted to {} data points", f, xs.len());
+        let args = values
+            .iter()
+            .map(|&p| literal(&Value::Num(p)).unwrap())
+            .collect::<Vec<_>>();
+        let source = format!(
+            "{}_fit: {} = {}({}, {}) # {}",
+            f,
+            x,
+            f,
+            x,
+            args.join(", "),
+            doc
+        );
+        let function = Function {
+            name: fitted.clone(),
+            incount: 1,
+            variables: model.variables[..1].to_vec(),
+            doc: Some(doc),
+            source: Some(
+                Source::new(source).capturing(vec![Capture::Function(f.into(), model.clone())]),
+            ),
+            fimpl: FunctionImpl::User(Body {
+                root: ExprOrValue::Expr(
+                    arena.push(Expression::Invoke(Some(model.clone()), inputs)),
+                ),
+                arena,
+            }),
+            overloads: vec![],
+        };
+        bindings.push(self.set_function(fitted, Arc::new(function)));
+        self.record(bindings);
+        Ok(params)
+    }
+
+    /// The error for the undefined `ident`, suggesting up to three defined names, or parameters
+    /// of the function being defined, within a small edit distance of it.
+    fn undefined(&self, ident: Ident) -> InputError {
+        let limit = (ident.len() + 1) / 3;
+        let mut candidates = self
+            .names
+            .iter()
+            .chain(&self.cur_variables)
+            .filter(|name| name.first().is_some_and(|c| c.is_ascii_alphabetic()))
+            .filter_map(|name| {
+                let distance = edit_distance(&ident, name);
+                match distance <= limit {
+                    true => Some((distance, String::from_utf8_lossy(name).into_owned())),
+                    false => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+        candidates.dedup();
+        let suggestions = candidates
+            .into_iter()
+            .take(3)
+            .map(|(_, name)| name)
+            .collect();
+        InputError::UndefinedIdentifier {
+            ident,
+            suggestions,
+            position: None,
         }
     }
 
-    pub fn last_result(&self) -> Real {
-        self.values.get(&b"_".to_vec()).unwrap().1
+    /// Record the evaluation steps of the following statements, retrieved with `trace()`.
+    ///
+    /// Operations on constants are then evaluated as written instead of at translation.
+    pub fn set_tracing(&mut self, on: bool) {
+        self.tracing = on;
+    }
+
+    /// Evaluation steps of the last statement when tracing, each after the steps of its operands.
+    pub fn trace(&self) -> &[TraceStep<T>] {
+        &self.trace
+    }
+
+    /// Pause at calls of the user function `name` while a debugger is set.
+    pub fn set_breakpoint(&mut self, name: &str) {
+        self.breakpoints.insert(name.as_bytes().to_vec());
+    }
+
+    pub fn remove_breakpoint(&mut self, name: &str) -> bool {
+        self.breakpoints.remove(name.as_bytes())
+    }
+
+    /// Call `debugger` at each breakpoint, and at every user function call of the statement
+    /// after it returns [`DebugAction::Step`].
+    ///
+    /// Calls with constant arguments then run at evaluation instead of at translation.
+    pub fn set_debugger<F: FnMut(&Frame<T>) -> DebugAction + Send + 'static>(
+        &mut self,
+        debugger: F,
+    ) {
+        self.debugger = Some(Box::new(debugger));
+    }
+
+    pub fn clear_debugger(&mut self) {
+        self.debugger = None;
+    }
+
+    /// Look up the names bound nowhere else with `resolver` in the following statements, which use
+    /// the number it returns at translation.
+    ///
+    /// The parse cache is bypassed while a resolver is set.
+    pub fn set_resolver<F: Fn(&str) -> Option<T> + Send + 'static>(&mut self, resolver: F) {
+        self.resolver = Some(Box::new(resolver));
+    }
+
+    pub fn clear_resolver(&mut self) {
+        self.resolver = None;
+    }
+
+    /// Start counting calls and timing them for each function, discarding earlier counts.
+    ///
+    /// Calls with constant arguments in later statements then run at evaluation instead of at
+    /// translation, functions defined before keep the results of theirs.
+    pub fn enable_profiling(&mut self) {
+        self.profile = Some(HashMap::new());
+    }
+
+    pub fn disable_profiling(&mut self) {
+        self.profile = None;
+    }
+
+    /// Calls of each function since profiling was enabled, the most time-consuming first.
+    pub fn profile(&self) -> Vec<ProfileEntry> {
+        let mut entries = self
+            .profile
+            .iter()
+            .flatten()
+            .map(|(ident, &(calls, time))| ProfileEntry {
+                name: String::from_utf8_lossy(ident).into_owned(),
+                calls,
+                time,
+            })
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by(|e1, e2| e2.time.cmp(&e1.time).then(e1.name.cmp(&e2.name)));
+        entries
+    }
+
+    fn evaluate(&mut self, statement: &Function<T>) -> Result<Value<T>, EvalError> {
+        span!("evaluate");
+        let breakpoints = &self.breakpoints;
+        let mut ctx = Context {
+            trace: match self.tracing {
+                true => Some(vec![]),
+                false => None,
+            },
+            debugger: self.debugger.as_mut().map(|handler| Debugger {
+                breakpoints,
+                handler,
+                stepping: false,
+            }),
+            profile: self.profile.as_mut(),
+            strict: self.strict,
+            budget: self.budget,
+            ..Context::default()
+        };
+        let result = statement.invoke(&[], &mut ctx);
+        self.trace = ctx.trace.unwrap_or_default();
+        result
+    }
+
+    fn lookup_function(
+        &self,
+        ident: &[u8],
+        incount: usize,
+    ) -> Result<&Arc<Function<T>>, InputError> {
+        let ident = ident.to_vec();
+        match self.functions.get(&ident).map(|f| f.overload(incount)) {
+            Some(Some(f)) => Ok(f),
+            Some(None) => Err(InputError::InconsistentVariablesCount {
+                ident,
+                position: None,
+            }),
+            None => Err(self.undefined(ident)),
+        }
     }
 
-    fn translate_ast(&mut self, ast: ASTNode) -> Result<InputState, InputError> {
+    fn translate_ast(&mut self, ast: ASTNode) -> Result<InputState<T>, InputError> {
+        span!("translate");
+        self.warnings.clear();
+        self.cur_captures.clear();
+        self.cur_arena = Arena::default();
+        let (ast, terminated) = unterminated(ast);
+        self.cur_terminated = terminated;
         match ast {
             // statement: assignment
             ASTNode::Inner(1, mut children) => match children.pop().unwrap() {
@@ -183,59 +2821,212 @@ impl Interpreter {
                 ASTNode::Inner(3, mut children) => {
                     let expr_ast = children.pop().unwrap();
                     children.pop();
-                    let ident = children.pop().unwrap().assume_leaf().assume_ident();
+                    let (ident, position) = children.pop().unwrap().assume_leaf_at()?;
+                    let ident = ident.assume_ident()?;
                     if self.is_builtin_value(&ident) {
-                        return Err(InputError::BuiltinIdentifier { ident });
+                        return Err(InputError::BuiltinIdentifier {
+                            ident,
+                            position: Some(position),
+                        });
+                    }
+                    let mut lifted = vec![];
+                    self.bare_functions(&expr_ast, &mut lifted);
+                    if !lifted.is_empty() {
+                        return self
+                            .translate_function_algebra(ident, lifted, expr_ast)
+                            .map_err(|e| e.at(Some(position)));
                     }
                     self.cur_ident.clear();
                     self.cur_variables.clear();
+                    let position = expr_ast.position();
                     let expression = self.translate_expression(expr_ast)?;
-                    self.values.insert(ident, (false, expression.assume_num()));
-                    Ok(InputState::Assignment)
+                    let body = self.take_body(expression);
+                    let value = self
+                        .evaluate(&Function::anonymous(body))
+                        .map_err(|e| InputError::from(e).at(position))?;
+                    let name = String::from_utf8_lossy(&ident).into_owned();
+                    let source = Source::new(self.cur_source.join("\n"));
+                    let binding = self.set_value(ident, value.clone(), Some(source));
+                    self.record(vec![binding]);
+                    Ok(InputState::Assignment {
+                        name,
+                        assigned: Assigned::Value(value),
+                        warnings: self.warnings.split_off(0),
+                        echo: self.echo(),
+                    })
                 }
                 // assignment: IDENT ':' variable_list '=' expression
                 ASTNode::Inner(4, mut children) => {
                     let expr_ast = children.pop().unwrap();
                     children.pop();
-                    let variables = self.translate_variable_list(children.pop().unwrap())?;
-                    for (i, var) in variables.iter().enumerate() {
-                        if variables.iter().rposition(|v| v == var).unwrap() != i {
-                            return Err(InputError::RepeatVariable { ident: var.clone() });
-                        }
-                    }
-                    self.cur_variables = variables;
+                    self.cur_variables = self.translate_variable_list(children.pop().unwrap())?;
                     children.pop();
-                    let ident = children.pop().unwrap().assume_leaf().assume_ident();
+                    let (ident, position) = children.pop().unwrap().assume_leaf_at()?;
+                    let ident = ident.assume_ident()?;
                     if self.is_builtin(&ident) {
-                        return Err(InputError::BuiltinIdentifier { ident });
+                        return Err(InputError::BuiltinIdentifier {
+                            ident,
+                            position: Some(position),
+                        });
                     }
                     self.cur_ident = ident;
                     let expression = self.translate_expression(expr_ast)?;
+                    let body = self.take_body(expression);
+                    self.analyze_function(&body);
+                    let body = self.share_common(self.inline_calls(body));
                     let function = Function {
+                        name: self.cur_ident.clone(),
                         incount: self.cur_variables.len(),
-                        fimpl: FunctionImpl::User(expression),
+                        variables: self.cur_variables.clone(),
+                        doc: self.cur_doc.take(),
+                        source: Some(
+                            Source::new(self.cur_source.join("\n"))
+                                .capturing(self.cur_captures.split_off(0)),
+                        ),
+                        fimpl: FunctionImpl::User(body),
+                        overloads: vec![],
                     };
-                    self.functions
-                        .insert(self.cur_ident.clone(), Arc::new(function));
-                    Ok(InputState::Assignment)
+                    let binding = self.set_function(self.cur_ident.clone(), Arc::new(function));
+                    let mut bindings = vec![binding];
+                    bindings.extend(self.cur_operator.take());
+                    self.record(bindings);
+                    Ok(InputState::Assignment {
+                        name: String::from_utf8_lossy(&self.cur_ident).into_owned(),
+                        assigned: Assigned::Function {
+                            arity: self.cur_variables.len(),
+                        },
+                        warnings: self.warnings.split_off(0),
+                        echo: self.echo(),
+                    })
                 }
-                _ => unreachable!(),
+                _ => Err(InputError::InternalError {
+                    message: "unknown assignment node",
+                }),
             },
             // statement: expression
             ASTNode::Inner(2, mut children) => {
+                if let Some(ident) = self.help_target(&children[0]) {
+                    let name = String::from_utf8_lossy(&ident).into_owned();
+                    return match self.help(&name) {
+                        Some(entry) => Ok(InputState::Help { entry }),
+                        None => Err(self.undefined(ident).at(children[0].position())),
+                    };
+                }
                 self.cur_ident.clear();
                 self.cur_variables.clear();
-                let expression = self.translate_expression(children.pop().unwrap())?;
-                self.values
-                    .insert(b"_".to_vec(), (false, expression.assume_num()));
-                Ok(InputState::Expression)
+                if called(&children[0]) == Some(b"fit") {
+                    return self.translate_fit(children.pop().unwrap());
+                }
+                let ast = children.pop().unwrap();
+                let cached = self.cache.capacity() > 0
+                    && self.cur_source.len() == 1
+                    && self.resolver.is_none();
+                let mut idents = vec![b"range".to_vec(), b"approx".to_vec()];
+                if cached {
+                    leaf_idents(&ast, &mut idents);
+                }
+                let position = ast.position();
+                let expression = self.translate_expression(ast)?;
+                let statement = Arc::new(Function::anonymous(self.take_body(expression)));
+                let warnings = self.warnings.split_off(0);
+                if cached {
+                    let bindings = idents
+                        .into_iter()
+                        .flat_map(|ident| {
+                            let value = self.values.get(&ident).cloned();
+                            let f = self.functions.get(&ident).cloned();
+                            vec![
+                                Binding::Value(ident.clone(), value),
+                                Binding::Function(ident, f),
+                            ]
+                        })
+                        .collect();
+                    let entry = CachedStatement {
+                        statement: statement.clone(),
+                        warnings: warnings.clone(),
+                        bindings,
+                        settings: self.translation_settings(),
+                        terminated: self.cur_terminated,
+                    };
+                    self.cache.insert(self.cur_source[0].clone(), entry);
+                }
+                let value = self
+                    .evaluate(&statement)
+                    .map_err(|e| InputError::from(e).at(position))?;
+                Ok(self.expression_result(value, warnings))
             }
-            _ => unreachable!(),
+            _ => Err(InputError::InternalError {
+                message: "unknown statement node",
+            }),
+        }
+    }
+
+    /// Bind the result names to the value of an expression statement, and `__` and `___` to the
+    /// two results before it.
+    fn expression_result(&mut self, value: Value<T>, warnings: Vec<Warning>) -> InputState<T> {
+        let second = self.last_result().clone();
+        let third = self.values[EARLIER_RESULTS[0]].1.clone();
+        let results = self
+            .result_names
+            .iter()
+            .map(|ident| (ident.clone(), value.clone()))
+            .chain([
+                (EARLIER_RESULTS[0].to_vec(), second),
+                (EARLIER_RESULTS[1].to_vec(), third),
+            ])
+            .collect::<Vec<_>>();
+        self.changes = results
+            .iter()
+            .map(|(ident, _)| Change::new(ident, false, true))
+            .collect();
+        for (ident, value) in results {
+            self.values.insert(ident, (false, value, None));
         }
+        InputState::Expression {
+            value,
+            warnings,
+            echo: self.echo(),
+        }
+    }
+
+    /// Whether the result of the statement is shown.
+    fn echo(&self) -> bool {
+        !(self.cur_terminated && self.quiet_semicolon)
+    }
+
+    /// Translate `ast`, errors inside it without a position taking its position.
+    fn translate_expression(&mut self, ast: ASTNode) -> Result<ExprOrValue<T>, InputError> {
+        let position = ast.position();
+        self.translate_node(ast).map_err(|e| e.at(position))
     }
 
-    fn translate_expression(&self, ast: ASTNode) -> Result<ExprOrNum, InputError> {
+    fn translate_node(&mut self, ast: ASTNode) -> Result<ExprOrValue<T>, InputError> {
+        let fold = self.folding();
         match ast {
+            // expression: expression OP expression, for any tier of a user-defined operator
+            ASTNode::Inner(10..=15, mut children)
+                if matches!(children.get(1), Some(ASTNode::Leaf(Token::OP(..), _))) =>
+            {
+                let ex2 = self.translate_expression(children.pop().unwrap())?;
+                let op = match children.pop().unwrap().assume_leaf()? {
+                    Token::OP(_, spelling) => spelling,
+                    _ => {
+                        return Err(InputError::InternalError {
+                            message: "expect an operator",
+                        })
+                    }
+                };
+                let ex1 = self.translate_expression(children.pop().unwrap())?;
+                if op == b".." {
+                    let step = ExprOrValue::Value(Value::Int(1));
+                    return self.translate_call(b"range".to_vec(), smallvec![ex1, ex2, step], fold);
+                }
+                if op == b"~=" {
+                    let tol = ExprOrValue::Value(Value::Num(T::from_f64(self.epsilon)));
+                    return self.translate_call(b"approx".to_vec(), smallvec![ex1, ex2, tol], fold);
+                }
+                self.translate_call(op, smallvec![ex1, ex2], fold)
+            }
             // expression: '(' expression ')'
             ASTNode::Inner(7, mut children) => {
                 children.pop();
@@ -245,23 +3036,27 @@ impl Interpreter {
             ASTNode::Inner(8, mut children) => {
                 let res = self.translate_expression(children.pop().unwrap())?;
                 Ok(match res {
-                    ExprOrNum::Expr(expr) => ExprOrNum::Expr(Box::new(Expression::Not(expr))),
-                    ExprOrNum::Num(real) => ExprOrNum::Num(if real == 0.0 { 1.0 } else { 0.0 }),
+                    ExprOrValue::Expr(expr) => self.node(Expression::Not(expr)),
+                    ExprOrValue::Value(val) => ExprOrValue::Value(val.not()?),
                 })
             }
             // expression: PN expression
             ASTNode::Inner(9, mut children) => {
                 let res = self.translate_expression(children.pop().unwrap())?;
-                let pn = children.pop().unwrap().assume_leaf().assume_pn();
+                let pn = children.pop().unwrap().assume_leaf()?.assume_pn()?;
                 Ok(match res {
-                    ExprOrNum::Expr(expr) => ExprOrNum::Expr(match pn {
-                        AddSubOp::ADD => expr,
-                        AddSubOp::SUB => Box::new(Expression::Neg(expr)),
-                    }),
-                    ExprOrNum::Num(real) => ExprOrNum::Num(match pn {
-                        AddSubOp::ADD => real,
-                        AddSubOp::SUB => -real,
-                    }),
+                    ExprOrValue::Expr(expr) => match pn {
+                        AddSubOp::ADD => ExprOrValue::Expr(expr),
+                        AddSubOp::SUB => self.node(Expression::Neg(expr)),
+                    },
+                    ExprOrValue::Value(val) => match (pn, val.neg()) {
+                        (AddSubOp::ADD, _) => ExprOrValue::Value(val),
+                        (AddSubOp::SUB, Ok(neg)) => ExprOrValue::Value(neg),
+                        (AddSubOp::SUB, Err(_)) if !fold => {
+                            self.deferred(b"-", |v| v[0].neg(), val)
+                        }
+                        (AddSubOp::SUB, Err(e)) => return Err(e.into()),
+                    },
                 })
             }
             // expression: expression '^' expression
@@ -270,150 +3065,335 @@ impl Interpreter {
                 children.pop();
                 let ex1 = self.translate_expression(children.pop().unwrap())?;
                 Ok(match (ex1, ex2) {
-                    (ExprOrNum::Num(r1), ExprOrNum::Num(r2)) => ExprOrNum::Num(r1.powf(r2)),
-                    (ex1, ex2) => ExprOrNum::Expr(Box::new(Expression::Exp(ex1, ex2))),
+                    (ExprOrValue::Value(v1), ExprOrValue::Value(v2)) if fold => {
+                        ExprOrValue::Value(v1.pow(&v2)?)
+                    }
+                    (ex1, ex2) => self.node(Expression::Exp(ex1, ex2)),
                 })
             }
             // expression: expression MD expression
             ASTNode::Inner(11, mut children) => {
                 let ex2 = self.translate_expression(children.pop().unwrap())?;
-                let md = children.pop().unwrap().assume_leaf().assume_md();
+                let md = children.pop().unwrap().assume_leaf()?.assume_md()?;
                 let ex1 = self.translate_expression(children.pop().unwrap())?;
                 Ok(match (ex1, ex2) {
-                    (ExprOrNum::Num(r1), ExprOrNum::Num(r2)) => ExprOrNum::Num(match md {
-                        MulDivOp::MUL => r1 * r2,
-                        MulDivOp::DIV => r1 / r2,
-                    }),
-                    (ex1, ex2) => ExprOrNum::Expr(Box::new(match md {
+                    (ExprOrValue::Value(v1), ExprOrValue::Value(v2)) if fold => {
+                        ExprOrValue::Value(match md {
+                            MulDivOp::MUL => v1.mul(&v2)?,
+                            MulDivOp::DIV => v1.div(&v2)?,
+                        })
+                    }
+                    (ex1, ex2) => self.node(match md {
                         MulDivOp::MUL => Expression::Mul(ex1, ex2),
                         MulDivOp::DIV => Expression::Div(ex1, ex2),
-                    })),
+                    }),
                 })
             }
             // expression: expression PN expression
             ASTNode::Inner(12, mut children) => {
                 let ex2 = self.translate_expression(children.pop().unwrap())?;
-                let pn = children.pop().unwrap().assume_leaf().assume_pn();
+                let pn = children.pop().unwrap().assume_leaf()?.assume_pn()?;
                 let ex1 = self.translate_expression(children.pop().unwrap())?;
                 Ok(match (ex1, ex2) {
-                    (ExprOrNum::Num(r1), ExprOrNum::Num(r2)) => ExprOrNum::Num(match pn {
-                        AddSubOp::ADD => r1 + r2,
-                        AddSubOp::SUB => r1 - r2,
-                    }),
-                    (ex1, ex2) => ExprOrNum::Expr(Box::new(match pn {
+                    (ExprOrValue::Value(v1), ExprOrValue::Value(v2)) if fold => {
+                        ExprOrValue::Value(match pn {
+                            AddSubOp::ADD => v1.add(&v2)?,
+                            AddSubOp::SUB => v1.sub(&v2)?,
+                        })
+                    }
+                    (ex1, ex2) => self.node(match pn {
                         AddSubOp::ADD => Expression::Add(ex1, ex2),
                         AddSubOp::SUB => Expression::Sub(ex1, ex2),
-                    })),
+                    }),
                 })
             }
             // expression: expression CMP expression
             ASTNode::Inner(13, mut children) => {
                 let ex2 = self.translate_expression(children.pop().unwrap())?;
-                let cmp = children.pop().unwrap().assume_leaf().assume_cmp();
+                let cmp = children.pop().unwrap().assume_leaf()?.assume_cmp()?;
                 let ex1 = self.translate_expression(children.pop().unwrap())?;
-                Ok(match (ex1, ex2) {
-                    (ExprOrNum::Num(r1), ExprOrNum::Num(r2)) => ExprOrNum::Num(cmp.on(r1, r2)),
-                    (ex1, ex2) => ExprOrNum::Expr(Box::new(Expression::Compare(cmp, ex1, ex2))),
+                if let (ExprOrValue::Expr(e1), ExprOrValue::Expr(e2)) = (&ex1, &ex2) {
+                    let (e1, e2) = (&self.cur_arena[*e1], &self.cur_arena[*e2]);
+                    if let (Expression::Variable(i), Expression::Variable(j)) = (e1, e2) {
+                        if i == j {
+                            let ident = self.variable_name(*i);
+                            self.warnings.push(Warning::SelfComparison { ident });
+                        }
+                    }
+                }
+                let compared = match (ex1, ex2) {
+                    (ExprOrValue::Value(v1), ExprOrValue::Value(v2)) if fold => {
+                        ExprOrValue::Value(v1.compare(&v2, cmp)?)
+                    }
+                    (ex1, ex2) => self.node(Expression::Compare(cmp, ex1, ex2)),
+                };
+                if !self.numeric_comparisons || cmp == CompareOp::CMP {
+                    return Ok(compared);
+                }
+                Ok(match compared {
+                    ExprOrValue::Value(val) => ExprOrValue::Value(numeric(&[val])?),
+                    expr => {
+                        let f = Function::builtin(b"number", 1, FunctionImpl::Generic(numeric));
+                        self.node(Expression::Invoke(Some(f), smallvec![expr]))
+                    }
                 })
             }
             // expression: expression OR expression
             ASTNode::Inner(14, mut children) => {
-                let ex2 = self.translate_expression(children.pop().unwrap())?;
+                let ex2 = self.translate_short_circuited(children.pop().unwrap())?;
                 children.pop();
                 let ex1 = self.translate_expression(children.pop().unwrap())?;
                 Ok(match (ex1, ex2) {
-                    (ExprOrNum::Num(r1), ExprOrNum::Num(r2)) => {
-                        ExprOrNum::Num(if r1 != 0.0 || r2 != 0.0 { 1.0 } else { 0.0 })
+                    (ExprOrValue::Value(v1), _) if fold && v1.short_circuits(true)? => {
+                        ExprOrValue::Value(Value::Bool(true))
                     }
-                    (ex1, ex2) => ExprOrNum::Expr(Box::new(Expression::Or(ex1, ex2))),
+                    (ExprOrValue::Value(v1), ExprOrValue::Value(v2)) if fold => {
+                        ExprOrValue::Value(v1.or(&v2)?)
+                    }
+                    (ex1, ex2) => self.node(Expression::Or(ex1, ex2)),
                 })
             }
             // expression: expression AND expression
             ASTNode::Inner(15, mut children) => {
-                let ex2 = self.translate_expression(children.pop().unwrap())?;
+                let ex2 = self.translate_short_circuited(children.pop().unwrap())?;
                 children.pop();
                 let ex1 = self.translate_expression(children.pop().unwrap())?;
                 Ok(match (ex1, ex2) {
-                    (ExprOrNum::Num(r1), ExprOrNum::Num(r2)) => {
-                        ExprOrNum::Num(if r1 != 0.0 && r2 != 0.0 { 1.0 } else { 0.0 })
+                    (ExprOrValue::Value(v1), _) if fold && v1.short_circuits(false)? => {
+                        ExprOrValue::Value(Value::Bool(false))
                     }
-                    (ex1, ex2) => ExprOrNum::Expr(Box::new(Expression::And(ex1, ex2))),
+                    (ExprOrValue::Value(v1), ExprOrValue::Value(v2)) if fold => {
+                        ExprOrValue::Value(v1.and(&v2)?)
+                    }
+                    (ex1, ex2) => self.node(Expression::And(ex1, ex2)),
                 })
             }
             // expression: expression '?' expression ':' expression
             ASTNode::Inner(16, mut children) => {
-                let ex2 = self.translate_expression(children.pop().unwrap())?;
+                // only the branch taken is evaluated, so neither may fail at translation
+                let ex2 = self.translate_short_circuited(children.pop().unwrap())?;
                 children.pop();
-                let ex1 = self.translate_expression(children.pop().unwrap())?;
+                let ex1 = self.translate_short_circuited(children.pop().unwrap())?;
                 children.pop();
                 let cond = self.translate_expression(children.pop().unwrap())?;
                 Ok(match cond {
-                    ExprOrNum::Expr(ex) => {
-                        ExprOrNum::Expr(Box::new(Expression::Condition(ex, ex1, ex2)))
-                    }
-                    ExprOrNum::Num(r) => {
-                        if r != 0.0 {
-                            ex1
-                        } else {
-                            ex2
-                        }
+                    ExprOrValue::Expr(ex) => self.node(Expression::Condition(ex, ex1, ex2)),
+                    ExprOrValue::Value(val) if fold || val.truth().is_ok() => {
+                        self.constant_branch(&val, ex1, ex2)?
+                    }
+                    ExprOrValue::Value(val) => {
+                        let cond = self.deferred(b"?", |v| Ok(v[0].clone()), val);
+                        self.node(Expression::Condition(cond.expr().unwrap(), ex1, ex2))
                     }
                 })
             }
             // expression: IDENT '(' parameter_list ')'
             ASTNode::Inner(17, mut children) => {
                 children.pop();
-                let params = self.translate_parameter_list(children.pop().unwrap())?;
+                let params_ast = children.pop().unwrap();
                 children.pop();
-                let ident = children.pop().unwrap().assume_leaf().assume_ident();
-                if ident == self.cur_ident {
-                    if params.len() != self.cur_variables.len() {
-                        return Err(InputError::InconsistentVariablesCount { ident });
-                    }
-                    Ok(ExprOrNum::Expr(Box::new(Expression::Invoke(None, params))))
-                } else {
-                    match self.functions.get(&ident) {
-                        Some(f) => {
-                            if params.len() != f.incount {
-                                return Err(InputError::InconsistentVariablesCount { ident });
-                            }
-                            let mut nums = vec![];
-                            for param in params.iter() {
-                                match param {
-                                    &ExprOrNum::Expr(_) => break,
-                                    &ExprOrNum::Num(r) => nums.push(r),
-                                }
-                            }
-                            Ok(if params.len() == nums.len() {
-                                ExprOrNum::Num(f.invoke(&nums))
-                            } else {
-                                ExprOrNum::Expr(Box::new(Expression::Invoke(
-                                    Some(f.clone()),
-                                    params,
-                                )))
-                            })
-                        }
-                        None => Err(InputError::UndefinedIdentifier { ident }),
-                    }
+                let ident = children.pop().unwrap().assume_leaf()?.assume_ident()?;
+                if ident == b"fold" {
+                    return self.translate_fold(params_ast);
+                }
+                if ident == b"fit" {
+                    let expect = "fit as a statement of its own";
+                    let error = EvalError::InvalidArgument { expect };
+                    return Err(InputError::EvalError(error, None));
                 }
+                if let Some(higher) = Higher::named(&ident) {
+                    return self.translate_higher(higher, params_ast);
+                }
+                // the arguments of a lazy builtin are evaluated only when it asks for them
+                let lazy = match self.functions.get(&ident) {
+                    Some(f) => matches!(f.fimpl, FunctionImpl::Lazy(_)),
+                    None => false,
+                };
+                self.warn_deprecated(&ident);
+                self.warn_atan2(&ident);
+                let outer = self.fold;
+                let params = parameter_asts(params_ast)?
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, ast)| {
+                        // the first argument of a lazy builtin is always evaluated
+                        self.fold = outer && (!lazy || i == 0);
+                        self.translate_expression(ast)
+                    })
+                    .collect::<Result<Args<T>, _>>();
+                self.fold = outer;
+                let mut params = params?;
+                if let (b"if", [ExprOrValue::Value(cond), _, _]) = (ident.as_slice(), &*params) {
+                    let cond = cond.clone();
+                    let ex2 = params.pop().unwrap();
+                    let ex1 = params.pop().unwrap();
+                    return self.constant_branch(&cond, ex1, ex2);
+                }
+                self.translate_call(ident, params, fold)
             }
             // expression: IDENT
             ASTNode::Inner(18, mut children) => {
-                let ident = children.pop().unwrap().assume_leaf().assume_ident();
-                match self.cur_variables.iter().position(|v| *v == ident) {
-                    Some(i) => Ok(ExprOrNum::Expr(Box::new(Expression::Variable(i)))),
-                    None => match self.values.get(&ident) {
-                        Some((_, val)) => Ok(ExprOrNum::Num(*val)),
-                        None => Err(InputError::UndefinedIdentifier { ident }),
-                    },
+                let ident = children.pop().unwrap().assume_leaf()?.assume_ident()?;
+                if let Some(i) = self.variable(&ident) {
+                    return Ok(self.node(Expression::Variable(i)));
+                }
+                if let Some((_, local)) = self.cur_locals.iter().rfind(|(l, _)| *l == ident) {
+                    return Ok(local.clone());
+                }
+                match self.values.get(&ident) {
+                    Some((builtin, val, _)) => {
+                        let val = val.clone();
+                        if !builtin {
+                            self.cur_captures
+                                .push(Capture::Value(ident.clone(), val.clone()));
+                        }
+                        self.warn_deprecated(&ident);
+                        Ok(ExprOrValue::Value(val))
+                    }
+                    // a function standing for its call on the parameters
+                    None if self.cur_lifted && self.functions.contains_key(&ident) => {
+                        let params = (0..self.cur_variables.len())
+                            .map(|i| self.node(Expression::Variable(i)))
+                            .collect();
+                        self.translate_call(ident, params, fold)
+                    }
+                    None => {
+                        let name = String::from_utf8_lossy(&ident);
+                        match self.resolver.as_ref().and_then(|resolve| resolve(&name)) {
+                            Some(num) => {
+                                self.cur_captures
+                                    .push(Capture::Value(ident, Value::Num(num)));
+                                Ok(ExprOrValue::Value(Value::Num(num)))
+                            }
+                            None => Err(self.undefined(ident)),
+                        }
+                    }
                 }
             }
             // expression: NUM
             ASTNode::Inner(19, mut children) => {
-                let num = children.pop().unwrap().assume_leaf().assume_num();
-                Ok(ExprOrNum::Num(num))
+                let num = children.pop().unwrap().assume_leaf()?.assume_num()?;
+                Ok(ExprOrValue::Value(Value::literal(&num)))
+            }
+            // expression: '{' locals expression '}', optionally with a ';' before the '}'
+            ASTNode::Inner(22..=23, children) => {
+                let mut children = children.into_iter().skip(1);
+                let locals = children.next().unwrap();
+                self.translate_block(locals, children.next().unwrap())
+            }
+            _ => Err(InputError::InternalError {
+                message: "unknown expression node",
+            }),
+        }
+    }
+
+    /// Translate the result of a block with its locals in scope, each standing for the
+    /// translation of its expression.
+    fn translate_block(
+        &mut self,
+        locals: ASTNode,
+        result: ASTNode,
+    ) -> Result<ExprOrValue<T>, InputError> {
+        let mut asts = vec![];
+        let mut cur = locals;
+        // locals: locals IDENT '=' expression ';'
+        while let ASTNode::Inner(24, mut children) = cur {
+            children.pop();
+            let expr = children.pop().unwrap();
+            children.pop();
+            let (ident, position) = children.pop().unwrap().assume_leaf_at()?;
+            asts.push((ident.assume_ident()?, position, expr));
+            cur = children.pop().unwrap();
+        }
+        let scope = self.cur_locals.len();
+        let translate = || {
+            for (ident, position, expr) in asts.into_iter().rev() {
+                if self.is_builtin_value(&ident) {
+                    return Err(InputError::BuiltinIdentifier {
+                        ident,
+                        position: Some(position),
+                    });
+                }
+                if self.variable(&ident).is_some()
+                    || self.cur_locals.iter().any(|(local, _)| *local == ident)
+                {
+                    return Err(InputError::RepeatVariable {
+                        ident,
+                        position: Some(position),
+                    });
+                }
+                let local = self.translate_expression(expr)?;
+                self.cur_locals.push((ident, local));
+            }
+            self.translate_expression(result)
+        };
+        let result = translate();
+        self.cur_locals.truncate(scope);
+        result
+    }
+
+    /// The index of a variable in scope, the innermost `fold` binding shadowing the others.
+    fn variable(&self, ident: &[u8]) -> Option<usize> {
+        match self.cur_bound.iter().rposition(|v| v == ident) {
+            Some(i) => Some(self.cur_variables.len() + i),
+            None => self.cur_variables.iter().position(|v| v == ident),
+        }
+    }
+
+    fn variable_name(&self, i: usize) -> Ident {
+        match self.cur_variables.get(i) {
+            Some(ident) => ident.clone(),
+            None => self.cur_bound[i - self.cur_variables.len()].clone(),
+        }
+    }
+
+    fn translate_call(
+        &mut self,
+        ident: Ident,
+        params: Args<T>,
+        fold: bool,
+    ) -> Result<ExprOrValue<T>, InputError> {
+        if ident == self.cur_ident {
+            if params.len() != self.cur_variables.len() {
+                return Err(InputError::InconsistentVariablesCount {
+                    ident,
+                    position: None,
+                });
+            }
+            Ok(self.node(Expression::Invoke(None, params)))
+        } else {
+            match self.functions.get(&ident) {
+                Some(f) => {
+                    if f.source.is_some() {
+                        let capture = Capture::Function(ident.clone(), f.clone());
+                        self.cur_captures.push(capture);
+                    }
+                    let f = match self.functions[&ident].overload(params.len()) {
+                        Some(f) => f,
+                        None => {
+                            return Err(InputError::InconsistentVariablesCount {
+                                ident,
+                                position: None,
+                            })
+                        }
+                    };
+                    let mut vals = vec![];
+                    for param in params.iter() {
+                        match param {
+                            ExprOrValue::Expr(_) => break,
+                            ExprOrValue::Value(val) => vals.push(val.clone()),
+                        }
+                    }
+                    if fold && params.len() == vals.len() {
+                        return Ok(ExprOrValue::Value(
+                            f.invoke(&vals, &mut Context::default())?,
+                        ));
+                    }
+                    let f = f.clone();
+                    Ok(self.node(Expression::Invoke(Some(f), params)))
+                }
+                None => Err(self.undefined(ident)),
             }
-            _ => unreachable!(),
         }
     }
 
@@ -421,148 +3401,965 @@ impl Interpreter {
         let mut variables = vec![];
         let mut cur = ast;
         loop {
-            match cur {
+            let (leaf, rest) = match cur {
                 // variable_list: variable_list ',' IDENT
                 ASTNode::Inner(5, mut children) => {
-                    let ident = children.pop().unwrap().assume_leaf().assume_ident();
-                    if self.is_builtin_value(&ident) {
-                        return Err(InputError::BuiltinIdentifier { ident });
-                    }
-                    variables.push(ident);
+                    let leaf = children.pop().unwrap();
                     children.pop();
-                    cur = children.pop().unwrap();
+                    (leaf, children.pop())
                 }
                 // variable_list: IDENT
-                ASTNode::Inner(6, mut children) => {
-                    let ident = children.pop().unwrap().assume_leaf().assume_ident();
-                    if self.is_builtin_value(&ident) {
-                        return Err(InputError::BuiltinIdentifier { ident });
+                ASTNode::Inner(6, mut children) => (children.pop().unwrap(), None),
+                _ => {
+                    return Err(InputError::InternalError {
+                        message: "unknown variable list node",
+                    })
+                }
+            };
+            let (ident, position) = leaf.assume_leaf_at()?;
+            let ident = ident.assume_ident()?;
+            if self.is_builtin_value(&ident) {
+                return Err(InputError::BuiltinIdentifier {
+                    ident,
+                    position: Some(position),
+                });
+            }
+            variables.push((ident, position));
+            match rest {
+                Some(rest) => cur = rest,
+                None => break,
+            }
+        }
+        variables.reverse();
+        for (i, (var, _)) in variables.iter().enumerate() {
+            let last = variables.iter().rposition(|(v, _)| v == var).unwrap();
+            if last != i {
+                return Err(InputError::RepeatVariable {
+                    ident: var.clone(),
+                    position: Some(variables[last].1.clone()),
+                });
+            }
+        }
+        Ok(variables.into_iter().map(|(ident, _)| ident).collect())
+    }
+
+    /// Translate `map(f, v)`, `filter(p, v)`, `reduce(f, init, v)`, `contfrac_fn(a, b, n)`,
+    /// `grad(f, x1, ..., xn)` or `jacobian(f, x1, ..., xn)` with the functions named by the first
+    /// arguments.
+    fn translate_higher(
+        &mut self,
+        higher: Higher,
+        params: ASTNode,
+    ) -> Result<ExprOrValue<T>, InputError> {
+        let mut asts = parameter_asts(params)?;
+        let (name, incount, functions, arity) = higher.signature(asts.len());
+        if asts.len() != incount {
+            let ident = name.to_vec();
+            return Err(InputError::InconsistentVariablesCount {
+                ident,
+                position: None,
+            });
+        }
+        let mut fs = vec![];
+        for ast in asts.drain(..functions) {
+            let position = ast.position();
+            let ident = match ast {
+                // expression: IDENT
+                ASTNode::Inner(18, mut children) => {
+                    children.pop().unwrap().assume_leaf()?.assume_ident()?
+                }
+                _ => {
+                    let expect = "a function name";
+                    let error = EvalError::InvalidArgument { expect };
+                    return Err(InputError::EvalError(error, position));
+                }
+            };
+            fs.push(if ident == self.cur_ident {
+                if self.cur_variables.len() != arity {
+                    return Err(InputError::InconsistentVariablesCount {
+                        ident,
+                        position: None,
+                    });
+                }
+                None
+            } else {
+                self.warn_deprecated(&ident);
+                self.warn_atan2(&ident);
+                let f = self.lookup_function(&ident, arity)?.clone();
+                if f.source.is_some() {
+                    self.cur_captures.push(Capture::Function(ident, f.clone()));
+                }
+                Some(f)
+            });
+        }
+        let operands = asts
+            .into_iter()
+            .map(|ast| self.translate_expression(ast))
+            .collect::<Result<Args<T>, _>>()?;
+        Ok(self.node(Expression::Higher(higher, fs, operands)))
+    }
+
+    /// Fit the function named by the first argument of the statement `fit(f, xs, ys)` to the
+    /// data vectors `xs` and `ys`, starting from parameters of 1.
+    fn translate_fit(&mut self, ast: ASTNode) -> Result<InputState<T>, InputError> {
+        let position = ast.position();
+        let mut asts = match ast {
+            // expression: IDENT '(' parameter_list ')'
+            ASTNode::Inner(17, mut children) => {
+                children.pop();
+                parameter_asts(children.pop().unwrap())?
+            }
+            _ => {
+                return Err(InputError::InternalError {
+                    message: "unknown fit node",
+                })
+            }
+        };
+        if asts.len() != 3 {
+            return Err(InputError::InconsistentVariablesCount {
+                ident: b"fit".to_vec(),
+                position,
+            });
+        }
+        let mut data = vec![];
+        for ast in asts.split_off(1) {
+            let position = ast.position();
+            let expression = self.translate_expression(ast)?;
+            let body = self.take_body(expression);
+            let value = self
+                .evaluate(&Function::anonymous(body))
+                .and_then(|v| Ok(v.vector()?.iter().map(|x| x.to_f64()).collect::<Vec<_>>()))
+                .map_err(|e| InputError::from(e).at(position))?;
+            data.push(value);
+        }
+        let ast = asts.pop().unwrap();
+        let model_position = ast.position();
+        let ident = match ast {
+            // expression: IDENT
+            ASTNode::Inner(18, mut children) => {
+                children.pop().unwrap().assume_leaf()?.assume_ident()?
+            }
+            _ => {
+                let expect = "a function name";
+                let error = EvalError::InvalidArgument { expect };
+                return Err(InputError::EvalError(error, model_position));
+            }
+        };
+        let incount = match self.functions.get(&ident) {
+            Some(f) => f.incount,
+            None => return Err(self.undefined(ident).at(model_position)),
+        };
+        if incount < 2 {
+            let expect = "a function of x and parameters";
+            let error = EvalError::InvalidArgument { expect };
+            return Err(InputError::EvalError(error, model_position));
+        }
+        let f = String::from_utf8_lossy(&ident).into_owned();
+        self.fit_bindings(&f, &data[0], &data[1], &vec![1.0; incount - 1])
+            .map_err(|e| InputError::from(e).at(position))?;
+        Ok(InputState::Assignment {
+            name: format!("{}_fit", f),
+            assigned: Assigned::Function { arity: 1 },
+            warnings: self.warnings.split_off(0),
+            echo: self.echo(),
+        })
+    }
+
+    /// Translate `fold(i, a, b, acc0, expr)`, binding the index `i` and the accumulator `acc` in
+    /// `expr`.
+    fn translate_fold(&mut self, params: ASTNode) -> Result<ExprOrValue<T>, InputError> {
+        let mut asts = parameter_asts(params)?;
+        if asts.len() != 5 {
+            let ident = b"fold".to_vec();
+            return Err(InputError::InconsistentVariablesCount {
+                ident,
+                position: None,
+            });
+        }
+        let body_ast = asts.pop().unwrap();
+        let index_ast = asts.remove(0);
+        let position = index_ast.position();
+        let index = match index_ast {
+            // expression: IDENT
+            ASTNode::Inner(18, mut children) => {
+                children.pop().unwrap().assume_leaf()?.assume_ident()?
+            }
+            _ => {
+                let expect = "a variable name as the index of fold";
+                let error = EvalError::InvalidArgument { expect };
+                return Err(InputError::EvalError(error, position));
+            }
+        };
+        let mut operands = asts
+            .into_iter()
+            .map(|ast| self.translate_expression(ast))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.cur_bound.push(index);
+        self.cur_bound.push(b"acc".to_vec());
+        let body = self.translate_expression(body_ast);
+        self.cur_bound.truncate(self.cur_bound.len() - 2);
+        let init = operands.pop().unwrap();
+        let hi = operands.pop().unwrap();
+        let lo = operands.pop().unwrap();
+        let body = body?;
+        Ok(self.node(Expression::Fold(lo, hi, init, body)))
+    }
+
+    /// The identifier of a `help(ident)` statement, unless `help` is a user function.
+    fn help_target(&self, ast: &ASTNode) -> Option<Ident> {
+        if self.functions.contains_key(b"help".as_slice()) {
+            return None;
+        }
+        // expression: IDENT '(' parameter_list ')'
+        if let ASTNode::Inner(17, children) = ast {
+            if let [ASTNode::Leaf(Token::IDENT(f), _), _, ASTNode::Inner(21, params), _] =
+                children.as_slice()
+            {
+                // parameter_list: expression, expression: IDENT
+                if let [ASTNode::Inner(18, param)] = params.as_slice() {
+                    if let [ASTNode::Leaf(Token::IDENT(ident), _)] = param.as_slice() {
+                        if f == b"help" {
+                            return Some(ident.clone());
+                        }
                     }
-                    variables.push(ident);
-                    return Ok(variables);
                 }
-                _ => unreachable!(),
             }
         }
+        None
     }
 
-    fn translate_parameter_list(&self, ast: ASTNode) -> Result<Vec<ExprOrNum>, InputError> {
-        let mut params = vec![];
-        let mut cur = ast;
-        loop {
-            match cur {
-                // parameter_list: parameter_list ',' expression
-                ASTNode::Inner(20, mut children) => {
-                    let expr = self.translate_expression(children.pop().unwrap())?;
-                    params.push(expr);
-                    children.pop();
-                    cur = children.pop().unwrap();
+    /// Functions named in `ast` without being called, where no variable has their name.
+    fn bare_functions(&self, ast: &ASTNode, found: &mut Vec<Ident>) {
+        match ast {
+            // expression: IDENT
+            ASTNode::Inner(18, children) => {
+                if let [ASTNode::Leaf(Token::IDENT(ident), _)] = children.as_slice() {
+                    if self.functions.contains_key(ident)
+                        && !self.values.contains_key(ident)
+                        && !found.contains(ident)
+                    {
+                        found.push(ident.clone());
+                    }
+                }
+            }
+            // expression: '{' locals expression '}', optionally with a ';' before the '}'
+            ASTNode::Inner(22..=23, children) => {
+                let locals = block_locals(&children[1]);
+                let mut inner = vec![];
+                for (_, expr) in &locals {
+                    self.bare_functions(expr, &mut inner);
                 }
-                // parameter_list: expression
-                ASTNode::Inner(21, mut children) => {
-                    let expr = self.translate_expression(children.pop().unwrap())?;
-                    params.push(expr);
-                    return Ok(params);
+                self.bare_functions(&children[2], &mut inner);
+                for ident in inner {
+                    if !found.contains(&ident) && locals.iter().all(|(local, _)| *local != &ident) {
+                        found.push(ident);
+                    }
+                }
+            }
+            ASTNode::Inner(_, children) => {
+                for child in children {
+                    self.bare_functions(child, found);
                 }
-                _ => unreachable!(),
+            }
+            ASTNode::Leaf(..) => (),
+        }
+    }
+
+    /// Define `ident` by an expression of the functions `lifted`, each standing for its call on
+    /// the parameters, as in `h = f + g` for `h: x = f(x) + g(x)`.
+    fn translate_function_algebra(
+        &mut self,
+        ident: Ident,
+        lifted: Vec<Ident>,
+        expr_ast: ASTNode,
+    ) -> Result<InputState<T>, InputError> {
+        if self.is_builtin(&ident) {
+            return Err(InputError::BuiltinIdentifier {
+                ident,
+                position: None,
+            });
+        }
+        let first = self.functions[&lifted[0]].clone();
+        for other in &lifted[1..] {
+            if self.functions[other].overload(first.incount).is_none() {
+                return Err(InputError::InconsistentVariablesCount {
+                    ident: other.clone(),
+                    position: None,
+                });
+            }
+        }
+        // the parameter names of the first function, unless the expression uses them otherwise
+        let mut idents = vec![];
+        leaf_idents(&expr_ast, &mut idents);
+        let mut variables = first.variables.clone();
+        if variables.len() != first.incount || variables.iter().any(|v| idents.contains(v)) {
+            let mut prefix = b"x".to_vec();
+            while idents.iter().any(|ident| ident.starts_with(&prefix)) {
+                prefix.push(b'_');
+            }
+            variables = (1..=first.incount)
+                .map(|i| [prefix.as_slice(), i.to_string().as_bytes()].concat())
+                .collect();
+        }
+        self.cur_ident = ident;
+        self.cur_variables = variables;
+        self.cur_lifted = true;
+        let expression = self.translate_expression(expr_ast);
+        self.cur_lifted = false;
+        let body = self.take_body(expression?);
+        self.analyze_function(&body);
+        let body = self.share_common(self.inline_calls(body));
+        let function = Function {
+            name: self.cur_ident.clone(),
+            incount: self.cur_variables.len(),
+            variables: self.cur_variables.clone(),
+            doc: self.cur_doc.take(),
+            source: Some(
+                Source::new(self.cur_source.join("\n")).capturing(self.cur_captures.split_off(0)),
+            ),
+            fimpl: FunctionImpl::User(body),
+            overloads: vec![],
+        };
+        let binding = self.set_function(self.cur_ident.clone(), Arc::new(function));
+        self.record(vec![binding]);
+        Ok(InputState::Assignment {
+            name: String::from_utf8_lossy(&self.cur_ident).into_owned(),
+            assigned: Assigned::Function {
+                arity: self.cur_variables.len(),
+            },
+            warnings: self.warnings.split_off(0),
+            echo: self.echo(),
+        })
+    }
+
+    /// Add `expr` to the expressions of the statement being translated.
+    fn node(&mut self, expr: Expression<T>) -> ExprOrValue<T> {
+        ExprOrValue::Expr(self.cur_arena.push(expr))
+    }
+
+    /// The translated expression `root`, taking the expressions of the statement.
+    fn take_body(&mut self, root: ExprOrValue<T>) -> Body<T> {
+        let arena = std::mem::take(&mut self.cur_arena);
+        Body { arena, root }.compact()
+    }
+
+    /// Warn about unused parameters and unconditional recursion of the function being defined.
+    fn analyze_function(&mut self, body: &Body<T>) {
+        let mut used = vec![false; self.cur_variables.len()];
+        if let ExprOrValue::Expr(expr) = body.root {
+            body.arena.mark_variables(expr, &mut used);
+            if body.arena.always_recurses(expr) {
+                let ident = self.cur_ident.clone();
+                self.warnings.push(Warning::UnboundedRecursion { ident });
+            }
+        }
+        for (ident, used) in self.cur_variables.iter().zip(used) {
+            if !used {
+                let ident = ident.clone();
+                self.warnings.push(Warning::UnusedParameter { ident });
             }
         }
     }
 
     fn is_builtin_value(&self, ident: &Ident) -> bool {
         match self.values.get(ident) {
-            Some((builtin, _)) => *builtin,
+            Some((builtin, _, _)) => *builtin,
             None => false,
         }
     }
 
     fn is_builtin(&self, ident: &Ident) -> bool {
-        self.is_builtin_value(ident)
+        SPECIAL_FORMS.contains(&ident.as_slice())
+            || self.is_builtin_value(ident)
             || match self.functions.get(ident) {
-                Some(f) => match f.fimpl {
-                    FunctionImpl::Lib(_) => true,
-                    FunctionImpl::User(_) => false,
-                },
+                Some(f) => !matches!(f.fimpl, FunctionImpl::User(_)),
                 None => false,
             }
     }
 }
 
-impl Function {
-    fn builtin(incount: usize, f: fn(&[Real]) -> Real) -> Arc<Self> {
+const DEFAULT_EPSILON: Real = 1e-9;
+
+/// Names bound to the second and third to last results.
+const EARLIER_RESULTS: [&[u8]; 2] = [b"__", b"___"];
+
+/// Values and functions held before a map of bindings grows, room for all builtins and as
+/// many user definitions.
+const BINDINGS_CAPACITY: (usize, usize) = (32, 128);
+
+/// Builtins translated from their unevaluated arguments.
+type GenericFn<T = Real> = fn(&[Value<T>]) -> Result<Value<T>, EvalError>;
+
+/// `||` or `&&` on values.
+type LogicFn<T> = fn(&Value<T>, &Value<T>) -> Result<Value<T>, EvalError>;
+
+/// Aggregates with a compensated and a fast form.
+const SUMS: &[(&[u8], GenericFn, GenericFn)] = &[
+    (b"sum", stats::sum, stats::fast_sum),
+    (b"mean", stats::mean, stats::fast_mean),
+    (b"var", stats::var, stats::fast_var),
+    (b"std", stats::std, stats::fast_std),
+];
+
+const SPECIAL_FORMS: &[&[u8]] = &[
+    b"fold",
+    b"map",
+    b"filter",
+    b"reduce",
+    b"contfrac_fn",
+    b"grad",
+    b"jacobian",
+    b"fit",
+];
+
+/// Feed the tokens of line `number` of a statement, `end` columns long, to `parser`, counting the
+/// `blocks` left open, and return whether the statement may end with it: not if it is continued
+/// with `...`, nor in a block, where the end of a line ends a local as a `;` does.
+fn feed_line(
+    parser: &mut Parser,
+    ts: TokenStream,
+    blocks: &mut usize,
+    number: usize,
+    end: usize,
+) -> Result<bool, InputError> {
+    // whether a `;` would be out of place after the tokens so far
+    let mut separated = true;
+    for (span, token) in ts.tokens {
+        match token {
+            Token::LBRACE => *blocks += 1,
+            Token::RBRACE => *blocks = blocks.saturating_sub(1),
+            _ => (),
+        }
+        separated = matches!(token, Token::LBRACE | Token::SEMI);
+        if let Err(e) = parser.action(token, (number, span)) {
+            return Err(syntax_error(Some(e), (number, end)));
+        }
+    }
+    if !ts.complete {
+        return Ok(false);
+    }
+    if *blocks > 0 && !separated {
+        if let Err(e) = parser.action(Token::SEMI, (number, end..end)) {
+            return Err(syntax_error(Some(e), (number, end)));
+        }
+    }
+    Ok(*blocks == 0)
+}
+
+/// The syntax error of `error`, at line and column `end` if at the end of the tokens or unknown.
+fn syntax_error(error: Option<Unexpected>, end: (usize, usize)) -> InputError {
+    let (position, expected) = match error {
+        Some(e) => (e.position, e.expected),
+        None => (None, vec![]),
+    };
+    let (line, column) = position.map_or(end, |(line, span)| (line, span.start));
+    InputError::SyntaxError {
+        line,
+        column,
+        expected: expected
+            .iter()
+            .map(|&id| Token::NAMES[id as usize])
+            .collect(),
+    }
+}
+
+/// The statement `ast` without its final `;`, and whether it had one.
+fn unterminated(ast: ASTNode) -> (ASTNode, bool) {
+    match ast {
+        // statement: assignment ';' or expression ';'
+        ASTNode::Inner(id @ 26..=27, mut children) => {
+            children.pop();
+            (ASTNode::Inner(id - 25, children), true)
+        }
+        ast => (ast, false),
+    }
+}
+
+/// The locals of a block, in order, with their expressions.
+fn block_locals(ast: &ASTNode) -> Vec<(&Ident, &ASTNode)> {
+    let mut locals = vec![];
+    let mut cur = ast;
+    // locals: locals IDENT '=' expression ';'
+    while let ASTNode::Inner(24, children) = cur {
+        if let [_, ASTNode::Leaf(Token::IDENT(ident), _), _, expr, _] = children.as_slice() {
+            locals.push((ident, expr));
+        }
+        cur = &children[0];
+    }
+    locals.reverse();
+    locals
+}
+
+/// Append the identifiers and operator spellings of `ast`.
+fn leaf_idents(ast: &ASTNode, idents: &mut Vec<Ident>) {
+    match ast {
+        ASTNode::Leaf(Token::IDENT(ident), _) | ASTNode::Leaf(Token::OP(_, ident), _) => {
+            idents.push(ident.clone())
+        }
+        ASTNode::Leaf(..) => (),
+        ASTNode::Inner(_, children) => {
+            for child in children {
+                leaf_idents(child, idents);
+            }
+        }
+    }
+}
+
+/// Number of insertions, deletions, substitutions and swaps of adjacent bytes turning `a` into
+/// `b`.
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    // rows of the distances from the prefixes of `a` to those of `b`
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut last: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            row[j] = (last[j] + 1).min(row[j - 1] + 1).min(last[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut last, row);
+    }
+    last[b.len()]
+}
+
+/// The arguments of a parameter list, in order.
+fn parameter_refs<'a>(ast: &'a ASTNode, args: &mut Vec<&'a ASTNode>) {
+    match ast {
+        // parameter_list: parameter_list ',' expression
+        ASTNode::Inner(20, children) => {
+            parameter_refs(&children[0], args);
+            args.push(&children[2]);
+        }
+        // parameter_list: expression
+        ASTNode::Inner(21, children) => args.push(&children[0]),
+        _ => (),
+    }
+}
+
+/// The flattened arguments of a parameter list.
+/// The name of the function called by the expression `ast`, if a call.
+fn called(ast: &ASTNode) -> Option<&[u8]> {
+    match ast {
+        // expression: IDENT '(' parameter_list ')'
+        ASTNode::Inner(17, children) => match children.first() {
+            Some(ASTNode::Leaf(Token::IDENT(f), _)) => Some(f),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn parameter_asts(ast: ASTNode) -> Result<Vec<ASTNode>, InputError> {
+    let mut asts = vec![];
+    let mut cur = ast;
+    loop {
+        match cur {
+            // parameter_list: parameter_list ',' expression
+            ASTNode::Inner(20, mut children) => {
+                asts.push(children.pop().unwrap());
+                children.pop();
+                cur = children.pop().unwrap();
+            }
+            // parameter_list: expression
+            ASTNode::Inner(21, mut children) => {
+                asts.push(children.pop().unwrap());
+                asts.reverse();
+                return Ok(asts);
+            }
+            _ => {
+                return Err(InputError::InternalError {
+                    message: "unknown parameter list node",
+                })
+            }
+        }
+    }
+}
+
+/// An element of a vector built by `map`, booleans counting as `1` and `0`.
+fn element<T: Numeric>(val: &Value<T>) -> Result<T, EvalError> {
+    match val {
+        Value::Bool(b) => Ok(T::from_i64(*b as i64)),
+        val => val.num(),
+    }
+}
+
+/// An integer bound of a `fold` range or the depth of a continued fraction.
+fn bound<T: Numeric>(val: &Value<T>) -> Result<i64, EvalError> {
+    match val {
+        Value::Int(i) => Ok(*i),
+        Value::Num(r) if r.fract() == T::ZERO && r.abs() <= T::from_f64(number::MAX_EXACT) => {
+            Ok(r.to_f64() as i64)
+        }
+        _ => Err(EvalError::InvalidArgument {
+            expect: "integer bound",
+        }),
+    }
+}
+
+/// A boolean as `1` or `0`, any other value as is.
+fn numeric<T: Numeric>(v: &[Value<T>]) -> Result<Value<T>, EvalError> {
+    Ok(match &v[0] {
+        Value::Bool(b) => Value::Num(T::from_i64(*b as i64)),
+        val => val.clone(),
+    })
+}
+
+/// `if(cond, a, b)`, evaluating only the branch taken.
+fn lazy_if<T: Numeric>(arg: &mut LazyArgs<T>) -> Result<Value<T>, EvalError> {
+    match arg(0)?.truth()? {
+        true => arg(1),
+        false => arg(2),
+    }
+}
+
+fn evaluate<T: Numeric>(body: Body<T>, ctx: &mut Context<T>) -> Result<Value<T>, EvalError> {
+    match body.root {
+        ExprOrValue::Value(val) => Ok(val),
+        _ => Function::anonymous(body).invoke(&[], ctx),
+    }
+}
+
+impl<T: Numeric> Function<T> {
+    /// A function of no arguments evaluating `body`, not traced as a call.
+    fn anonymous(body: Body<T>) -> Self {
+        Function {
+            name: vec![],
+            incount: 0,
+            variables: vec![],
+            doc: None,
+            source: None,
+            fimpl: FunctionImpl::User(body),
+            overloads: vec![],
+        }
+    }
+
+    fn builtin(name: &[u8], incount: usize, fimpl: FunctionImpl<T>) -> Arc<Self> {
         Arc::new(Function {
+            name: name.to_vec(),
             incount,
-            fimpl: FunctionImpl::Lib(f),
+            variables: vec![],
+            doc: None,
+            source: None,
+            fimpl,
+            overloads: vec![],
         })
     }
 
-    fn invoke(&self, args: &[Real]) -> Real {
-        match &self.fimpl {
-            FunctionImpl::Lib(f) => f(args),
-            FunctionImpl::User(expr) => self.calc_expr_or_num(expr, args),
+    /// This function or its overload taking `incount` arguments.
+    fn overload(self: &Arc<Self>, incount: usize) -> Option<&Arc<Function<T>>> {
+        std::iter::once(self)
+            .chain(&self.overloads)
+            .find(|f| f.incount == incount)
+    }
+
+    fn invoke(&self, args: &[Value<T>], ctx: &mut Context<T>) -> Result<Value<T>, EvalError> {
+        let start = match (&ctx.profile, self.name.is_empty()) {
+            (Some(_), false) => Some(Instant::now()),
+            _ => None,
+        };
+        let result = match &self.fimpl {
+            FunctionImpl::Lib(f) => Value::broadcast(args, *f)?,
+            FunctionImpl::Generic(f) => f(args)?,
+            FunctionImpl::RealLib(f) => {
+                let args = args.iter().map(Value::to_real).collect::<Vec<_>>();
+                Value::from_real(Value::broadcast(&args, *f)?)
+            }
+            FunctionImpl::RealGeneric(f) => {
+                let args = args.iter().map(Value::to_real).collect::<Vec<_>>();
+                Value::from_real(f(&args)?)
+            }
+            FunctionImpl::Lazy(f) => f(&mut |i| Ok(args[i].clone()))?,
+            FunctionImpl::User(body) if self.name.is_empty() => {
+                self.calc_expr_or_value(&body.arena, &body.root, args, ctx)?
+            }
+            FunctionImpl::User(body) => {
+                if ctx.budget.is_some_and(|budget| ctx.depth >= budget.depth) {
+                    return Err(EvalError::BudgetExceeded {
+                        limit: "call depth",
+                    });
+                }
+                ctx.pause(self, args);
+                ctx.depth += 1;
+                let result = self.calc_expr_or_value(&body.arena, &body.root, args, ctx);
+                ctx.depth -= 1;
+                result?
+            }
+        };
+        if !self.name.is_empty() {
+            ctx.record(
+                &String::from_utf8_lossy(&self.name),
+                || args.to_vec(),
+                &result,
+            )?;
+        }
+        if let (Some(start), Some(profile)) = (start, &mut ctx.profile) {
+            let (calls, time) = profile.entry(self.name.clone()).or_default();
+            *calls += 1;
+            *time += start.elapsed();
         }
+        Ok(result)
+    }
+
+    fn invoke_real(&self, args: &[Real]) -> Result<Real, EvalError> {
+        let args = args
+            .iter()
+            .map(|&r| Value::Num(T::from_f64(r)))
+            .collect::<Vec<_>>();
+        Ok(self.invoke(&args, &mut Context::default())?.num()?.to_f64())
     }
 
-    fn calc_expr_or_num(&self, expr: &ExprOrNum, args: &[Real]) -> Real {
+    fn calc_expr_or_value(
+        &self,
+        arena: &Arena<T>,
+        expr: &ExprOrValue<T>,
+        args: &[Value<T>],
+        ctx: &mut Context<T>,
+    ) -> Result<Value<T>, EvalError> {
         match expr {
-            ExprOrNum::Expr(expr) => self.calc_expr(expr, args),
-            ExprOrNum::Num(r) => *r,
+            ExprOrValue::Expr(expr) => self.calc_expr(arena, *expr, args, ctx),
+            ExprOrValue::Value(val) => Ok(val.clone()),
         }
     }
 
-    fn calc_expr(&self, expr: &Expression, args: &[Real]) -> Real {
-        match expr {
-            Expression::Not(expr) => match self.calc_expr(expr, args) == 0.0 {
-                true => 1.0,
-                false => 0.0,
-            },
-            Expression::Neg(expr) => -self.calc_expr(expr, args),
-            Expression::Exp(ex1, ex2) => self
-                .calc_expr_or_num(ex1, args)
-                .powf(self.calc_expr_or_num(ex2, args)),
+    #[allow(clippy::too_many_arguments)]
+    fn calc_binary<F: Fn(&Value<T>, &Value<T>) -> Result<Value<T>, EvalError>>(
+        &self,
+        arena: &Arena<T>,
+        operation: &str,
+        ex1: &ExprOrValue<T>,
+        ex2: &ExprOrValue<T>,
+        args: &[Value<T>],
+        ctx: &mut Context<T>,
+        f: F,
+    ) -> Result<Value<T>, EvalError> {
+        let v1 = self.calc_expr_or_value(arena, ex1, args, ctx)?;
+        let v2 = self.calc_expr_or_value(arena, ex2, args, ctx)?;
+        let result = f(&v1, &v2)?;
+        ctx.record(operation, || vec![v1, v2], &result)?;
+        Ok(result)
+    }
+
+    /// Evaluate `ex1 || ex2` (`stop` true) or `ex1 && ex2` (`stop` false) from left to right,
+    /// skipping `ex2` when a scalar `ex1` decides the result.
+    #[allow(clippy::too_many_arguments)]
+    fn calc_logic(
+        &self,
+        arena: &Arena<T>,
+        operation: &str,
+        stop: bool,
+        ex1: &ExprOrValue<T>,
+        ex2: &ExprOrValue<T>,
+        args: &[Value<T>],
+        ctx: &mut Context<T>,
+        f: LogicFn<T>,
+    ) -> Result<Value<T>, EvalError> {
+        let v1 = self.calc_expr_or_value(arena, ex1, args, ctx)?;
+        if v1.short_circuits(stop)? {
+            let result = Value::Bool(stop);
+            ctx.record(operation, || vec![v1], &result)?;
+            return Ok(result);
+        }
+        let v2 = self.calc_expr_or_value(arena, ex2, args, ctx)?;
+        let result = f(&v1, &v2)?;
+        ctx.record(operation, || vec![v1, v2], &result)?;
+        Ok(result)
+    }
+
+    fn calc_expr(
+        &self,
+        arena: &Arena<T>,
+        id: ExprId,
+        args: &[Value<T>],
+        ctx: &mut Context<T>,
+    ) -> Result<Value<T>, EvalError> {
+        match &arena[id] {
+            Expression::Not(expr) => {
+                let val = self.calc_expr(arena, *expr, args, ctx)?;
+                let result = val.not()?;
+                ctx.record("!", || vec![val], &result)?;
+                Ok(result)
+            }
+            Expression::Neg(expr) => {
+                let val = self.calc_expr(arena, *expr, args, ctx)?;
+                let result = val.neg()?;
+                ctx.record("-", || vec![val], &result)?;
+                Ok(result)
+            }
+            Expression::Exp(ex1, ex2) => {
+                self.calc_binary(arena, "^", ex1, ex2, args, ctx, Value::pow)
+            }
             Expression::Mul(ex1, ex2) => {
-                self.calc_expr_or_num(ex1, args) * self.calc_expr_or_num(ex2, args)
+                self.calc_binary(arena, "*", ex1, ex2, args, ctx, Value::mul)
             }
             Expression::Div(ex1, ex2) => {
-                self.calc_expr_or_num(ex1, args) / self.calc_expr_or_num(ex2, args)
+                self.calc_binary(arena, "/", ex1, ex2, args, ctx, Value::div)
             }
             Expression::Add(ex1, ex2) => {
-                self.calc_expr_or_num(ex1, args) + self.calc_expr_or_num(ex2, args)
+                self.calc_binary(arena, "+", ex1, ex2, args, ctx, Value::add)
             }
             Expression::Sub(ex1, ex2) => {
-                self.calc_expr_or_num(ex1, args) - self.calc_expr_or_num(ex2, args)
+                self.calc_binary(arena, "-", ex1, ex2, args, ctx, Value::sub)
+            }
+            Expression::Compare(cmp, ex1, ex2) => {
+                self.calc_binary(arena, cmp.symbol(), ex1, ex2, args, ctx, |v1, v2| {
+                    v1.compare(v2, *cmp)
+                })
+            }
+            Expression::Or(ex1, ex2) => {
+                self.calc_logic(arena, "||", true, ex1, ex2, args, ctx, Value::or)
+            }
+            Expression::And(ex1, ex2) => {
+                self.calc_logic(arena, "&&", false, ex1, ex2, args, ctx, Value::and)
+            }
+            Expression::Condition(expr, ex1, ex2) => {
+                let cond = self.calc_expr(arena, *expr, args, ctx)?;
+                let result = match cond.truth()? {
+                    true => self.calc_expr_or_value(arena, ex1, args, ctx)?,
+                    false => self.calc_expr_or_value(arena, ex2, args, ctx)?,
+                };
+                ctx.record("?:", || vec![cond], &result)?;
+                Ok(result)
             }
-            Expression::Compare(cmp, ex1, ex2) => cmp.on(
-                self.calc_expr_or_num(ex1, args),
-                self.calc_expr_or_num(ex2, args),
-            ),
-            Expression::Or(ex1, ex2) => match self.calc_expr_or_num(ex1, args) != 0.0
-                || self.calc_expr_or_num(ex2, args) != 0.0
-            {
-                true => 1.0,
-                false => 0.0,
-            },
-            Expression::And(ex1, ex2) => match self.calc_expr_or_num(ex1, args) != 0.0
-                && self.calc_expr_or_num(ex2, args) != 0.0
-            {
-                true => 1.0,
-                false => 0.0,
-            },
-            Expression::Condition(expr, ex1, ex2) => match self.calc_expr(expr, args) != 0.0 {
-                true => self.calc_expr_or_num(ex1, args),
-                false => self.calc_expr_or_num(ex2, args),
-            },
             Expression::Invoke(f, expr) => {
-                let args = expr
+                if let Some((f, FunctionImpl::Lazy(lazy))) = f.as_ref().map(|f| (f, &f.fimpl)) {
+                    let mut evaluated = vec![];
+                    let result = lazy(&mut |i| {
+                        let value = self.calc_expr_or_value(arena, &expr[i], args, ctx)?;
+                        evaluated.push(value.clone());
+                        Ok(value)
+                    })?;
+                    ctx.record(&String::from_utf8_lossy(&f.name), || evaluated, &result)?;
+                    return Ok(result);
+                }
+                let mut frame = ctx.frames.pop().unwrap_or_default();
+                let result = expr
                     .iter()
-                    .map(|e| self.calc_expr_or_num(e, args))
-                    .collect::<Vec<_>>();
-                match f {
-                    Some(f) => f.invoke(args.as_slice()),
-                    None => self.invoke(args.as_slice()),
+                    .try_for_each(|e| {
+                        frame.push(self.calc_expr_or_value(arena, e, args, ctx)?);
+                        Ok(())
+                    })
+                    .and_then(|()| match f {
+                        Some(f) => f.invoke(&frame, ctx),
+                        None => self.invoke(&frame, ctx),
+                    });
+                frame.clear();
+                ctx.frames.push(frame);
+                result
+            }
+            Expression::Higher(higher, fs, operands) => {
+                let operands = operands
+                    .iter()
+                    .map(|e| self.calc_expr_or_value(arena, e, args, ctx))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let f = fs[0].as_deref().unwrap_or(self);
+                let v = operands.last().unwrap();
+                match higher {
+                    Higher::Map => {
+                        let mut mapped = vec![];
+                        for &x in v.vector()? {
+                            mapped.push(element(&f.invoke(&[Value::Num(x)], ctx)?)?);
+                        }
+                        Ok(mapped.into())
+                    }
+                    Higher::Filter => {
+                        let mut kept = vec![];
+                        for &x in v.vector()? {
+                            if f.invoke(&[Value::Num(x)], ctx)?.truth()? {
+                                kept.push(x);
+                            }
+                        }
+                        Ok(kept.into())
+                    }
+                    Higher::Reduce => {
+                        let mut acc = operands[0].clone();
+                        for &x in v.vector()? {
+                            acc = f.invoke(&[acc, Value::Num(x)], ctx)?;
+                        }
+                        Ok(acc)
+                    }
+                    Higher::ContFrac => {
+                        let b = fs[1].as_deref().unwrap_or(self);
+                        let mut term = |f: &Function<T>, k: i64| f.invoke(&[Value::Int(k)], ctx);
+                        // b(0) + a(1) / (b(1) + a(2) / (... + a(n) / b(n))), innermost first
+                        let n = bound(v)?;
+                        if n < 0 {
+                            let expect = "non-negative depth";
+                            return Err(EvalError::InvalidArgument { expect });
+                        }
+                        let mut t = term(b, n)?;
+                        for k in (1..=n).rev() {
+                            t = term(b, k - 1)?.add(&term(f, k)?.div(&t)?)?;
+                        }
+                        Ok(t)
+                    }
+                    Higher::Grad | Higher::Jacobian => {
+                        let x = operands
+                            .iter()
+                            .map(|x| Ok(element(x)?.to_f64()))
+                            .collect::<Result<Vec<_>, EvalError>>()?;
+                        // the number of outputs of `f`, the same at every point
+                        let mut outputs = None;
+                        let jac = solver::differences(
+                            |x| {
+                                let args = x.iter().map(|&r| Value::Num(T::from_f64(r)));
+                                let y = match (higher, f.invoke(&args.collect::<Vec<_>>(), ctx)?) {
+                                    (Higher::Jacobian, Value::Vector(y)) => y.to_vec(),
+                                    (_, y) => vec![element(&y)?],
+                                };
+                                let left = *outputs.get_or_insert(y.len());
+                                if y.len() != left {
+                                    let right = y.len();
+                                    return Err(EvalError::LengthMismatch { left, right });
+                                }
+                                Ok(y.into_iter().map(T::to_f64).collect())
+                            },
+                            &x,
+                        )?;
+                        Ok(jac
+                            .concat()
+                            .into_iter()
+                            .map(T::from_f64)
+                            .collect::<Vec<_>>()
+                            .into())
+                    }
                 }
             }
-            Expression::Variable(i) => args[*i],
+            Expression::Fold(lo, hi, init, body) => {
+                let lo = bound(&self.calc_expr_or_value(arena, lo, args, ctx)?)?;
+                let hi = bound(&self.calc_expr_or_value(arena, hi, args, ctx)?)?;
+                let init = self.calc_expr_or_value(arena, init, args, ctx)?;
+                let mut scope = ctx.frames.pop().unwrap_or_default();
+                scope.extend_from_slice(args);
+                let result = (lo..=hi).try_fold(init, |acc, i| {
+                    scope.truncate(args.len());
+                    scope.push(Value::Int(i));
+                    scope.push(acc);
+                    self.calc_expr_or_value(arena, body, &scope, ctx)
+                });
+                scope.clear();
+                ctx.frames.push(scope);
+                result
+            }
+            Expression::Let(temps, body) => {
+                let base = ctx.temps.len();
+                let outer = std::mem::replace(&mut ctx.temp_base, base);
+                let result = temps
+                    .iter()
+                    .try_for_each(|temp| {
+                        let value = self.calc_expr(arena, *temp, args, ctx)?;
+                        ctx.temps.push(value);
+                        Ok(())
+                    })
+                    .and_then(|()| self.calc_expr(arena, *body, args, ctx));
+                ctx.temps.truncate(base);
+                ctx.temp_base = outer;
+                result
+            }
+            Expression::Temp(k) => Ok(ctx.temps[ctx.temp_base + k].clone()),
+            Expression::Variable(i) => Ok(args[*i].clone()),
         }
     }
 }
@@ -0,0 +1,235 @@
+//! Translated expressions, each referring to its subexpressions by index in an arena
+
+use std::{
+    ops::{Index, IndexMut},
+    sync::Arc,
+};
+
+use smallvec::SmallVec;
+
+use super::{Function, FunctionImpl, Higher};
+use crate::{lexer::CompareOp, value::Value, Numeric};
+
+/// A translated expression, its subexpressions in `arena`.
+pub(super) struct Body<T> {
+    pub(super) arena: Arena<T>,
+    pub(super) root: ExprOrValue<T>,
+}
+
+/// The expressions of a body, each referring to its subexpressions by index instead of owning
+/// them, so that a body is one allocation.
+#[derive(Default)]
+pub(super) struct Arena<T>(pub(super) Vec<Expression<T>>);
+
+/// Index of an expression in its arena.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) struct ExprId(u32);
+
+#[derive(Clone)]
+pub(super) enum ExprOrValue<T> {
+    Expr(ExprId),
+    Value(Value<T>),
+}
+
+/// Arguments of a call, inline up to three.
+pub(super) type Args<T> = SmallVec<[ExprOrValue<T>; 3]>;
+
+#[derive(Clone)]
+pub(super) enum Expression<T> {
+    Not(ExprId),
+    Neg(ExprId),
+    Exp(ExprOrValue<T>, ExprOrValue<T>),
+    Mul(ExprOrValue<T>, ExprOrValue<T>),
+    Div(ExprOrValue<T>, ExprOrValue<T>),
+    Add(ExprOrValue<T>, ExprOrValue<T>),
+    Sub(ExprOrValue<T>, ExprOrValue<T>),
+    Compare(CompareOp, ExprOrValue<T>, ExprOrValue<T>),
+    Or(ExprOrValue<T>, ExprOrValue<T>),
+    And(ExprOrValue<T>, ExprOrValue<T>),
+    Condition(ExprId, ExprOrValue<T>, ExprOrValue<T>),
+    Invoke(Option<Arc<Function<T>>>, Args<T>),
+    /// A builtin taking functions, `None` for the one being defined, then the other arguments.
+    Higher(Higher, Vec<Option<Arc<Function<T>>>>, Args<T>),
+    /// `fold(i, lo, hi, init, body)`, `body` seeing the index and the accumulator after the
+    /// variables of its scope.
+    Fold(
+        ExprOrValue<T>,
+        ExprOrValue<T>,
+        ExprOrValue<T>,
+        ExprOrValue<T>,
+    ),
+    /// Temporaries evaluated in order, each seeing the earlier ones, then the body seeing them.
+    Let(Vec<ExprId>, ExprId),
+    /// A temporary of the innermost `Let`.
+    Temp(usize),
+    Variable(usize),
+}
+
+impl<T: Numeric> ExprOrValue<T> {
+    pub(super) fn expr(&self) -> Option<ExprId> {
+        match self {
+            ExprOrValue::Expr(id) => Some(*id),
+            ExprOrValue::Value(_) => None,
+        }
+    }
+}
+
+impl<T: Numeric> Expression<T> {
+    pub(super) fn children(&self) -> Vec<ExprId> {
+        let (exprs, operands) = match self {
+            Expression::Not(expr) | Expression::Neg(expr) => (vec![*expr], vec![]),
+            Expression::Exp(ex1, ex2)
+            | Expression::Mul(ex1, ex2)
+            | Expression::Div(ex1, ex2)
+            | Expression::Add(ex1, ex2)
+            | Expression::Sub(ex1, ex2)
+            | Expression::Compare(_, ex1, ex2)
+            | Expression::Or(ex1, ex2)
+            | Expression::And(ex1, ex2) => (vec![], vec![ex1, ex2]),
+            Expression::Condition(expr, ex1, ex2) => (vec![*expr], vec![ex1, ex2]),
+            Expression::Invoke(_, params) | Expression::Higher(_, _, params) => {
+                (vec![], params.iter().collect())
+            }
+            Expression::Fold(lo, hi, init, body) => (vec![], vec![lo, hi, init, body]),
+            Expression::Let(temps, body) => {
+                (temps.iter().copied().chain([*body]).collect(), vec![])
+            }
+            Expression::Temp(_) | Expression::Variable(_) => (vec![], vec![]),
+        };
+        let operands = operands.into_iter().filter_map(ExprOrValue::expr);
+        exprs.into_iter().chain(operands).collect()
+    }
+
+    /// The subexpressions evaluated whenever this one is, apart from those in a `Let`.
+    pub(super) fn eager_children(&self) -> Vec<ExprId> {
+        match self {
+            Expression::Condition(cond, _, _) => vec![*cond],
+            Expression::Or(ex1, _) | Expression::And(ex1, _) => ex1.expr().into_iter().collect(),
+            Expression::Invoke(Some(f), params) if matches!(f.fimpl, FunctionImpl::Lazy(_)) => {
+                params
+                    .first()
+                    .and_then(ExprOrValue::expr)
+                    .into_iter()
+                    .collect()
+            }
+            Expression::Fold(lo, hi, init, _) => [lo, hi, init]
+                .iter()
+                .filter_map(|operand| operand.expr())
+                .collect(),
+            Expression::Let(..) => vec![],
+            expr => expr.children(),
+        }
+    }
+
+    /// The subexpressions that must be expressions, then the operands.
+    pub(super) fn slots_mut(&mut self) -> (Vec<&mut ExprId>, Vec<&mut ExprOrValue<T>>) {
+        match self {
+            Expression::Not(expr) | Expression::Neg(expr) => (vec![expr], vec![]),
+            Expression::Exp(ex1, ex2)
+            | Expression::Mul(ex1, ex2)
+            | Expression::Div(ex1, ex2)
+            | Expression::Add(ex1, ex2)
+            | Expression::Sub(ex1, ex2)
+            | Expression::Compare(_, ex1, ex2)
+            | Expression::Or(ex1, ex2)
+            | Expression::And(ex1, ex2) => (vec![], vec![ex1, ex2]),
+            Expression::Condition(expr, ex1, ex2) => (vec![expr], vec![ex1, ex2]),
+            Expression::Invoke(_, params) | Expression::Higher(_, _, params) => {
+                (vec![], params.iter_mut().collect())
+            }
+            Expression::Fold(lo, hi, init, body) => (vec![], vec![lo, hi, init, body]),
+            Expression::Let(temps, body) => (temps.iter_mut().chain([body]).collect(), vec![]),
+            Expression::Temp(_) | Expression::Variable(_) => (vec![], vec![]),
+        }
+    }
+}
+
+impl<T: Numeric> Index<ExprId> for Arena<T> {
+    type Output = Expression<T>;
+
+    fn index(&self, id: ExprId) -> &Expression<T> {
+        &self.0[id.0 as usize]
+    }
+}
+
+impl<T: Numeric> IndexMut<ExprId> for Arena<T> {
+    fn index_mut(&mut self, id: ExprId) -> &mut Expression<T> {
+        &mut self.0[id.0 as usize]
+    }
+}
+
+impl<T: Numeric> Arena<T> {
+    pub(super) fn push(&mut self, expr: Expression<T>) -> ExprId {
+        self.0.push(expr);
+        ExprId(self.0.len() as u32 - 1)
+    }
+
+    pub(super) fn mark_variables(&self, id: ExprId, used: &mut [bool]) {
+        match &self[id] {
+            Expression::Variable(i) => {
+                // variables bound by `fold` come after the parameters
+                if let Some(used) = used.get_mut(*i) {
+                    *used = true;
+                }
+            }
+            expr => {
+                for child in expr.children() {
+                    self.mark_variables(child, used);
+                }
+            }
+        }
+    }
+
+    /// Whether every evaluation of `id` invokes the function being defined.
+    pub(super) fn always_recurses(&self, id: ExprId) -> bool {
+        let recurses = |operand: &ExprOrValue<T>| match operand {
+            ExprOrValue::Expr(expr) => self.always_recurses(*expr),
+            ExprOrValue::Value(_) => false,
+        };
+        match &self[id] {
+            Expression::Invoke(None, _) => true,
+            // a lazy builtin may skip any argument but the first
+            Expression::Invoke(Some(f), params) if matches!(f.fimpl, FunctionImpl::Lazy(_)) => {
+                params.first().is_some_and(recurses)
+            }
+            Expression::Condition(expr, ex1, ex2) => {
+                self.always_recurses(*expr) || (recurses(ex1) && recurses(ex2))
+            }
+            // the right operand is skipped when the left one decides
+            Expression::Or(ex1, _) | Expression::And(ex1, _) => recurses(ex1),
+            // the range may be empty
+            Expression::Fold(lo, hi, init, _) => recurses(lo) || recurses(hi) || recurses(init),
+            expr => expr
+                .children()
+                .into_iter()
+                .any(|child| self.always_recurses(child)),
+        }
+    }
+
+    /// Copy `operand` of this arena into `into`, subexpressions first.
+    pub(super) fn copy(&self, operand: &ExprOrValue<T>, into: &mut Arena<T>) -> ExprOrValue<T> {
+        let id = match operand {
+            ExprOrValue::Expr(id) => *id,
+            ExprOrValue::Value(val) => return ExprOrValue::Value(val.clone()),
+        };
+        let mut expr = self[id].clone();
+        let (exprs, operands) = expr.slots_mut();
+        for slot in exprs {
+            *slot = self.copy(&ExprOrValue::Expr(*slot), into).expr().unwrap();
+        }
+        for operand in operands {
+            *operand = self.copy(operand, into);
+        }
+        ExprOrValue::Expr(into.push(expr))
+    }
+}
+
+impl<T: Numeric> Body<T> {
+    /// This body with only the expressions its root refers to, each subexpression before the
+    /// expression using it, and none shared by two expressions.
+    pub(super) fn compact(&self) -> Body<T> {
+        let mut arena = Arena(Vec::with_capacity(self.arena.0.len()));
+        let root = self.arena.copy(&self.root, &mut arena);
+        Body { arena, root }
+    }
+}
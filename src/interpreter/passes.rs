@@ -0,0 +1,556 @@
+//! Optimisation passes: folding constants at translation, inlining small functions, sharing
+//! common subexpressions and comparing bodies up to equivalence
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use smallvec::smallvec;
+
+use super::{
+    Arena, Body, ExprId, ExprOrValue, Expression, Function, FunctionImpl, GenericFn, InputError,
+    Interpreter, Warning,
+};
+use crate::{lexer::CompareOp, parser::ASTNode, value::Value, Numeric};
+
+/// Operations and calls in the largest function body inlined.
+const INLINE_SIZE: usize = 16;
+
+impl<T: Numeric> Interpreter<T> {
+    /// Whether the user functions `a` and `b` compute the same expression, up to the names of
+    /// their parameters, the order of commutative operands and combined constants.
+    pub fn equivalent(&self, a: &str, b: &str) -> Result<bool, InputError> {
+        let key = |name: &str| match self.functions.get(name.as_bytes()) {
+            Some(f) => match &f.fimpl {
+                FunctionImpl::User(body) => {
+                    Ok((f.incount, body.arena.operand_key(&body.root, &[])))
+                }
+                _ => Err(InputError::BuiltinIdentifier {
+                    ident: name.as_bytes().to_vec(),
+                    position: None,
+                }),
+            },
+            None => Err(self.undefined(name.as_bytes().to_vec())),
+        };
+        Ok(key(a)? == key(b)?)
+    }
+
+    /// Whether operations on constants are evaluated at translation.
+    pub(super) fn folding(&self) -> bool {
+        // binary operations and calls are kept to be traced, paused at and profiled
+        self.fold
+            && !self.strict
+            && self.budget.is_none()
+            && !self.tracing
+            && self.debugger.is_none()
+            && self.profile.is_none()
+    }
+
+    /// The branch of `?:` or `if` taken on the constant condition `cond`, warning in a function
+    /// definition that the other one is unreachable.
+    pub(super) fn constant_branch(
+        &mut self,
+        cond: &Value<T>,
+        ex1: ExprOrValue<T>,
+        ex2: ExprOrValue<T>,
+    ) -> Result<ExprOrValue<T>, InputError> {
+        let value = cond.truth()?;
+        if !self.cur_ident.is_empty() {
+            self.warnings.push(Warning::ConstantCondition { value });
+        }
+        Ok(match value {
+            true => ex1,
+            false => ex2,
+        })
+    }
+
+    /// A call of `f` on the constant `val` at evaluation instead of at translation, for an
+    /// operation failing where it may be skipped.
+    pub(super) fn deferred(
+        &mut self,
+        name: &[u8],
+        f: GenericFn<T>,
+        val: Value<T>,
+    ) -> ExprOrValue<T> {
+        let f = Function::builtin(name, 1, FunctionImpl::Generic(f));
+        self.node(Expression::Invoke(
+            Some(f),
+            smallvec![ExprOrValue::Value(val)],
+        ))
+    }
+
+    /// Translate the right operand of `||` or `&&` without evaluating anything, as it may be
+    /// skipped.
+    pub(super) fn translate_short_circuited(
+        &mut self,
+        ast: ASTNode,
+    ) -> Result<ExprOrValue<T>, InputError> {
+        let outer = self.fold;
+        self.fold = false;
+        let result = self.translate_expression(ast);
+        self.fold = outer;
+        result
+    }
+
+    /// `body` with the calls of small user functions replaced by their bodies, if inlining is on
+    /// and operations aren't kept as written.
+    pub(super) fn inline_calls(&self, body: Body<T>) -> Body<T> {
+        match self.inlining && self.folding() {
+            true => body.inline_calls(),
+            false => body,
+        }
+    }
+
+    /// `body` with the subexpressions it repeats computed once per call, unless operations are
+    /// kept as written.
+    pub(super) fn share_common(&self, body: Body<T>) -> Body<T> {
+        match self.folding() {
+            true => body.share_common(),
+            false => body,
+        }
+    }
+}
+
+impl<T: Numeric> Function<T> {
+    /// The body of a user function small enough to inline, with its root.
+    fn inline_body(&self) -> Option<(&Arena<T>, ExprId)> {
+        match &self.fimpl {
+            FunctionImpl::User(Body {
+                arena,
+                root: ExprOrValue::Expr(root),
+            }) if !self.name.is_empty()
+                && arena.size(*root) <= INLINE_SIZE
+                && arena.inlinable(*root) =>
+            {
+                Some((arena, *root))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<T: Numeric> Arena<T> {
+    /// A key shared by expressions equal up to the order of the operands of `+`, `*`, `&&`,
+    /// `||`, `==` and `!=`, constants combined, and subtraction and `>` written the other way.
+    ///
+    /// `temps` are the temporaries of the enclosing `Let`, keyed as the expressions they hold.
+    fn canonical(&self, id: ExprId, temps: &[ExprId]) -> String {
+        let pair = |name: &str, ex1: &ExprOrValue<T>, ex2: &ExprOrValue<T>| {
+            format!(
+                "{}({},{})",
+                name,
+                self.operand_key(ex1, temps),
+                self.operand_key(ex2, temps)
+            )
+        };
+        let list = |operands: &[ExprOrValue<T>]| {
+            operands
+                .iter()
+                .map(|operand| self.operand_key(operand, temps))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        match &self[id] {
+            Expression::Temp(k) => self.canonical(temps[*k], &temps[..*k]),
+            Expression::Let(temps, body) => self.canonical(*body, temps),
+            Expression::Add(..) | Expression::Sub(..) | Expression::Neg(_) => {
+                let (mut terms, mut constant) = (vec![], Value::Int(0));
+                self.sum_terms(id, false, &mut terms, &mut constant, temps);
+                if !matches!(constant, Value::Int(0)) && constant != Value::Num(T::ZERO) {
+                    terms.push(format!("{:?}", constant));
+                }
+                match terms.len() {
+                    1 => terms.pop().unwrap(),
+                    _ => {
+                        terms.sort();
+                        format!("+({})", terms.join(","))
+                    }
+                }
+            }
+            Expression::Mul(..) => {
+                let (mut factors, mut constant) = (vec![], Value::Int(1));
+                self.factors(id, &mut factors, &mut constant, temps);
+                if !matches!(constant, Value::Int(1)) {
+                    factors.push(format!("{:?}", constant));
+                }
+                match factors.len() {
+                    1 => factors.pop().unwrap(),
+                    _ => {
+                        factors.sort();
+                        format!("*({})", factors.join(","))
+                    }
+                }
+            }
+            expr @ (Expression::Or(..) | Expression::And(..)) => {
+                let mut operands = vec![];
+                self.chain(id, &mut operands, temps);
+                operands.sort();
+                let name = match expr {
+                    Expression::Or(..) => "||",
+                    _ => "&&",
+                };
+                format!("{}({})", name, operands.join(","))
+            }
+            Expression::Not(expr) => format!("!({})", self.canonical(*expr, temps)),
+            Expression::Exp(ex1, ex2) => pair("^", ex1, ex2),
+            Expression::Div(ex1, ex2) => pair("/", ex1, ex2),
+            Expression::Compare(op, ex1, ex2) => match op {
+                CompareOp::GT => pair("<", ex2, ex1),
+                CompareOp::GE => pair("<=", ex2, ex1),
+                CompareOp::EQ | CompareOp::NE => {
+                    let mut keys = [self.operand_key(ex1, temps), self.operand_key(ex2, temps)];
+                    keys.sort();
+                    format!("{}({},{})", op.symbol(), keys[0], keys[1])
+                }
+                op => pair(op.symbol(), ex1, ex2),
+            },
+            Expression::Condition(expr, ex1, ex2) => {
+                format!("?({},{})", self.canonical(*expr, temps), pair("", ex1, ex2))
+            }
+            Expression::Invoke(f, params) => {
+                let name = f
+                    .as_ref()
+                    .map_or("@self".into(), |f| String::from_utf8_lossy(&f.name));
+                format!("{}({})", name, list(params))
+            }
+            Expression::Higher(higher, fs, params) => {
+                let fs = fs
+                    .iter()
+                    .map(|f| {
+                        f.as_ref()
+                            .map_or("@self".into(), |f| String::from_utf8_lossy(&f.name))
+                    })
+                    .collect::<Vec<_>>();
+                format!("{:?}[{}]({})", higher, fs.join(","), list(params))
+            }
+            Expression::Fold(lo, hi, init, body) => {
+                let operands = [lo, hi, init, body].map(|operand| self.operand_key(operand, temps));
+                format!("fold({})", operands.join(","))
+            }
+            Expression::Variable(i) => format!("${}", i),
+        }
+    }
+
+    /// `canonical` of an operand.
+    fn operand_key(&self, operand: &ExprOrValue<T>, temps: &[ExprId]) -> String {
+        match operand {
+            ExprOrValue::Expr(expr) => self.canonical(*expr, temps),
+            ExprOrValue::Value(val) => format!("{:?}", val),
+        }
+    }
+
+    /// The expression standing for `id`, outside of temporaries and `Let`, with the
+    /// temporaries it sees.
+    fn resolve<'a>(&'a self, id: ExprId, temps: &'a [ExprId]) -> (ExprId, &'a [ExprId]) {
+        match &self[id] {
+            Expression::Temp(k) => self.resolve(temps[*k], &temps[..*k]),
+            Expression::Let(temps, body) => self.resolve(*body, temps),
+            _ => (id, temps),
+        }
+    }
+
+    /// Add the keys of the terms of the sum `id` to `terms`, and its constants to `constant`.
+    fn sum_terms(
+        &self,
+        id: ExprId,
+        negate: bool,
+        terms: &mut Vec<String>,
+        constant: &mut Value<T>,
+        temps: &[ExprId],
+    ) {
+        let (id, temps) = self.resolve(id, temps);
+        let mut operand = |operand: &ExprOrValue<T>, negate: bool| match operand {
+            ExprOrValue::Expr(expr) => self.sum_terms(*expr, negate, terms, constant, temps),
+            ExprOrValue::Value(val) => {
+                let term = match negate {
+                    true => val.neg(),
+                    false => Ok(val.clone()),
+                };
+                match term.and_then(|term| constant.add(&term)) {
+                    Ok(sum) => *constant = sum,
+                    Err(_) => terms.push(format!("{}{:?}", if negate { "-" } else { "" }, val)),
+                }
+            }
+        };
+        match &self[id] {
+            Expression::Add(ex1, ex2) => {
+                operand(ex1, negate);
+                operand(ex2, negate);
+            }
+            Expression::Sub(ex1, ex2) => {
+                operand(ex1, negate);
+                operand(ex2, !negate);
+            }
+            Expression::Neg(expr) => self.sum_terms(*expr, !negate, terms, constant, temps),
+            _ if negate => terms.push(format!("-{}", self.canonical(id, temps))),
+            _ => terms.push(self.canonical(id, temps)),
+        }
+    }
+
+    /// Add the keys of the factors of the product `id` to `factors`, and its constants to
+    /// `constant`.
+    fn factors(
+        &self,
+        id: ExprId,
+        factors: &mut Vec<String>,
+        constant: &mut Value<T>,
+        temps: &[ExprId],
+    ) {
+        let (id, temps) = self.resolve(id, temps);
+        match &self[id] {
+            Expression::Mul(ex1, ex2) => {
+                for operand in [ex1, ex2] {
+                    match operand {
+                        ExprOrValue::Expr(expr) => self.factors(*expr, factors, constant, temps),
+                        ExprOrValue::Value(val) => match constant.mul(val) {
+                            Ok(product) => *constant = product,
+                            Err(_) => factors.push(format!("{:?}", val)),
+                        },
+                    }
+                }
+            }
+            _ => factors.push(self.canonical(id, temps)),
+        }
+    }
+
+    /// Add the keys of the operands of the chain of `||` or of `&&` `id` to `operands`.
+    fn chain(&self, id: ExprId, operands: &mut Vec<String>, temps: &[ExprId]) {
+        let (id, temps) = self.resolve(id, temps);
+        let expr = &self[id];
+        let same = |other: ExprId| {
+            matches!(
+                (expr, &self[self.resolve(other, temps).0]),
+                (Expression::Or(..), Expression::Or(..))
+                    | (Expression::And(..), Expression::And(..))
+            )
+        };
+        match expr {
+            Expression::Or(ex1, ex2) | Expression::And(ex1, ex2) => {
+                for operand in [ex1, ex2] {
+                    match operand {
+                        ExprOrValue::Expr(other) if same(*other) => {
+                            self.chain(*other, operands, temps)
+                        }
+                        operand => operands.push(self.operand_key(operand, temps)),
+                    }
+                }
+            }
+            _ => operands.push(self.canonical(id, temps)),
+        }
+    }
+
+    /// A key shared only by expressions written alike, calling the same functions.
+    fn key(&self, id: ExprId) -> String {
+        let operand = |operand: &ExprOrValue<T>| match operand {
+            ExprOrValue::Expr(expr) => self.key(*expr),
+            ExprOrValue::Value(val) => format!("{:?}", val),
+        };
+        let list = |operands: &[ExprOrValue<T>]| {
+            operands.iter().map(operand).collect::<Vec<_>>().join(",")
+        };
+        let function =
+            |f: &Option<Arc<Function<T>>>| f.as_ref().map_or(0, |f| Arc::as_ptr(f) as usize);
+        match &self[id] {
+            Expression::Not(expr) => format!("!({})", self.key(*expr)),
+            Expression::Neg(expr) => format!("-({})", self.key(*expr)),
+            Expression::Exp(ex1, ex2) => format!("^({},{})", operand(ex1), operand(ex2)),
+            Expression::Mul(ex1, ex2) => format!("*({},{})", operand(ex1), operand(ex2)),
+            Expression::Div(ex1, ex2) => format!("/({},{})", operand(ex1), operand(ex2)),
+            Expression::Add(ex1, ex2) => format!("+({},{})", operand(ex1), operand(ex2)),
+            Expression::Sub(ex1, ex2) => format!("-({},{})", operand(ex1), operand(ex2)),
+            Expression::Compare(op, ex1, ex2) => {
+                format!("{}({},{})", op.symbol(), operand(ex1), operand(ex2))
+            }
+            Expression::Or(ex1, ex2) => format!("||({},{})", operand(ex1), operand(ex2)),
+            Expression::And(ex1, ex2) => format!("&&({},{})", operand(ex1), operand(ex2)),
+            Expression::Condition(expr, ex1, ex2) => {
+                format!("?({},{},{})", self.key(*expr), operand(ex1), operand(ex2))
+            }
+            Expression::Invoke(f, params) => format!("{:x}({})", function(f), list(params)),
+            Expression::Higher(higher, fs, params) => {
+                let fs = fs.iter().map(|f| format!("{:x}", function(f)));
+                let fs = fs.collect::<Vec<_>>().join(",");
+                format!("{:?}[{}]({})", higher, fs, list(params))
+            }
+            Expression::Fold(lo, hi, init, body) => {
+                format!("fold({})", [lo, hi, init, body].map(operand).join(","))
+            }
+            Expression::Let(temps, body) => {
+                let temps = temps.iter().map(|temp| self.key(*temp)).collect::<Vec<_>>();
+                format!("let[{}]({})", temps.join(","), self.key(*body))
+            }
+            Expression::Temp(k) => format!("#{}", k),
+            Expression::Variable(i) => format!("${}", i),
+        }
+    }
+
+    fn size(&self, id: ExprId) -> usize {
+        1 + self[id]
+            .children()
+            .into_iter()
+            .map(|child| self.size(child))
+            .sum::<usize>()
+    }
+
+    /// Whether `id` means the same in the body of any function, calling neither the function it
+    /// is in nor `fold`.
+    fn inlinable(&self, id: ExprId) -> bool {
+        match &self[id] {
+            Expression::Invoke(None, _) | Expression::Fold(..) => false,
+            Expression::Higher(_, fs, _) if fs.iter().any(Option::is_none) => false,
+            expr => expr
+                .children()
+                .into_iter()
+                .all(|child| self.inlinable(child)),
+        }
+    }
+
+    /// Copy the expression `id` of `from` with its parameters replaced by `args`, `None` if a
+    /// constant would take the place of an expression.
+    ///
+    /// Each argument is shared by all the uses of its parameter, see `compact`.
+    fn graft(
+        &mut self,
+        from: &Arena<T>,
+        id: ExprId,
+        args: &[ExprOrValue<T>],
+    ) -> Option<ExprOrValue<T>> {
+        let mut expr = from[id].clone();
+        if let Expression::Variable(i) = expr {
+            return Some(args[i].clone());
+        }
+        let (exprs, operands) = expr.slots_mut();
+        for slot in exprs {
+            *slot = self.graft(from, *slot, args)?.expr()?;
+        }
+        for operand in operands {
+            if let ExprOrValue::Expr(child) = operand {
+                *operand = self.graft(from, *child, args)?;
+            }
+        }
+        Some(ExprOrValue::Expr(self.push(expr)))
+    }
+
+    /// Replace the calls of small user functions under `id` by their bodies on the arguments.
+    fn inline_calls(&mut self, id: ExprId) {
+        for child in self[id].children() {
+            self.inline_calls(child);
+        }
+        let (f, params) = match &self[id] {
+            Expression::Invoke(Some(f), params) => (f.clone(), params),
+            _ => return,
+        };
+        let (from, root) = match f.inline_body() {
+            Some(body) => body,
+            None => return,
+        };
+        let params = params.clone();
+        let start = self.0.len();
+        match self.graft(from, root, &params) {
+            // the root of a graft comes last
+            Some(ExprOrValue::Expr(_)) => self[id] = self.0.pop().unwrap(),
+            _ => self.0.truncate(start),
+        }
+    }
+
+    /// Count the subexpressions of `id` outside of `Let` by their key, returning the size of
+    /// `id`.
+    fn count_shared(&self, id: ExprId, counts: &mut HashMap<String, (usize, usize)>) -> usize {
+        let expr = &self[id];
+        if matches!(
+            expr,
+            Expression::Variable(_) | Expression::Temp(_) | Expression::Let(..)
+        ) {
+            return 1;
+        }
+        let size = 1 + expr
+            .children()
+            .into_iter()
+            .map(|child| self.count_shared(child, counts))
+            .sum::<usize>();
+        counts.entry(self.key(id)).or_insert((0, size)).0 += 1;
+        size
+    }
+
+    /// Add the keys of the subexpressions evaluated whenever `id` is to `eager`.
+    fn mark_eager(&self, id: ExprId, eager: &mut HashSet<String>) {
+        eager.insert(self.key(id));
+        for child in self[id].eager_children() {
+            self.mark_eager(child, eager);
+        }
+    }
+
+    /// Replace the subexpressions of `id` of key `key` outside of `Let` by the temporary
+    /// `slot`, moving the first one into `taken`.
+    fn take_shared(&mut self, id: ExprId, key: &str, slot: usize, taken: &mut Option<ExprId>) {
+        if matches!(
+            self[id],
+            Expression::Variable(_) | Expression::Temp(_) | Expression::Let(..)
+        ) {
+            return;
+        }
+        if self.key(id) == key {
+            let expr = std::mem::replace(&mut self[id], Expression::Temp(slot));
+            if taken.is_none() {
+                *taken = Some(self.push(expr));
+            }
+            return;
+        }
+        for child in self[id].children() {
+            self.take_shared(child, key, slot, taken);
+        }
+    }
+}
+
+impl<T: Numeric> Body<T> {
+    /// Replace the calls of small user functions by their bodies on the arguments.
+    fn inline_calls(mut self) -> Body<T> {
+        match self.root {
+            ExprOrValue::Expr(root) => {
+                self.arena.inline_calls(root);
+                self.compact()
+            }
+            ExprOrValue::Value(_) => self,
+        }
+    }
+
+    /// This body with each subexpression that occurs more than once, and is evaluated whenever
+    /// the body is, computed once beforehand into a temporary.
+    fn share_common(mut self) -> Body<T> {
+        let mut body = match self.root {
+            ExprOrValue::Expr(root) => root,
+            ExprOrValue::Value(_) => return self,
+        };
+        let arena = &mut self.arena;
+        let mut temps = vec![];
+        loop {
+            let mut counts = HashMap::new();
+            let mut eager = HashSet::new();
+            for &expr in temps.iter().chain([&body]) {
+                arena.count_shared(expr, &mut counts);
+                arena.mark_eager(expr, &mut eager);
+            }
+            // the smallest first, so that temporaries only refer to earlier ones
+            let shared = counts
+                .into_iter()
+                .filter(|(key, (count, _))| *count > 1 && eager.contains(key))
+                .min_by(|(k1, (_, s1)), (k2, (_, s2))| s1.cmp(s2).then(k1.cmp(k2)));
+            let key = match shared {
+                Some((key, _)) => key,
+                None => break,
+            };
+            let (slot, mut taken) = (temps.len(), None);
+            for &expr in temps.iter().chain([&body]) {
+                arena.take_shared(expr, &key, slot, &mut taken);
+            }
+            temps.push(taken.unwrap());
+        }
+        if temps.is_empty() {
+            return self;
+        }
+        body = arena.push(Expression::Let(temps, body));
+        self.root = ExprOrValue::Expr(body);
+        self.compact()
+    }
+}
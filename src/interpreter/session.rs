@@ -0,0 +1,492 @@
+//! Workspaces, snapshots, the undo journal, the history, auditing and dumping definitions
+
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::Range,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    Bindings, Capture, Function, InputError, Interpreter, Source, Variable, EARLIER_RESULTS,
+};
+use crate::{lexer::Ident, value::Value, Numeric, Real};
+
+/// A binding before a change, `None` if it was undefined.
+#[derive(Clone)]
+pub(super) enum Binding<T> {
+    Value(Ident, Option<Variable<T>>),
+    Function(Ident, Option<Arc<Function<T>>>),
+    /// An operator spelling with the token id of its previous precedence tier.
+    Operator(Ident, Option<u32>),
+}
+
+const UNDO_LIMIT: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Created,
+    Changed,
+}
+
+/// A binding created or changed by an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub name: String,
+    pub function: bool,
+    pub kind: ChangeKind,
+}
+
+impl Change {
+    pub(super) fn new(ident: &[u8], function: bool, existed: bool) -> Self {
+        Change {
+            name: String::from_utf8_lossy(ident).into_owned(),
+            function,
+            kind: match existed {
+                true => ChangeKind::Changed,
+                false => ChangeKind::Created,
+            },
+        }
+    }
+}
+
+/// Handle of an environment saved with [`Interpreter::snapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SnapshotId(pub(super) usize);
+
+pub(super) struct Snapshot<T> {
+    workspace: String,
+    values: Bindings<Variable<T>>,
+    functions: Bindings<Arc<Function<T>>>,
+    history: usize,
+    journal: VecDeque<Vec<Binding<T>>>,
+    operators: Vec<(Ident, u32)>,
+}
+
+/// User definitions of an inactive workspace.
+pub(super) struct Workspace<T> {
+    values: Bindings<Variable<T>>,
+    functions: Bindings<Arc<Function<T>>>,
+    journal: VecDeque<Vec<Binding<T>>>,
+    operators: Vec<(Ident, u32)>,
+}
+
+pub(super) const DEFAULT_WORKSPACE: &str = "default";
+
+/// A change logged while auditing, see [`Interpreter::enable_audit`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry<T = Real> {
+    /// When it was accepted.
+    pub time: SystemTime,
+    pub workspace: String,
+    /// The statement as typed, `None` for bindings of the host, e.g. with `set_many`.
+    pub statement: Option<String>,
+    /// Variables bound with their values, and functions defined with `None`.
+    pub bindings: Vec<(String, Option<Value<T>>)>,
+}
+
+impl<T: Numeric> Interpreter<T> {
+    /// Accepted statements, lines of a multi-line statement joined with newlines.
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Input the statements `range` of the history again, without recording them twice.
+    pub fn replay(&mut self, range: Range<usize>) -> Result<(), InputError> {
+        let len = self.history.len();
+        let statements = self.history[range.start.min(len)..range.end.min(len)].to_vec();
+        for statement in statements {
+            for line in statement.split('\n') {
+                self.input_statement(line.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove all user definitions of the current workspace, keeping the history, snapshots,
+    /// other workspaces and settings.
+    pub fn reset(&mut self) {
+        let (values, functions) = self.fresh_bindings();
+        self.values = values;
+        self.functions = functions;
+        self.operators.clear();
+        self.insert_settings();
+        self.journal.clear();
+        self.cancel();
+    }
+
+    /// Name of the current workspace.
+    pub fn workspace(&self) -> &str {
+        &self.workspace
+    }
+
+    /// Names of all workspaces, sorted.
+    pub fn workspaces(&self) -> Vec<&str> {
+        let mut names = self
+            .workspaces
+            .keys()
+            .map(String::as_str)
+            .chain(std::iter::once(self.workspace.as_str()))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names
+    }
+
+    /// Switch to the workspace `name`, creating it with only the builtins if it doesn't exist.
+    pub fn switch_workspace(&mut self, name: &str) {
+        self.parser = None;
+        self.cur_blocks = 0;
+        self.pending.clear();
+        if name == self.workspace {
+            return;
+        }
+        let target = match self.workspaces.remove(name) {
+            Some(workspace) => workspace,
+            None => {
+                let (values, functions) = self.fresh_bindings();
+                Workspace {
+                    values,
+                    functions,
+                    journal: VecDeque::new(),
+                    operators: vec![],
+                }
+            }
+        };
+        let current = Workspace {
+            values: std::mem::replace(&mut self.values, target.values),
+            functions: std::mem::replace(&mut self.functions, target.functions),
+            journal: std::mem::replace(&mut self.journal, target.journal),
+            operators: std::mem::replace(&mut self.operators, target.operators),
+        };
+        let previous = std::mem::replace(&mut self.workspace, name.to_string());
+        self.workspaces.insert(previous, current);
+        self.insert_settings();
+    }
+
+    /// Bindings of the builtins of a new workspace, the results being bound by `insert_settings`.
+    pub(super) fn fresh_bindings(&self) -> (Bindings<Variable<T>>, Bindings<Arc<Function<T>>>) {
+        let Interpreter {
+            mut values,
+            functions,
+            ..
+        } = Interpreter::with_groups(self.groups.clone());
+        for ident in [b"_".as_slice()].iter().chain(&EARLIER_RESULTS) {
+            values.remove(*ident);
+        }
+        (values, functions)
+    }
+
+    /// Delete the workspace `name`, returning `false` if it is current or doesn't exist.
+    pub fn delete_workspace(&mut self, name: &str) -> bool {
+        self.workspaces.remove(name).is_some()
+    }
+
+    /// Save the current definitions, to be restored with [`Interpreter::rollback`].
+    pub fn snapshot(&mut self) -> SnapshotId {
+        self.snapshots.push(Snapshot {
+            values: self.values.clone(),
+            workspace: self.workspace.clone(),
+            functions: self.functions.clone(),
+            history: self.history.len(),
+            journal: self.journal.clone(),
+            operators: self.operators.clone(),
+        });
+        SnapshotId(self.snapshots.len() - 1)
+    }
+
+    /// Restore the workspace, definitions and history saved by `id`, discarding an unfinished
+    /// statement.
+    ///
+    /// Returns `false` if `id` was not taken from this interpreter.
+    pub fn rollback(&mut self, id: SnapshotId) -> bool {
+        let name = match self.snapshots.get(id.0) {
+            Some(snapshot) => snapshot.workspace.clone(),
+            None => return false,
+        };
+        self.switch_workspace(&name);
+        let snapshot = &self.snapshots[id.0];
+        self.values = snapshot.values.clone();
+        self.functions = snapshot.functions.clone();
+        self.history.truncate(snapshot.history);
+        self.journal = snapshot.journal.clone();
+        self.operators = snapshot.operators.clone();
+        self.insert_settings();
+        true
+    }
+
+    /// User variables and functions as a script reproducing them, in the order they were defined.
+    ///
+    /// Functions keep the variables and functions they read as bound at their definition, so
+    /// the earlier values of variables and the earlier versions of functions they read are
+    /// defined again before them. Vectors have no literal syntax and are listed as comments.
+    pub fn dump_script(&self) -> String {
+        let user_values = self
+            .values
+            .iter()
+            .filter(|(ident, (builtin, _, _))| !builtin && !self.is_result(ident))
+            .collect::<Vec<_>>();
+        // the current functions, then the earlier versions they read
+        let mut versions = self
+            .functions
+            .values()
+            .filter(|f| f.source.is_some())
+            .collect::<Vec<_>>();
+        let mut i = 0;
+        while i < versions.len() {
+            for capture in &versions[i].source.as_ref().unwrap().captures {
+                if let Capture::Function(_, f) = capture {
+                    if !versions.iter().any(|v| Arc::ptr_eq(v, f)) {
+                        versions.push(f);
+                    }
+                }
+            }
+            i += 1;
+        }
+        let serial = |source: &Option<Source<T>>| source.as_ref().map_or(0, |s| s.serial);
+        // definitions by serial and name, the function or else a variable
+        let mut definitions = user_values
+            .iter()
+            .map(|&(ident, (_, _, source))| (serial(source), ident, None))
+            .chain(
+                versions
+                    .into_iter()
+                    .map(|f| (serial(&f.source), &f.name, Some(f))),
+            )
+            .collect::<Vec<_>>();
+        definitions.sort_by(|(s1, n1, _), (s2, n2, _)| (s1, n1).cmp(&(s2, n2)));
+        let mut script = String::new();
+        let mut bound = HashMap::new();
+        for (_, ident, function) in definitions {
+            let f = match function {
+                Some(f) => f,
+                None => {
+                    let (_, value, source) = &self.values[ident];
+                    script += &dump_value(ident, value, source.as_ref());
+                    bound.insert(ident.as_slice(), value);
+                    continue;
+                }
+            };
+            let source = f.source.as_ref().unwrap();
+            for capture in &source.captures {
+                if let Capture::Value(ident, value) = capture {
+                    if bound.get(ident.as_slice()) != Some(&value) && !self.is_result(ident) {
+                        script += &dump_value(ident, value, None);
+                        bound.insert(ident, value);
+                    }
+                }
+            }
+            script += &source.text;
+            script.push('\n');
+        }
+        // variables bound again for a function after their definition
+        let mut changed = user_values
+            .into_iter()
+            .filter(|(ident, (_, value, _))| bound.get(ident.as_slice()) != Some(&value))
+            .collect::<Vec<_>>();
+        changed.sort_unstable_by_key(|(ident, _)| *ident);
+        for (ident, (_, value, source)) in changed {
+            script += &dump_value(ident, value, source.as_ref());
+        }
+        script
+    }
+
+    /// Revert the most recent assignment or definition, returning whether there was one.
+    pub fn undo(&mut self) -> bool {
+        let bindings = match self.journal.pop_back() {
+            Some(bindings) => bindings,
+            None => return false,
+        };
+        for binding in bindings.into_iter().rev() {
+            match binding {
+                Binding::Value(ident, Some(old)) => {
+                    self.names.insert(ident.clone());
+                    self.values.insert(ident, old);
+                }
+                Binding::Value(ident, None) => {
+                    self.values.remove(&ident);
+                    self.unindex(&ident);
+                }
+                Binding::Function(ident, Some(old)) => {
+                    self.names.insert(ident.clone());
+                    self.functions.insert(ident, old);
+                }
+                Binding::Function(ident, None) => {
+                    self.functions.remove(&ident);
+                    self.unindex(&ident);
+                }
+                Binding::Operator(spelling, tier) => {
+                    self.set_operator(spelling, tier);
+                }
+            }
+        }
+        true
+    }
+
+    pub(super) fn set_value(
+        &mut self,
+        ident: Ident,
+        value: Value<T>,
+        source: Option<Source<T>>,
+    ) -> Binding<T> {
+        let old = self.values.insert(ident.clone(), (false, value, source));
+        if old.is_none() {
+            self.names.insert(ident.clone());
+        }
+        Binding::Value(ident, old)
+    }
+
+    /// Bind the operator `spelling` to the precedence tier `tier`, or remove it, returning its
+    /// previous tier.
+    pub(super) fn set_operator(&mut self, spelling: Ident, tier: Option<u32>) -> Option<u32> {
+        let i = self.operators.iter().position(|(s, _)| *s == spelling);
+        let old = i.map(|i| self.operators.remove(i).1);
+        if let Some(tier) = tier {
+            self.operators.push((spelling, tier));
+        }
+        old
+    }
+
+    pub(super) fn set_function(&mut self, ident: Ident, function: Arc<Function<T>>) -> Binding<T> {
+        let old = self.functions.insert(ident.clone(), function);
+        if old.is_none() {
+            self.names.insert(ident.clone());
+        }
+        Binding::Function(ident, old)
+    }
+
+    /// Remove `ident` from `names` unless it is still a value or function.
+    pub(super) fn unindex(&mut self, ident: &Ident) {
+        if !self.values.contains_key(ident) && !self.functions.contains_key(ident) {
+            self.names.remove(ident);
+        }
+    }
+
+    /// Start logging each accepted statement and binding of the host with the bindings it changed,
+    /// if not already; the log is only appended to, surviving `reset`, `undo` and `rollback`.
+    pub fn enable_audit(&mut self) {
+        self.audit.get_or_insert_with(Vec::new);
+    }
+
+    /// Changes logged since auditing was enabled, oldest first.
+    pub fn audit_log(&self) -> &[AuditEntry<T>] {
+        self.audit.as_deref().unwrap_or_default()
+    }
+
+    /// The audit log as tab-separated lines of the time in seconds since the Unix epoch, the
+    /// workspace, the statement with tabs, newlines and backslashes escaped, and the bindings.
+    pub fn export_audit(&self) -> String {
+        let mut text = String::new();
+        for entry in self.audit_log() {
+            let time = entry.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            let statement = entry.statement.as_deref().unwrap_or_default();
+            let bindings = entry
+                .bindings
+                .iter()
+                .map(|(name, value)| match value {
+                    Some(value) => format!("{} = {}", name, value),
+                    None => name.clone(),
+                })
+                .collect::<Vec<_>>();
+            text += &format!(
+                "{}.{:03}\t{}\t{}\t{}\n",
+                time.as_secs(),
+                time.subsec_millis(),
+                entry.workspace,
+                escape_audit(statement),
+                escape_audit(&bindings.join("; "))
+            );
+        }
+        text
+    }
+
+    /// Log the bindings changed by `statement`, or by the host, if auditing.
+    pub(super) fn audit(&mut self, statement: Option<String>) {
+        if self.audit.is_none() {
+            return;
+        }
+        let bindings = self
+            .changes
+            .iter()
+            .map(|change| {
+                let value = match change.function {
+                    true => None,
+                    false => self.values.get(change.name.as_bytes()),
+                };
+                (
+                    change.name.clone(),
+                    value.map(|(_, value, _)| value.clone()),
+                )
+            })
+            .collect();
+        let entry = AuditEntry {
+            time: SystemTime::now(),
+            workspace: self.workspace.clone(),
+            statement,
+            bindings,
+        };
+        self.audit.as_mut().unwrap().push(entry);
+    }
+
+    /// Bindings created or changed by the last statement, or by the last `set_vector` or `fit`.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub(super) fn record(&mut self, bindings: Vec<Binding<T>>) {
+        self.changes = bindings
+            .iter()
+            .filter_map(|binding| match binding {
+                Binding::Value(ident, old) => Some(Change::new(ident, false, old.is_some())),
+                Binding::Function(ident, old) => Some(Change::new(ident, true, old.is_some())),
+                Binding::Operator(..) => None,
+            })
+            .collect();
+        if self.journal.len() == UNDO_LIMIT {
+            self.journal.pop_front();
+        }
+        self.journal.push_back(bindings);
+    }
+}
+
+/// `text` with backslashes, tabs and newlines escaped for a line of the exported audit log.
+fn escape_audit(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+/// The line of `dump_script` assigning `value` to `ident`, with what was typed in `source` when it
+/// differs.
+fn dump_value<T: Numeric>(ident: &[u8], value: &Value<T>, source: Option<&Source<T>>) -> String {
+    let ident = String::from_utf8_lossy(ident);
+    let line = match literal(value) {
+        Some(literal) => format!("{} = {}", ident, literal),
+        None => format!("# {} = {}", ident, value),
+    };
+    let typed = source.map(|source| source.text.replace('\n', " "));
+    let squeeze = |s: &str| s.split_whitespace().collect::<String>();
+    let (code, comment) = match &typed {
+        Some(typed) => typed.split_once('#').unwrap_or((typed, "")),
+        None => ("", ""),
+    };
+    match typed {
+        // keep what was typed when it differs from the value
+        Some(typed) if squeeze(code) != squeeze(&line) => format!("{} # {}\n", line, typed),
+        _ if !comment.is_empty() => format!("{} #{}\n", line, comment),
+        _ => format!("{}\n", line),
+    }
+}
+
+/// Source text evaluating to `value`, `None` for vectors.
+pub(super) fn literal<T: Numeric>(value: &Value<T>) -> Option<String> {
+    Some(match value {
+        Value::Bool(true) => "(0 == 0)".to_string(),
+        Value::Bool(false) => "(0 != 0)".to_string(),
+        Value::Int(i64::MIN) => format!("({} - 1)", i64::MIN + 1),
+        Value::Int(i) => i.to_string(),
+        Value::Num(r) if r.is_nan() => "(0 / 0)".to_string(),
+        Value::Num(r) if r.is_infinite() => format!("({} / 0)", r.signum()),
+        Value::Num(r) => r.literal(),
+        Value::Vector(_) => return None,
+    })
+}
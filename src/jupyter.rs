@@ -0,0 +1,620 @@
+//! Jupyter kernel
+//!
+//! [`run`] answers the Jupyter messaging protocol on the sockets of a connection file, speaking
+//! ZeroMQ's wire protocol (ZMTP 3.0 with the NULL mechanism) over TCP itself. The lines of a cell
+//! are evaluated like a script, up to the first error; the value of a final expression is the
+//! result of the cell, as plain text and LaTeX, and what the other lines show is printed.
+//! Meta-commands are run as in the REPL. Messages are signed with HMAC-SHA256 under the key of the
+//! connection file, and requests with a wrong signature are dropped.
+//!
+//! There are no plots: the interpreter computes values, not graphics.
+
+use std::{
+    convert::TryFrom,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc, Mutex, MutexGuard,
+    },
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hmac_sha256::HMAC;
+use serde_json::{json, Value as Json};
+
+use crate::{server, Interpreter};
+
+/// Version of the messaging protocol.
+const PROTOCOL_VERSION: &str = "5.3";
+
+/// Frame separating the routing identities of a message from its signature.
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+/// Largest frame accepted, in bytes.
+const MAX_FRAME: u64 = 64 * 1024 * 1024;
+
+/// Stack of a socket thread, room for user function calls a few hundred deep.
+const STACK_SIZE: usize = 32 * 1024 * 1024;
+
+const MORE: u8 = 1;
+const LONG: u8 = 2;
+const COMMAND: u8 = 4;
+
+/// The frames of a ZeroMQ message.
+type Frames = Vec<Vec<u8>>;
+
+/// The address, ports and key of a connection file written by Jupyter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    pub ip: String,
+    pub key: String,
+    pub shell_port: u16,
+    pub iopub_port: u16,
+    pub stdin_port: u16,
+    pub control_port: u16,
+    pub hb_port: u16,
+}
+
+impl Connection {
+    /// Read the JSON of a connection file, which must use the `tcp` transport and `hmac-sha256`
+    /// signatures.
+    pub fn parse(text: &str) -> io::Result<Connection> {
+        let json: Json = serde_json::from_str(text).map_err(invalid)?;
+        if json["transport"] != "tcp" {
+            return Err(invalid("transport other than tcp"));
+        }
+        if !matches!(
+            json["signature_scheme"].as_str(),
+            None | Some("hmac-sha256")
+        ) {
+            return Err(invalid("signature scheme other than hmac-sha256"));
+        }
+        let port = |name: &str| {
+            json[name]
+                .as_u64()
+                .and_then(|port| u16::try_from(port).ok())
+                .ok_or_else(|| invalid(format!("missing {}", name)))
+        };
+        Ok(Connection {
+            ip: json["ip"].as_str().unwrap_or("127.0.0.1").to_string(),
+            key: json["key"].as_str().unwrap_or("").to_string(),
+            shell_port: port("shell_port")?,
+            iopub_port: port("iopub_port")?,
+            stdin_port: port("stdin_port")?,
+            control_port: port("control_port")?,
+            hb_port: port("hb_port")?,
+        })
+    }
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Exchange greetings and `READY` commands as a socket of `socket_type`.
+fn handshake<S: Read + Write>(stream: &mut S, socket_type: &str) -> io::Result<()> {
+    let mut greeting = [0; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    stream.write_all(&greeting)?;
+    let mut ready = b"\x05READY\x0bSocket-Type".to_vec();
+    ready.extend_from_slice(&(socket_type.len() as u32).to_be_bytes());
+    ready.extend_from_slice(socket_type.as_bytes());
+    write_frame(stream, COMMAND, &ready)?;
+    stream.flush()?;
+    let mut peer = [0; 64];
+    stream.read_exact(&mut peer)?;
+    if peer[0] != 0xff || peer[9] != 0x7f || peer[10] < 3 || &peer[12..17] != b"NULL\0" {
+        return Err(invalid("peer without ZMTP 3 and the NULL mechanism"));
+    }
+    loop {
+        let (flags, body) = read_frame(stream)?;
+        if flags & COMMAND != 0 && body.starts_with(b"\x05READY") {
+            return Ok(());
+        }
+    }
+}
+
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<(u8, Vec<u8>)> {
+    let mut flags = [0];
+    reader.read_exact(&mut flags)?;
+    let size = match flags[0] & LONG {
+        0 => {
+            let mut size = [0];
+            reader.read_exact(&mut size)?;
+            size[0] as u64
+        }
+        _ => {
+            let mut size = [0; 8];
+            reader.read_exact(&mut size)?;
+            u64::from_be_bytes(size)
+        }
+    };
+    if size > MAX_FRAME {
+        return Err(invalid("frame too large"));
+    }
+    let mut body = vec![0; size as usize];
+    reader.read_exact(&mut body)?;
+    Ok((flags[0], body))
+}
+
+fn write_frame<W: Write>(writer: &mut W, flags: u8, body: &[u8]) -> io::Result<()> {
+    match u8::try_from(body.len()) {
+        Ok(size) => writer.write_all(&[flags, size])?,
+        Err(_) => {
+            writer.write_all(&[flags | LONG])?;
+            writer.write_all(&(body.len() as u64).to_be_bytes())?;
+        }
+    }
+    writer.write_all(body)
+}
+
+/// The frames of the next message, skipping commands.
+fn read_message<R: Read>(reader: &mut R) -> io::Result<Frames> {
+    let mut frames = vec![];
+    loop {
+        let (flags, body) = read_frame(reader)?;
+        if flags & COMMAND != 0 {
+            continue;
+        }
+        frames.push(body);
+        if flags & MORE == 0 {
+            return Ok(frames);
+        }
+    }
+}
+
+fn write_message<W: Write>(writer: &mut W, frames: &[Vec<u8>]) -> io::Result<()> {
+    for (i, frame) in frames.iter().enumerate() {
+        let flags = match i + 1 < frames.len() {
+            true => MORE,
+            false => 0,
+        };
+        write_frame(writer, flags, frame)?;
+    }
+    writer.flush()
+}
+
+/// A signed request, with the identities to route its reply to.
+struct Request {
+    idents: Frames,
+    header: Json,
+    content: Json,
+}
+
+/// The signing key and the identity of the kernel's messages.
+struct Session {
+    key: Vec<u8>,
+    id: String,
+    sent: AtomicU64,
+}
+
+impl Session {
+    /// The hexadecimal HMAC-SHA256 of `parts`, empty without a key.
+    fn sign(&self, parts: &[&[u8]]) -> String {
+        if self.key.is_empty() {
+            return String::new();
+        }
+        let mut mac = HMAC::new(&self.key);
+        for part in parts {
+            mac.update(part);
+        }
+        mac.finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// A message of `msg_type` with `content`, answering `parent` and routed to `idents`.
+    fn message(&self, idents: &[Vec<u8>], msg_type: &str, parent: &Json, content: Json) -> Frames {
+        let header = json!({
+            "msg_id": format!("{}-{}", self.id, self.sent.fetch_add(1, Ordering::Relaxed)),
+            "session": self.id,
+            "username": "mfni",
+            "date": now(),
+            "msg_type": msg_type,
+            "version": PROTOCOL_VERSION,
+        });
+        let parts =
+            [header, parent.clone(), json!({}), content].map(|part| part.to_string().into_bytes());
+        let mut frames = idents.to_vec();
+        frames.push(DELIMITER.to_vec());
+        frames.push(
+            self.sign(&[&parts[0], &parts[1], &parts[2], &parts[3]])
+                .into_bytes(),
+        );
+        frames.extend(parts);
+        frames
+    }
+
+    /// The request in `frames`, unless malformed or not signed with the key.
+    fn request(&self, mut frames: Frames) -> Option<Request> {
+        let delimiter = frames.iter().position(|frame| frame == DELIMITER)?;
+        let parts = frames.split_off(delimiter);
+        let (signature, parts) = parts.get(1..6)?.split_first()?;
+        let expected = self.sign(&[&parts[0], &parts[1], &parts[2], &parts[3]]);
+        let differences = signature
+            .iter()
+            .zip(expected.as_bytes())
+            .fold(0, |d, (a, b)| d | (a ^ b));
+        if signature.len() != expected.len() || differences != 0 {
+            return None;
+        }
+        Some(Request {
+            idents: frames,
+            header: serde_json::from_slice(&parts[0]).ok()?,
+            content: serde_json::from_slice(&parts[3]).ok()?,
+        })
+    }
+}
+
+/// The current time in ISO 8601, in UTC.
+fn now() -> String {
+    let since = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let (days, seconds) = ((since.as_secs() / 86_400) as i64, since.as_secs() % 86_400);
+    // the civil date of a day count, by Howard Hinnant's algorithm
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let m = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * m + 2) / 5 + 1;
+    let month = if m < 10 { m + 3 } else { m - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        since.subsec_micros()
+    )
+}
+
+/// LaTeX for a value as displayed, numbers in scientific notation as powers of ten.
+fn latex(text: &str) -> String {
+    let number = |n: &str| match n {
+        "inf" => r"\infty".to_string(),
+        "-inf" => r"-\infty".to_string(),
+        _ => match n.split_once('e') {
+            Some((m, e)) if m.parse::<f64>().is_ok() && e.parse::<i32>().is_ok() => {
+                format!(r"{} \times 10^{{{}}}", m, e)
+            }
+            _ if n.parse::<f64>().is_ok() => n.to_string(),
+            _ => format!(r"\mathrm{{{}}}", n),
+        },
+    };
+    match text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        Some("") => r"$\left[\right]$".to_string(),
+        Some(items) => {
+            let items = items.split(", ").map(number).collect::<Vec<_>>();
+            format!(r"$\left[{}\right]$", items.join(", "))
+        }
+        None => format!("${}$", number(text)),
+    }
+}
+
+/// What the response of `server::respond` shows, unless silent.
+fn shown(response: &Json) -> Option<String> {
+    if response["silent"] == true {
+        return None;
+    }
+    match (response["name"].as_str(), response["value"].as_str()) {
+        (Some(name), Some(value)) => Some(format!("{} = {}", name, value)),
+        (None, Some(value)) => Some(value.to_string()),
+        _ => response["output"]
+            .as_str()
+            .or_else(|| response["help"].as_str())
+            .map(|text| text.trim_end().to_string())
+            .or_else(|| {
+                let name = response["workspace"].as_str()?;
+                Some(format!("workspace {}", name))
+            }),
+    }
+}
+
+fn kernel_info() -> Json {
+    json!({
+        "status": "ok",
+        "protocol_version": PROTOCOL_VERSION,
+        "implementation": "mfni",
+        "implementation_version": env!("CARGO_PKG_VERSION"),
+        "language_info": {
+            "name": "mfni",
+            "version": env!("CARGO_PKG_VERSION"),
+            "mimetype": "text/x-mfni",
+            "file_extension": ".mfn",
+        },
+        "banner": "mfni, a math function interpreter",
+    })
+}
+
+struct Kernel {
+    session: Session,
+    interpreter: Mutex<Interpreter>,
+    /// Clients of the IOPub socket.
+    subscribers: Mutex<Vec<TcpStream>>,
+    execution_count: AtomicU64,
+    shutdown: Mutex<mpsc::Sender<()>>,
+}
+
+impl Kernel {
+    /// Send a message to every client of the IOPub socket, forgetting those disconnected.
+    fn publish(&self, parent: &Json, msg_type: &str, content: Json) {
+        let topic = [msg_type.as_bytes().to_vec()];
+        let frames = self.session.message(&topic, msg_type, parent, content);
+        lock(&self.subscribers).retain_mut(|stream| write_message(stream, &frames).is_ok());
+    }
+
+    /// The type and content of the reply to `request`, the kernel busy meanwhile.
+    fn answer(&self, request: &Request) -> Option<(&'static str, Json)> {
+        let parent = &request.header;
+        self.publish(parent, "status", json!({ "execution_state": "busy" }));
+        let reply = match parent["msg_type"].as_str().unwrap_or("") {
+            "kernel_info_request" => Some(("kernel_info_reply", kernel_info())),
+            "execute_request" => Some(("execute_reply", self.execute(parent, &request.content))),
+            "complete_request" => Some(("complete_reply", self.complete(&request.content))),
+            "is_complete_request" => {
+                let code = request.content["code"].as_str().unwrap_or("");
+                let status = match code.trim_end().ends_with("...") {
+                    true => "incomplete",
+                    false => "complete",
+                };
+                Some((
+                    "is_complete_reply",
+                    json!({ "status": status, "indent": "" }),
+                ))
+            }
+            "comm_info_request" => {
+                Some(("comm_info_reply", json!({ "status": "ok", "comms": {} })))
+            }
+            "interrupt_request" => Some((
+                "interrupt_reply",
+                json!({
+                    "status": "error",
+                    "ename": "Interrupt",
+                    "evalue": "Evaluations can't be interrupted",
+                    "traceback": [],
+                }),
+            )),
+            "shutdown_request" => Some((
+                "shutdown_reply",
+                json!({ "status": "ok", "restart": request.content["restart"] == true }),
+            )),
+            _ => None,
+        };
+        self.publish(parent, "status", json!({ "execution_state": "idle" }));
+        reply
+    }
+
+    /// Evaluate the lines of a cell up to the first error, publishing what they show.
+    fn execute(&self, parent: &Json, content: &Json) -> Json {
+        let code = content["code"].as_str().unwrap_or("");
+        let silent = content["silent"] == true;
+        let count = match silent {
+            true => self.execution_count.load(Ordering::Relaxed),
+            false => self.execution_count.fetch_add(1, Ordering::Relaxed) + 1,
+        };
+        if !silent {
+            let input = json!({ "code": code, "execution_count": count });
+            self.publish(parent, "execute_input", input);
+        }
+        let mut interpreter = lock(&self.interpreter);
+        // the value of the last line, if an expression, and what the lines before showed
+        let (mut result, mut printed, mut warnings) = (None, String::new(), String::new());
+        let (mut error, mut incomplete) = (None, false);
+        // lines before the statement being read
+        let mut before = 0;
+        for (i, line) in code.lines().enumerate() {
+            let response = server::respond_at(&mut interpreter, line, before);
+            incomplete = response["incomplete"] == true;
+            if !incomplete {
+                before = i + 1;
+            }
+            if response.get("error").is_some() {
+                error = Some(response["error"].clone());
+                break;
+            }
+            for warning in response["warnings"].as_array().into_iter().flatten() {
+                warnings += &format!("warning: {}\n", warning["message"].as_str().unwrap_or(""));
+            }
+            if let Some(text) = shown(&response) {
+                printed.extend(result.take().map(|r: String| r + "\n"));
+                match response.get("name").is_none() && response.get("value").is_some() {
+                    true => result = Some(text),
+                    false => printed += &(text + "\n"),
+                }
+            }
+        }
+        if incomplete {
+            interpreter.cancel();
+            let message = "Statement continued with `...` at the end of the cell";
+            error = Some(json!({ "message": message }));
+        }
+        drop(interpreter);
+        if !silent {
+            for (name, text) in [("stdout", printed), ("stderr", warnings)] {
+                if !text.is_empty() {
+                    self.publish(parent, "stream", json!({ "name": name, "text": text }));
+                }
+            }
+            if let (Some(result), None) = (result, &error) {
+                let data = json!({ "text/plain": result, "text/latex": latex(&result) });
+                let result = json!({ "execution_count": count, "data": data, "metadata": {} });
+                self.publish(parent, "execute_result", result);
+            }
+        }
+        let error = match error {
+            Some(error) => error,
+            None => {
+                return json!({
+                    "status": "ok",
+                    "execution_count": count,
+                    "user_expressions": {},
+                    "payload": [],
+                })
+            }
+        };
+        let message = error["message"].as_str().unwrap_or("").to_string();
+        let traceback = match error["line"].as_u64() {
+            Some(line) => format!("line {}: {}", line, message),
+            None => message.clone(),
+        };
+        let error = json!({
+            "ename": error["code"].as_str().unwrap_or("Error"),
+            "evalue": message,
+            "traceback": [traceback],
+        });
+        if !silent {
+            self.publish(parent, "error", error.clone());
+        }
+        let mut reply = json!({ "status": "error", "execution_count": count });
+        reply
+            .as_object_mut()
+            .unwrap()
+            .extend(error.as_object().unwrap().clone());
+        reply
+    }
+
+    /// Names completing the word before the cursor.
+    fn complete(&self, content: &Json) -> Json {
+        let code = content["code"].as_str().unwrap_or("");
+        // the cursor counts characters, the interpreter bytes of a line
+        let cursor = content["cursor_pos"].as_u64().unwrap_or(0) as usize;
+        let at = code
+            .char_indices()
+            .nth(cursor)
+            .map_or(code.len(), |(i, _)| i);
+        let line = &code[code[..at].rfind('\n').map_or(0, |i| i + 1)..at];
+        let mut matches = lock(&self.interpreter)
+            .complete(line, line.len())
+            .into_iter()
+            .map(|completion| completion.text)
+            .collect::<Vec<_>>();
+        matches.dedup();
+        let word = line
+            .chars()
+            .rev()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .count();
+        json!({
+            "status": "ok",
+            "matches": matches,
+            "cursor_start": code[..at].chars().count() - word,
+            "cursor_end": code[..at].chars().count(),
+            "metadata": {},
+        })
+    }
+}
+
+/// Answer the requests of a client of the shell or control socket until it disconnects.
+fn serve_requests(kernel: &Kernel, mut stream: TcpStream) -> io::Result<()> {
+    handshake(&mut stream, "ROUTER")?;
+    loop {
+        let frames = read_message(&mut stream)?;
+        let request = match kernel.session.request(frames) {
+            Some(request) => request,
+            None => continue,
+        };
+        if let Some((msg_type, content)) = kernel.answer(&request) {
+            let reply = kernel
+                .session
+                .message(&request.idents, msg_type, &request.header, content);
+            write_message(&mut stream, &reply)?;
+        }
+        if request.header["msg_type"] == "shutdown_request" {
+            let _ = lock(&kernel.shutdown).send(());
+        }
+    }
+}
+
+/// Publish to a client of the IOPub socket, reading its subscriptions until it disconnects.
+fn serve_subscriber(kernel: &Kernel, mut stream: TcpStream) -> io::Result<()> {
+    handshake(&mut stream, "PUB")?;
+    lock(&kernel.subscribers).push(stream.try_clone()?);
+    loop {
+        read_message(&mut stream)?;
+    }
+}
+
+/// Echo the messages of a client of the heartbeat socket.
+fn serve_heartbeat(mut stream: TcpStream) -> io::Result<()> {
+    handshake(&mut stream, "REP")?;
+    loop {
+        let frames = read_message(&mut stream)?;
+        write_message(&mut stream, &frames)?;
+    }
+}
+
+/// Accept a client of the stdin socket, which the kernel never asks for input.
+fn serve_stdin(mut stream: TcpStream) -> io::Result<()> {
+    handshake(&mut stream, "ROUTER")?;
+    loop {
+        read_message(&mut stream)?;
+    }
+}
+
+/// Bind `ip:port`, serving each client on its own thread.
+fn listen<F>(ip: &str, port: u16, serve: F) -> io::Result<()>
+where
+    F: Fn(TcpStream) -> io::Result<()> + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind((ip, port))?;
+    let serve = Arc::new(serve);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let serve = serve.clone();
+            let _ = thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn(move || serve(stream));
+        }
+    });
+    Ok(())
+}
+
+/// Serve the sockets of `connection` on `interpreter` until a client asks the kernel to shut down.
+pub fn run(connection: &Connection, interpreter: Interpreter) -> io::Result<()> {
+    let (shutdown, requested) = mpsc::channel();
+    let since = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let kernel = Arc::new(Kernel {
+        session: Session {
+            key: connection.key.as_bytes().to_vec(),
+            id: format!("{:x}-{:x}", since.as_nanos(), process::id()),
+            sent: AtomicU64::new(0),
+        },
+        interpreter: Mutex::new(interpreter),
+        subscribers: Mutex::new(vec![]),
+        execution_count: AtomicU64::new(0),
+        shutdown: Mutex::new(shutdown),
+    });
+    let ip = connection.ip.as_str();
+    for port in [connection.shell_port, connection.control_port] {
+        let kernel = kernel.clone();
+        listen(ip, port, move |stream| serve_requests(&kernel, stream))?;
+    }
+    let iopub = kernel.clone();
+    listen(ip, connection.iopub_port, move |stream| {
+        serve_subscriber(&iopub, stream)
+    })?;
+    listen(ip, connection.stdin_port, serve_stdin)?;
+    listen(ip, connection.hb_port, serve_heartbeat)?;
+    let _ = requested.recv();
+    Ok(())
+}
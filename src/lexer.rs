@@ -1,6 +1,8 @@
 //! The lexer
 
-use crate::Real;
+use std::{fmt, ops::Range};
+
+use crate::{consts::PI, value::Value, InputError, Real};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum MulDivOp {
@@ -26,22 +28,34 @@ pub(crate) enum CompareOp {
 }
 
 impl CompareOp {
-    pub(crate) fn on(self, r1: Real, r2: Real) -> Real {
+    pub(crate) fn symbol(self) -> &'static str {
+        match self {
+            CompareOp::LT => "<",
+            CompareOp::GT => ">",
+            CompareOp::LE => "<=",
+            CompareOp::GE => ">=",
+            CompareOp::EQ => "==",
+            CompareOp::NE => "!=",
+            CompareOp::CMP => "<=>",
+        }
+    }
+
+    pub(crate) fn on<T: PartialOrd>(self, r1: T, r2: T) -> i64 {
         if r1 > r2 {
             match self {
-                CompareOp::GT | CompareOp::GE | CompareOp::NE | CompareOp::CMP => 1.0,
-                CompareOp::LT | CompareOp::LE | CompareOp::EQ => 0.0,
+                CompareOp::GT | CompareOp::GE | CompareOp::NE | CompareOp::CMP => 1,
+                CompareOp::LT | CompareOp::LE | CompareOp::EQ => 0,
             }
         } else if r1 < r2 {
             match self {
-                CompareOp::LT | CompareOp::LE | CompareOp::NE => 1.0,
-                CompareOp::GT | CompareOp::GE | CompareOp::EQ => 0.0,
-                CompareOp::CMP => -1.0,
+                CompareOp::LT | CompareOp::LE | CompareOp::NE => 1,
+                CompareOp::GT | CompareOp::GE | CompareOp::EQ => 0,
+                CompareOp::CMP => -1,
             }
         } else {
             match self {
-                CompareOp::GE | CompareOp::LE | CompareOp::EQ => 1.0,
-                CompareOp::GT | CompareOp::LT | CompareOp::NE | CompareOp::CMP => 0.0,
+                CompareOp::GE | CompareOp::LE | CompareOp::EQ => 1,
+                CompareOp::GT | CompareOp::LT | CompareOp::NE | CompareOp::CMP => 0,
             }
         }
     }
@@ -49,10 +63,28 @@ impl CompareOp {
 
 pub(crate) type Ident = Vec<u8>;
 
+/// A number literal, with the digits of a fraction for number types more precise than `Real`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Literal {
+    pub(crate) value: Value,
+    /// The number in the notation of `str::parse` and whether it is in degrees, for a fraction,
+    /// an exponent or an integer too large for `Int` written in decimal.
+    pub(crate) decimal: Option<(String, bool)>,
+}
+
+impl From<Value> for Literal {
+    fn from(value: Value) -> Self {
+        Literal {
+            value,
+            decimal: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum Token {
     IDENT(Ident),
-    NUM(Real),
+    NUM(Literal),
     ASSIGN,
     LPAREN,
     RPAREN,
@@ -66,10 +98,37 @@ pub(crate) enum Token {
     COND,
     COLON,
     COMMA,
+    LBRACE,
+    RBRACE,
+    SEMI,
+    /// A user-defined operator with the token id of its precedence tier.
+    OP(u32, Ident),
 }
 
 impl Token {
-    pub(crate) const COUNT: usize = 15;
+    pub(crate) const COUNT: usize = 18;
+
+    /// Names of the tokens by id, for syntax errors.
+    pub(crate) const NAMES: [&'static str; Token::COUNT] = [
+        "identifier",
+        "number",
+        "'='",
+        "'('",
+        "')'",
+        "'!'",
+        "'^'",
+        "'*', '/'",
+        "'+', '-'",
+        "comparison",
+        "'||'",
+        "'&&'",
+        "'?'",
+        "':'",
+        "','",
+        "'{'",
+        "'}'",
+        "';'",
+    ];
 
     pub(crate) const fn id(&self) -> u32 {
         match self {
@@ -88,65 +147,153 @@ impl Token {
             Token::COND => 12,
             Token::COLON => 13,
             Token::COMMA => 14,
+            Token::LBRACE => 15,
+            Token::RBRACE => 16,
+            Token::SEMI => 17,
+            Token::OP(tier, _) => *tier,
         }
     }
 
-    pub(crate) fn assume_ident(self) -> Ident {
+    pub(crate) fn assume_ident(self) -> Result<Ident, InputError> {
         match self {
-            Token::IDENT(ident) => ident,
-            _ => panic!("Can't unwrap an ident"),
+            Token::IDENT(ident) => Ok(ident),
+            _ => Err(InputError::InternalError {
+                message: "expect an identifier token",
+            }),
         }
     }
 
-    pub(crate) fn assume_num(self) -> Real {
+    pub(crate) fn assume_num(self) -> Result<Literal, InputError> {
         match self {
-            Token::NUM(num) => num,
-            _ => panic!("Can't unwrap an ident"),
+            Token::NUM(num) => Ok(num),
+            _ => Err(InputError::InternalError {
+                message: "expect a number token",
+            }),
         }
     }
 
-    pub(crate) fn assume_md(self) -> MulDivOp {
+    pub(crate) fn assume_md(self) -> Result<MulDivOp, InputError> {
         match self {
-            Token::MD(md) => md,
-            _ => panic!("Can't unwrap mul or div assign"),
+            Token::MD(md) => Ok(md),
+            _ => Err(InputError::InternalError {
+                message: "expect a '*' or '/' operator token",
+            }),
         }
     }
 
-    pub(crate) fn assume_pn(self) -> AddSubOp {
+    pub(crate) fn assume_pn(self) -> Result<AddSubOp, InputError> {
         match self {
-            Token::PN(pn) => pn,
-            _ => panic!("Can't unwrap add or sub sign"),
+            Token::PN(pn) => Ok(pn),
+            _ => Err(InputError::InternalError {
+                message: "expect a '+' or '-' sign token",
+            }),
         }
     }
 
-    pub(crate) fn assume_cmp(self) -> CompareOp {
+    pub(crate) fn assume_cmp(self) -> Result<CompareOp, InputError> {
         match self {
-            Token::CMP(cmp) => cmp,
-            _ => panic!("Can't unwrap add or sub sign"),
+            Token::CMP(cmp) => Ok(cmp),
+            _ => Err(InputError::InternalError {
+                message: "expect a comparison operator token",
+            }),
         }
     }
 }
 
-impl ToString for Token {
-    fn to_string(&self) -> String {
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Token::IDENT(ident) => {
-                format!("IDENT(\"{}\")", String::from_utf8(ident.clone()).unwrap())
-            }
-            _ => format!("{:?}", self),
+            Token::IDENT(ident) => write!(f, "IDENT(\"{}\")", String::from_utf8_lossy(ident)),
+            Token::OP(_, spelling) => write!(f, "OP(\"{}\")", String::from_utf8_lossy(spelling)),
+            _ => write!(f, "{:?}", self),
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct InvalidToken {
+    /// Line in the statement, from 1.
+    line: usize,
     column: usize,
     expect: &'static str,
     found: String,
 }
 
+impl InvalidToken {
+    pub(crate) fn line(&self) -> usize {
+        self.line
+    }
+
+    pub(crate) fn offset_lines(&mut self, lines: usize) {
+        self.line += lines;
+    }
+
+    pub(crate) fn column(&self) -> usize {
+        self.column
+    }
+}
+
+/// Classification of a token for syntax highlighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Identifier,
+    Number,
+    Operator,
+    Builtin,
+    Comment,
+    Error,
+}
+
+/// A classified token with its byte range in the input line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpannedToken {
+    pub kind: TokenKind,
+    pub span: Range<usize>,
+}
+
+/// Whether `c` starts no token of the language, so it may start a user-defined operator.
+pub(crate) fn is_operator_start(c: u8) -> bool {
+    !(c.is_ascii_alphanumeric()
+        || c.is_ascii_whitespace()
+        || b"_=!<>|&()^*/+-?:,.~#\0".contains(&c))
+}
+
+/// SI prefixes with their powers of ten, `u` and the micro sign for micro.
+const SI_PREFIXES: &[(&[u8], i32)] = &[
+    (b"Y", 24),
+    (b"Z", 21),
+    (b"E", 18),
+    (b"P", 15),
+    (b"T", 12),
+    (b"G", 9),
+    (b"M", 6),
+    (b"k", 3),
+    (b"m", -3),
+    (b"u", -6),
+    ("\u{b5}".as_bytes(), -6),
+    ("\u{3bc}".as_bytes(), -6),
+    (b"n", -9),
+    (b"p", -12),
+    (b"f", -15),
+    (b"a", -18),
+    (b"z", -21),
+    (b"y", -24),
+];
+
+/// Angle units, whether in degrees rather than radians.
+const ANGLE_SUFFIXES: &[(&[u8], bool)] =
+    &[(b"deg", true), ("\u{b0}".as_bytes(), true), (b"rad", false)];
+
 pub(crate) struct Lexer<'a> {
     line: &'a [u8],
+    /// Spellings of user-defined operators and the token ids of their tiers.
+    operators: &'a [(Ident, u32)],
+    /// Accept `,` as decimal separator and `_` or thin space digit grouping in numbers.
+    decimal_comma: bool,
+    /// Accept SI magnitude suffixes like `k` and `u` on numbers.
+    si_suffixes: bool,
+    /// Line of `line` in the statement, from 1.
+    line_number: usize,
     column: usize,
     begin: usize,
     stream: TokenStream,
@@ -154,32 +301,86 @@ pub(crate) struct Lexer<'a> {
 
 pub(crate) struct TokenStream {
     pub(crate) complete: bool,
-    pub(crate) tokens: Vec<(usize, Token)>,
+    /// Span of a `#` comment running to the end of the line.
+    pub(crate) comment: Option<Range<usize>>,
+    pub(crate) tokens: Vec<(Range<usize>, Token)>,
 }
 
 impl<'a> Lexer<'a> {
+    /// Lex `line` up to its first NUL byte or its end.
     pub(crate) fn new(line: &'a [u8]) -> Self {
         Lexer {
             line,
+            operators: &[],
+            decimal_comma: false,
+            si_suffixes: false,
+            line_number: 1,
             column: 0,
             begin: 0,
             stream: TokenStream {
                 complete: true,
+                comment: None,
                 tokens: vec![],
             },
         }
     }
 
+    /// Lex `line`, also recognizing user-defined operators.
+    pub(crate) fn with_operators(line: &'a [u8], operators: &'a [(Ident, u32)]) -> Self {
+        Lexer {
+            operators,
+            ..Lexer::new(line)
+        }
+    }
+
+    /// Read a `,` between digits as decimal separator, and skip `_` and thin spaces between digits.
+    pub(crate) fn decimal_comma(mut self, on: bool) -> Self {
+        self.decimal_comma = on;
+        self
+    }
+
+    /// Scale numbers directly followed by an SI prefix, like `4.7k` or `3u`, by its power of ten.
+    pub(crate) fn si_suffixes(mut self, on: bool) -> Self {
+        self.si_suffixes = on;
+        self
+    }
+
+    /// Report invalid tokens at line `line` of a multi-line statement.
+    pub(crate) fn line_number(mut self, line: usize) -> Self {
+        self.line_number = line;
+        self
+    }
+
+    /// Start lexing at `column`, keeping the spans relative to the whole line.
+    pub(crate) fn starting_at(mut self, column: usize) -> Self {
+        self.column = column.min(self.line.len());
+        self
+    }
+
     pub(crate) fn tokenize(mut self) -> Result<TokenStream, InvalidToken> {
+        self.run()?;
+        Ok(self.stream)
+    }
+
+    /// Tokenize up to the first invalid token, keeping the tokens before it.
+    pub(crate) fn tokenize_partial(mut self) -> (TokenStream, Option<InvalidToken>) {
+        let err = self.run().err();
+        (self.stream, err)
+    }
+
+    fn run(&mut self) -> Result<(), InvalidToken> {
         loop {
             let c = self.skip_whitespace();
-            if c.is_ascii_alphabetic() || c == b'_' {
+            if let Some((spelling, tier)) = self.operator() {
+                self.column += spelling.len();
+                self.push(Token::OP(tier, spelling));
+            } else if c.is_ascii_alphabetic() || c == b'_' {
                 self.eat();
                 while self.cur().is_ascii_alphanumeric() || self.cur() == b'_' {
                     self.eat();
                 }
                 self.push(Token::IDENT(self.line[self.begin..self.column].to_vec()));
-            } else if c.is_ascii_digit() {
+            } else if c.is_ascii_digit() || (c == b'.' && self.peek().is_ascii_digit()) {
                 self.read_number()?;
             } else {
                 self.eat();
@@ -214,8 +415,9 @@ impl<'a> Lexer<'a> {
                             if self.cur() == b'>' {
                                 self.eat();
                                 self.push(Token::CMP(CompareOp::CMP));
+                            } else {
+                                self.push(Token::CMP(CompareOp::LE));
                             }
-                            self.push(Token::CMP(CompareOp::LE));
                         } else {
                             self.push(Token::CMP(CompareOp::LT))
                         }
@@ -236,6 +438,15 @@ impl<'a> Lexer<'a> {
                             return self.err("logical 'and' operator");
                         }
                     }
+                    b'~' => {
+                        if self.cur() == b'=' {
+                            self.eat();
+                            // approximately equal, at the priority of comparisons
+                            self.push(Token::OP(9, b"~=".to_vec()));
+                        } else {
+                            return self.err("approximately equal ('~=') operator");
+                        }
+                    }
                     b'(' => self.push(Token::LPAREN),
                     b')' => self.push(Token::RPAREN),
                     b'^' => self.push(Token::EXP),
@@ -246,6 +457,9 @@ impl<'a> Lexer<'a> {
                     b'?' => self.push(Token::COND),
                     b':' => self.push(Token::COLON),
                     b',' => self.push(Token::COMMA),
+                    b'{' => self.push(Token::LBRACE),
+                    b'}' => self.push(Token::RBRACE),
+                    b';' => self.push(Token::SEMI),
                     b'.' => {
                         if self.cur() == b'.' {
                             self.eat();
@@ -254,70 +468,202 @@ impl<'a> Lexer<'a> {
                                 self.stream.complete = false;
                                 break;
                             }
+                            // a range, at the priority of comparisons
+                            self.push(Token::OP(9, b"..".to_vec()));
+                            continue;
                         }
-                        return self.err("wrap ('...') token");
+                        return self.err("range ('..') or wrap ('...') token");
+                    }
+                    b'#' => {
+                        while self.cur() != b'\0' {
+                            self.eat();
+                        }
+                        self.stream.comment = Some(self.begin..self.column);
+                        break;
                     }
                     b'\0' => break,
-                    _ => return self.err("a valid token"),
+                    _ => {
+                        self.column = self.begin;
+                        return self.err("a valid token");
+                    }
                 }
             }
         }
-        Ok(self.stream)
+        Ok(())
     }
 
     fn read_number(&mut self) -> Result<(), InvalidToken> {
-        let to_digit = |c: u8| ((c as i8) - (b'0' as i8)) as i32;
-
-        let mut num = 0.0;
-        while self.cur().is_ascii_digit() {
-            num *= 10.0;
-            num += to_digit(self.cur()) as Real;
-            self.eat()
+        if self.cur() == b'0' {
+            let radix = match self.peek() {
+                b'x' | b'X' => 16,
+                b'o' | b'O' => 8,
+                b'b' | b'B' => 2,
+                _ => 0,
+            };
+            if radix != 0 {
+                self.eat();
+                self.eat();
+                return self.read_radix(radix);
+            }
         }
 
-        if self.cur() == b'.' {
+        let mut literal = String::new();
+        let mut float = false;
+        self.eat_digits(&mut literal);
+
+        // `1..n` is a range
+        let comma = self.decimal_comma && self.cur() == b',' && self.peek().is_ascii_digit();
+        if comma || self.cur() == b'.' && self.peek() != b'.' {
+            float = true;
+            literal.push('.');
             self.eat();
-            let mut num2 = 0.0;
-            let mut div = 1.0;
-            while self.cur().is_ascii_digit() {
-                div *= 0.1;
-                num2 += to_digit(self.cur()) as Real * div;
-                self.eat()
-            }
-            num += num2;
+            self.eat_digits(&mut literal);
         }
 
-        if self.cur() == b'e' || self.cur() == b'E' {
+        // `2E` is 2 exa with SI suffixes
+        let exponent = match self.peek() {
+            b'+' | b'-' => self.line.get(self.column + 2).copied().unwrap_or(b'\0'),
+            c => c,
+        }
+        .is_ascii_digit();
+        if self.cur() == b'e' || self.cur() == b'E' && (exponent || !self.si_suffixes) {
+            float = true;
+            literal.push('e');
             self.eat();
-            let mut neg = false;
-            if self.cur() == b'-' {
-                neg = true;
+            if self.cur() == b'-' || self.cur() == b'+' {
+                literal.push(self.cur() as char);
                 self.eat();
-            } else if self.cur() == b'+' {
+            }
+            if !self.cur().is_ascii_digit() {
+                return self.err("number index part");
+            }
+            while self.cur().is_ascii_digit() {
+                literal.push(self.cur() as char);
                 self.eat();
             }
-            if self.cur().is_ascii_digit() {
-                let mut n = to_digit(self.cur());
+        } else if let Some((len, power)) = self.si_prefix() {
+            self.column += len;
+            match float || power < 0 {
+                true => literal += &format!("e{}", power),
+                false => literal.extend((0..power).map(|_| '0')),
+            }
+        }
+
+        // in radians, like the trigonometric builtins
+        let angle = self.suffix(ANGLE_SUFFIXES);
+        if let Some((len, _)) = angle {
+            float = true;
+            self.column += len;
+        }
+
+        // parsed by the standard library to be correctly rounded
+        let num = match literal.parse::<Real>() {
+            Ok(num) => num,
+            Err(_) => return self.err("number"),
+        };
+        let degrees = angle.is_some_and(|(_, degrees)| degrees);
+        let value = match (float, angle) {
+            (_, Some(_)) if degrees => Value::Num(num * (PI / 180.0)),
+            (false, None) => literal.parse().map_or(Value::Num(num), Value::Int),
+            _ => Value::Num(num),
+        };
+        let decimal = match value {
+            Value::Num(_) => Some((literal, degrees)),
+            _ => None,
+        };
+        self.push(Token::NUM(Literal { value, decimal }));
+        Ok(())
+    }
+
+    /// Length and power of ten of an SI prefix at the cursor.
+    fn si_prefix(&self) -> Option<(usize, i32)> {
+        match self.si_suffixes {
+            true => self.suffix(SI_PREFIXES),
+            false => None,
+        }
+    }
+
+    /// Length and value of one of `suffixes` at the cursor, not followed by an identifier character.
+    fn suffix<T: Copy>(&self, suffixes: &[(&[u8], T)]) -> Option<(usize, T)> {
+        let rest = &self.line[self.column..];
+        let &(suffix, value) = suffixes.iter().find(|(s, _)| rest.starts_with(s))?;
+        match rest.get(suffix.len()) {
+            Some(c) if c.is_ascii_alphanumeric() || *c == b'_' || !c.is_ascii() => None,
+            _ => Some((suffix.len(), value)),
+        }
+    }
+
+    /// Append digits to `literal`, skipping digit grouping with `decimal_comma`.
+    fn eat_digits(&mut self, literal: &mut String) {
+        loop {
+            let c = self.cur();
+            if c.is_ascii_digit() {
+                literal.push(c as char);
                 self.eat();
-                while self.cur().is_ascii_digit() {
-                    n *= 10;
-                    n += to_digit(self.cur());
-                    self.eat();
-                }
-                if neg {
-                    n = -n;
-                }
-                num *= 10.0f64.powi(n);
-            } else {
-                return self.err("number index part");
+                continue;
+            }
+            let rest = &self.line[self.column..];
+            let group = match rest {
+                // thin space and narrow no-break space
+                [0xe2, 0x80, 0x89 | 0xaf, ..] => 3,
+                [b'_', ..] => 1,
+                _ => 0,
+            };
+            let next = rest.get(group).copied().unwrap_or(b'\0');
+            if !(self.decimal_comma
+                && group > 0
+                && literal.ends_with(|c: char| c.is_ascii_digit())
+                && next.is_ascii_digit())
+            {
+                return;
             }
+            self.column += group;
         }
+    }
 
-        Ok(self.push(Token::NUM(num)))
+    fn read_radix(&mut self, radix: u32) -> Result<(), InvalidToken> {
+        let mut num = 0.0;
+        let mut int = Some(0i64);
+        let begin = self.column;
+        while let Some(d) = (self.cur() as char).to_digit(radix) {
+            num = num * radix as Real + d as Real;
+            int = int.and_then(|i| i.checked_mul(radix as i64)?.checked_add(d as i64));
+            self.eat();
+        }
+        if self.column == begin || self.cur().is_ascii_alphanumeric() {
+            return self.err(match radix {
+                16 => "hexadecimal digits",
+                8 => "octal digits",
+                _ => "binary digits",
+            });
+        }
+        self.push(Token::NUM(Literal::from(match int {
+            Some(i) => Value::Int(i),
+            None => Value::Num(num),
+        })));
+        Ok(())
     }
 
+    /// The longest user-defined operator at the current column.
+    fn operator(&self) -> Option<(Ident, u32)> {
+        let rest = self.line.get(self.column..).unwrap_or_default();
+        self.operators
+            .iter()
+            .filter(|(spelling, _)| rest.starts_with(spelling))
+            .max_by_key(|(spelling, _)| spelling.len())
+            .cloned()
+    }
+
+    /// The current byte, NUL past the end of the line.
     fn cur(&self) -> u8 {
-        self.line[self.column]
+        self.line.get(self.column).copied().unwrap_or(b'\0')
+    }
+
+    fn peek(&self) -> u8 {
+        match self.cur() {
+            b'\0' => b'\0',
+            _ => self.line.get(self.column + 1).copied().unwrap_or(b'\0'),
+        }
     }
 
     fn eat(&mut self) {
@@ -329,19 +675,24 @@ impl<'a> Lexer<'a> {
             self.eat();
         }
         self.begin = self.column;
-        return self.cur();
+        self.cur()
     }
 
     fn push(&mut self, token: Token) {
-        self.stream.tokens.push((self.begin, token));
+        self.stream.tokens.push((self.begin..self.column, token));
     }
 
     fn err<T>(&self, expect: &'static str) -> Result<T, InvalidToken> {
         let found = match self.cur() {
             b'\0' => "end of command".to_string(),
-            c => (c as char).to_string(),
+            c if c.is_ascii() => (c as char).to_string(),
+            _ => {
+                let rest = String::from_utf8_lossy(&self.line[self.column..]);
+                rest.chars().next().unwrap_or_default().to_string()
+            }
         };
         Err(InvalidToken {
+            line: self.line_number,
             column: self.column,
             expect,
             found,
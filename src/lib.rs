@@ -1,10 +1,79 @@
 //! Mathematical Functional Interpreter
 
+#![allow(clippy::upper_case_acronyms)]
+
+/// Enter a debug `tracing` span until the end of the block, with the `tracing` feature.
+macro_rules! span {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($($arg)*).entered();
+    };
+}
+
+/// Emit a trace `tracing` event, with the `tracing` feature.
+macro_rules! event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
+
+mod cache;
+mod command;
+mod csv;
+mod diagnostic;
+mod dist;
+#[cfg(feature = "fft")]
+mod fft;
+#[cfg(feature = "finance")]
+mod finance;
+#[cfg(feature = "harness")]
+pub mod harness;
+mod help;
+#[cfg(feature = "http")]
+pub mod http;
+mod interpolate;
 mod interpreter;
+#[cfg(feature = "jupyter")]
+pub mod jupyter;
 mod lexer;
+mod number;
+mod numeric;
 mod parser;
+#[cfg(feature = "pratt")]
+mod pratt;
+#[cfg(feature = "repl")]
+mod repl;
+mod sequence;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "sheet")]
+pub mod sheet;
+mod solver;
+mod stats;
+mod value;
 
+/// Number type of the builtin libraries, and the default of the interpreter.
 pub type Real = f64;
 
-pub use interpreter::{InputError, InputState, Interpreter};
-pub use lexer::InvalidToken;
+pub(crate) use std::f64::consts;
+
+pub use cache::CacheStats;
+pub use command::CommandError;
+pub use csv::CsvError;
+pub use diagnostic::{render_error, ColorChoice, Diagnostic, Severity};
+pub use help::HelpEntry;
+pub use interpreter::{
+    Assigned, Atan2Order, AuditEntry, Budget, BuiltinGroup, Change, ChangeKind, Completion,
+    CompletionKind, DebugAction, Definition, Frame, FreeVariables, IdentInfo, InputError,
+    InputRole, InputState, Interpreter, InterpreterBuilder, ProfileEntry, RequiredInput,
+    ResultViews, SnapshotId, Timing, TraceStep, Warning,
+};
+pub use lexer::{InvalidToken, SpannedToken, TokenKind};
+#[cfg(feature = "decimal")]
+pub use numeric::Decimal;
+pub use numeric::{DoubleDouble, Numeric};
+#[cfg(feature = "repl")]
+pub use repl::Repl;
+pub use solver::SolveError;
+pub use value::{EvalError, Value};
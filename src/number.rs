@@ -0,0 +1,338 @@
+//! Number theory
+//!
+//! Integers are exact as integers up to 2^63 and as numbers up to 2^53, larger numbers are rejected.
+
+use crate::{
+    value::{EvalError, Value},
+    Real,
+};
+
+pub(crate) const MAX_EXACT: Real = 9_007_199_254_740_992.0;
+
+fn positive_integer(val: &Value) -> Result<u64, EvalError> {
+    match *val {
+        Value::Int(i) if i >= 1 => return Ok(i as u64),
+        Value::Int(_) => {}
+        _ => {
+            let r = val.num()?;
+            if (1.0..=MAX_EXACT).contains(&r) && r.fract() == 0.0 {
+                return Ok(r as u64);
+            }
+        }
+    }
+    Err(EvalError::InvalidArgument {
+        expect: "positive integer, or number not above 2^53",
+    })
+}
+
+fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    ((a as u128 * b as u128) % m as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exp: u64, m: u64) -> u64 {
+    let mut result = 1;
+    base %= m;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exp >>= 1;
+    }
+    result
+}
+
+/// Deterministic Miller-Rabin test for 64-bit integers.
+pub(crate) fn is_prime(n: u64) -> bool {
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    for &p in BASES.iter() {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+    BASES.iter().all(|&a| {
+        let mut x = pow_mod(a, d, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..s {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// A nontrivial divisor of the odd composite `n` (Pollard's rho, Brent's variant).
+fn rho(n: u64) -> u64 {
+    let mut c = 0;
+    loop {
+        c += 1;
+        let f = |x| (mul_mod(x, x, n) + c) % n;
+        let (mut x, mut y, mut d) = (2, 2, 1);
+        while d == 1 {
+            x = f(x);
+            y = f(f(y));
+            d = gcd(x.abs_diff(y), n);
+        }
+        if d != n {
+            return d;
+        }
+    }
+}
+
+fn prime_factors(n: u64, factors: &mut Vec<u64>) {
+    if n == 1 {
+        return;
+    }
+    if is_prime(n) {
+        factors.push(n);
+        return;
+    }
+    for p in [2, 3, 5, 7] {
+        if n.is_multiple_of(p) {
+            factors.push(p);
+            return prime_factors(n / p, factors);
+        }
+    }
+    let d = rho(n);
+    prime_factors(d, factors);
+    prime_factors(n / d, factors);
+}
+
+fn factorize(n: u64) -> Vec<u64> {
+    let mut factors = vec![];
+    prime_factors(n, &mut factors);
+    factors.sort_unstable();
+    factors
+}
+
+pub(crate) fn factor(args: &[Value]) -> Result<Value, EvalError> {
+    let factors = factorize(positive_integer(&args[0])?);
+    // vectors hold numbers, exact up to 2^53
+    if factors.last().is_some_and(|&p| p as Real > MAX_EXACT) {
+        return Err(EvalError::InvalidArgument {
+            expect: "integer without prime factors above 2^53",
+        });
+    }
+    Ok(Value::from(
+        factors.into_iter().map(|p| p as Real).collect::<Vec<_>>(),
+    ))
+}
+
+/// The smallest prime greater than the argument.
+pub(crate) fn nextprime(args: &[Value]) -> Result<Value, EvalError> {
+    let n = match args[0] {
+        Value::Int(i) => i,
+        _ => match args[0].num()?.floor() {
+            r if r < 2.0 => 2,
+            r => positive_integer(&Value::Num(r))? as i64,
+        },
+    };
+    if n < 2 {
+        return Ok(Value::Int(2));
+    }
+    let mut n = n as u64 + 1;
+    while !is_prime(n) {
+        n += 1;
+    }
+    match n <= i64::MAX as u64 {
+        true => Ok(Value::Int(n as i64)),
+        false => Err(EvalError::InvalidArgument {
+            expect: "integer below the largest prime under 2^63",
+        }),
+    }
+}
+
+/// Euler's totient function.
+pub(crate) fn totient(args: &[Value]) -> Result<Value, EvalError> {
+    let n = positive_integer(&args[0])?;
+    let mut factors = factorize(n);
+    factors.dedup();
+    let phi = factors.into_iter().fold(n, |phi, p| phi / p * (p - 1));
+    Ok(Value::Int(phi as i64))
+}
+
+fn radix(val: &Value) -> Result<u64, EvalError> {
+    let b = val.num()?;
+    match (2.0..=36.0).contains(&b) && b.fract() == 0.0 {
+        true => Ok(b as u64),
+        false => Err(EvalError::InvalidArgument {
+            expect: "integer base from 2 to 36",
+        }),
+    }
+}
+
+/// Digits of a non-negative integer in base `b`, the most significant first.
+pub(crate) fn tobase(args: &[Value]) -> Result<Value, EvalError> {
+    let b = radix(&args[1])?;
+    let mut n = match args[0].num()? == 0.0 {
+        true => 0,
+        false => positive_integer(&args[0])?,
+    };
+    let mut digits = vec![];
+    loop {
+        digits.push((n % b) as Real);
+        n /= b;
+        if n == 0 {
+            break;
+        }
+    }
+    digits.reverse();
+    Ok(Value::from(digits))
+}
+
+/// The integer with the digits `digits` in base `b`, the most significant first.
+pub(crate) fn frombase(args: &[Value]) -> Result<Value, EvalError> {
+    let digits = args[0].vector()?;
+    let b = radix(&args[1])? as Real;
+    if !digits
+        .iter()
+        .all(|&d| (0.0..b).contains(&d) && d.fract() == 0.0)
+    {
+        return Err(EvalError::InvalidArgument {
+            expect: "integer digits less than the base",
+        });
+    }
+    Ok(Value::Num(digits.iter().fold(0.0, |n, d| n * b + d)))
+}
+
+fn check_all(val: &Value, ok: fn(Real) -> bool, expect: &'static str) -> Result<(), EvalError> {
+    match val.all(ok)? {
+        true => Ok(()),
+        false => Err(EvalError::InvalidArgument { expect }),
+    }
+}
+
+/// Apply a 64-bit two's complement operation, element by element over vector arguments.
+///
+/// Integer arguments use all 64 bits, floating point ones must be exact integers.
+fn bitwise(args: &[Value], shift: bool, f: fn(&[i64]) -> i64) -> Result<Value, EvalError> {
+    let (operands, amount) = match shift {
+        true => args.split_at(1),
+        false => (args, &[][..]),
+    };
+    for val in operands.iter().filter(|val| !matches!(val, Value::Int(_))) {
+        check_all(
+            val,
+            |r| r.abs() <= MAX_EXACT && r.fract() == 0.0,
+            "integer not above 2^53 in magnitude",
+        )?;
+    }
+    for val in amount {
+        check_all(
+            val,
+            |r| (0.0..64.0).contains(&r) && r.fract() == 0.0,
+            "shift amount from 0 to 63",
+        )?;
+    }
+    let ints = args
+        .iter()
+        .map(|val| match val {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    match ints {
+        Some(ints) => Ok(Value::Int(f(&ints))),
+        None => Value::broadcast(args, |v| {
+            f(&v.iter().map(|&r| r as i64).collect::<Vec<_>>()) as Real
+        }),
+    }
+}
+
+pub(crate) fn band(args: &[Value]) -> Result<Value, EvalError> {
+    bitwise(args, false, |v| v[0] & v[1])
+}
+
+pub(crate) fn bor(args: &[Value]) -> Result<Value, EvalError> {
+    bitwise(args, false, |v| v[0] | v[1])
+}
+
+pub(crate) fn bxor(args: &[Value]) -> Result<Value, EvalError> {
+    bitwise(args, false, |v| v[0] ^ v[1])
+}
+
+pub(crate) fn bnot(args: &[Value]) -> Result<Value, EvalError> {
+    bitwise(args, false, |v| !v[0])
+}
+
+/// Left shift, discarding bits shifted out of 64 bits.
+pub(crate) fn shl(args: &[Value]) -> Result<Value, EvalError> {
+    bitwise(args, true, |v| v[0] << v[1])
+}
+
+/// Arithmetic right shift.
+pub(crate) fn shr(args: &[Value]) -> Result<Value, EvalError> {
+    bitwise(args, true, |v| v[0] >> v[1])
+}
+
+/// `c0 + 1 / (c1 + 1 / (... + 1 / cn))` of the vector `[c0, c1, ..., cn]`.
+pub(crate) fn contfrac(args: &[Value]) -> Result<Value, EvalError> {
+    let coeffs = args[0].vector()?;
+    match coeffs.split_last() {
+        Some((&last, rest)) => Ok(Value::Num(rest.iter().rev().fold(last, |t, c| c + 1.0 / t))),
+        None => Err(EvalError::InvalidArgument {
+            expect: "non-empty vector",
+        }),
+    }
+}
+
+/// The fraction `p/q` with `0 < q <= max_den` closest to `x`, if equal within rounding error.
+fn rational(x: Real, max_den: i64) -> Option<(i64, i64)> {
+    if !x.is_finite() || x.abs() > MAX_EXACT {
+        return None;
+    }
+    // convergents of the continued fraction of |x|
+    let (mut h0, mut h1, mut k0, mut k1) = (0i64, 1i64, 1i64, 0i64);
+    let mut y = x.abs();
+    loop {
+        let a = y.floor() as i64;
+        let (h, k) = (
+            a.checked_mul(h1)?.checked_add(h0)?,
+            a.checked_mul(k1)?.checked_add(k0)?,
+        );
+        if k > max_den {
+            return None;
+        }
+        if (x.abs() - h as Real / k as Real).abs() <= 1e-12 * x.abs().max(1.0) {
+            return Some((h * x.signum() as i64, k));
+        }
+        y = 1.0 / (y - a as Real);
+        (h0, h1, k0, k1) = (h1, h, k1, k);
+    }
+}
+
+/// `x` as a simple fraction like `1/3` or multiple of pi like `3*pi/4`, unless it is an integer.
+pub(crate) fn fraction(x: Real) -> Option<String> {
+    if x.fract() == 0.0 {
+        return None;
+    }
+    if let Some((p, q)) = rational(x, 1000) {
+        return Some(format!("{}/{}", p, q));
+    }
+    let (p, q) = rational(x / crate::consts::PI, 100)?;
+    let coefficient = match p {
+        1 => String::new(),
+        -1 => "-".to_string(),
+        p => format!("{}*", p),
+    };
+    Some(match q {
+        1 => format!("{}pi", coefficient),
+        q => format!("{}pi/{}", coefficient, q),
+    })
+}
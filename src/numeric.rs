@@ -0,0 +1,376 @@
+//! Number types
+//!
+//! [`Numeric`] is the number type an [`Interpreter`](crate::Interpreter) computes in, `f64` by
+//! default. Functions a type doesn't provide itself, like the trigonometric ones, go through
+//! `f64`, as do the builtins of the libraries written for [`Real`].
+
+use std::{
+    fmt,
+    ops::{Add, Div, Mul, Neg, Rem, Sub},
+    sync::Arc,
+};
+
+use crate::Real;
+
+#[cfg(feature = "decimal")]
+mod decimal;
+mod double;
+
+#[cfg(feature = "decimal")]
+pub use self::decimal::Decimal;
+pub use self::double::DoubleDouble;
+
+/// Digits of the constants, for number types more precise than `Real`.
+pub(crate) mod digits {
+    pub(crate) const PI: &str = "3.14159265358979323846264338327950288";
+    pub(crate) const E: &str = "2.71828182845904523536028747135266250";
+    pub(crate) const TAU: &str = "6.28318530717958647692528676655900577";
+    pub(crate) const PHI: &str = "1.61803398874989484820458683436563812";
+    pub(crate) const SQRT_2: &str = "1.41421356237309504880168872420969808";
+    pub(crate) const LN_2: &str = "0.693147180559945309417232121458176568";
+    pub(crate) const LN_10: &str = "2.30258509299404568401799145468436421";
+}
+
+/// A number type of the interpreter.
+pub trait Numeric:
+    Copy
+    + Default
+    + PartialOrd
+    + fmt::Debug
+    + fmt::Display
+    + fmt::LowerExp
+    + Send
+    + Sync
+    + 'static
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Rem<Output = Self>
+    + Neg<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const NAN: Self;
+    const INFINITY: Self;
+
+    /// The number nearest to `r`.
+    fn from_f64(r: f64) -> Self;
+
+    /// The `f64` nearest to this number.
+    fn to_f64(self) -> f64;
+
+    /// The number nearest to `i`.
+    fn from_i64(i: i64) -> Self {
+        Self::from_f64(i as f64)
+    }
+
+    /// The number nearest to the decimal `literal`, in the notation of `str::parse::<f64>`.
+    fn parse(literal: &str) -> Option<Self> {
+        literal.parse::<f64>().ok().map(Self::from_f64)
+    }
+
+    /// A literal read back as this number, with a fraction or an exponent.
+    fn literal(self) -> String {
+        format!("{:e}", self)
+    }
+
+    fn floor(self) -> Self;
+    fn ceil(self) -> Self;
+    /// The nearest integer, halfway cases away from zero.
+    fn round(self) -> Self;
+    fn trunc(self) -> Self;
+
+    fn fract(self) -> Self {
+        self - self.trunc()
+    }
+
+    #[allow(clippy::eq_op)]
+    fn is_nan(self) -> bool {
+        self != self
+    }
+
+    fn is_infinite(self) -> bool {
+        self == Self::INFINITY || self == -Self::INFINITY
+    }
+
+    fn is_finite(self) -> bool {
+        !self.is_nan() && !self.is_infinite()
+    }
+
+    fn abs(self) -> Self {
+        match self < Self::ZERO {
+            true => -self,
+            false => self,
+        }
+    }
+
+    fn signum(self) -> Self {
+        Self::from_f64(self.to_f64().signum())
+    }
+
+    /// `self` with the sign of `sign`.
+    fn copysign(self, sign: Self) -> Self {
+        match self.to_f64().is_sign_negative() == sign.to_f64().is_sign_negative() {
+            true => self,
+            false => -self,
+        }
+    }
+
+    /// The larger number, ignoring NaN.
+    fn max(self, other: Self) -> Self {
+        match self.is_nan() || self < other {
+            true => other,
+            false => self,
+        }
+    }
+
+    /// The smaller number, ignoring NaN.
+    fn min(self, other: Self) -> Self {
+        match self.is_nan() || self > other {
+            true => other,
+            false => self,
+        }
+    }
+
+    /// `self` to the power `n`, by repeated squaring.
+    fn powi(self, n: i32) -> Self {
+        let (mut base, mut result, mut power) = (self, Self::ONE, n.unsigned_abs());
+        while power > 0 {
+            if power & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            power >>= 1;
+        }
+        match n < 0 {
+            true => Self::ONE / result,
+            false => result,
+        }
+    }
+
+    /// `self` to the power `exponent`, with `powi` for an integer exponent.
+    fn powf(self, exponent: Self) -> Self {
+        match exponent.fract() == Self::ZERO && exponent.abs() <= Self::from_i64(i32::MAX as i64) {
+            true => self.powi(exponent.to_f64() as i32),
+            false => self.map(|r| r.powf(exponent.to_f64())),
+        }
+    }
+
+    fn sqrt(self) -> Self {
+        self.map(f64::sqrt)
+    }
+
+    fn cbrt(self) -> Self {
+        self.map(f64::cbrt)
+    }
+
+    fn exp(self) -> Self {
+        self.map(f64::exp)
+    }
+
+    fn ln(self) -> Self {
+        self.map(f64::ln)
+    }
+
+    fn log10(self) -> Self {
+        self.map(f64::log10)
+    }
+
+    fn sin(self) -> Self {
+        self.map(f64::sin)
+    }
+
+    fn cos(self) -> Self {
+        self.map(f64::cos)
+    }
+
+    fn tan(self) -> Self {
+        self.map(f64::tan)
+    }
+
+    fn asin(self) -> Self {
+        self.map(f64::asin)
+    }
+
+    fn acos(self) -> Self {
+        self.map(f64::acos)
+    }
+
+    fn atan(self) -> Self {
+        self.map(f64::atan)
+    }
+
+    /// The angle of the point `(other, self)`.
+    fn atan2(self, other: Self) -> Self {
+        Self::from_f64(self.to_f64().atan2(other.to_f64()))
+    }
+
+    /// `f` applied to this number through `f64`.
+    fn map<F: Fn(f64) -> f64>(self, f: F) -> Self {
+        Self::from_f64(f(self.to_f64()))
+    }
+
+    /// The elements of `v` as `f64`, `v` itself for `f64`.
+    fn vector_to_f64(v: &Arc<[Self]>) -> Arc<[Real]> {
+        v.iter().map(|r| r.to_f64()).collect()
+    }
+
+    /// The elements of `v` as this type, `v` itself for `f64`.
+    fn vector_from_f64(v: Arc<[Real]>) -> Arc<[Self]> {
+        v.iter().map(|&r| Self::from_f64(r)).collect()
+    }
+}
+
+/// Implement `Numeric` for a primitive floating point type with its own functions, and `items`.
+macro_rules! float {
+    ($t:ident $(, $item:item)*) => {
+        impl Numeric for $t {
+            const ZERO: Self = 0.0;
+            const ONE: Self = 1.0;
+            const NAN: Self = $t::NAN;
+            const INFINITY: Self = $t::INFINITY;
+
+            fn from_f64(r: f64) -> Self {
+                r as $t
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn from_i64(i: i64) -> Self {
+                i as $t
+            }
+
+            fn parse(literal: &str) -> Option<Self> {
+                literal.parse().ok()
+            }
+
+            fn literal(self) -> String {
+                format!("{:?}", self)
+            }
+
+            fn floor(self) -> Self {
+                $t::floor(self)
+            }
+
+            fn ceil(self) -> Self {
+                $t::ceil(self)
+            }
+
+            fn round(self) -> Self {
+                $t::round(self)
+            }
+
+            fn trunc(self) -> Self {
+                $t::trunc(self)
+            }
+
+            fn fract(self) -> Self {
+                $t::fract(self)
+            }
+
+            fn is_nan(self) -> bool {
+                $t::is_nan(self)
+            }
+
+            fn is_infinite(self) -> bool {
+                $t::is_infinite(self)
+            }
+
+            fn is_finite(self) -> bool {
+                $t::is_finite(self)
+            }
+
+            fn abs(self) -> Self {
+                $t::abs(self)
+            }
+
+            fn signum(self) -> Self {
+                $t::signum(self)
+            }
+
+            fn copysign(self, sign: Self) -> Self {
+                $t::copysign(self, sign)
+            }
+
+            fn max(self, other: Self) -> Self {
+                $t::max(self, other)
+            }
+
+            fn min(self, other: Self) -> Self {
+                $t::min(self, other)
+            }
+
+            fn powi(self, n: i32) -> Self {
+                $t::powi(self, n)
+            }
+
+            fn powf(self, exponent: Self) -> Self {
+                $t::powf(self, exponent)
+            }
+
+            fn sqrt(self) -> Self {
+                $t::sqrt(self)
+            }
+
+            fn cbrt(self) -> Self {
+                $t::cbrt(self)
+            }
+
+            fn exp(self) -> Self {
+                $t::exp(self)
+            }
+
+            fn ln(self) -> Self {
+                $t::ln(self)
+            }
+
+            fn log10(self) -> Self {
+                $t::log10(self)
+            }
+
+            fn sin(self) -> Self {
+                $t::sin(self)
+            }
+
+            fn cos(self) -> Self {
+                $t::cos(self)
+            }
+
+            fn tan(self) -> Self {
+                $t::tan(self)
+            }
+
+            fn asin(self) -> Self {
+                $t::asin(self)
+            }
+
+            fn acos(self) -> Self {
+                $t::acos(self)
+            }
+
+            fn atan(self) -> Self {
+                $t::atan(self)
+            }
+
+            fn atan2(self, other: Self) -> Self {
+                $t::atan2(self, other)
+            }
+
+            $($item)*
+        }
+    };
+}
+
+float!(f32);
+float!(
+    f64,
+    fn vector_to_f64(v: &Arc<[Self]>) -> Arc<[Real]> {
+        v.clone()
+    },
+    fn vector_from_f64(v: Arc<[Real]>) -> Arc<[Self]> {
+        v
+    }
+);
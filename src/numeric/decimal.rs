@@ -0,0 +1,305 @@
+//! Decimal floating point numbers
+
+use std::{
+    cmp::Ordering,
+    fmt,
+    ops::{Add, Div, Mul, Neg, Rem, Sub},
+    str::FromStr,
+};
+
+use rust_decimal::prelude::{FromPrimitive, RoundingStrategy, ToPrimitive};
+
+use super::Numeric;
+
+/// A number with a 96-bit integer mantissa and a power of ten from `0` to `-28`, from
+/// `rust_decimal`, for 28 significant digits, so that decimal fractions like `0.1` are exact.
+///
+/// Arithmetic, `sqrt`, rounding and powers to integers are computed in decimal, the other functions
+/// through `f64`. Results too large for the mantissa are infinite, as are quotients by zero.
+#[derive(Debug, Clone, Copy)]
+pub struct Decimal(Kind);
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Finite(rust_decimal::Decimal),
+    /// Negative when `true`.
+    Infinite(bool),
+    NaN,
+}
+
+impl Decimal {
+    /// The `rust_decimal` number, unless infinite or NaN.
+    pub fn value(self) -> Option<rust_decimal::Decimal> {
+        match self.0 {
+            Kind::Finite(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// `op` on two finite numbers, or `f` on their `f64` when either is not finite or `op`
+    /// overflows or divides by zero.
+    fn binary(
+        self,
+        other: Self,
+        op: fn(rust_decimal::Decimal, rust_decimal::Decimal) -> Option<rust_decimal::Decimal>,
+        f: fn(f64, f64) -> f64,
+    ) -> Self {
+        match (self.0, other.0) {
+            (Kind::Finite(a), Kind::Finite(b)) => match op(a, b) {
+                Some(d) => d.into(),
+                None => Decimal::from_f64(f(self.to_f64(), other.to_f64())),
+            },
+            _ => Decimal::from_f64(f(self.to_f64(), other.to_f64())),
+        }
+    }
+
+    /// `f` applied to a finite number, infinity and NaN kept.
+    fn finite(self, f: fn(rust_decimal::Decimal) -> rust_decimal::Decimal) -> Self {
+        match self.0 {
+            Kind::Finite(d) => f(d).into(),
+            _ => self,
+        }
+    }
+
+    /// `inf`, `-inf` or `NaN` like `f64`, returning whether the number is finite.
+    fn write_special(&self, f: &mut fmt::Formatter) -> Result<bool, fmt::Error> {
+        match self.0 {
+            Kind::Finite(_) => Ok(true),
+            Kind::Infinite(false) => f.write_str("inf").map(|_| false),
+            Kind::Infinite(true) => f.write_str("-inf").map(|_| false),
+            Kind::NaN => f.write_str("NaN").map(|_| false),
+        }
+    }
+}
+
+impl Default for Decimal {
+    fn default() -> Self {
+        Decimal::ZERO
+    }
+}
+
+impl From<rust_decimal::Decimal> for Decimal {
+    fn from(d: rust_decimal::Decimal) -> Self {
+        Decimal(Kind::Finite(d))
+    }
+}
+
+impl Add for Decimal {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.binary(other, rust_decimal::Decimal::checked_add, |a, b| a + b)
+    }
+}
+
+impl Sub for Decimal {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.binary(other, rust_decimal::Decimal::checked_sub, |a, b| a - b)
+    }
+}
+
+impl Mul for Decimal {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        self.binary(other, rust_decimal::Decimal::checked_mul, |a, b| a * b)
+    }
+}
+
+impl Div for Decimal {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        self.binary(other, rust_decimal::Decimal::checked_div, |a, b| a / b)
+    }
+}
+
+/// The remainder of truncated division, of the sign of the dividend.
+impl Rem for Decimal {
+    type Output = Self;
+
+    fn rem(self, other: Self) -> Self {
+        match (self.0, other.0) {
+            (Kind::Finite(_), Kind::Infinite(_)) => self,
+            _ => self.binary(other, rust_decimal::Decimal::checked_rem, |a, b| a % b),
+        }
+    }
+}
+
+impl Neg for Decimal {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Decimal(match self.0 {
+            Kind::Finite(d) => Kind::Finite(-d),
+            Kind::Infinite(negative) => Kind::Infinite(!negative),
+            Kind::NaN => Kind::NaN,
+        })
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self.0, other.0) {
+            (Kind::Finite(a), Kind::Finite(b)) => Some(a.cmp(&b)),
+            _ => self.to_f64().partial_cmp(&other.to_f64()),
+        }
+    }
+}
+
+/// Positional notation, rounded to the precision or with the digits of the number.
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.write_special(f)? {
+            return Ok(());
+        }
+        let d = self.value().unwrap();
+        match f.precision() {
+            Some(p) => {
+                let d = d.round_dp_with_strategy(p as u32, RoundingStrategy::MidpointNearestEven);
+                write!(f, "{:.*}", p, d)
+            }
+            None => write!(f, "{}", d.normalize()),
+        }
+    }
+}
+
+/// Scientific notation like `f64`, rounded to the precision or with the digits of the number.
+impl fmt::LowerExp for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.write_special(f)? {
+            return Ok(());
+        }
+        let mut d = self.value().unwrap();
+        if let Some(p) = f.precision() {
+            d = d
+                .round_sf_with_strategy(p as u32 + 1, RoundingStrategy::MidpointNearestEven)
+                .unwrap_or(d);
+        }
+        let d = d.normalize();
+        let mut digits = d.mantissa().unsigned_abs().to_string();
+        let exponent = digits.len() as i32 - 1 - d.scale() as i32;
+        if let Some(p) = f.precision() {
+            while digits.len() < p + 1 {
+                digits.push('0');
+            }
+        }
+        if d.is_sign_negative() {
+            f.write_str("-")?;
+        }
+        let (first, rest) = digits.split_at(1);
+        match rest.is_empty() {
+            true => write!(f, "{}e{}", first, exponent),
+            false => write!(f, "{}.{}e{}", first, rest, exponent),
+        }
+    }
+}
+
+impl Numeric for Decimal {
+    const ZERO: Self = Decimal(Kind::Finite(rust_decimal::Decimal::ZERO));
+    const ONE: Self = Decimal(Kind::Finite(rust_decimal::Decimal::ONE));
+    const NAN: Self = Decimal(Kind::NaN);
+    const INFINITY: Self = Decimal(Kind::Infinite(false));
+
+    /// Infinite beyond the largest mantissa, zero below the smallest fraction.
+    fn from_f64(r: f64) -> Self {
+        if r.is_nan() {
+            return Decimal::NAN;
+        }
+        match rust_decimal::Decimal::from_f64(r) {
+            Some(d) if r.is_finite() => d.into(),
+            _ if r.abs() < 1.0 => Decimal::ZERO,
+            _ => Decimal(Kind::Infinite(r < 0.0)),
+        }
+    }
+
+    fn to_f64(self) -> f64 {
+        match self.0 {
+            Kind::Finite(d) => d.to_f64().unwrap_or(f64::NAN),
+            Kind::Infinite(false) => f64::INFINITY,
+            Kind::Infinite(true) => f64::NEG_INFINITY,
+            Kind::NaN => f64::NAN,
+        }
+    }
+
+    fn from_i64(i: i64) -> Self {
+        rust_decimal::Decimal::from(i).into()
+    }
+
+    /// Exact to 28 significant digits, through `f64` beyond the range of the mantissa.
+    fn parse(literal: &str) -> Option<Self> {
+        let d = match literal.contains(['e', 'E']) {
+            true => rust_decimal::Decimal::from_scientific(literal).ok(),
+            false => rust_decimal::Decimal::from_str(literal).ok(),
+        };
+        match d {
+            Some(d) => Some(d.into()),
+            None => literal.parse::<f64>().ok().map(Decimal::from_f64),
+        }
+    }
+
+    fn literal(self) -> String {
+        let text = self.to_string();
+        match self.is_finite() && !text.contains('.') {
+            true => text + ".0",
+            false => text,
+        }
+    }
+
+    fn floor(self) -> Self {
+        self.finite(|d| d.floor())
+    }
+
+    fn ceil(self) -> Self {
+        self.finite(|d| d.ceil())
+    }
+
+    fn round(self) -> Self {
+        self.finite(|d| d.round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero))
+    }
+
+    fn trunc(self) -> Self {
+        self.finite(|d| d.trunc())
+    }
+
+    fn fract(self) -> Self {
+        match self.0 {
+            Kind::Finite(d) => d.fract().into(),
+            _ => Decimal::NAN,
+        }
+    }
+
+    fn is_nan(self) -> bool {
+        matches!(self.0, Kind::NaN)
+    }
+
+    fn is_infinite(self) -> bool {
+        matches!(self.0, Kind::Infinite(_))
+    }
+
+    fn is_finite(self) -> bool {
+        matches!(self.0, Kind::Finite(_))
+    }
+
+    /// The square root of the `f64`, corrected by steps of Newton's method.
+    fn sqrt(self) -> Self {
+        let a = match self.0 {
+            Kind::Finite(a) if a.is_sign_positive() && !a.is_zero() => Decimal::from(a),
+            _ => return self.map(f64::sqrt),
+        };
+        let half = Decimal::from(rust_decimal::Decimal::new(5, 1));
+        let mut x = self.map(f64::sqrt);
+        for _ in 0..3 {
+            x = (x + a / x) * half;
+        }
+        x
+    }
+}
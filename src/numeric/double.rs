@@ -0,0 +1,451 @@
+//! Double-double numbers
+
+use std::{
+    cmp::Ordering,
+    fmt,
+    ops::{Add, Div, Mul, Neg, Rem, Sub},
+};
+
+use super::Numeric;
+
+/// A number as the unevaluated sum of two `f64`, the second at most half a unit in the last place
+/// of the first, for about 31 significant digits.
+///
+/// Arithmetic, `sqrt`, rounding and powers to integers are computed to that precision, the other
+/// functions through `f64`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DoubleDouble {
+    hi: f64,
+    lo: f64,
+}
+
+/// Significant digits shown by default.
+const DIGITS: usize = 31;
+
+/// `a + b` and its rounding error.
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let b_part = s - a;
+    (s, (a - (s - b_part)) + (b - b_part))
+}
+
+/// `a + b` and its rounding error, for `|a| >= |b|`.
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+/// `a * b` and its rounding error.
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, a.mul_add(b, -p))
+}
+
+impl DoubleDouble {
+    /// The sum of `hi` and `lo`.
+    pub fn new(hi: f64, lo: f64) -> Self {
+        let (hi, lo) = two_sum(hi, lo);
+        DoubleDouble::finite(hi, lo)
+    }
+
+    /// The leading `f64` of the sum.
+    pub fn hi(self) -> f64 {
+        self.hi
+    }
+
+    /// The remainder after `hi`.
+    pub fn lo(self) -> f64 {
+        self.lo
+    }
+
+    /// `hi` alone when it is not finite.
+    fn finite(hi: f64, lo: f64) -> Self {
+        match hi.is_finite() {
+            true => DoubleDouble { hi, lo },
+            false => DoubleDouble { hi, lo: 0.0 },
+        }
+    }
+
+    /// The first `count` significant decimal digits of the finite non-zero `|self|`, rounded,
+    /// with the power of ten of the first.
+    fn digits(self, count: usize) -> (Vec<u8>, i32) {
+        let ten = DoubleDouble::from_f64(10.0);
+        let mut x = self.abs();
+        let mut exponent = 0;
+        // keep the powers of ten finite
+        if x.hi < 1e-290 {
+            x = x * ten.powi(290);
+            exponent -= 290;
+        }
+        let e = x.hi.log10().floor() as i32;
+        let mut r = match e < 0 {
+            true => x * ten.powi(-e),
+            false => x / ten.powi(e),
+        };
+        exponent += e;
+        if r.hi >= 10.0 {
+            r = r / ten;
+            exponent += 1;
+        } else if r.hi < 1.0 {
+            r = r * ten;
+            exponent -= 1;
+        }
+        let mut digits = Vec::with_capacity(count + 1);
+        for _ in 0..=count {
+            let d = r.hi.floor().clamp(0.0, 9.0);
+            digits.push(d as u8);
+            r = (r - DoubleDouble::from_f64(d)) * ten;
+        }
+        if digits.pop().unwrap() >= 5 {
+            match digits.iter().rposition(|&d| d < 9) {
+                Some(i) => {
+                    digits[i] += 1;
+                    digits.truncate(i + 1);
+                    digits.resize(count, 0);
+                }
+                None => {
+                    digits = vec![0; count];
+                    digits[0] = 1;
+                    exponent += 1;
+                }
+            }
+        }
+        (digits, exponent)
+    }
+
+    /// Write the sign of a negative number, returning whether `self` is finite and non-zero,
+    /// after writing it as an `f64` otherwise.
+    fn write_sign(self, f: &mut fmt::Formatter, exp: bool) -> Result<bool, fmt::Error> {
+        if !self.hi.is_finite() || self.hi == 0.0 {
+            match (exp, f.precision()) {
+                (false, Some(p)) => write!(f, "{:.*}", p, self.hi)?,
+                (false, None) => write!(f, "{}", self.hi)?,
+                (true, Some(p)) => write!(f, "{:.*e}", p, self.hi)?,
+                (true, None) => write!(f, "{:e}", self.hi)?,
+            }
+            return Ok(false);
+        }
+        if self.hi < 0.0 {
+            f.write_str("-")?;
+        }
+        Ok(true)
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let (s1, s2) = two_sum(self.hi, other.hi);
+        if !s1.is_finite() {
+            return DoubleDouble::from_f64(s1);
+        }
+        let (t1, t2) = two_sum(self.lo, other.lo);
+        let (s1, s2) = quick_two_sum(s1, s2 + t1);
+        let (hi, lo) = quick_two_sum(s1, s2 + t2);
+        DoubleDouble::finite(hi, lo)
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + -other
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let (p1, p2) = two_prod(self.hi, other.hi);
+        if !p1.is_finite() {
+            return DoubleDouble::from_f64(p1);
+        }
+        let (hi, lo) = quick_two_sum(p1, p2 + (self.hi * other.lo + self.lo * other.hi));
+        DoubleDouble::finite(hi, lo)
+    }
+}
+
+impl Div for DoubleDouble {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        let q1 = self.hi / other.hi;
+        if !q1.is_finite() || q1 == 0.0 || !other.hi.is_finite() {
+            return DoubleDouble::from_f64(q1);
+        }
+        let r = self - other * DoubleDouble::from_f64(q1);
+        let q2 = r.hi / other.hi;
+        let r = r - other * DoubleDouble::from_f64(q2);
+        let q3 = r.hi / other.hi;
+        let (hi, lo) = quick_two_sum(q1, q2);
+        DoubleDouble { hi, lo } + DoubleDouble::from_f64(q3)
+    }
+}
+
+/// The remainder of truncated division, of the sign of the dividend.
+impl Rem for DoubleDouble {
+    type Output = Self;
+
+    fn rem(self, other: Self) -> Self {
+        if !self.hi.is_finite() || other.hi.is_infinite() || other.hi == 0.0 || other.is_nan() {
+            return DoubleDouble::from_f64(self.hi % other.hi);
+        }
+        self - other * (self / other).trunc()
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        DoubleDouble {
+            hi: -self.hi,
+            lo: -self.lo,
+        }
+    }
+}
+
+impl PartialOrd for DoubleDouble {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.hi.partial_cmp(&other.hi)? {
+            Ordering::Equal => self.lo.partial_cmp(&other.lo),
+            ord => Some(ord),
+        }
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(r: f64) -> Self {
+        DoubleDouble::from_f64(r)
+    }
+}
+
+/// Positional notation like `f64`, rounded to the precision or to 31 significant digits.
+impl fmt::Display for DoubleDouble {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.write_sign(f, false)? {
+            return Ok(());
+        }
+        let (mut digits, exponent) = match f.precision() {
+            Some(p) => {
+                let count = exponent_of(*self) + 1 + p as i32;
+                match count {
+                    count if count > 0 => self.digits(count as usize),
+                    // below the last digit shown, rounded to it or to zero
+                    0 if self.digits(1).0[0] >= 5 => (vec![1], -(p as i32)),
+                    _ => (vec![], -(p as i32) - 1),
+                }
+            }
+            None => self.digits(DIGITS),
+        };
+        if f.precision().is_none() {
+            while digits.len() > 1 && digits.last() == Some(&0) {
+                digits.pop();
+            }
+        }
+        let point = exponent + 1;
+        let digit = |i: i32| match i >= 0 && (i as usize) < digits.len() {
+            true => (b'0' + digits[i as usize]) as char,
+            false => '0',
+        };
+        let mut text = String::new();
+        for i in 0..point.max(1) {
+            text.push(match point > 0 {
+                true => digit(i),
+                false => '0',
+            });
+        }
+        let fraction = match f.precision() {
+            Some(p) => p as i32,
+            None => digits.len() as i32 - point,
+        };
+        if fraction > 0 {
+            text.push('.');
+            for i in 0..fraction {
+                text.push(digit(point + i));
+            }
+        }
+        f.write_str(&text)
+    }
+}
+
+/// Scientific notation like `f64`, rounded to the precision or to 31 significant digits.
+impl fmt::LowerExp for DoubleDouble {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.write_sign(f, true)? {
+            return Ok(());
+        }
+        let (mut digits, exponent) = self.digits(f.precision().map_or(DIGITS, |p| p + 1));
+        if f.precision().is_none() {
+            while digits.len() > 1 && digits.last() == Some(&0) {
+                digits.pop();
+            }
+        }
+        let mut text = String::new();
+        for (i, d) in digits.iter().enumerate() {
+            if i == 1 {
+                text.push('.');
+            }
+            text.push((b'0' + d) as char);
+        }
+        write!(f, "{}e{}", text, exponent)
+    }
+}
+
+/// The power of ten of the first significant digit of the finite non-zero `x`.
+fn exponent_of(x: DoubleDouble) -> i32 {
+    x.digits(DIGITS).1
+}
+
+impl Numeric for DoubleDouble {
+    const ZERO: Self = DoubleDouble { hi: 0.0, lo: 0.0 };
+    const ONE: Self = DoubleDouble { hi: 1.0, lo: 0.0 };
+    const NAN: Self = DoubleDouble {
+        hi: f64::NAN,
+        lo: 0.0,
+    };
+    const INFINITY: Self = DoubleDouble {
+        hi: f64::INFINITY,
+        lo: 0.0,
+    };
+
+    fn from_f64(r: f64) -> Self {
+        DoubleDouble { hi: r, lo: 0.0 }
+    }
+
+    fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+
+    fn from_i64(i: i64) -> Self {
+        let hi = i as f64;
+        DoubleDouble::new(hi, (i as i128 - hi as i128) as f64)
+    }
+
+    /// Up to 36 significant digits are read, exactly below 2^106, then scaled by the power of ten.
+    fn parse(literal: &str) -> Option<Self> {
+        let (mantissa, exponent) = match literal.find(['e', 'E']) {
+            Some(i) => (&literal[..i], literal[i + 1..].parse::<i32>().ok()?),
+            None => (literal, 0),
+        };
+        let (negative, mantissa) = match mantissa.as_bytes().first() {
+            Some(b'-') => (true, &mantissa[1..]),
+            Some(b'+') => (false, &mantissa[1..]),
+            _ => (false, mantissa),
+        };
+        let (int, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits = int.bytes().chain(fraction.bytes());
+        if int.len() + fraction.len() == 0 || !digits.clone().all(|c| c.is_ascii_digit()) {
+            return literal.parse::<f64>().ok().map(DoubleDouble::from_f64);
+        }
+        let ten = DoubleDouble::from_f64(10.0);
+        let (mut value, mut read, mut dropped) = (DoubleDouble::ZERO, 0, 0);
+        for d in digits {
+            match read < 36 {
+                true => {
+                    value = value * ten + DoubleDouble::from_f64((d - b'0') as f64);
+                    if value.hi != 0.0 {
+                        read += 1;
+                    }
+                }
+                false => dropped += 1,
+            }
+        }
+        let exponent = exponent.saturating_sub(fraction.len() as i32) + dropped;
+        let value = match exponent {
+            e if e >= 0 => value * ten.powi(e),
+            // keep the powers of ten finite
+            e if e < -290 => value / ten.powi(290) / ten.powi(-e - 290),
+            e => value / ten.powi(-e),
+        };
+        Some(match negative {
+            true => -value,
+            false => value,
+        })
+    }
+
+    /// The fewest digits from 31 read back as this number.
+    fn literal(self) -> String {
+        let text = (DIGITS..DIGITS + 4)
+            .map(|count| format!("{:.*e}", count - 1, self))
+            .find(|text| DoubleDouble::parse(text) == Some(self))
+            .unwrap_or_else(|| format!("{:.*e}", DIGITS + 3, self));
+        let (mantissa, exponent) = text.split_once('e').unwrap();
+        let mantissa = match mantissa.contains('.') {
+            true => mantissa.trim_end_matches('0').trim_end_matches('.'),
+            false => mantissa,
+        };
+        format!("{}e{}", mantissa, exponent)
+    }
+
+    fn floor(self) -> Self {
+        let hi = self.hi.floor();
+        match hi == self.hi {
+            true => DoubleDouble::new(hi, self.lo.floor()),
+            false => DoubleDouble::from_f64(hi),
+        }
+    }
+
+    fn ceil(self) -> Self {
+        let hi = self.hi.ceil();
+        match hi == self.hi {
+            true => DoubleDouble::new(hi, self.lo.ceil()),
+            false => DoubleDouble::from_f64(hi),
+        }
+    }
+
+    fn round(self) -> Self {
+        let floor = self.floor();
+        let half = DoubleDouble::from_f64(0.5);
+        match (self - floor).partial_cmp(&half) {
+            Some(Ordering::Greater) => floor + DoubleDouble::ONE,
+            Some(Ordering::Equal) if self.hi > 0.0 => floor + DoubleDouble::ONE,
+            _ => floor,
+        }
+    }
+
+    fn trunc(self) -> Self {
+        match self.hi < 0.0 {
+            true => self.ceil(),
+            false => self.floor(),
+        }
+    }
+
+    fn is_nan(self) -> bool {
+        self.hi.is_nan()
+    }
+
+    fn is_infinite(self) -> bool {
+        self.hi.is_infinite()
+    }
+
+    fn is_finite(self) -> bool {
+        self.hi.is_finite()
+    }
+
+    fn signum(self) -> Self {
+        DoubleDouble::from_f64(self.hi.signum())
+    }
+
+    fn copysign(self, sign: Self) -> Self {
+        match self.hi.is_sign_negative() == sign.hi.is_sign_negative() {
+            true => self,
+            false => -self,
+        }
+    }
+
+    /// The square root of the leading `f64`, corrected by a step of Newton's method.
+    fn sqrt(self) -> Self {
+        if self.hi <= 0.0 || !self.hi.is_finite() {
+            return DoubleDouble::from_f64(self.hi.sqrt());
+        }
+        let x = self.hi.sqrt();
+        let (p, e) = two_prod(x, x);
+        let r = self - DoubleDouble { hi: p, lo: e };
+        let (hi, lo) = quick_two_sum(x, r.hi / (2.0 * x));
+        DoubleDouble { hi, lo }
+    }
+}
@@ -1,18 +1,29 @@
-//! Grammer Parser
+//! Grammar Parser
+//!
+//! An LALR(1) parser, its tables generated by build.rs from the grammar in `grammar.txt`.
 
-use crate::lexer::Token;
+// with `pratt` the AST is built by `pratt::Parser`, the tables only naming its nodes
+#![cfg_attr(feature = "pratt", allow(dead_code))]
 
-#[cfg(feature = "enable_log")]
-use log;
+use std::{fmt, ops::Range};
+
+use crate::{lexer::Token, InputError};
+
+include!(concat!(env!("OUT_DIR"), "/grammar.rs"));
+
+const _: () = assert!(TOKENS == Token::COUNT);
+
+/// Line in the statement, from 1, and columns of that line.
+pub(crate) type Position = (usize, Range<usize>);
 
 pub(crate) enum ASTNode {
     Inner(u32, Vec<ASTNode>),
-    Leaf(Token),
+    Leaf(Token, Position),
 }
 
-impl ToString for ASTNode {
-    fn to_string(&self) -> String {
-        self.to_string_impl(0)
+impl fmt::Display for ASTNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_string_impl(0))
     }
 }
 
@@ -27,23 +38,48 @@ impl ASTNode {
                     .collect();
                 format!(
                     "Inner(\"{}\", [\n{}{}])",
-                    Parser::GRAMMER[*id as usize],
-                    children_fmt,
-                    indents
+                    GRAMMAR[*id as usize], children_fmt, indents
                 )
             }
-            ASTNode::Leaf(token) => format!("Leaf({})", token.to_string()),
+            ASTNode::Leaf(token, _) => format!("Leaf({})", token),
         }
     }
 
-    pub(crate) fn assume_leaf(self) -> Token {
+    /// Position of the first token directly under this node: the operator of an operation, the
+    /// name of a call or the token of a leaf.
+    pub(crate) fn position(&self) -> Option<Position> {
         match self {
-            ASTNode::Leaf(token) => token,
-            _ => panic!("Can't unwrap an ast leaf node"),
+            ASTNode::Inner(_, children) => children.iter().find_map(|child| match child {
+                ASTNode::Leaf(_, position) => Some(position.clone()),
+                ASTNode::Inner(..) => None,
+            }),
+            ASTNode::Leaf(_, position) => Some(position.clone()),
+        }
+    }
+
+    pub(crate) fn assume_leaf(self) -> Result<Token, InputError> {
+        self.assume_leaf_at().map(|(token, _)| token)
+    }
+
+    pub(crate) fn assume_leaf_at(self) -> Result<(Token, Position), InputError> {
+        match self {
+            ASTNode::Leaf(token, position) => Ok((token, position)),
+            _ => Err(InputError::InternalError {
+                message: "expect an ast leaf node",
+            }),
         }
     }
 }
 
+/// A token no statement continues with, or the end of the tokens, with the ids of the tokens
+/// that could come instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Unexpected {
+    /// `None` at the end of the tokens.
+    pub(crate) position: Option<Position>,
+    pub(crate) expected: Vec<u32>,
+}
+
 pub(crate) struct Parser {
     stack: Vec<u32>,
     top: u32,
@@ -59,199 +95,187 @@ impl Parser {
         }
     }
 
-    const GRAMMER: &'static [&'static str; 22] = &[
-        "",
-        "S -> A",
-        "S -> E",
-        "A -> i=E",
-        "A -> i:V=E",
-        "V -> V,i",
-        "V -> i",
-        "E -> (E)",
-        "E -> !E",
-        "E -> pE",
-        "E -> E^E",
-        "E -> EmE",
-        "E -> EpE",
-        "E -> EcE",
-        "E -> EoE",
-        "E -> EaE",
-        "E -> E?E:E",
-        "E -> i(P)",
-        "E -> i",
-        "E -> n",
-        "P -> P,i",
-        "P -> i",
-    ];
-
-    //   i  n  =  (  )  !  ^  m  p  c  o  a  ?  :  ,
-    const ACTION: &'static [[i32; Token::COUNT]; 44] = &[
-        [3, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [0; Token::COUNT],
-        [0, 0, 0, 0, 0, 0, 8, 9, 10, 11, 12, 13, 14, 0, 0],
-        [0, 0, 15, 16, 0, 0, -18, -18, -18, -18, -18, -18, -18, 17, 0],
-        [
-            0, 0, 0, 0, -19, 0, -19, -19, -19, -19, -19, -19, -19, -19, -19,
-        ],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [33, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-        [0, 0, 0, 0, 34, 0, 8, 9, 10, 11, 12, 13, 14, 0, 0],
-        [
-            0, 0, 0, 16, -18, 0, -18, -18, -18, -18, -18, -18, -18, -18, -18,
-        ],
-        [0, 0, 0, 0, -8, 0, -8, -8, -8, -8, -8, -8, -8, -8, -8],
-        [0, 0, 0, 0, -9, 0, -9, -9, -9, -9, -9, -9, -9, -9, -9],
-        [
-            0, 0, 0, 0, -10, 0, -10, -10, -10, -10, -10, -10, -10, -10, -10,
-        ],
-        [
-            0, 0, 0, 0, -11, 0, 8, -11, -11, -11, -11, -11, -11, -11, -11,
-        ],
-        [0, 0, 0, 0, -12, 0, 8, 9, -12, -12, -12, -12, -12, -12, -12],
-        [0, 0, 0, 0, -13, 0, 8, 9, 10, -13, -13, -13, -13, -13, -13],
-        [0, 0, 0, 0, -14, 0, 8, 9, 10, 11, -14, -14, -14, -14, -14],
-        [0, 0, 0, 0, -15, 0, 8, 9, 10, 11, 12, -15, -15, -15, -15],
-        [0, 0, 0, 0, 0, 0, 8, 9, 10, 11, 12, 13, 14, 35, 0],
-        [0, 0, 0, 0, 0, 0, 8, 9, 10, 11, 12, 13, 14, 0, 0],
-        [0, 0, 0, 0, -21, 0, 8, 9, 10, 11, 12, 13, 14, 0, -21],
-        [0, 0, 0, 0, 36, 0, 0, 0, 0, 0, 0, 0, 0, 0, 37],
-        [0, 0, 38, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 39],
-        [0, 0, -6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, -6],
-        [0, 0, 0, 0, -7, 0, -7, -7, -7, -7, -7, -7, -7, -7, -7],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [
-            0, 0, 0, 0, -17, 0, -17, -17, -17, -17, -17, -17, -17, -17, -17,
-        ],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [19, 4, 0, 5, 0, 6, 0, 0, 7, 0, 0, 0, 0, 0, 0],
-        [43, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-        [0, 0, 0, 0, -16, 0, 8, 9, 10, 11, 12, 13, 14, -16, -16],
-        [0, 0, 0, 0, -20, 0, 8, 9, 10, 11, 12, 13, 14, 0, -20],
-        [0, 0, 0, 0, 0, 0, 8, 9, 10, 11, 12, 13, 14, 0, 0],
-        [0, 0, -5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, -5],
-    ];
-
-    pub(crate) fn action(&mut self, token: Token) -> bool {
-        let act = Self::ACTION[self.top as usize][token.id() as usize];
+    /// Add a token at `position`, failing if no statement starts with the tokens so far.
+    pub(crate) fn action(&mut self, token: Token, position: Position) -> Result<(), Unexpected> {
+        let act = ACTION[self.top as usize][token.id() as usize];
+        if act == 0 {
+            return Err(Unexpected {
+                position: Some(position),
+                expected: self.expected(),
+            });
+        }
         self.stack.push(self.top);
-        let state = if act > 0 {
-            act as u32
-        } else if act < 0 {
-            self.reduce(-act as u32)
-        } else {
-            return false;
+        self.top = match act > 0 {
+            true => act as u32,
+            false => self.reduce(-act as u32),
         };
-        self.top = state;
         if act < 0 {
-            #[cfg(feature = "enable_log")]
-            log::info!(
-                "Token {}; Reduce {}; Goto {}; Stack = {:?}",
-                token.to_string(),
-                -act,
-                self.top,
-                self.stack
-            );
-            self.action(token)
+            event!(token = %token, reduce = -act, goto = self.top, stack = ?self.stack);
+            self.action(token, position)
         } else {
-            #[cfg(feature = "enable_log")]
-            log::info!(
-                "Token {}; Shift {}; Stack = {:?}",
-                token.to_string(),
-                self.top,
-                self.stack
-            );
-            self.nodes.push(ASTNode::Leaf(token));
-            return true;
+            event!(token = %token, shift = self.top, stack = ?self.stack);
+            self.nodes.push(ASTNode::Leaf(token, position));
+            Ok(())
         }
     }
 
-    pub(crate) fn accept(mut self) -> Option<ASTNode> {
-        let reduce = match self.top {
-            1 | 2 => return Some(ASTNode::Inner(self.top, self.nodes)),
-            3 => 18,
-            4 => 19,
-            19 => 18,
-            20 => 8,
-            21 => 9,
-            22 => 10,
-            23 => 11,
-            24 => 12,
-            25 => 13,
-            26 => 14,
-            27 => 15,
-            29 => 3,
-            30 => 21,
-            34 => 7,
-            36 => 17,
-            40 => 16,
-            42 => 4,
-            _ => return None,
-        };
+    /// The statement of the tokens added, or the error at their end. The table-driven parser
+    /// stops at its first error, so there is one.
+    pub(crate) fn accept(mut self) -> Result<ASTNode, Vec<Unexpected>> {
+        if self.top == ACCEPT {
+            return Ok(self.nodes.pop().unwrap());
+        }
+        let act = ACTION[self.top as usize][TOKENS];
+        if act >= 0 {
+            return Err(vec![Unexpected {
+                position: None,
+                expected: self.expected(),
+            }]);
+        }
         self.stack.push(self.top);
-        self.top = self.reduce(reduce);
-        #[cfg(feature = "enable_log")]
-        log::info!(
-            "Accepting; Reduce {}; Goto {}; Stack = {:?}",
-            reduce,
-            self.top,
-            self.stack
-        );
+        self.top = self.reduce(-act as u32);
+        event!(accept = true, reduce = -act, goto = self.top, stack = ?self.stack);
         self.accept()
     }
 
+    /// Ids of the tokens shifted after the reductions they lead to from the current state, as
+    /// merged states of the LALR tables reduce before some tokens that can't follow.
+    fn expected(&self) -> Vec<u32> {
+        (0..TOKENS as u32).filter(|&id| self.shifts(id)).collect()
+    }
+
+    fn shifts(&self, id: u32) -> bool {
+        let (mut stack, mut top) = (self.stack.clone(), self.top);
+        loop {
+            let act = ACTION[top as usize][id as usize];
+            if act >= 0 {
+                return act > 0;
+            }
+            stack.push(top);
+            let (lhs, len) = PRODUCTIONS[-act as usize];
+            stack.truncate(stack.len() - len);
+            top = GOTO[*stack.last().unwrap() as usize][lhs];
+        }
+    }
+
     fn reduce(&mut self, id: u32) -> u32 {
-        let len = match id {
-            6 | 18 | 19 | 21 => 1,
-            8 | 9 => 2,
-            3 | 5 | 7 | 10 | 11 | 12 | 13 | 14 | 15 | 20 => 3,
-            17 => 4,
-            4 | 16 => 5,
-            _ => unreachable!(),
-        };
+        let (lhs, len) = PRODUCTIONS[id as usize];
         self.stack.truncate(self.stack.len() - len);
         let node = ASTNode::Inner(id, self.nodes.drain((self.nodes.len() - len)..).collect());
         self.nodes.push(node);
-        let k = *self.stack.last().unwrap();
-        if id >= 7 && id < 20 {
-            if k >= 6 && k < 17 {
-                k + 14
-            } else {
-                match k {
-                    0 => 2,
-                    5 => 18,
-                    35 => 40,
-                    37 => 41,
-                    38 => 42,
-                    _ => unreachable!(),
+        GOTO[*self.stack.last().unwrap() as usize][lhs]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ASTNode;
+    use crate::lexer::{AddSubOp, Lexer, MulDivOp, Token};
+
+    #[cfg(not(feature = "pratt"))]
+    use super::Parser;
+    #[cfg(feature = "pratt")]
+    use crate::pratt::Parser;
+
+    /// The expression statement `text` with every operation in parentheses.
+    fn grouped(text: &str) -> String {
+        let ts = Lexer::new(text.as_bytes()).tokenize().unwrap();
+        let mut parser = Parser::new();
+        for (span, token) in ts.tokens {
+            assert!(parser.action(token, (1, span)).is_ok(), "rejected {}", text);
+        }
+        match parser.accept() {
+            Ok(ASTNode::Inner(2, mut children)) => show(children.pop().unwrap()),
+            _ => panic!("not an expression: {}", text),
+        }
+    }
+
+    fn show(node: ASTNode) -> String {
+        match node {
+            ASTNode::Inner(7, mut children) => show(children.swap_remove(1)),
+            ASTNode::Inner(id, children) => {
+                let text = children.into_iter().map(show).collect::<String>();
+                if (8..=16).contains(&id) {
+                    format!("({})", text)
+                } else {
+                    text
                 }
             }
-        } else {
-            match id {
-                3 | 4 => match k {
-                    0 => 1,
-                    _ => unreachable!(),
-                },
-                5 | 6 => match k {
-                    17 => 32,
-                    _ => unreachable!(),
-                },
-                20 | 21 => match k {
-                    16 => 31,
-                    _ => unreachable!(),
-                },
-                _ => unreachable!(),
-            }
+            ASTNode::Leaf(token, _) => match token {
+                Token::IDENT(ident) => String::from_utf8(ident).unwrap(),
+                Token::NUM(num) => num.value.to_string(),
+                Token::NOT => "!".to_string(),
+                Token::EXP => "^".to_string(),
+                Token::MD(MulDivOp::MUL) => "*".to_string(),
+                Token::MD(MulDivOp::DIV) => "/".to_string(),
+                Token::PN(AddSubOp::ADD) => "+".to_string(),
+                Token::PN(AddSubOp::SUB) => "-".to_string(),
+                Token::CMP(cmp) => cmp.symbol().to_string(),
+                Token::OR => "||".to_string(),
+                Token::AND => "&&".to_string(),
+                Token::COND => "?".to_string(),
+                Token::COLON => ":".to_string(),
+                Token::COMMA => ",".to_string(),
+                Token::LPAREN => "(".to_string(),
+                Token::RPAREN => ")".to_string(),
+                token => panic!("unexpected {}", token),
+            },
         }
     }
+
+    #[test]
+    fn exponentiation() {
+        assert_eq!(grouped("2^3^2"), "((2^3)^2)");
+        assert_eq!(grouped("a^b*c"), "((a^b)*c)");
+        assert_eq!(grouped("a*b^c"), "(a*(b^c))");
+    }
+
+    #[test]
+    fn prefix() {
+        assert_eq!(grouped("-f(x)^2"), "(-(f(x)^2))");
+        assert_eq!(grouped("-2^2"), "(-(2^2))");
+        assert_eq!(grouped("2^-x^2"), "(2^(-(x^2)))");
+        assert_eq!(grouped("!g(x) && h(x)"), "((!g(x))&&h(x))");
+        assert_eq!(grouped("-a*b"), "((-a)*b)");
+        assert_eq!(grouped("!-a"), "(!(-a))");
+    }
+
+    #[test]
+    fn multiplication_and_division() {
+        assert_eq!(grouped("a/b*c"), "((a/b)*c)");
+        assert_eq!(grouped("a+b*c"), "(a+(b*c))");
+    }
+
+    #[test]
+    fn sum_and_difference() {
+        assert_eq!(grouped("a-b-c"), "((a-b)-c)");
+        assert_eq!(grouped("a<b+c"), "(a<(b+c))");
+    }
+
+    #[test]
+    fn comparison() {
+        assert_eq!(grouped("a<b<c"), "((a<b)<c)");
+        assert_eq!(grouped("a<=>b==c"), "((a<=>b)==c)");
+        assert_eq!(grouped("a||b<c"), "(a||(b<c))");
+    }
+
+    #[test]
+    fn or() {
+        assert_eq!(grouped("a||b||c"), "((a||b)||c)");
+        assert_eq!(grouped("a&&b||c"), "(a&&(b||c))");
+    }
+
+    #[test]
+    fn and() {
+        assert_eq!(grouped("a&&b&&c"), "((a&&b)&&c)");
+        assert_eq!(grouped("a||b&&c"), "((a||b)&&c)");
+        assert_eq!(grouped("a&&b?c:d"), "((a&&b)?c:d)");
+    }
+
+    #[test]
+    fn condition() {
+        assert_eq!(grouped("a?b:c?d:e"), "(a?b:(c?d:e))");
+        assert_eq!(grouped("a?b?c:d:e"), "(a?(b?c:d):e)");
+        assert_eq!(grouped("f(a?b:c, -d)"), "f((a?b:c),(-d))");
+    }
 }
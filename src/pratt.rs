@@ -0,0 +1,621 @@
+//! Pratt parser
+//!
+//! A recursive descent parser for the grammar of `grammar.txt`, climbing the precedence of binary
+//! operators, in place of the table-driven parser with the `pratt` feature. It builds the same AST,
+//! parsing the tokens of a statement once it is complete. An error names the tokens expected in its
+//! place, and parsing resumes after an error in an argument, a parenthesized expression or a local
+//! to report the errors after it too.
+
+use crate::{
+    lexer::Token,
+    parser::{ASTNode, Position, Unexpected},
+};
+
+/// Ids of the tokens the grammar tells apart, as in `Token::id`.
+mod id {
+    pub(super) const IDENT: u32 = 0;
+    pub(super) const NUM: u32 = 1;
+    pub(super) const ASSIGN: u32 = 2;
+    pub(super) const LPAREN: u32 = 3;
+    pub(super) const RPAREN: u32 = 4;
+    pub(super) const NOT: u32 = 5;
+    pub(super) const PN: u32 = 8;
+    pub(super) const COND: u32 = 12;
+    pub(super) const COLON: u32 = 13;
+    pub(super) const COMMA: u32 = 14;
+    pub(super) const LBRACE: u32 = 15;
+    pub(super) const RBRACE: u32 = 16;
+    pub(super) const SEMI: u32 = 17;
+}
+
+/// Precedence of `?:`, below all binary operators.
+const CONDITION: u32 = 0;
+
+/// Precedence of `^`, the operators in the operand of a prefix operator.
+const EXP: u32 = 6;
+
+/// The AST node id and precedence, higher binding tighter, of a left-associative binary operator.
+fn binary(token: u32) -> Option<(u32, u32)> {
+    match token {
+        // '^'
+        6 => Some((10, EXP)),
+        // MD
+        7 => Some((11, 5)),
+        // PN
+        8 => Some((12, 4)),
+        // CMP
+        9 => Some((13, 3)),
+        // OR
+        10 => Some((14, 2)),
+        // AND
+        11 => Some((15, 1)),
+        _ => None,
+    }
+}
+
+/// Tokens an operand starts with.
+const OPERAND: &[u32] = &[id::IDENT, id::NUM, id::LPAREN, id::NOT, id::PN, id::LBRACE];
+
+/// Tokens ending an argument.
+const PARAMETER_END: &[u32] = &[id::COMMA, id::RPAREN];
+
+/// Tokens continuing an expression at the end of a statement.
+const CONTINUATION: &[u32] = &[6, 7, 8, 9, 10, 11, id::COND, id::SEMI];
+
+/// Why a statement couldn't be parsed.
+enum Stop {
+    /// The tokens ran out where one of these was expected, so more may complete it.
+    End(Vec<u32>),
+    /// A token can't continue it, the error already recorded.
+    Unexpected,
+}
+
+pub(crate) struct Parser {
+    tokens: Vec<(Token, Position)>,
+}
+
+impl Parser {
+    pub(crate) fn new() -> Self {
+        Parser { tokens: vec![] }
+    }
+
+    /// Add a token at `position`, parsed with the others by `accept`.
+    pub(crate) fn action(&mut self, token: Token, position: Position) -> Result<(), Unexpected> {
+        self.tokens.push((token, position));
+        Ok(())
+    }
+
+    /// The statement of the tokens added, or its errors in order.
+    pub(crate) fn accept(self) -> Result<ASTNode, Vec<Unexpected>> {
+        let mut descent = Descent::new(&self.tokens);
+        let ast = match descent.statement() {
+            Ok(ast) => Some(ast),
+            Err(Stop::End(expected)) => {
+                descent.errors.push(Unexpected {
+                    position: None,
+                    expected,
+                });
+                None
+            }
+            Err(Stop::Unexpected) => None,
+        };
+        event!(tokens = self.tokens.len(), errors = descent.errors.len());
+        match ast {
+            Some(ast) if descent.errors.is_empty() => Ok(ast),
+            _ => Err(descent.errors),
+        }
+    }
+}
+
+struct Descent<'a> {
+    tokens: &'a [(Token, Position)],
+    pos: usize,
+    errors: Vec<Unexpected>,
+}
+
+impl<'a> Descent<'a> {
+    fn new(tokens: &'a [(Token, Position)]) -> Self {
+        Descent {
+            tokens,
+            pos: 0,
+            errors: vec![],
+        }
+    }
+
+    fn id(&self, pos: usize) -> Option<u32> {
+        self.tokens.get(pos).map(|(token, _)| token.id())
+    }
+
+    fn peek(&self) -> Option<u32> {
+        self.id(self.pos)
+    }
+
+    /// The error of the next token, or of the end of the tokens, where one of `expected` could be.
+    fn unexpected(&mut self, expected: &[u32]) -> Stop {
+        match self.tokens.get(self.pos) {
+            Some((_, position)) => {
+                self.errors.push(Unexpected {
+                    position: Some(position.clone()),
+                    expected: expected.to_vec(),
+                });
+                Stop::Unexpected
+            }
+            None => Stop::End(expected.to_vec()),
+        }
+    }
+
+    /// The next token as a leaf if its id is `id`.
+    fn expect(&mut self, id: u32) -> Result<ASTNode, Stop> {
+        match self.peek() {
+            Some(next) if next == id => Ok(self.leaf()),
+            _ => Err(self.unexpected(&[id])),
+        }
+    }
+
+    fn leaf(&mut self) -> ASTNode {
+        self.pos += 1;
+        let (token, position) = self.tokens[self.pos - 1].clone();
+        ASTNode::Leaf(token, position)
+    }
+
+    /// `result`, or after an error the tokens skipped up to one of `sync` outside brackets opened
+    /// since, leaving a placeholder node for a statement that can't be accepted anymore.
+    fn recover(&mut self, result: Result<ASTNode, Stop>, sync: &[u32]) -> Result<ASTNode, Stop> {
+        if !matches!(result, Err(Stop::Unexpected)) {
+            return result;
+        }
+        let mut depth = 0;
+        while let Some(next) = self.peek() {
+            match next {
+                _ if depth == 0 && sync.contains(&next) => return Ok(ASTNode::Inner(0, vec![])),
+                id::LPAREN | id::LBRACE => depth += 1,
+                id::RPAREN | id::RBRACE if depth == 0 => break,
+                id::RPAREN | id::RBRACE => depth -= 1,
+                _ => (),
+            }
+            self.pos += 1;
+        }
+        Err(Stop::Unexpected)
+    }
+
+    fn statement(&mut self) -> Result<ASTNode, Stop> {
+        let (first, second) = (self.id(0), self.id(1));
+        let (mut node, statement) = match (first, second) {
+            (Some(id::IDENT), Some(id::ASSIGN)) => {
+                let children = vec![self.leaf(), self.leaf(), self.expression(CONDITION)?];
+                (1, ASTNode::Inner(3, children))
+            }
+            (Some(id::IDENT), Some(id::COLON)) => {
+                let (ident, colon) = (self.leaf(), self.leaf());
+                let mut variables = ASTNode::Inner(6, vec![self.expect(id::IDENT)?]);
+                while self.peek() == Some(id::COMMA) {
+                    let comma = self.leaf();
+                    variables = ASTNode::Inner(5, vec![variables, comma, self.expect(id::IDENT)?]);
+                }
+                let assign = match self.peek() {
+                    Some(id::ASSIGN) => self.leaf(),
+                    _ => return Err(self.unexpected(&[id::ASSIGN, id::COMMA])),
+                };
+                let children = vec![ident, colon, variables, assign, self.expression(CONDITION)?];
+                (1, ASTNode::Inner(4, children))
+            }
+            _ => (2, self.expression(CONDITION)?),
+        };
+        let mut children = vec![statement];
+        // statement: assignment ';' or expression ';'
+        if self.peek() == Some(id::SEMI) {
+            children.push(self.leaf());
+            node += 25;
+        }
+        match self.peek() {
+            Some(_) if children.len() == 2 => Err(self.unexpected(&[])),
+            Some(_) => Err(self.unexpected(CONTINUATION)),
+            None => Ok(ASTNode::Inner(node, children)),
+        }
+    }
+
+    /// An expression of operators of precedence `min` and above.
+    fn expression(&mut self, min: u32) -> Result<ASTNode, Stop> {
+        let mut lhs = self.unary()?;
+        while let Some(next) = self.peek() {
+            lhs = match binary(next) {
+                Some((node, prec)) if prec >= min => {
+                    let op = self.leaf();
+                    ASTNode::Inner(node, vec![lhs, op, self.expression(prec + 1)?])
+                }
+                None if next == id::COND && min == CONDITION => {
+                    let cond = self.leaf();
+                    let ex1 = self.expression(CONDITION)?;
+                    let colon = self.expect(id::COLON)?;
+                    let ex2 = self.expression(CONDITION)?;
+                    ASTNode::Inner(16, vec![lhs, cond, ex1, colon, ex2])
+                }
+                _ => break,
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// An operand with its prefix operators, which bind tighter than any binary operator but `^`.
+    fn unary(&mut self) -> Result<ASTNode, Stop> {
+        match self.peek() {
+            Some(id::NOT) => Ok(ASTNode::Inner(8, vec![self.leaf(), self.expression(EXP)?])),
+            Some(id::PN) => Ok(ASTNode::Inner(9, vec![self.leaf(), self.expression(EXP)?])),
+            Some(id::LPAREN) => {
+                let lparen = self.leaf();
+                let parsed = self.expression(CONDITION);
+                let (expr, rparen) = match parsed.and_then(|e| Ok((e, self.expect(id::RPAREN)?))) {
+                    // resume after the ')' closing this one
+                    Err(Stop::Unexpected) => {
+                        self.recover(Err(Stop::Unexpected), &[id::RPAREN])?;
+                        (ASTNode::Inner(0, vec![]), self.leaf())
+                    }
+                    parsed => parsed?,
+                };
+                Ok(ASTNode::Inner(7, vec![lparen, expr, rparen]))
+            }
+            Some(id::LBRACE) => self.block(),
+            Some(id::NUM) => Ok(ASTNode::Inner(19, vec![self.leaf()])),
+            Some(id::IDENT) => {
+                let ident = self.leaf();
+                if self.peek() != Some(id::LPAREN) {
+                    return Ok(ASTNode::Inner(18, vec![ident]));
+                }
+                let lparen = self.leaf();
+                let param = self.expression(CONDITION);
+                let mut params = ASTNode::Inner(21, vec![self.recover(param, PARAMETER_END)?]);
+                while self.peek() == Some(id::COMMA) {
+                    let comma = self.leaf();
+                    let param = self.expression(CONDITION);
+                    let param = self.recover(param, PARAMETER_END)?;
+                    params = ASTNode::Inner(20, vec![params, comma, param]);
+                }
+                Ok(ASTNode::Inner(
+                    17,
+                    vec![ident, lparen, params, self.expect(id::RPAREN)?],
+                ))
+            }
+            _ => Err(self.unexpected(OPERAND)),
+        }
+    }
+
+    /// A block of locals, each ending with a `;`, and its result.
+    fn block(&mut self) -> Result<ASTNode, Stop> {
+        let lbrace = self.leaf();
+        let mut locals = ASTNode::Inner(25, vec![]);
+        loop {
+            let next = self.id(self.pos + 1);
+            if (self.peek(), next) != (Some(id::IDENT), Some(id::ASSIGN)) {
+                break;
+            }
+            let (ident, assign) = (self.leaf(), self.leaf());
+            let expr = self.expression(CONDITION);
+            let expr = self.recover(expr, &[id::SEMI])?;
+            let semi = self.expect(id::SEMI)?;
+            locals = ASTNode::Inner(24, vec![locals, ident, assign, expr, semi]);
+        }
+        let result = self.expression(CONDITION)?;
+        if self.peek() != Some(id::SEMI) {
+            let rbrace = self.expect(id::RBRACE)?;
+            return Ok(ASTNode::Inner(22, vec![lbrace, locals, result, rbrace]));
+        }
+        let semi = self.leaf();
+        let rbrace = self.expect(id::RBRACE)?;
+        Ok(ASTNode::Inner(
+            23,
+            vec![lbrace, locals, result, semi, rbrace],
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Parser;
+    use crate::{
+        lexer::{AddSubOp, CompareOp, MulDivOp, Token},
+        parser, Value,
+    };
+
+    /// Random choices by xorshift.
+    struct Random(u64);
+
+    impl Random {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        /// A token of id `id`, a user-defined operator for half the binary operator ids.
+        fn token(&mut self, id: u32) -> Token {
+            match id {
+                0 => Token::IDENT(b"x".to_vec()),
+                1 => Token::NUM(Value::Int(1).into()),
+                2 => Token::ASSIGN,
+                3 => Token::LPAREN,
+                4 => Token::RPAREN,
+                5 => Token::NOT,
+                6..=11 if self.below(2) == 0 => Token::OP(id, b"<+>".to_vec()),
+                6 => Token::EXP,
+                7 => Token::MD(MulDivOp::MUL),
+                8 => Token::PN(AddSubOp::SUB),
+                9 => Token::CMP(CompareOp::LT),
+                10 => Token::OR,
+                11 => Token::AND,
+                12 => Token::COND,
+                13 => Token::COLON,
+                14 => Token::COMMA,
+                15 => Token::LBRACE,
+                16 => Token::RBRACE,
+                _ => Token::SEMI,
+            }
+        }
+
+        fn any_token(&mut self) -> Token {
+            let id = self.below(Token::COUNT) as u32;
+            self.token(id)
+        }
+
+        /// The tokens of a statement of the grammar, with expressions nested up to `depth` deep.
+        fn statement(&mut self, depth: usize) -> Vec<Token> {
+            let mut tokens = match self.below(6) {
+                0 | 1 => vec![Token::IDENT(b"x".to_vec()), Token::ASSIGN],
+                2 => {
+                    let mut tokens = vec![Token::IDENT(b"f".to_vec()), Token::COLON];
+                    for i in 0..=self.below(3) {
+                        if i > 0 {
+                            tokens.push(Token::COMMA);
+                        }
+                        tokens.push(Token::IDENT(b"x".to_vec()));
+                    }
+                    tokens.push(Token::ASSIGN);
+                    tokens
+                }
+                _ => vec![],
+            };
+            self.expression(depth, &mut tokens);
+            if self.below(4) == 0 {
+                tokens.push(Token::SEMI);
+            }
+            tokens
+        }
+
+        fn expression(&mut self, depth: usize, tokens: &mut Vec<Token>) {
+            if depth == 0 || self.below(3) == 0 {
+                let id = self.below(2) as u32;
+                tokens.push(self.token(id));
+                return;
+            }
+            match self.below(10) {
+                0 => {
+                    tokens.push(Token::LPAREN);
+                    self.expression(depth - 1, tokens);
+                    tokens.push(Token::RPAREN);
+                }
+                1 => {
+                    let prefix = [Token::NOT, Token::PN(AddSubOp::SUB)][self.below(2)].clone();
+                    tokens.push(prefix);
+                    self.expression(depth - 1, tokens);
+                }
+                2 => {
+                    self.expression(depth - 1, tokens);
+                    tokens.push(Token::COND);
+                    self.expression(depth - 1, tokens);
+                    tokens.push(Token::COLON);
+                    self.expression(depth - 1, tokens);
+                }
+                3 => {
+                    tokens.extend([Token::IDENT(b"f".to_vec()), Token::LPAREN]);
+                    for i in 0..=self.below(3) {
+                        if i > 0 {
+                            tokens.push(Token::COMMA);
+                        }
+                        self.expression(depth - 1, tokens);
+                    }
+                    tokens.push(Token::RPAREN);
+                }
+                4 => {
+                    tokens.push(Token::LBRACE);
+                    for _ in 0..self.below(3) {
+                        tokens.extend([Token::IDENT(b"y".to_vec()), Token::ASSIGN]);
+                        self.expression(depth - 1, tokens);
+                        tokens.push(Token::SEMI);
+                    }
+                    self.expression(depth - 1, tokens);
+                    if self.below(2) == 0 {
+                        tokens.push(Token::SEMI);
+                    }
+                    tokens.push(Token::RBRACE);
+                }
+                _ => {
+                    self.expression(depth - 1, tokens);
+                    let id = 6 + self.below(6) as u32;
+                    tokens.push(self.token(id));
+                    self.expression(depth - 1, tokens);
+                }
+            }
+        }
+
+        /// `tokens` with one token removed, added, replaced or swapped with the next.
+        fn mutate(&mut self, mut tokens: Vec<Token>) -> Vec<Token> {
+            let i = self.below(tokens.len());
+            match self.below(4) {
+                0 => {
+                    tokens.remove(i);
+                }
+                1 => {
+                    let token = self.any_token();
+                    tokens.insert(i, token);
+                }
+                2 => tokens[i] = self.any_token(),
+                _ if i + 1 < tokens.len() => tokens.swap(i, i + 1),
+                _ => (),
+            }
+            tokens
+        }
+    }
+
+    /// The AST of `tokens` shown, or the index of the first token rejected, `None` for the end.
+    type Parse = Result<String, Option<usize>>;
+
+    fn first_error(errors: Vec<parser::Unexpected>) -> Option<usize> {
+        errors[0].position.as_ref().map(|(_, span)| span.start)
+    }
+
+    fn by_tables(tokens: &[Token]) -> Parse {
+        let mut parser = parser::Parser::new();
+        for (i, token) in tokens.iter().enumerate() {
+            if parser.action(token.clone(), (1, i..i + 1)).is_err() {
+                return Err(Some(i));
+            }
+        }
+        parser
+            .accept()
+            .map(|ast| ast.to_string())
+            .map_err(first_error)
+    }
+
+    fn by_descent(tokens: &[Token]) -> Parse {
+        let mut parser = Parser::new();
+        for (i, token) in tokens.iter().enumerate() {
+            parser.action(token.clone(), (1, i..i + 1)).unwrap();
+        }
+        parser
+            .accept()
+            .map(|ast| ast.to_string())
+            .map_err(first_error)
+    }
+
+    fn assert_same(tokens: &[Token]) {
+        let shown = tokens.iter().map(Token::to_string).collect::<Vec<_>>();
+        assert_eq!(by_tables(tokens), by_descent(tokens), "{}", shown.join(" "));
+    }
+
+    /// Both parsers build the same AST of generated statements, and reject the same token of
+    /// statements with a token removed, added, replaced or swapped.
+    #[test]
+    fn same_statements() {
+        let mut random = Random(0x9E37_79B9_7F4A_7C15);
+        for i in 0..600_000 {
+            let tokens = random.statement(4);
+            match i % 2 {
+                0 => assert_same(&tokens),
+                _ => assert_same(&random.mutate(tokens)),
+            }
+        }
+    }
+
+    /// Both parsers reject the same token of random sequences and of statements with a few
+    /// mutations, and the tokens the tables expect instead are shifted after the same tokens.
+    #[test]
+    fn same_token_sequences() {
+        let mut random = Random(0x2545_F491_4F6C_DD1D);
+        for i in 0..600_000 {
+            let tokens = match i % 2 {
+                0 => (0..=random.below(12)).map(|_| random.any_token()).collect(),
+                _ => {
+                    let mut tokens = random.statement(3);
+                    for _ in 0..2 + random.below(3) {
+                        tokens = random.mutate(tokens);
+                        if tokens.is_empty() {
+                            tokens.push(random.any_token());
+                        }
+                    }
+                    tokens
+                }
+            };
+            assert_same(&tokens);
+            if i % 10 == 0 {
+                assert_expected_shift(&tokens);
+            }
+        }
+    }
+
+    /// Each token the tables expect in place of the first one rejected in `tokens` is shifted.
+    fn assert_expected_shift(tokens: &[Token]) {
+        let mut parser = parser::Parser::new();
+        let rejected = tokens.iter().enumerate().find_map(|(i, token)| {
+            let position = (1, i..i + 1);
+            parser
+                .action(token.clone(), position)
+                .err()
+                .map(|e| (i, e.expected))
+        });
+        let (at, expected) = match rejected {
+            Some(rejected) => rejected,
+            None => return,
+        };
+        let mut random = Random(at as u64 + 1);
+        for id in expected {
+            let mut parser = parser::Parser::new();
+            for (i, token) in tokens[..at].iter().enumerate() {
+                parser.action(token.clone(), (1, i..i + 1)).unwrap();
+            }
+            let token = random.token(id);
+            assert!(parser.action(token, (1, at..at + 1)).is_ok());
+        }
+    }
+
+    fn errors(tokens: Vec<Token>) -> Vec<(Option<usize>, Vec<u32>)> {
+        let mut parser = Parser::new();
+        for (i, token) in tokens.into_iter().enumerate() {
+            parser.action(token, (1, i..i + 1)).unwrap();
+        }
+        match parser.accept() {
+            Ok(_) => vec![],
+            Err(errors) => errors
+                .into_iter()
+                .map(|e| (e.position.map(|(_, span)| span.start), e.expected))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn expected_tokens() {
+        let (x, n) = (
+            Token::IDENT(b"x".to_vec()),
+            Token::NUM(Value::Int(1).into()),
+        );
+        // '(' x
+        assert_eq!(errors(vec![Token::LPAREN, x.clone()]), [(None, vec![4])]);
+        // x = ')'
+        let operand = vec![0, 1, 3, 5, 8, 15];
+        let tokens = vec![x.clone(), Token::ASSIGN, Token::RPAREN];
+        assert_eq!(errors(tokens), [(Some(2), operand)]);
+        // 1 1
+        let continuation = vec![6, 7, 8, 9, 10, 11, 12, 17];
+        assert_eq!(errors(vec![n.clone(), n]), [(Some(1), continuation)]);
+    }
+
+    #[test]
+    fn recovery() {
+        let x = || Token::IDENT(b"x".to_vec());
+        let plus = || Token::PN(AddSubOp::ADD);
+        // x(x +, (x x), x) + (x + )
+        let tokens = vec![
+            x(),
+            Token::LPAREN,
+            x(),
+            plus(),
+            Token::COMMA,
+            Token::LPAREN,
+            x(),
+            x(),
+            Token::RPAREN,
+            Token::COMMA,
+            x(),
+            Token::RPAREN,
+            plus(),
+            Token::LPAREN,
+            x(),
+            plus(),
+            Token::RPAREN,
+        ];
+        let at = errors(tokens)
+            .into_iter()
+            .map(|(at, _)| at)
+            .collect::<Vec<_>>();
+        assert_eq!(at, [Some(4), Some(7), Some(16)]);
+    }
+}
@@ -0,0 +1,186 @@
+//! Interactive read-eval-print loop
+
+use std::{ffi::CString, path::PathBuf};
+
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::FileHistory,
+    validate::{ValidationContext, ValidationResult, Validator},
+    Context, Editor, Helper,
+};
+
+use crate::{Assigned, InputState, Interpreter};
+
+/// A terminal session over an interpreter, with line editing, history and completion.
+///
+/// A line ending with `...` continues in the same edit buffer, Ctrl-C discards the
+/// statement being entered and Ctrl-D ends the session. Lines starting with `:` are
+/// meta-commands, see `:help`.
+pub struct Repl {
+    editor: Editor<ReplHelper, FileHistory>,
+    history: Option<PathBuf>,
+}
+
+struct ReplHelper {
+    interpreter: Interpreter,
+}
+
+impl Repl {
+    /// Create a session, loading and saving the line history at `history` if given.
+    pub fn new(interpreter: Interpreter, history: Option<PathBuf>) -> rustyline::Result<Self> {
+        let mut editor = Editor::new()?;
+        editor.set_helper(Some(ReplHelper { interpreter }));
+        if let Some(path) = &history {
+            // a missing history file is created on exit
+            let _ = editor.load_history(path);
+        }
+        Ok(Repl { editor, history })
+    }
+
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.editor.helper_mut().unwrap().interpreter
+    }
+
+    /// Read and evaluate lines until end of input.
+    pub fn run(&mut self) -> rustyline::Result<()> {
+        let mut prompt = ">>> ";
+        loop {
+            let buffer = match self.editor.readline(prompt) {
+                Ok(buffer) => buffer,
+                Err(ReadlineError::Interrupted) => {
+                    self.interpreter().cancel();
+                    prompt = ">>> ";
+                    continue;
+                }
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e),
+            };
+            self.editor.add_history_entry(buffer.as_str())?;
+            prompt = match self.input(&buffer) {
+                true => "... ",
+                false => ">>> ",
+            };
+        }
+        if let Some(path) = &self.history {
+            self.editor.save_history(path)?;
+        }
+        Ok(())
+    }
+
+    /// Input the lines of an edit buffer, returning whether the statement is unfinished.
+    fn input(&mut self, buffer: &str) -> bool {
+        let it = self.interpreter();
+        if let Some(result) = it.command(buffer) {
+            match result {
+                Ok(output) if output.is_empty() => (),
+                Ok(output) => println!("{}", output),
+                Err(e) => eprintln!("!Error: {}", e),
+            }
+            return false;
+        }
+        let mut unfinished = false;
+        for line in buffer.split('\n') {
+            let line = match CString::new(line) {
+                Ok(line) => line,
+                Err(_) => {
+                    eprintln!("!Error: NUL character in input");
+                    it.cancel();
+                    return false;
+                }
+            };
+            unfinished = false;
+            match it.input(line.as_bytes_with_nul()) {
+                Ok(InputState::Empty) => (),
+                Ok(InputState::Incomplete) => unfinished = true,
+                Ok(InputState::Assignment {
+                    name,
+                    assigned,
+                    warnings,
+                    echo,
+                }) => {
+                    for w in warnings {
+                        eprintln!("!Warning: {}", w);
+                    }
+                    for step in it.trace() {
+                        println!("{}", step);
+                    }
+                    if let (Assigned::Value(value), true) = (assigned, echo) {
+                        println!("{} = {}", name, it.display(&value));
+                    }
+                }
+                Ok(InputState::Expression {
+                    value,
+                    warnings,
+                    echo,
+                }) => {
+                    for w in warnings {
+                        eprintln!("!Warning: {}", w);
+                    }
+                    for step in it.trace() {
+                        println!("{}", step);
+                    }
+                    if echo {
+                        println!("{}", it.display(&value));
+                    }
+                }
+                Ok(InputState::Help { entry }) => println!("{}", entry),
+                Ok(InputState::Workspace { name }) => println!("workspace {}", name),
+                Err(e) => {
+                    eprintln!("!Error: {}", e);
+                    return false;
+                }
+            }
+        }
+        unfinished
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + 1);
+        let candidates = self
+            .interpreter
+            .complete(line, pos)
+            .into_iter()
+            .map(|completion| Pair {
+                display: match completion.arity {
+                    Some(arity) => format!("{}/{}", completion.text, arity),
+                    None => completion.text.clone(),
+                },
+                replacement: completion.text,
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let last = ctx.input().rsplit('\n').next().unwrap_or_default();
+        let code = last.split('#').next().unwrap_or_default();
+        Ok(match code.contains("...") {
+            true => ValidationResult::Incomplete,
+            false => ValidationResult::Valid(None),
+        })
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Helper for ReplHelper {}
@@ -0,0 +1,54 @@
+//! Evenly spaced vectors
+
+use crate::{
+    value::{EvalError, Value},
+    Real,
+};
+
+/// Largest number of elements of a generated vector.
+const MAX_LEN: Real = 1e7;
+
+fn length(count: Real) -> Result<usize, EvalError> {
+    match count <= MAX_LEN {
+        true => Ok(count as usize),
+        false => Err(EvalError::InvalidArgument {
+            expect: "at most 10^7 elements",
+        }),
+    }
+}
+
+/// `a, a + step, ...` up to and including `b`, empty if `step` leads away from `b`.
+pub(crate) fn range(args: &[Value]) -> Result<Value, EvalError> {
+    let (a, b, step) = (args[0].num()?, args[1].num()?, args[2].num()?);
+    let steps = (b - a) / step;
+    if step == 0.0 || !steps.is_finite() {
+        return Err(EvalError::InvalidArgument {
+            expect: "finite bounds and a non-zero step",
+        });
+    }
+    // tolerate rounding like in range(0, 1, 0.1)
+    let len = match steps < 0.0 {
+        true => 0,
+        false => length((steps + 1e-9).floor() + 1.0)?,
+    };
+    Ok((0..len)
+        .map(|k| a + k as Real * step)
+        .collect::<Vec<_>>()
+        .into())
+}
+
+/// `n` numbers evenly spaced from `a` to `b`.
+pub(crate) fn linspace(args: &[Value]) -> Result<Value, EvalError> {
+    let (a, b, n) = (args[0].num()?, args[1].num()?, args[2].num()?);
+    if !(n >= 1.0 && n.fract() == 0.0) {
+        return Err(EvalError::InvalidArgument {
+            expect: "positive integer count",
+        });
+    }
+    let len = length(n)?;
+    let v = (0..len).map(|k| match k + 1 == len && len > 1 {
+        true => b,
+        false => a + (b - a) * k as Real / (len - 1).max(1) as Real,
+    });
+    Ok(v.collect::<Vec<_>>().into())
+}
@@ -0,0 +1,200 @@
+//! Line-oriented evaluation server
+//!
+//! Each line sent is a statement or a `:command`, answered by one line of JSON:
+//!
+//! + `{"value": "3", "kind": "integer", "warnings": [...]}` for an expression
+//! + `{"name": "x", "value": "3", "warnings": [...]}` or `{"name": "f", "arity": 1, ...}` for a
+//!   definition
+//! + `"silent": true` added to those of a statement silenced by a final `;`
+//! + `{"incomplete": true}` for a line continued by `...`
+//! + `{"output": "..."}` for a command, `{"help": "..."}` and `{"workspace": "name"}`
+//! + `{"error": {"code": "E0004", "severity": "error", "message": "...", "line": 1, "span": [0, 1]}}`,
+//!   the line counted from the start of the statement
+//! + `{}` for an empty line
+//!
+//! All connections share one interpreter. A connection keeps the lines of a multi-line statement
+//! and evaluates them together once it ends, so statements of different connections never
+//! interleave and a connection waiting for its next line holds nothing. Meta-commands are
+//! refused, as they reach the file system and the state of every connection.
+
+use std::{
+    ffi::CString,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use serde_json::{json, Value as Json};
+
+use crate::{command, Assigned, Diagnostic, InputState, Interpreter, Severity};
+
+/// Stack of a connection thread, room for user function calls a few hundred deep.
+const STACK_SIZE: usize = 32 * 1024 * 1024;
+
+/// Answer one line on `interpreter` as described in the module documentation.
+pub fn respond(interpreter: &mut Interpreter, line: &str) -> Json {
+    respond_at(interpreter, line, 0)
+}
+
+/// Answer a line of a script whose statement starts after its first `before` lines, errors
+/// counting lines from the start of the script.
+pub fn respond_at(interpreter: &mut Interpreter, line: &str, before: usize) -> Json {
+    if let Some(result) = interpreter.command(line) {
+        return match result {
+            Ok(output) => json!({ "output": output }),
+            Err(e) => json!({ "error": { "severity": "error", "message": e.to_string() } }),
+        };
+    }
+    let line = match CString::new(line) {
+        Ok(line) => line,
+        Err(_) => {
+            interpreter.cancel();
+            return json!({ "error": { "severity": "error", "message": "NUL character in input" } });
+        }
+    };
+    match interpreter.input(line.as_bytes_with_nul()) {
+        Ok(InputState::Empty) => json!({}),
+        Ok(InputState::Incomplete) => json!({ "incomplete": true }),
+        Ok(InputState::Expression {
+            value,
+            warnings,
+            echo,
+        }) => silenced(
+            json!({
+                "value": interpreter.display(&value),
+                "kind": value.kind(),
+                "warnings": warnings.iter().map(|w| diagnostic(&w.diagnostic())).collect::<Vec<_>>(),
+            }),
+            echo,
+        ),
+        Ok(InputState::Assignment {
+            name,
+            assigned,
+            warnings,
+            echo,
+        }) => {
+            let warnings = warnings
+                .iter()
+                .map(|w| diagnostic(&w.diagnostic()))
+                .collect::<Vec<_>>();
+            let response = match assigned {
+                Assigned::Value(value) => json!({
+                    "name": name,
+                    "value": interpreter.display(&value),
+                    "kind": value.kind(),
+                    "warnings": warnings,
+                }),
+                Assigned::Function { arity } => {
+                    json!({ "name": name, "arity": arity, "warnings": warnings })
+                }
+            };
+            silenced(response, echo)
+        }
+        Ok(InputState::Help { entry }) => json!({ "help": entry.to_string() }),
+        Ok(InputState::Workspace { name }) => json!({ "workspace": name }),
+        Err(e) => json!({ "error": diagnostic(&e.offset_lines(before).diagnostic()) }),
+    }
+}
+
+/// `response` marked silent unless the result is echoed.
+fn silenced(mut response: Json, echo: bool) -> Json {
+    if !echo {
+        response["silent"] = json!(true);
+    }
+    response
+}
+
+fn diagnostic(d: &Diagnostic) -> Json {
+    json!({
+        "code": d.code,
+        "severity": match d.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        },
+        "message": d.message,
+        "line": d.line,
+        "span": d.span.as_ref().map(|span| [span.start, span.end]),
+    })
+}
+
+/// Answer the lines of one connection until it closes.
+pub fn serve<S: io::Read + Write>(interpreter: &Mutex<Interpreter>, stream: S) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    // lines of an unfinished statement, evaluated again with the line ending it
+    let mut pending = vec![];
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end_matches(&['\r', '\n'][..]);
+        let response = match command::parse(line) {
+            Some(_) => json!({
+                "error": { "severity": "error", "message": "Meta-commands are not available" }
+            }),
+            None => {
+                let mut guard = interpreter.lock().unwrap_or_else(|e| e.into_inner());
+                statement(&mut guard, &mut pending, line)
+            }
+        };
+        let stream = reader.get_mut();
+        writeln!(stream, "{}", response)?;
+        stream.flush()?;
+    }
+    Ok(())
+}
+
+/// Answer `line` after the `pending` lines of its statement, leaving no statement unfinished in
+/// `interpreter`.
+fn statement(interpreter: &mut Interpreter, pending: &mut Vec<String>, line: &str) -> Json {
+    for earlier in pending.iter() {
+        let response = respond(interpreter, earlier);
+        // ended differently by definitions of another connection
+        if response["incomplete"] != true {
+            interpreter.cancel();
+            pending.clear();
+            return response;
+        }
+    }
+    let response = respond(interpreter, line);
+    if response["incomplete"] == true {
+        interpreter.cancel();
+        pending.push(line.to_string());
+    } else {
+        pending.clear();
+    }
+    response
+}
+
+/// Accept connections at `addr`, serving each on its own thread, on `interpreter`, which should
+/// set a `Budget`.
+pub fn serve_tcp<A: ToSocketAddrs>(addr: A, interpreter: Interpreter) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let interpreter = Arc::new(Mutex::new(interpreter));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let interpreter = interpreter.clone();
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || serve(&interpreter, stream))?;
+    }
+    Ok(())
+}
+
+/// Accept connections on the Unix socket `path`, serving each on its own thread, on `interpreter`,
+/// which should set a `Budget`.
+#[cfg(unix)]
+pub fn serve_unix<P: AsRef<std::path::Path>>(path: P, interpreter: Interpreter) -> io::Result<()> {
+    let listener = std::os::unix::net::UnixListener::bind(path)?;
+    let interpreter = Arc::new(Mutex::new(interpreter));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let interpreter = interpreter.clone();
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || serve(&interpreter, stream))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,265 @@
+//! Spreadsheet formulas
+//!
+//! A [`Sheet`] evaluates formulas referring to cells by column letters and row number, like `A1`
+//! or `AB12`. A cell holding a formula has its result, any other the number of the host's
+//! [`Grid`], read through the resolver of the interpreter. After cells of the grid change,
+//! [`Sheet::invalidate`] recalculates the formulas depending on them, callees first.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    fmt,
+    sync::{Arc, RwLock, RwLockWriteGuard},
+};
+
+use crate::{InputError, Interpreter, Real, Value};
+
+/// A cell, by 0-based column and row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Cell {
+    pub column: u32,
+    pub row: u32,
+}
+
+impl Cell {
+    /// The cell named by upper case column letters and a row number from 1, like `B7`.
+    pub fn parse(name: &str) -> Option<Cell> {
+        let digits = name.find(|c: char| c.is_ascii_digit())?;
+        let (letters, row) = name.split_at(digits);
+        if letters.is_empty() || !letters.bytes().all(|c| c.is_ascii_uppercase()) {
+            return None;
+        }
+        if row.starts_with('0') || !row.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        // columns are numbered A to Z, then AA to ZZ, and so on
+        let column = letters.bytes().try_fold(0u32, |column, c| {
+            column.checked_mul(26)?.checked_add(u32::from(c - b'A') + 1)
+        })?;
+        Some(Cell {
+            column: column - 1,
+            row: row.parse::<u32>().ok()? - 1,
+        })
+    }
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut letters = vec![];
+        let mut column = self.column as u64 + 1;
+        while column > 0 {
+            letters.push(b'A' + ((column - 1) % 26) as u8);
+            column = (column - 1) / 26;
+        }
+        letters.reverse();
+        write!(
+            f,
+            "{}{}",
+            String::from_utf8_lossy(&letters),
+            self.row as u64 + 1
+        )
+    }
+}
+
+/// Numbers of the cells without formulas, provided by the host.
+pub trait Grid: Send + Sync {
+    fn value(&self, cell: Cell) -> Option<Real>;
+}
+
+impl<F: Fn(Cell) -> Option<Real> + Send + Sync> Grid for F {
+    fn value(&self, cell: Cell) -> Option<Real> {
+        self(cell)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SheetError {
+    /// The formula would refer to its own cell, directly or through other formulas.
+    Cycle {
+        cell: Cell,
+    },
+    InvalidFormula(InputError),
+}
+
+impl fmt::Display for SheetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SheetError::Cycle { cell } => write!(f, "Circular Reference: {}", cell),
+            SheetError::InvalidFormula(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<InputError> for SheetError {
+    fn from(e: InputError) -> Self {
+        SheetError::InvalidFormula(e)
+    }
+}
+
+struct Formula {
+    text: String,
+    /// Cells it refers to.
+    refs: Vec<Cell>,
+    result: Result<Value, InputError>,
+}
+
+pub struct Sheet {
+    interpreter: Interpreter,
+    formulas: HashMap<Cell, Formula>,
+    /// Numbers computed by the formulas, read before the grid.
+    computed: Arc<RwLock<HashMap<Cell, Real>>>,
+}
+
+impl Sheet {
+    /// A sheet evaluating formulas with `interpreter`, whose resolver it sets, on `grid`.
+    pub fn new<G: Grid + 'static>(mut interpreter: Interpreter, grid: G) -> Self {
+        let computed = Arc::new(RwLock::new(HashMap::new()));
+        let numbers = computed.clone();
+        interpreter.set_resolver(move |name| {
+            let cell = Cell::parse(name)?;
+            let computed = numbers.read().unwrap_or_else(|e| e.into_inner());
+            computed.get(&cell).copied().or_else(|| grid.value(cell))
+        });
+        Sheet {
+            interpreter,
+            formulas: HashMap::new(),
+            computed,
+        }
+    }
+
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.interpreter
+    }
+
+    /// The interpreter, e.g. to define functions for the formulas; its resolver should be kept.
+    pub fn interpreter_mut(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    /// Put the formula `text` in `cell`, returning the cells recalculated, `cell` first.
+    ///
+    /// Failing to evaluate is its result, unlike syntax errors and circular references, which
+    /// leave the sheet unchanged.
+    pub fn set_formula(&mut self, cell: Cell, text: &str) -> Result<Vec<Cell>, SheetError> {
+        let refs = self
+            .interpreter
+            .free_variables(text)?
+            .missing
+            .iter()
+            .filter_map(|name| Cell::parse(name))
+            .collect::<Vec<_>>();
+        if refs.iter().any(|r| *r == cell || self.depends_on(*r, cell)) {
+            return Err(SheetError::Cycle { cell });
+        }
+        let formula = Formula {
+            text: text.to_string(),
+            refs,
+            result: Ok(Value::Int(0)),
+        };
+        self.formulas.insert(cell, formula);
+        Ok(self.recalculate(&[cell]))
+    }
+
+    /// Empty `cell` of its formula, returning the formulas recalculated.
+    pub fn remove_formula(&mut self, cell: Cell) -> Vec<Cell> {
+        if self.formulas.remove(&cell).is_none() {
+            return vec![];
+        }
+        self.computed_mut().remove(&cell);
+        self.recalculate(&[cell])
+    }
+
+    /// The result of the formula in `cell`, if any.
+    pub fn result(&self, cell: Cell) -> Option<&Result<Value, InputError>> {
+        self.formulas.get(&cell).map(|formula| &formula.result)
+    }
+
+    /// The formula in `cell`, as set.
+    pub fn formula(&self, cell: Cell) -> Option<&str> {
+        self.formulas
+            .get(&cell)
+            .map(|formula| formula.text.as_str())
+    }
+
+    /// Recalculate the formulas depending on the cells of the grid `changed`, directly or through
+    /// other formulas, each once and after those it refers to, returning them in that order.
+    pub fn invalidate(&mut self, changed: &[Cell]) -> Vec<Cell> {
+        self.recalculate(changed)
+    }
+
+    /// Whether the formula in `cell` refers to `target`, directly or through other formulas.
+    fn depends_on(&self, cell: Cell, target: Cell) -> bool {
+        let mut pending = vec![cell];
+        let mut seen = HashSet::new();
+        while let Some(cell) = pending.pop() {
+            if let Some(formula) = self.formulas.get(&cell) {
+                if formula.refs.contains(&target) {
+                    return true;
+                }
+                pending.extend(formula.refs.iter().filter(|r| seen.insert(**r)));
+            }
+        }
+        false
+    }
+
+    /// Recalculate the formulas in `roots` and those depending on them.
+    fn recalculate(&mut self, roots: &[Cell]) -> Vec<Cell> {
+        let mut dependents = HashMap::<Cell, Vec<Cell>>::new();
+        for (cell, formula) in &self.formulas {
+            for r in &formula.refs {
+                dependents.entry(*r).or_default().push(*cell);
+            }
+        }
+        let mut dirty = roots
+            .iter()
+            .filter(|cell| self.formulas.contains_key(cell))
+            .copied()
+            .collect::<HashSet<_>>();
+        let mut pending = roots.to_vec();
+        while let Some(cell) = pending.pop() {
+            for dependent in dependents.get(&cell).into_iter().flatten() {
+                if dirty.insert(*dependent) {
+                    pending.push(*dependent);
+                }
+            }
+        }
+        // the formulas each waits for, then those ready, in order of their cells
+        let mut waiting = dirty
+            .iter()
+            .map(|cell| {
+                let refs = &self.formulas[cell].refs;
+                (*cell, refs.iter().filter(|r| dirty.contains(r)).count())
+            })
+            .collect::<HashMap<_, _>>();
+        let mut ready = waiting
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(cell, _)| Reverse(*cell))
+            .collect::<BinaryHeap<_>>();
+        let mut order = Vec::with_capacity(dirty.len());
+        while let Some(Reverse(cell)) = ready.pop() {
+            order.push(cell);
+            for dependent in dependents.get(&cell).into_iter().flatten() {
+                if let Some(count) = waiting.get_mut(dependent) {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push(Reverse(*dependent));
+                    }
+                }
+            }
+        }
+        for cell in &order {
+            let result = self.interpreter.eval_pure(&self.formulas[cell].text);
+            match result.as_ref().map(Value::num) {
+                Ok(Ok(num)) => self.computed_mut().insert(*cell, num),
+                _ => self.computed_mut().remove(cell),
+            };
+            self.formulas.get_mut(cell).unwrap().result = result;
+        }
+        order
+    }
+
+    fn computed_mut(&self) -> RwLockWriteGuard<'_, HashMap<Cell, Real>> {
+        self.computed.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
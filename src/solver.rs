@@ -0,0 +1,421 @@
+//! Numerical solvers
+
+use std::fmt;
+
+use crate::{value::EvalError, InputError, Real};
+
+const MAX_ITERATIONS: usize = 100;
+const TOLERANCE: Real = 1e-12;
+const SAMPLES: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SolveError {
+    InvalidFunction(InputError),
+    NotBracketed {
+        fa: Real,
+        fb: Real,
+        subinterval: Option<(Real, Real)>,
+    },
+    EvenMultiplicity {
+        x: Real,
+    },
+    NotANumber {
+        x: Vec<Real>,
+    },
+    NoConvergence {
+        x: Real,
+    },
+    DimensionMismatch {
+        equations: usize,
+        unknowns: usize,
+    },
+    SingularJacobian,
+    SystemNoConvergence {
+        residual: Real,
+    },
+    DataMismatch {
+        xs: usize,
+        ys: usize,
+    },
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolveError::InvalidFunction(e) => write!(f, "{}", e),
+            SolveError::NotBracketed {
+                fa,
+                fb,
+                subinterval,
+            } => {
+                write!(f, "Root Not Bracketed: f(a) = {}, f(b) = {}", fa, fb)?;
+                match subinterval {
+                    Some((a, b)) => write!(f, ", sign changes in [{}, {}]", a, b),
+                    None => Ok(()),
+                }
+            }
+            SolveError::EvenMultiplicity { x } => {
+                write!(f, "Root of Even Multiplicity near {}", x)
+            }
+            SolveError::NotANumber { x } => write!(f, "Not a Number at {:?}", x),
+            SolveError::NoConvergence { x } => write!(f, "No Convergence near {}", x),
+            SolveError::DimensionMismatch {
+                equations,
+                unknowns,
+            } => write!(
+                f,
+                "Dimension Mismatch: {} equations, {} unknowns",
+                equations, unknowns
+            ),
+            SolveError::SingularJacobian => write!(f, "Singular Jacobian"),
+            SolveError::SystemNoConvergence { residual } => {
+                write!(f, "No Convergence, residual {}", residual)
+            }
+            SolveError::DataMismatch { xs, ys } => {
+                write!(f, "Data Mismatch: {} xs, {} ys", xs, ys)
+            }
+        }
+    }
+}
+
+impl From<InputError> for SolveError {
+    fn from(e: InputError) -> Self {
+        SolveError::InvalidFunction(e)
+    }
+}
+
+impl From<EvalError> for SolveError {
+    fn from(e: EvalError) -> Self {
+        SolveError::InvalidFunction(e.into())
+    }
+}
+
+/// Find a root of `f` in `[a, b]` with Brent's method.
+pub(crate) fn find_root<F: Fn(Real) -> Result<Real, SolveError>>(
+    f: F,
+    a: Real,
+    b: Real,
+) -> Result<Real, SolveError> {
+    let fa = eval(&f, a)?;
+    let fb = eval(&f, b)?;
+    if fa == 0.0 {
+        return Ok(a);
+    }
+    if fb == 0.0 {
+        return Ok(b);
+    }
+    if fa.signum() == fb.signum() {
+        return Err(diagnose(&f, a, b, fa, fb)?);
+    }
+    brent(&f, a, b, fa, fb)
+}
+
+fn eval<F: Fn(Real) -> Result<Real, SolveError>>(f: &F, x: Real) -> Result<Real, SolveError> {
+    let y = f(x)?;
+    match y.is_nan() {
+        true => Err(SolveError::NotANumber { x: vec![x] }),
+        false => Ok(y),
+    }
+}
+
+fn brent<F: Fn(Real) -> Result<Real, SolveError>>(
+    f: &F,
+    a: Real,
+    b: Real,
+    fa: Real,
+    fb: Real,
+) -> Result<Real, SolveError> {
+    let (mut a, mut b, mut fa, mut fb) = (a, b, fa, fb);
+    let (mut c, mut fc) = (b, fb);
+    let (mut d, mut e) = (b - a, b - a);
+    for _ in 0..MAX_ITERATIONS {
+        if fb.signum() == fc.signum() {
+            c = a;
+            fc = fa;
+            d = b - a;
+            e = d;
+        }
+        if fc.abs() < fb.abs() {
+            a = b;
+            b = c;
+            c = a;
+            fa = fb;
+            fb = fc;
+            fc = fa;
+        }
+        let tol = 2.0 * Real::EPSILON * b.abs() + 0.5 * TOLERANCE;
+        let m = 0.5 * (c - b);
+        if m.abs() <= tol || fb == 0.0 {
+            return Ok(b);
+        }
+        if e.abs() >= tol && fa.abs() > fb.abs() {
+            // inverse quadratic interpolation, or secant when a == c
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                (2.0 * m * s, 1.0 - s)
+            } else {
+                let q = fa / fc;
+                let r = fb / fc;
+                (
+                    s * (2.0 * m * q * (q - r) - (b - a) * (r - 1.0)),
+                    (q - 1.0) * (r - 1.0) * (s - 1.0),
+                )
+            };
+            if p > 0.0 {
+                q = -q;
+            } else {
+                p = -p;
+            }
+            if 2.0 * p < (3.0 * m * q - (tol * q).abs()).min((e * q).abs()) {
+                e = d;
+                d = p / q;
+            } else {
+                d = m;
+                e = m;
+            }
+        } else {
+            d = m;
+            e = m;
+        }
+        a = b;
+        fa = fb;
+        b += if d.abs() > tol { d } else { tol.copysign(m) };
+        fb = eval(f, b)?;
+    }
+    Err(SolveError::NoConvergence { x: b })
+}
+
+/// Explain why `[a, b]` does not bracket a root of `f`.
+fn diagnose<F: Fn(Real) -> Result<Real, SolveError>>(
+    f: &F,
+    a: Real,
+    b: Real,
+    fa: Real,
+    fb: Real,
+) -> Result<SolveError, SolveError> {
+    let step = (b - a) / SAMPLES as Real;
+    let (mut x0, mut y0) = (a, fa);
+    let (mut best, mut best_y) = (a, fa.abs());
+    for i in 1..SAMPLES {
+        let x = a + step * i as Real;
+        let y = eval(f, x)?;
+        if y.signum() != y0.signum() {
+            return Ok(SolveError::NotBracketed {
+                fa,
+                fb,
+                subinterval: Some((x0, x)),
+            });
+        }
+        if y.abs() < best_y {
+            best = x;
+            best_y = y.abs();
+        }
+        x0 = x;
+        y0 = y;
+    }
+    // golden-section search for a minimum of |f| touching zero
+    let ratio = (Real::sqrt(5.0) - 1.0) / 2.0;
+    let (mut lo, mut hi) = ((best - step).max(a), (best + step).min(b));
+    for _ in 0..MAX_ITERATIONS {
+        let x1 = hi - ratio * (hi - lo);
+        let x2 = lo + ratio * (hi - lo);
+        if eval(f, x1)?.abs() < eval(f, x2)?.abs() {
+            hi = x2;
+        } else {
+            lo = x1;
+        }
+    }
+    let x = 0.5 * (lo + hi);
+    let scale = fa.abs().max(fb.abs()).max(1.0);
+    Ok(if eval(f, x)?.abs() <= scale * 1e-9 {
+        SolveError::EvenMultiplicity { x }
+    } else {
+        SolveError::NotBracketed {
+            fa,
+            fb,
+            subinterval: None,
+        }
+    })
+}
+
+/// Solve `f(x) = 0` for a system of equations with Newton's method, starting from `x0`.
+pub(crate) fn find_system_root<F: Fn(&[Real]) -> Result<Vec<Real>, SolveError>>(
+    f: F,
+    x0: &[Real],
+) -> Result<Vec<Real>, SolveError> {
+    let mut x = x0.to_vec();
+    let mut fx = eval_system(&f, &x)?;
+    let mut residual = norm(&fx);
+    for _ in 0..MAX_ITERATIONS {
+        if residual <= TOLERANCE {
+            return Ok(x);
+        }
+        let mut jac = jacobian(&f, &x)?;
+        let mut rhs = fx.iter().map(|y| -y).collect::<Vec<_>>();
+        let dx = match solve_linear(&mut jac, &mut rhs) {
+            Some(dx) => dx,
+            None => return Err(SolveError::SingularJacobian),
+        };
+        // backtrack until the residual decreases
+        let mut t = 1.0;
+        loop {
+            let xt = x
+                .iter()
+                .zip(&dx)
+                .map(|(x, d)| x + t * d)
+                .collect::<Vec<_>>();
+            let ft = eval_system(&f, &xt)?;
+            let rt = norm(&ft);
+            if rt < residual || t < 1e-4 {
+                x = xt;
+                fx = ft;
+                residual = rt;
+                break;
+            }
+            t *= 0.5;
+        }
+        if norm(&dx) * t <= TOLERANCE * (1.0 + norm(&x)) {
+            break;
+        }
+    }
+    match residual <= TOLERANCE.sqrt() {
+        true => Ok(x),
+        false => Err(SolveError::SystemNoConvergence { residual }),
+    }
+}
+
+/// Minimize the sum of squared `residuals(p)` with the Levenberg-Marquardt method,
+/// starting from `p0`.
+pub(crate) fn least_squares<F: Fn(&[Real]) -> Result<Vec<Real>, SolveError>>(
+    residuals: F,
+    p0: &[Real],
+) -> Result<Vec<Real>, SolveError> {
+    let n = p0.len();
+    let mut p = p0.to_vec();
+    let mut r = eval_system(&residuals, &p)?;
+    let mut cost = norm(&r);
+    let mut lambda = 1e-3;
+    for _ in 0..MAX_ITERATIONS * 10 {
+        let jac = jacobian(&residuals, &p)?;
+        // normal equations (J'J + lambda * diag(J'J)) dp = -J'r
+        let jtj = (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| jac.iter().map(|row| row[i] * row[j]).sum())
+                    .collect::<Vec<Real>>()
+            })
+            .collect::<Vec<_>>();
+        let jtr = (0..n)
+            .map(|i| -jac.iter().zip(&r).map(|(row, r)| row[i] * r).sum::<Real>())
+            .collect::<Vec<_>>();
+        loop {
+            let mut a = jtj.clone();
+            for (i, row) in a.iter_mut().enumerate() {
+                row[i] += lambda * jtj[i][i].max(Real::EPSILON);
+            }
+            let dp = match solve_linear(&mut a, &mut jtr.clone()) {
+                Some(dp) => dp,
+                None => return Err(SolveError::SingularJacobian),
+            };
+            let pt = p.iter().zip(&dp).map(|(p, d)| p + d).collect::<Vec<_>>();
+            let rt = eval_system(&residuals, &pt)?;
+            let ct = norm(&rt);
+            if ct < cost {
+                let converged = cost - ct <= TOLERANCE * (1.0 + cost)
+                    || norm(&dp) <= TOLERANCE * (1.0 + norm(&p));
+                p = pt;
+                r = rt;
+                cost = ct;
+                lambda = (lambda * 0.1).max(1e-12);
+                if converged {
+                    return Ok(p);
+                }
+                break;
+            }
+            lambda *= 10.0;
+            if lambda > 1e12 {
+                // no downhill step left: a local minimum
+                return Ok(p);
+            }
+        }
+    }
+    Err(SolveError::SystemNoConvergence { residual: cost })
+}
+
+fn eval_system<F: Fn(&[Real]) -> Result<Vec<Real>, SolveError>>(
+    f: &F,
+    x: &[Real],
+) -> Result<Vec<Real>, SolveError> {
+    let y = f(x)?;
+    match y.iter().any(|y| y.is_nan()) {
+        true => Err(SolveError::NotANumber { x: x.to_vec() }),
+        false => Ok(y),
+    }
+}
+
+fn norm(v: &[Real]) -> Real {
+    v.iter().map(|x| x * x).sum::<Real>().sqrt()
+}
+
+/// Central-difference Jacobian of `f` at `x`, one row per output.
+pub(crate) fn jacobian<F: Fn(&[Real]) -> Result<Vec<Real>, SolveError>>(
+    f: &F,
+    x: &[Real],
+) -> Result<Vec<Vec<Real>>, SolveError> {
+    differences(|x| eval_system(f, x), x)
+}
+
+/// `jacobian` without rejecting NaN, `f` giving as many outputs at every point.
+pub(crate) fn differences<E, F: FnMut(&[Real]) -> Result<Vec<Real>, E>>(
+    mut f: F,
+    x: &[Real],
+) -> Result<Vec<Vec<Real>>, E> {
+    let mut columns = Vec::with_capacity(x.len());
+    let mut xh = x.to_vec();
+    for j in 0..x.len() {
+        let h = Real::EPSILON.cbrt() * x[j].abs().max(1.0);
+        xh[j] = x[j] + h;
+        let f1 = f(&xh)?;
+        xh[j] = x[j] - h;
+        let f2 = f(&xh)?;
+        xh[j] = x[j];
+        columns.push(
+            f1.iter()
+                .zip(&f2)
+                .map(|(y1, y2)| (y1 - y2) / (2.0 * h))
+                .collect::<Vec<_>>(),
+        );
+    }
+    let rows = columns.first().map_or(0, |c| c.len());
+    Ok((0..rows)
+        .map(|i| columns.iter().map(|c| c[i]).collect())
+        .collect())
+}
+
+/// Gaussian elimination with partial pivoting; `None` for a singular matrix.
+pub(crate) fn solve_linear(a: &mut [Vec<Real>], b: &mut [Real]) -> Option<Vec<Real>> {
+    let n = b.len();
+    for k in 0..n {
+        let p = (k..n).max_by(|&i, &j| a[i][k].abs().total_cmp(&a[j][k].abs()))?;
+        if a[p][k].abs() <= Real::EPSILON * 16.0 {
+            return None;
+        }
+        a.swap(k, p);
+        b.swap(k, p);
+        let (upper, lower) = a.split_at_mut(k + 1);
+        let pivot = &upper[k];
+        for (i, row) in lower.iter_mut().enumerate() {
+            let factor = row[k] / pivot[k];
+            for (x, p) in row[k..].iter_mut().zip(&pivot[k..]) {
+                *x -= factor * p;
+            }
+            b[k + 1 + i] -= factor * b[k];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for i in (0..n).rev() {
+        let sum: Real = (i + 1..n).map(|j| a[i][j] * x[j]).sum();
+        x[i] = (b[i] - sum) / a[i][i];
+    }
+    Some(x)
+}
@@ -0,0 +1,144 @@
+//! Statistics over vectors
+
+use crate::{
+    value::{EvalError, Value},
+    Real,
+};
+
+/// Running mean and sum of squared deviations (Welford's algorithm).
+fn welford(v: &[Real]) -> (Real, Real) {
+    let (mut mean, mut m2) = (0.0, 0.0);
+    for (i, x) in v.iter().enumerate() {
+        let delta = x - mean;
+        mean += delta / (i + 1) as Real;
+        m2 += delta * (x - mean);
+    }
+    (mean, m2)
+}
+
+/// Sum with a running compensation for the low-order bits lost (Neumaier's algorithm).
+fn neumaier<I: IntoIterator<Item = Real>>(xs: I) -> Real {
+    let (mut sum, mut c) = (0.0, 0.0);
+    for x in xs {
+        let t = sum + x;
+        c += match sum.abs() >= x.abs() {
+            true => (sum - t) + x,
+            false => (x - t) + sum,
+        };
+        sum = t;
+    }
+    sum + c
+}
+
+/// Compensated mean and sum of squared deviations (corrected two-pass algorithm).
+fn two_pass(v: &[Real]) -> (Real, Real) {
+    let mean = neumaier(v.iter().copied()) / v.len() as Real;
+    let m2 = neumaier(v.iter().map(|x| (x - mean) * (x - mean)));
+    let drift = neumaier(v.iter().map(|x| x - mean));
+    (mean, m2 - drift * drift / v.len() as Real)
+}
+
+fn samples(val: &Value, min_len: usize) -> Result<&[Real], EvalError> {
+    let v = val.vector()?;
+    match v.len() < min_len {
+        true => Err(EvalError::InvalidArgument {
+            expect: match min_len {
+                1 => "non-empty vector",
+                _ => "at least 2 elements",
+            },
+        }),
+        false => Ok(v),
+    }
+}
+
+fn sorted(v: &[Real]) -> Vec<Real> {
+    let mut v = v.to_vec();
+    v.sort_by(Real::total_cmp);
+    v
+}
+
+pub(crate) fn sum(args: &[Value]) -> Result<Value, EvalError> {
+    Ok(Value::Num(neumaier(args[0].vector()?.iter().copied())))
+}
+
+pub(crate) fn fast_sum(args: &[Value]) -> Result<Value, EvalError> {
+    Ok(Value::Num(args[0].vector()?.iter().sum()))
+}
+
+pub(crate) fn mean(args: &[Value]) -> Result<Value, EvalError> {
+    Ok(Value::Num(two_pass(samples(&args[0], 1)?).0))
+}
+
+pub(crate) fn fast_mean(args: &[Value]) -> Result<Value, EvalError> {
+    Ok(Value::Num(welford(samples(&args[0], 1)?).0))
+}
+
+pub(crate) fn median(args: &[Value]) -> Result<Value, EvalError> {
+    let v = sorted(samples(&args[0], 1)?);
+    let n = v.len();
+    Ok(Value::Num(match n % 2 {
+        0 => 0.5 * (v[n / 2 - 1] + v[n / 2]),
+        _ => v[n / 2],
+    }))
+}
+
+/// Sample variance.
+pub(crate) fn var(args: &[Value]) -> Result<Value, EvalError> {
+    let v = samples(&args[0], 2)?;
+    Ok(Value::Num(two_pass(v).1 / (v.len() - 1) as Real))
+}
+
+pub(crate) fn fast_var(args: &[Value]) -> Result<Value, EvalError> {
+    let v = samples(&args[0], 2)?;
+    Ok(Value::Num(welford(v).1 / (v.len() - 1) as Real))
+}
+
+pub(crate) fn std(args: &[Value]) -> Result<Value, EvalError> {
+    Ok(var(args)?.map(Real::sqrt))
+}
+
+pub(crate) fn fast_std(args: &[Value]) -> Result<Value, EvalError> {
+    Ok(fast_var(args)?.map(Real::sqrt))
+}
+
+/// Quantile with linear interpolation between order statistics.
+pub(crate) fn quantile(args: &[Value]) -> Result<Value, EvalError> {
+    let v = sorted(samples(&args[0], 1)?);
+    let p = &args[1];
+    if !p.all(|p| (0.0..=1.0).contains(&p))? {
+        return Err(EvalError::InvalidArgument {
+            expect: "probability in [0, 1]",
+        });
+    }
+    Ok(p.map(|p| {
+        let h = (v.len() - 1) as Real * p;
+        let k = (h.floor() as usize).min(v.len() - 1);
+        match v.get(k + 1) {
+            Some(next) => v[k] + (h - k as Real) * (next - v[k]),
+            None => v[k],
+        }
+    }))
+}
+
+/// Pearson correlation coefficient.
+pub(crate) fn corr(args: &[Value]) -> Result<Value, EvalError> {
+    let (xs, ys) = (samples(&args[0], 2)?, samples(&args[1], 2)?);
+    if xs.len() != ys.len() {
+        return Err(EvalError::LengthMismatch {
+            left: xs.len(),
+            right: ys.len(),
+        });
+    }
+    let (mut mx, mut my, mut cxx, mut cyy, mut cxy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (i, (x, y)) in xs.iter().zip(ys).enumerate() {
+        let n = (i + 1) as Real;
+        let dx = x - mx;
+        let dy = y - my;
+        mx += dx / n;
+        my += dy / n;
+        cxx += dx * (x - mx);
+        cyy += dy * (y - my);
+        cxy += dx * (y - my);
+    }
+    Ok(Value::Num(cxy / (cxx * cyy).sqrt()))
+}
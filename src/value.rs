@@ -0,0 +1,410 @@
+//! Values
+
+use std::{convert::TryFrom, fmt, sync::Arc};
+
+use crate::{
+    lexer::{CompareOp, Literal},
+    numeric::{digits, Numeric},
+    Real,
+};
+
+/// A value, its numbers of the number type of the interpreter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value<T = Real> {
+    Bool(bool),
+    Int(i64),
+    Num(T),
+    Vector(Arc<[T]>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    TypeMismatch {
+        expect: &'static str,
+        found: &'static str,
+    },
+    LengthMismatch {
+        left: usize,
+        right: usize,
+    },
+    InvalidArgument {
+        expect: &'static str,
+    },
+    /// A NaN or infinite result in strict mode, with the step producing it.
+    NonFinite {
+        step: String,
+    },
+    /// An evaluation exceeding the step count or call depth of its budget.
+    BudgetExceeded {
+        limit: &'static str,
+    },
+    /// A numerical method of a statement like `fit` finding no solution.
+    Unsolved {
+        reason: String,
+    },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::TypeMismatch { expect, found } => {
+                write!(f, "Type Mismatch: expect {}, found {}", expect, found)
+            }
+            EvalError::LengthMismatch { left, right } => {
+                write!(f, "Length Mismatch: {} and {}", left, right)
+            }
+            EvalError::InvalidArgument { expect } => {
+                write!(f, "Invalid Argument: expect {}", expect)
+            }
+            EvalError::NonFinite { step } => write!(f, "Non-Finite Result: {}", step),
+            EvalError::BudgetExceeded { limit } => write!(f, "Budget Exceeded: {}", limit),
+            EvalError::Unsolved { reason } => write!(f, "Unsolved: {}", reason),
+        }
+    }
+}
+
+impl<T: Numeric> fmt::Display for Value<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Num(r) => write!(f, "{}", r),
+            Value::Vector(v) => {
+                f.write_str("[")?;
+                for (i, r) in v.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", r)?;
+                }
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+impl<T> From<bool> for Value<T> {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl<T> From<i64> for Value<T> {
+    fn from(i: i64) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl<T: Numeric> From<T> for Value<T> {
+    fn from(r: T) -> Self {
+        Value::Num(r)
+    }
+}
+
+impl<T: Numeric> From<Vec<T>> for Value<T> {
+    fn from(v: Vec<T>) -> Self {
+        Value::Vector(v.into())
+    }
+}
+
+impl<T: Numeric> Value<T> {
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Value::Bool(_) => "boolean",
+            Value::Int(_) => "integer",
+            Value::Num(_) => "number",
+            Value::Vector(_) => "vector",
+        }
+    }
+
+    pub(crate) fn num(&self) -> Result<T, EvalError> {
+        match self {
+            Value::Int(i) => Ok(T::from_i64(*i)),
+            Value::Num(r) => Ok(*r),
+            _ => Err(self.mismatch("number")),
+        }
+    }
+
+    pub(crate) fn vector(&self) -> Result<&[T], EvalError> {
+        match self {
+            Value::Vector(v) => Ok(v),
+            _ => Err(self.mismatch("vector")),
+        }
+    }
+
+    /// This value with its numbers as `Real`, for the builtins of the libraries.
+    pub(crate) fn to_real(&self) -> Value {
+        match self {
+            Value::Bool(b) => Value::Bool(*b),
+            Value::Int(i) => Value::Int(*i),
+            Value::Num(r) => Value::Num(r.to_f64()),
+            Value::Vector(v) => Value::Vector(T::vector_to_f64(v)),
+        }
+    }
+
+    /// The value `val` of a builtin of the libraries with its numbers of this type.
+    pub(crate) fn from_real(val: Value) -> Self {
+        match val {
+            Value::Bool(b) => Value::Bool(b),
+            Value::Int(i) => Value::Int(i),
+            Value::Num(r) => Value::Num(T::from_f64(r)),
+            Value::Vector(v) => Value::Vector(T::vector_from_f64(v)),
+        }
+    }
+
+    /// The value of a number literal, a fraction parsed from its digits.
+    pub(crate) fn literal(lit: &Literal) -> Self {
+        let parsed = lit.decimal.as_ref().and_then(|(digits, degrees)| {
+            let r = T::parse(digits)?;
+            match degrees {
+                true => Some(r * (T::parse(digits::PI)? / T::from_i64(180))),
+                false => Some(r),
+            }
+        });
+        match parsed {
+            Some(r) => Value::Num(r),
+            None => Value::from_real(lit.value.clone()),
+        }
+    }
+
+    fn mismatch(&self, expect: &'static str) -> EvalError {
+        EvalError::TypeMismatch {
+            expect,
+            found: self.kind(),
+        }
+    }
+
+    /// The truth of a boolean, or of a number being non-zero.
+    pub(crate) fn truth(&self) -> Result<bool, EvalError> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            Value::Vector(_) => Err(self.mismatch("boolean")),
+            val => Ok(val.num()? != T::ZERO),
+        }
+    }
+
+    /// Whether the number, or every element of the vector, satisfies `f`.
+    pub(crate) fn all<F: Fn(T) -> bool>(&self, f: F) -> Result<bool, EvalError> {
+        Ok(match self {
+            Value::Bool(_) => return Err(self.mismatch("number")),
+            Value::Int(i) => f(T::from_i64(*i)),
+            Value::Num(r) => f(*r),
+            Value::Vector(v) => v.iter().all(|r| f(*r)),
+        })
+    }
+
+    /// Whether the number, or any element of the vector, is NaN or infinite.
+    pub(crate) fn is_non_finite(&self) -> bool {
+        match self {
+            Value::Num(r) => !r.is_finite(),
+            Value::Vector(v) => v.iter().any(|r| !r.is_finite()),
+            _ => false,
+        }
+    }
+
+    pub(crate) fn map<F: Fn(T) -> T>(&self, f: F) -> Value<T> {
+        match self {
+            Value::Bool(b) => Value::Num(f(T::from_i64(*b as i64))),
+            Value::Int(i) => Value::Num(f(T::from_i64(*i))),
+            Value::Num(r) => Value::Num(f(*r)),
+            Value::Vector(v) => Value::Vector(v.iter().map(|r| f(*r)).collect()),
+        }
+    }
+
+    pub(crate) fn zip<F: Fn(T, T) -> T>(
+        &self,
+        other: &Value<T>,
+        f: F,
+    ) -> Result<Value<T>, EvalError> {
+        Ok(match (self, other) {
+            (Value::Vector(v1), Value::Vector(v2)) => {
+                if v1.len() != v2.len() {
+                    return Err(EvalError::LengthMismatch {
+                        left: v1.len(),
+                        right: v2.len(),
+                    });
+                }
+                Value::Vector(
+                    v1.iter()
+                        .zip(v2.iter())
+                        .map(|(r1, r2)| f(*r1, *r2))
+                        .collect(),
+                )
+            }
+            (Value::Vector(v1), val) => {
+                let r2 = val.num()?;
+                Value::Vector(v1.iter().map(|r1| f(*r1, r2)).collect())
+            }
+            (val, Value::Vector(v2)) => {
+                let r1 = val.num()?;
+                Value::Vector(v2.iter().map(|r2| f(r1, *r2)).collect())
+            }
+            (v1, v2) => Value::Num(f(v1.num()?, v2.num()?)),
+        })
+    }
+
+    /// Apply `int` to two integers, falling back to `f` on overflow or for other values.
+    fn arith<F: Fn(T, T) -> T>(
+        &self,
+        other: &Value<T>,
+        int: fn(i64, i64) -> Option<i64>,
+        f: F,
+    ) -> Result<Value<T>, EvalError> {
+        if let (Value::Int(i1), Value::Int(i2)) = (self, other) {
+            if let Some(i) = int(*i1, *i2) {
+                return Ok(Value::Int(i));
+            }
+        }
+        self.zip(other, f)
+    }
+
+    pub(crate) fn add(&self, other: &Value<T>) -> Result<Value<T>, EvalError> {
+        self.arith(other, i64::checked_add, |r1, r2| r1 + r2)
+    }
+
+    pub(crate) fn sub(&self, other: &Value<T>) -> Result<Value<T>, EvalError> {
+        self.arith(other, i64::checked_sub, |r1, r2| r1 - r2)
+    }
+
+    pub(crate) fn mul(&self, other: &Value<T>) -> Result<Value<T>, EvalError> {
+        self.arith(other, i64::checked_mul, |r1, r2| r1 * r2)
+    }
+
+    /// Division always produces a floating point result.
+    pub(crate) fn div(&self, other: &Value<T>) -> Result<Value<T>, EvalError> {
+        self.zip(other, |r1, r2| r1 / r2)
+    }
+
+    pub(crate) fn pow(&self, other: &Value<T>) -> Result<Value<T>, EvalError> {
+        self.arith(
+            other,
+            |i1, i2| i1.checked_pow(u32::try_from(i2).ok()?),
+            T::powf,
+        )
+    }
+
+    pub(crate) fn neg(&self) -> Result<Value<T>, EvalError> {
+        Ok(match self {
+            Value::Bool(_) => return Err(self.mismatch("number")),
+            Value::Int(i) => match i.checked_neg() {
+                Some(i) => Value::Int(i),
+                None => Value::Num(-T::from_i64(*i)),
+            },
+            val => val.map(|r| -r),
+        })
+    }
+
+    /// Compare two integers exactly, other numbers as floating point numbers.
+    ///
+    /// Vectors are compared element by element into vectors of `1` and `0`.
+    pub(crate) fn compare(&self, other: &Value<T>, cmp: CompareOp) -> Result<Value<T>, EvalError> {
+        let ord = match (self, other) {
+            (Value::Vector(_), _) | (_, Value::Vector(_)) => {
+                return self.zip(other, |r1, r2| T::from_i64(cmp.on(r1, r2)))
+            }
+            (Value::Bool(b1), Value::Bool(b2)) if cmp == CompareOp::EQ || cmp == CompareOp::NE => {
+                cmp.on(b1, b2)
+            }
+            (Value::Int(i1), Value::Int(i2)) => cmp.on(i1, i2),
+            (v1, v2) => cmp.on(v1.num()?, v2.num()?),
+        };
+        Ok(match cmp {
+            CompareOp::CMP => Value::Int(ord),
+            _ => Value::Bool(ord != 0),
+        })
+    }
+
+    /// Whether two numbers differ by at most `tol` times the larger magnitude, or `tol` below
+    /// magnitude 1, element by element into `1` and `0` over vectors.
+    pub(crate) fn approx(&self, other: &Value<T>, tol: &Value<T>) -> Result<Value<T>, EvalError> {
+        let tol = tol.num()?;
+        let close = move |r1: T, r2: T| {
+            r1 == r2 || (r1 - r2).abs() <= tol * r1.abs().max(r2.abs()).max(T::ONE)
+        };
+        match (self, other) {
+            (Value::Vector(_), _) | (_, Value::Vector(_)) => {
+                self.zip(other, |r1, r2| T::from_i64(close(r1, r2) as i64))
+            }
+            (v1, v2) => Ok(Value::Bool(close(v1.num()?, v2.num()?))),
+        }
+    }
+
+    /// Logical negation, element by element into `1` and `0` over a vector.
+    pub(crate) fn not(&self) -> Result<Value<T>, EvalError> {
+        match self {
+            Value::Vector(_) => Ok(self.map(|r| T::from_i64((r == T::ZERO) as i64))),
+            val => Ok(Value::Bool(!val.truth()?)),
+        }
+    }
+
+    fn logic(&self, other: &Value<T>, f: fn(bool, bool) -> bool) -> Result<Value<T>, EvalError> {
+        match (self, other) {
+            (Value::Vector(_), _) | (_, Value::Vector(_)) => self.zip(other, |r1, r2| {
+                T::from_i64(f(r1 != T::ZERO, r2 != T::ZERO) as i64)
+            }),
+            (v1, v2) => Ok(Value::Bool(f(v1.truth()?, v2.truth()?))),
+        }
+    }
+
+    /// Whether a scalar of truth `stop` decides `||` (`stop` true) or `&&` (`stop` false)
+    /// without the right operand.
+    pub(crate) fn short_circuits(&self, stop: bool) -> Result<bool, EvalError> {
+        match self {
+            Value::Vector(_) => Ok(false),
+            val => Ok(val.truth()? == stop),
+        }
+    }
+
+    pub(crate) fn or(&self, other: &Value<T>) -> Result<Value<T>, EvalError> {
+        self.logic(other, |b1, b2| b1 || b2)
+    }
+
+    pub(crate) fn and(&self, other: &Value<T>) -> Result<Value<T>, EvalError> {
+        self.logic(other, |b1, b2| b1 && b2)
+    }
+
+    /// Apply a scalar function to arguments, element by element over vector arguments.
+    pub(crate) fn broadcast<F: Fn(&[T]) -> T>(
+        args: &[Value<T>],
+        f: F,
+    ) -> Result<Value<T>, EvalError> {
+        enum Operand<'a, T> {
+            Number(T),
+            Elements(&'a [T]),
+        }
+        let mut len = None;
+        let mut operands = Vec::with_capacity(args.len());
+        for arg in args {
+            operands.push(match arg {
+                Value::Int(i) => Operand::Number(T::from_i64(*i)),
+                Value::Num(r) => Operand::Number(*r),
+                Value::Vector(v) => {
+                    match len {
+                        Some(n) if n != v.len() => {
+                            return Err(EvalError::LengthMismatch {
+                                left: n,
+                                right: v.len(),
+                            })
+                        }
+                        _ => len = Some(v.len()),
+                    }
+                    Operand::Elements(v)
+                }
+                Value::Bool(_) => return Err(arg.mismatch("number")),
+            });
+        }
+        let at = |i: usize| {
+            operands
+                .iter()
+                .map(|operand| match operand {
+                    Operand::Number(r) => *r,
+                    Operand::Elements(v) => v[i],
+                })
+                .collect::<Vec<_>>()
+        };
+        Ok(match len {
+            None => Value::Num(f(&at(0))),
+            Some(n) => Value::Vector((0..n).map(|i| f(&at(i))).collect()),
+        })
+    }
+}
@@ -0,0 +1,56 @@
+//! `grad` and `jacobian` of defined functions, from expressions
+
+use mfni::{InputError, Interpreter, Value};
+
+fn vector(itp: &mut Interpreter, expression: &str) -> Vec<f64> {
+    match itp.eval_pure(expression).unwrap() {
+        Value::Vector(v) => v.to_vec(),
+        value => panic!("{} is not a vector", value),
+    }
+}
+
+fn assert_close(found: &[f64], expect: &[f64]) {
+    assert_eq!(found.len(), expect.len());
+    for (x, y) in found.iter().zip(expect) {
+        assert!((x - y).abs() < 1e-6, "{:?} != {:?}", found, expect);
+    }
+}
+
+#[test]
+fn gradient() {
+    let mut itp = Interpreter::new();
+    itp.input(b"g: x, y = x^2 * y").unwrap();
+    assert_close(&vector(&mut itp, "grad(g, 1, 2)"), &[4.0, 1.0]);
+    assert_close(&vector(&mut itp, "grad(sin, 0)"), &[1.0]);
+    assert_close(&vector(&mut itp, "2 * grad(g, 1, 1 + 1)"), &[8.0, 2.0]);
+    // the point is evaluated like any other argument
+    itp.input(b"slope: x = grad(g, x, 3)").unwrap();
+    assert_close(&vector(&mut itp, "slope(2)"), &[12.0, 4.0]);
+}
+
+#[test]
+fn jacobian_rows() {
+    let mut itp = Interpreter::new();
+    itp.input(b"r: x, y = x * (1..2) + y^2").unwrap();
+    assert_close(
+        &vector(&mut itp, "jacobian(r, 1, 3)"),
+        &[1.0, 6.0, 2.0, 6.0],
+    );
+    itp.input(b"g: x, y = x * y").unwrap();
+    assert_close(&vector(&mut itp, "jacobian(g, 2, 5)"), &[5.0, 2.0]);
+}
+
+#[test]
+fn function_arguments() {
+    let mut itp = Interpreter::new();
+    itp.input(b"g: x, y = x * y").unwrap();
+    assert!(matches!(
+        itp.eval_pure("grad(g, 1)"),
+        Err(InputError::InconsistentVariablesCount { .. })
+    ));
+    assert!(matches!(
+        itp.eval_pure("grad(g)"),
+        Err(InputError::InconsistentVariablesCount { .. })
+    ));
+    assert!(itp.eval_pure("grad(1 + 1, 2)").is_err());
+}
@@ -0,0 +1,26 @@
+//! Evaluation paths agreeing on generated expressions
+
+#![cfg(feature = "harness")]
+
+use mfni::harness::Harness;
+
+fn run(seed: u64) {
+    if let Err(discrepancy) = Harness::new().run(seed, 20_000, 5) {
+        panic!("seed {}: {}", seed, discrepancy);
+    }
+}
+
+#[test]
+fn seed_1() {
+    run(1);
+}
+
+#[test]
+fn seed_7() {
+    run(7);
+}
+
+#[test]
+fn seed_42() {
+    run(42);
+}
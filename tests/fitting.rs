@@ -0,0 +1,67 @@
+//! `fit(f, xs, ys)` statements fitting the parameters of defined functions to data vectors
+
+use mfni::{Assigned, EvalError, InputError, InputState, Interpreter, Value};
+
+fn number(itp: &mut Interpreter, expression: &str) -> f64 {
+    match itp.eval_pure(expression).unwrap() {
+        Value::Num(x) => x,
+        value => panic!("{} is not a number", value),
+    }
+}
+
+fn assert_close(x: f64, y: f64) {
+    assert!((x - y).abs() < 1e-6, "{} != {}", x, y);
+}
+
+#[test]
+fn fitted_definitions() {
+    let mut itp = Interpreter::new();
+    itp.input(b"line: x, a, b = a * x + b").unwrap();
+    itp.input(b"xs = 0..4").unwrap();
+    itp.input(b"ys = 2 * xs + 1").unwrap();
+    match itp.input(b"fit(line, xs, ys)") {
+        Ok(InputState::Assignment { name, assigned, .. }) => {
+            assert_eq!(name, "line_fit");
+            assert_eq!(assigned, Assigned::Function { arity: 1 });
+        }
+        _ => panic!("fit is not an assignment"),
+    }
+    assert_close(number(&mut itp, "a"), 2.0);
+    assert_close(number(&mut itp, "b"), 1.0);
+    assert_close(number(&mut itp, "line_fit(10)"), 21.0);
+    let changed = itp
+        .changes()
+        .iter()
+        .map(|change| change.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(changed, ["a", "b", "line_fit"]);
+
+    itp.input(b"decay: t, k = e^(-k * t)").unwrap();
+    itp.input(b"fit(decay, xs, e^(-0.5 * xs));").unwrap();
+    assert_close(number(&mut itp, "k"), 0.5);
+}
+
+#[test]
+fn fit_errors() {
+    let mut itp = Interpreter::new();
+    itp.input(b"line: x, a, b = a * x + b").unwrap();
+    assert!(matches!(
+        itp.input(b"fit(line, 0..3, 0..4)"),
+        Err(InputError::EvalError(EvalError::LengthMismatch { .. }, _))
+    ));
+    assert!(matches!(
+        itp.input(b"fit(line, 1..1, 1..1)"),
+        Err(InputError::EvalError(EvalError::Unsolved { .. }, _))
+    ));
+    assert!(matches!(
+        itp.input(b"fit(sin, 0..3, 0..3)"),
+        Err(InputError::EvalError(EvalError::InvalidArgument { .. }, _))
+    ));
+    assert!(matches!(
+        itp.input(b"fit(line, 0..3)"),
+        Err(InputError::InconsistentVariablesCount { .. })
+    ));
+    assert!(itp.input(b"1 + fit(line, 0..3, 0..3)").is_err());
+    assert!(itp.input(b"fit: x = x").is_err());
+    assert!(itp.eval_pure("a").is_err());
+}
@@ -0,0 +1,224 @@
+//! A Jupyter client talking to the kernel over its sockets
+
+#![cfg(feature = "jupyter")]
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use hmac_sha256::HMAC;
+use mfni::{
+    jupyter::{self, Connection},
+    Interpreter,
+};
+use serde_json::{json, Value as Json};
+
+const KEY: &str = "c0ffee";
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// A client socket of `socket_type` connected to `port`, retried until the kernel listens.
+fn connect(port: u16, socket_type: &str) -> TcpStream {
+    let mut stream = (0..100)
+        .find_map(|_| {
+            TcpStream::connect(("127.0.0.1", port))
+                .map_err(|_| thread::sleep(Duration::from_millis(20)))
+                .ok()
+        })
+        .unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let mut greeting = [0; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    stream.write_all(&greeting).unwrap();
+    let mut ready = b"\x05READY\x0bSocket-Type".to_vec();
+    ready.extend_from_slice(&(socket_type.len() as u32).to_be_bytes());
+    ready.extend_from_slice(socket_type.as_bytes());
+    stream.write_all(&[4, ready.len() as u8]).unwrap();
+    stream.write_all(&ready).unwrap();
+    let mut peer = [0; 64];
+    stream.read_exact(&mut peer).unwrap();
+    assert_eq!(&peer[12..16], b"NULL");
+    let ready = receive_frames(&mut stream);
+    assert!(ready[0].starts_with(b"\x05READY"));
+    stream
+}
+
+fn send_frames(stream: &mut TcpStream, frames: &[Vec<u8>]) {
+    for (i, frame) in frames.iter().enumerate() {
+        let more = (i + 1 < frames.len()) as u8;
+        stream.write_all(&[more | 2]).unwrap();
+        stream
+            .write_all(&(frame.len() as u64).to_be_bytes())
+            .unwrap();
+        stream.write_all(frame).unwrap();
+    }
+}
+
+fn receive_frames(stream: &mut TcpStream) -> Vec<Vec<u8>> {
+    let mut frames = vec![];
+    loop {
+        let mut flags = [0];
+        stream.read_exact(&mut flags).unwrap();
+        let size = match flags[0] & 2 {
+            0 => {
+                let mut size = [0];
+                stream.read_exact(&mut size).unwrap();
+                size[0] as usize
+            }
+            _ => {
+                let mut size = [0; 8];
+                stream.read_exact(&mut size).unwrap();
+                u64::from_be_bytes(size) as usize
+            }
+        };
+        let mut frame = vec![0; size];
+        stream.read_exact(&mut frame).unwrap();
+        frames.push(frame);
+        if flags[0] & 1 == 0 {
+            return frames;
+        }
+    }
+}
+
+fn sign(parts: &[Vec<u8>]) -> Vec<u8> {
+    let mut mac = HMAC::new(KEY);
+    for part in parts {
+        mac.update(part);
+    }
+    let mac = mac.finalize();
+    let hex = mac.iter().map(|b| format!("{:02x}", b));
+    hex.collect::<String>().into_bytes()
+}
+
+fn request(stream: &mut TcpStream, msg_type: &str, content: Json) {
+    let header = json!({ "msg_id": msg_type, "session": "test", "msg_type": msg_type });
+    let parts = [header, json!({}), json!({}), content].map(|p| p.to_string().into_bytes());
+    let mut frames = vec![b"<IDS|MSG>".to_vec(), sign(&parts)];
+    frames.extend(parts);
+    send_frames(stream, &frames);
+}
+
+/// The type and content of the next message, checking its signature.
+fn receive(stream: &mut TcpStream) -> (String, Json) {
+    let frames = receive_frames(stream);
+    let delimiter = frames.iter().position(|f| f == b"<IDS|MSG>").unwrap();
+    let parts = &frames[delimiter + 2..delimiter + 6];
+    assert_eq!(frames[delimiter + 1], sign(parts));
+    let header: Json = serde_json::from_slice(&parts[0]).unwrap();
+    let content = serde_json::from_slice(&parts[3]).unwrap();
+    (header["msg_type"].as_str().unwrap().to_string(), content)
+}
+
+/// The content of the next message of `msg_type` published, skipping others.
+fn published(iopub: &mut TcpStream, msg_type: &str) -> Json {
+    loop {
+        let (t, content) = receive(iopub);
+        if t == msg_type {
+            return content;
+        }
+    }
+}
+
+#[test]
+fn session() {
+    let connection = Connection::parse(
+        &json!({
+            "transport": "tcp",
+            "ip": "127.0.0.1",
+            "signature_scheme": "hmac-sha256",
+            "key": KEY,
+            "shell_port": free_port(),
+            "iopub_port": free_port(),
+            "stdin_port": free_port(),
+            "control_port": free_port(),
+            "hb_port": free_port(),
+        })
+        .to_string(),
+    )
+    .unwrap();
+    let kernel = {
+        let connection = connection.clone();
+        thread::spawn(move || jupyter::run(&connection, Interpreter::new()))
+    };
+
+    let mut heartbeat = connect(connection.hb_port, "REQ");
+    send_frames(&mut heartbeat, &[vec![], b"ping".to_vec()]);
+    assert_eq!(receive_frames(&mut heartbeat), [vec![], b"ping".to_vec()]);
+
+    let mut iopub = connect(connection.iopub_port, "SUB");
+    send_frames(&mut iopub, &[vec![1]]);
+    let mut shell = connect(connection.shell_port, "DEALER");
+    // published once the kernel has taken the subscriber
+    loop {
+        request(&mut shell, "kernel_info_request", json!({}));
+        let (reply, info) = receive(&mut shell);
+        assert_eq!(reply, "kernel_info_reply");
+        assert_eq!(info["language_info"]["name"], "mfni");
+        iopub
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let subscribed = iopub.peek(&mut [0]).is_ok();
+        iopub
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        if subscribed {
+            published(&mut iopub, "status");
+            published(&mut iopub, "status");
+            break;
+        }
+    }
+
+    let code = "a = 0.5\nb = 2 * ...\n  a\n-(a * 3 + b) / 0";
+    request(&mut shell, "execute_request", json!({ "code": code }));
+    let (reply, content) = receive(&mut shell);
+    assert_eq!(reply, "execute_reply");
+    assert_eq!(content["status"], "ok");
+    assert_eq!(content["execution_count"], 1);
+    assert_eq!(published(&mut iopub, "execute_input")["code"], code);
+    assert_eq!(published(&mut iopub, "stream")["text"], "a = 0.5\nb = 1\n");
+    let result = published(&mut iopub, "execute_result");
+    assert_eq!(result["data"]["text/plain"], "-inf");
+    assert_eq!(result["data"]["text/latex"], r"$-\infty$");
+
+    request(&mut shell, "execute_request", json!({ "code": "b + qq" }));
+    let (_, content) = receive(&mut shell);
+    assert_eq!(content["status"], "error");
+    assert_eq!(content["execution_count"], 2);
+    assert!(content["evalue"].as_str().unwrap().contains("qq"));
+    assert_eq!(published(&mut iopub, "error")["ename"], content["ename"]);
+
+    request(
+        &mut shell,
+        "complete_request",
+        json!({ "code": "y = sq", "cursor_pos": 6 }),
+    );
+    let (_, content) = receive(&mut shell);
+    assert_eq!(content["cursor_start"], 4);
+    assert!(content["matches"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("sqrt")));
+
+    let mut control = connect(connection.control_port, "DEALER");
+    request(
+        &mut control,
+        "shutdown_request",
+        json!({ "restart": false }),
+    );
+    assert_eq!(receive(&mut control).0, "shutdown_reply");
+    kernel.join().unwrap().unwrap();
+}
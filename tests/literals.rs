@@ -0,0 +1,45 @@
+//! Number literals read to exactly the `f64` of `str::parse`
+
+use mfni::{Interpreter, Value};
+
+fn assert_round_trip(literals: &[&str]) {
+    let mut itp = Interpreter::new();
+    for literal in literals {
+        let expected = literal.parse::<f64>().unwrap();
+        match itp.eval_pure(literal) {
+            Ok(Value::Num(r)) => assert_eq!(r.to_bits(), expected.to_bits(), "{}", literal),
+            result => panic!("{}: {:?}", literal, result),
+        }
+    }
+}
+
+#[test]
+fn short_fractions() {
+    assert_round_trip(&["0.3", "0.1", "0.7", "2.675", "1.005", "123.456", "0.000123"]);
+}
+
+#[test]
+fn exponents() {
+    assert_round_trip(&[
+        "1e23",
+        "8.589973e9",
+        "1.7976931348623157e308",
+        "2.2250738585072014e-308",
+        "2.2250738585072011e-308",
+        "4.9e-324",
+        "1e-400",
+        "1.5E+10",
+    ]);
+}
+
+#[test]
+fn long_mantissas() {
+    assert_round_trip(&[
+        "9007199254740993.0",
+        "0.1000000000000000055511151231257827021181583404541015625",
+        "3.14159265358979323846264338327950288419716939937510",
+        "123456789012345678901234567890.123456789",
+        "0.30000000000000004440892098500626161694526672363281250000001",
+        "2.47032822920623272088284396434110686182529901307162382212792841250337753635104375932649918180817996189898282347722858865463328355177969898199387398005390939063150356595155702263922908583924491051844359318028499365361525003193704576782492193656236698636584807570236756e-324",
+    ]);
+}
@@ -0,0 +1,79 @@
+//! Interpreters computing in number types other than `f64`
+
+use mfni::{BuiltinGroup, DoubleDouble, Interpreter, Numeric, Value};
+
+fn show<T: Numeric>(itp: &mut Interpreter<T>, expression: &str) -> String {
+    itp.eval_pure(expression).unwrap().to_string()
+}
+
+#[test]
+fn single_precision() {
+    let mut itp = Interpreter::<f32>::default();
+    assert_eq!(itp.eval_pure("1 / 3"), Ok(Value::Num(1.0 / 3.0)));
+    assert_eq!(itp.eval_pure("sqrt(2)"), Ok(Value::Num(2f32.sqrt())));
+    assert_eq!(itp.eval_pure("pi"), Ok(Value::Num(std::f32::consts::PI)));
+    assert_eq!(itp.eval_pure("0.1"), Ok(Value::Num(0.1)));
+    // the libraries compute in f64
+    assert_eq!(itp.eval_pure("normcdf(0, 0, 1)"), Ok(Value::Num(0.5)));
+    assert_eq!(itp.eval_pure("2^3"), Ok(Value::Int(8)));
+}
+
+#[test]
+fn double_double_digits() {
+    let mut itp = Interpreter::<DoubleDouble>::default();
+    assert_eq!(show(&mut itp, "sqrt(2)"), "1.41421356237309504880168872421");
+    assert_eq!(show(&mut itp, "pi"), "3.14159265358979323846264338328");
+    assert_eq!(show(&mut itp, "1 / 3"), "0.3333333333333333333333333333333");
+    assert_eq!(
+        show(&mut itp, "2.0^100 + 1"),
+        "1267650600228229401496703205377"
+    );
+    assert_eq!(show(&mut itp, "90deg"), "1.57079632679489661923132169164");
+    assert_eq!(show(&mut itp, "round(2.5) + floor(-2.5)"), "0");
+    assert_eq!(show(&mut itp, "1 / 0"), "inf");
+    let third = itp.eval_pure("1 / 3").unwrap().to_string();
+    assert_ne!(
+        third,
+        Interpreter::new().eval_pure("1 / 3").unwrap().to_string()
+    );
+}
+
+#[test]
+fn double_double_dump() {
+    let mut itp = Interpreter::<DoubleDouble>::default();
+    itp.input(b"x = 1 / 3").unwrap();
+    let mut copy = Interpreter::<DoubleDouble>::default();
+    copy.load_script(&itp.dump_script()).unwrap();
+    assert_eq!(copy.export_values(), itp.export_values());
+}
+
+#[test]
+fn builder() {
+    let mut itp = Interpreter::builder()
+        .exclude(BuiltinGroup::Trig)
+        .build_numeric::<f32>();
+    assert_eq!(itp.eval_pure("abs(-0.5)"), Ok(Value::Num(0.5f32)));
+    assert!(itp.eval_pure("sin(1)").is_err());
+}
+
+#[cfg(feature = "decimal")]
+#[test]
+fn decimal_currency() {
+    use mfni::Decimal;
+
+    let mut itp = Interpreter::<Decimal>::default();
+    assert_eq!(itp.eval_pure("0.1 + 0.2 == 0.3"), Ok(Value::Bool(true)));
+    assert_eq!(show(&mut itp, "0.1 + 0.2"), "0.3");
+    assert_eq!(show(&mut itp, "19.99 * 3 - 0.97"), "59");
+    assert_eq!(show(&mut itp, "round(2.675, 2)"), "2.68");
+    assert_eq!(show(&mut itp, "1 / 3"), "0.3333333333333333333333333333");
+    assert_eq!(show(&mut itp, "sqrt(2)"), "1.4142135623730950488016887242");
+    assert_eq!(show(&mut itp, "pi"), "3.1415926535897932384626433833");
+    assert_eq!(show(&mut itp, "1 / 0"), "inf");
+    assert_eq!(show(&mut itp, "1e30 * 1e30"), "inf");
+    assert_eq!(show(&mut itp, "round(-2.5) - floor(0.5)"), "-3");
+    itp.input(b"price = 0.1 * 3").unwrap();
+    let mut copy = Interpreter::<Decimal>::default();
+    copy.load_script(&itp.dump_script()).unwrap();
+    assert_eq!(copy.export_values(), itp.export_values());
+}
@@ -0,0 +1,83 @@
+//! Lines and columns of errors, counted from the start of the statement or of a script
+
+use std::ops::Range;
+
+use mfni::{CommandError, InputError, InputState, Interpreter};
+
+/// The position of the error of the last of `lines`, input one after the other.
+fn position(lines: &[&str]) -> Option<(usize, Range<usize>)> {
+    let mut itp = Interpreter::new();
+    let (last, first) = lines.split_last().unwrap();
+    for line in first {
+        itp.input(line.as_bytes()).unwrap();
+    }
+    match itp.input(last.as_bytes()) {
+        Err(e) => e.position(),
+        Ok(_) => panic!("accepted {}", last),
+    }
+}
+
+#[test]
+fn undefined_identifier() {
+    assert_eq!(position(&["y = sqr(2) + 1"]), Some((1, 4..7)));
+    assert_eq!(position(&["1 + undefined_x"]), Some((1, 4..15)));
+}
+
+#[test]
+fn repeat_variable() {
+    assert_eq!(position(&["f: x, y, x = x"]), Some((1, 9..10)));
+    assert_eq!(position(&["{ a = 1; a = 2; a }"]), Some((1, 9..10)));
+}
+
+#[test]
+fn builtin_identifier() {
+    assert_eq!(position(&["pi = 3"]), Some((1, 0..2)));
+    assert_eq!(position(&["f: x, e = x"]), Some((1, 6..7)));
+}
+
+#[test]
+fn inconsistent_variables_count() {
+    assert_eq!(position(&["g: x = x", "1 + g(1, 2)"]), Some((1, 4..5)));
+}
+
+#[test]
+fn eval_error() {
+    // the operation folded at translation, or the outermost one evaluated
+    assert_eq!(position(&["1 + (2 < 3)"]), Some((1, 2..3)));
+    assert_eq!(
+        position(&["h: x = sqrt(x) + true", "y = h(1)"]),
+        Some((1, 4..5))
+    );
+}
+
+#[test]
+fn continued_statement() {
+    let mut itp = Interpreter::new();
+    assert!(matches!(
+        itp.input(b"a = 1 + ..."),
+        Ok(InputState::Incomplete)
+    ));
+    let error = match itp.input(b"  zz_q") {
+        Err(e) => e,
+        Ok(_) => panic!("accepted an undefined identifier"),
+    };
+    assert_eq!(error.position(), Some((2, 2..6)));
+    assert_eq!(error.offset_lines(3).position(), Some((5, 2..6)));
+}
+
+#[test]
+fn script_lines() {
+    let mut itp = Interpreter::new();
+    match itp.load_script("a = 1\nb = 2\nc = ...\n  1 + qq") {
+        Err(CommandError::Load { line, error }) => {
+            assert_eq!(line, 4);
+            assert!(matches!(error, InputError::UndefinedIdentifier { .. }));
+            assert_eq!(error.position(), Some((4, 6..8)));
+        }
+        _ => panic!("loaded a script with an undefined identifier"),
+    }
+    match itp.load_script("a = 1\n\nb = (1 +") {
+        Err(CommandError::Load { error, .. }) => assert_eq!(error.position(), Some((3, 8..9))),
+        _ => panic!("loaded an unfinished script"),
+    }
+}
@@ -0,0 +1,98 @@
+//! Left to right evaluation of `||` and `&&`, skipping the right operand when the left one decides
+
+use std::sync::{Arc, Mutex};
+
+use mfni::{Budget, DebugAction, InputError, InputState, Interpreter, Value, Warning};
+
+/// An interpreter with `a: x = x` and `b: x = x`, stepping through the user functions it calls from
+/// `a` on, and their names.
+fn recording() -> (Interpreter, Arc<Mutex<Vec<String>>>) {
+    let mut itp = Interpreter::new();
+    itp.input(b"a: x = x").unwrap();
+    itp.input(b"b: x = x").unwrap();
+    let calls = Arc::new(Mutex::new(vec![]));
+    let record = calls.clone();
+    itp.set_debugger(move |frame| {
+        record.lock().unwrap().push(frame.function.clone());
+        DebugAction::Step
+    });
+    itp.set_breakpoint("a");
+    (itp, calls)
+}
+
+fn calls(statement: &str) -> Vec<String> {
+    let (mut itp, calls) = recording();
+    itp.input(statement.as_bytes()).unwrap();
+    let calls = calls.lock().unwrap();
+    calls.clone()
+}
+
+#[test]
+fn or_calls_from_left_to_right() {
+    assert_eq!(calls("a(0) || b(1)"), ["a", "b"]);
+    assert_eq!(calls("a(1) || b(1)"), ["a"]);
+}
+
+#[test]
+fn and_calls_from_left_to_right() {
+    assert_eq!(calls("a(1) && b(0)"), ["a", "b"]);
+    assert_eq!(calls("a(0) && b(1)"), ["a"]);
+}
+
+#[test]
+fn nested_operands() {
+    assert_eq!(calls("(a(0) || b(0)) && a(2)"), ["a", "b"]);
+    assert_eq!(calls("a(0) || b(0) && a(2)"), ["a", "b"]);
+    assert_eq!(calls("a(1) && (b(0) || a(2))"), ["a", "b", "a"]);
+}
+
+#[test]
+fn errors_of_skipped_operands() {
+    let mut itp = Interpreter::new();
+    itp.set_budget(Some(Budget {
+        steps: 10_000,
+        depth: 64,
+    }));
+    itp.input(b"r: n = r(n) + 1").unwrap();
+    let mut value = |statement: &str| match itp.input(statement.as_bytes()) {
+        Ok(InputState::Expression { value, .. }) => Ok(value),
+        Ok(_) => unreachable!(),
+        Err(e) => Err(e),
+    };
+    assert_eq!(value("1 || r(1)"), Ok(Value::Bool(true)));
+    assert_eq!(value("0 && r(1)"), Ok(Value::Bool(false)));
+    assert!(matches!(value("0 || r(1)"), Err(InputError::EvalError(..))));
+    assert!(matches!(value("1 && r(1)"), Err(InputError::EvalError(..))));
+}
+
+#[test]
+fn traced_steps() {
+    let mut itp = Interpreter::new();
+    itp.set_tracing(true);
+    itp.input(b"1 < 2 || 3 < 4").unwrap();
+    let operations = itp
+        .trace()
+        .iter()
+        .map(|step| step.operation.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(operations, ["<", "||"]);
+}
+
+fn warnings(statement: &[u8]) -> Vec<Warning> {
+    match Interpreter::new().input(statement).unwrap() {
+        InputState::Assignment { warnings, .. } => warnings,
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn recursion_in_the_right_operand() {
+    let unbounded = |warnings: Vec<Warning>| {
+        warnings
+            .iter()
+            .any(|w| matches!(w, Warning::UnboundedRecursion { .. }))
+    };
+    assert!(!unbounded(warnings(b"q: x = x > 0 || q(x - 1)")));
+    assert!(!unbounded(warnings(b"q: x = x > 0 && q(x - 1)")));
+    assert!(unbounded(warnings(b"q: x = q(x - 1) || x > 0")));
+}